@@ -8,6 +8,7 @@
  */
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Display;
@@ -21,15 +22,20 @@ use buck2_build_api::actions::artifact::build_artifact::BuildArtifact;
 use buck2_build_api::actions::execute::action_execution_target::ActionExecutionTarget;
 use buck2_build_api::actions::execute::action_executor::ActionOutputs;
 use buck2_build_api::actions::impls::dep_files::FLUSH_DEP_FILES;
+use buck2_build_api::actions::impls::dep_files::INIT_DEP_FILES_TABLE;
 use buck2_build_api::actions::impls::expanded_command_line::ExpandedCommandLineDigest;
 use buck2_build_api::actions::ActionExecutionCtx;
 use buck2_build_api::artifact_groups::ArtifactGroup;
 use buck2_build_api::deferred::base_deferred_key::BaseDeferredKey;
 use buck2_build_api::interpreter::rule_defs::artifact_tagging::ArtifactTag;
 use buck2_build_api::interpreter::rule_defs::cmd_args::CommandLineArtifactVisitor;
+use buck2_common::cas_digest::CasDigestConfig;
 use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::FileMetadata;
 use buck2_common::file_ops::TrackedFileDigest;
+use buck2_common::sqlite::OrderedKeyValueSqliteTable;
 use buck2_core::category::Category;
+use buck2_core::directory::DirectoryEntry;
 use buck2_core::directory::DirectorySelector;
 use buck2_core::directory::FingerprintedDirectory;
 use buck2_core::env_helper::EnvHelper;
@@ -38,9 +44,11 @@ use buck2_core::fs::fs_util;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::soft_error;
 use buck2_execute::artifact::artifact_dyn::ArtifactDyn;
+use buck2_execute::artifact_value::ArtifactValue;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::directory::expand_selector_for_dependencies;
 use buck2_execute::directory::ActionDirectoryBuilder;
+use buck2_execute::directory::ActionDirectoryMember;
 use buck2_execute::directory::ActionImmutableDirectory;
 use buck2_execute::directory::ActionSharedDirectory;
 use buck2_execute::directory::INTERNER;
@@ -50,17 +58,35 @@ use ctor::ctor;
 use dashmap::DashMap;
 use derive_more::Display;
 use dupe::Dupe;
+use futures::channel::mpsc;
 use futures::StreamExt;
+use indexmap::IndexMap;
 use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
 use parking_lot::MappedMutexGuard;
 use parking_lot::Mutex;
 use parking_lot::MutexGuard;
+use serde::Deserialize;
+use serde::Serialize;
 use thiserror::Error;
 use tracing::instrument;
 
 #[allocative::root]
 static DEP_FILES: Lazy<DashMap<DepFilesKey, Arc<DepFileState>>> = Lazy::new(DashMap::new);
 
+/// Dep file state that a previous daemon persisted, and that we haven't used yet. This is keyed by
+/// the `DepFilesKey` the state was persisted for, formatted as a string. When an action looks for
+/// its dep file state and doesn't find it in `DEP_FILES`, we try to restore it from here.
+#[allocative::root]
+static PERSISTED_DEP_FILES: Lazy<DashMap<String, PersistedDepFileState>> = Lazy::new(DashMap::new);
+
+/// Writes to the table we persist dep file state to, if that's enabled.
+static DEP_FILES_TABLE_WRITER: OnceCell<DepFilesTableWriter> = OnceCell::new();
+
+/// The maximum number of actions whose dep file state we persist. Past that, we forget about the
+/// state that was least recently used.
+const MAX_PERSISTED_DEP_FILES: usize = 100_000;
+
 /// When this is set, we retain directories after fingerprintig, so that we can output them later
 /// for debugging via `buck2 audit dep-files`.
 static KEEP_DIRECTORIES: EnvHelper<bool> = EnvHelper::new("BUCK2_KEEP_DEP_FILE_DIRECTORIES");
@@ -69,6 +95,12 @@ static KEEP_DIRECTORIES: EnvHelper<bool> = EnvHelper::new("BUCK2_KEEP_DEP_FILE_D
 /// file was produced and the user wants unblocking, this will provide it.
 fn flush_dep_files() {
     DEP_FILES.clear();
+    PERSISTED_DEP_FILES.clear();
+    if let Some(writer) = DEP_FILES_TABLE_WRITER.get() {
+        if let Err(e) = writer.write(DepFilesTableWrite::DeleteAll) {
+            tracing::warn!("Error flushing persisted dep files: {:#}", e);
+        }
+    }
 }
 
 #[ctor]
@@ -76,12 +108,116 @@ fn set_flush_dep_files() {
     FLUSH_DEP_FILES.init(flush_dep_files);
 }
 
+/// Load the dep file state persisted in `table`, and use it to persist dep file state from now on.
+/// If we are already persisting dep file state, this does nothing.
+fn init_dep_files_table(table: OrderedKeyValueSqliteTable) -> anyhow::Result<()> {
+    DEP_FILES_TABLE_WRITER.get_or_try_init(|| {
+        let (table, persisted) = BoundedDepFilesTable::load(table, MAX_PERSISTED_DEP_FILES)?;
+        for (key, state) in persisted {
+            PERSISTED_DEP_FILES.insert(key, state);
+        }
+        DepFilesTableWriter::spawn(table)
+    })?;
+    Ok(())
+}
+
+#[ctor]
+fn set_init_dep_files_table() {
+    INIT_DEP_FILES_TABLE.init(init_dep_files_table);
+}
+
+/// Persist `state` (or forget about the persisted state, if `state` is None) for `key`, if we are
+/// persisting dep file state. Errors are only logged, since losing dep file state only means we
+/// might rerun an action.
+fn persist_dep_file_state(key: &DepFilesKey, state: Option<&DepFileState>, fs: &ArtifactFs) {
+    let writer = match DEP_FILES_TABLE_WRITER.get() {
+        Some(writer) => writer,
+        None => return,
+    };
+
+    let key = key.to_string();
+
+    let res: anyhow::Result<()> = try {
+        let persisted = match state {
+            Some(state) => PersistedDepFileState::new(state, fs)?,
+            None => None,
+        };
+
+        let write = match persisted {
+            Some(persisted) => DepFilesTableWrite::Insert {
+                key: key.clone(),
+                value: serde_json::to_string(&persisted)?,
+            },
+            None => DepFilesTableWrite::Delete { key: key.clone() },
+        };
+        writer.write(write)?
+    };
+
+    if let Err(e) = res {
+        tracing::warn!("Error persisting dep file state for `{}`: {:#}", key, e);
+    }
+}
+
+/// Record that the dep file state persisted for `key` was just used, so that it's the last to be
+/// evicted.
+fn touch_persisted_dep_file_state(key: &DepFilesKey) {
+    if let Some(writer) = DEP_FILES_TABLE_WRITER.get() {
+        if let Err(e) = writer.write(DepFilesTableWrite::Touch {
+            key: key.to_string(),
+        }) {
+            tracing::warn!("Error persisting dep file state for `{}`: {:#}", key, e);
+        }
+    }
+}
+
+/// Restore the dep file state that a previous daemon persisted for `key`, if any, and if it is
+/// compatible with the action we are about to run.
+fn restore_persisted_dep_file_state(
+    key: &DepFilesKey,
+    digests: &CommandDigests,
+    declared_inputs: &PartitionedInputs<Vec<ArtifactGroup>>,
+    declared_outputs: &[BuildArtifact],
+    declared_dep_files: &DeclaredDepFiles,
+    ctx: &dyn ActionExecutionCtx,
+) -> Option<Arc<DepFileState>> {
+    let (_, persisted) = PERSISTED_DEP_FILES.remove(&key.to_string())?;
+
+    let digest_config = ctx.digest_config();
+    let state = persisted.restore(
+        digests,
+        declared_outputs,
+        declared_dep_files,
+        ctx.fs(),
+        digest_config,
+        || Ok(declared_inputs.to_directories(ctx)?.share(digest_config)),
+    );
+
+    match state {
+        Ok(Some(state)) => {
+            tracing::trace!("Restored persisted dep files");
+            let state = Arc::new(state);
+            DEP_FILES.insert(key.clone(), state.dupe());
+            Some(state)
+        }
+        Ok(None) => {
+            tracing::trace!("Persisted dep files do not match the action");
+            persist_dep_file_state(key, None, ctx.fs());
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Error restoring persisted dep files for `{}`: {:#}", key, e);
+            persist_dep_file_state(key, None, ctx.fs());
+            None
+        }
+    }
+}
+
 pub fn get_dep_files(key: &DepFilesKey) -> Option<Arc<DepFileState>> {
     DEP_FILES.get(key).map(|s| s.dupe())
 }
 
 /// A key used to associate a RunAction with a possible previous dep file.
-#[derive(Eq, PartialEq, Hash, Display, Allocative)]
+#[derive(Clone, Eq, PartialEq, Hash, Display, Allocative)]
 #[display(
     fmt = "{} {} {}",
     owner,
@@ -275,10 +411,21 @@ pub(crate) async fn match_or_clear_dep_file(
 ) -> anyhow::Result<Option<ActionOutputs>> {
     let previous_state = match get_dep_files(key) {
         Some(d) => d.dupe(),
-        None => return Ok(None),
+        None => match restore_persisted_dep_file_state(
+            key,
+            digests,
+            declared_inputs,
+            declared_outputs,
+            declared_dep_files,
+            ctx,
+        ) {
+            Some(d) => d,
+            None => return Ok(None),
+        },
     };
 
     if dep_files_match(
+        key,
         &previous_state,
         digests,
         declared_inputs,
@@ -307,16 +454,19 @@ pub(crate) async fn match_or_clear_dep_file(
 
         if materializer_accepts {
             tracing::trace!("Dep files are a hit");
+            touch_persisted_dep_file_state(key);
             return Ok(Some(previous_state.result.dupe()));
         }
     }
 
     tracing::trace!("Dep files are a miss");
     DEP_FILES.remove(key);
+    persist_dep_file_state(key, None, ctx.fs());
     Ok(None)
 }
 
 async fn dep_files_match(
+    key: &DepFilesKey,
     previous_state: &DepFileState,
     digests: &CommandDigests,
     declared_inputs: &PartitionedInputs<Vec<ArtifactGroup>>,
@@ -368,6 +518,8 @@ async fn dep_files_match(
 
     let digest_config = ctx.digest_config();
 
+    let had_fingerprints = previous_state.has_signatures();
+
     let fingerprints_match = {
         // NOTE: We don't bother releasing the guard here (we'd have to clone the fingerprints to do
         // so), because this Mutex won't be contended: only one action will look at its value.
@@ -389,6 +541,11 @@ async fn dep_files_match(
         *previous_fingerprints == new_fingerprints
     };
 
+    if fingerprints_match && !had_fingerprints {
+        // Persist the fingerprints we just computed, so that a future daemon can use them.
+        persist_dep_file_state(key, Some(previous_state), ctx.fs());
+    }
+
     Ok(fingerprints_match)
 }
 
@@ -442,11 +599,358 @@ pub(crate) async fn populate_dep_files(
         ));
     }
 
+    persist_dep_file_state(&key, Some(&state), ctx.fs());
+
     DEP_FILES.insert(key, Arc::new(state));
 
     Ok(())
 }
 
+/// The on-disk representation of a `DepFileState`. We only persist the state of actions whose
+/// outputs are all files, since that's all we need to restore their `ActionOutputs`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Allocative)]
+struct PersistedDepFileState {
+    /// Hex-encoded `ExpandedCommandLineDigest`.
+    cli_digest: String,
+    directory_digest: String,
+    /// Maps the label of each dep file to the path it is written to.
+    dep_files: BTreeMap<String, String>,
+    /// Maps the path of each output (relative to its owner's buck-out) to its metadata.
+    outputs: BTreeMap<String, PersistedOutput>,
+    /// The fingerprints of the inputs that were listed in the dep files, if we had computed them.
+    fingerprints: Option<PersistedFingerprints>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Allocative)]
+struct PersistedOutput {
+    digest: String,
+    is_executable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Allocative)]
+struct PersistedFingerprints {
+    untagged: String,
+    tagged: BTreeMap<String, String>,
+}
+
+impl PersistedFingerprints {
+    fn new(fingerprints: &PartitionedInputs<TrackedFileDigest>) -> Self {
+        Self {
+            untagged: fingerprints.untagged.to_string(),
+            tagged: fingerprints
+                .tagged
+                .iter()
+                .map(|(label, digest)| (label.to_string(), digest.to_string()))
+                .collect(),
+        }
+    }
+
+    fn restore(
+        &self,
+        config: CasDigestConfig,
+    ) -> anyhow::Result<PartitionedInputs<TrackedFileDigest>> {
+        Ok(PartitionedInputs {
+            untagged: parse_tracked_digest(&self.untagged, config)?,
+            tagged: self
+                .tagged
+                .iter()
+                .map(|(label, digest)| {
+                    anyhow::Ok((
+                        Arc::from(label.as_str()),
+                        parse_tracked_digest(digest, config)?,
+                    ))
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+fn parse_digest(digest: &str, config: CasDigestConfig) -> anyhow::Result<FileDigest> {
+    let (digest, _) = FileDigest::parse_digest(digest, config)
+        .with_context(|| format!("Invalid digest: `{}`", digest))?;
+    Ok(digest)
+}
+
+fn parse_tracked_digest(
+    digest: &str,
+    config: CasDigestConfig,
+) -> anyhow::Result<TrackedFileDigest> {
+    Ok(TrackedFileDigest::new(
+        parse_digest(digest, config)?,
+        config,
+    ))
+}
+
+impl PersistedDepFileState {
+    /// Returns None if this state can't be persisted.
+    fn new(state: &DepFileState, fs: &ArtifactFs) -> anyhow::Result<Option<Self>> {
+        let mut outputs = BTreeMap::new();
+        for (path, value) in state.result.iter() {
+            match value.entry() {
+                DirectoryEntry::Leaf(ActionDirectoryMember::File(metadata)) => {
+                    outputs.insert(
+                        path.path().as_str().to_owned(),
+                        PersistedOutput {
+                            digest: metadata.digest.to_string(),
+                            is_executable: metadata.is_executable,
+                        },
+                    );
+                }
+                _ => return Ok(None),
+            }
+        }
+
+        let fingerprints = match &*state.input_signatures.lock() {
+            DepFileStateInputSignatures::Computed(StoredFingerprints::Digests(fingerprints)) => {
+                Some(PersistedFingerprints::new(fingerprints))
+            }
+            DepFileStateInputSignatures::Computed(StoredFingerprints::Dirs(dirs)) => {
+                Some(PersistedFingerprints::new(&dirs.as_fingerprints()))
+            }
+            DepFileStateInputSignatures::Deferred(..) => None,
+        };
+
+        Ok(Some(Self {
+            cli_digest: hex::encode(state.digests.cli.as_bytes()),
+            directory_digest: state.digests.directory.to_string(),
+            dep_files: state.declared_dep_files.resolved_paths(fs)?,
+            outputs,
+            fingerprints,
+        }))
+    }
+
+    /// Produce a DepFileState for an action that declares `declared_outputs` and
+    /// `declared_dep_files`. Returns None if the action doesn't declare the same dep files and
+    /// outputs as the action this state was persisted for, or if we can't use this state.
+    /// `input_directories` produces the action's current inputs, which we only need if we hadn't
+    /// persisted fingerprints.
+    fn restore(
+        &self,
+        digests: &CommandDigests,
+        declared_outputs: &[BuildArtifact],
+        declared_dep_files: &DeclaredDepFiles,
+        fs: &ArtifactFs,
+        digest_config: DigestConfig,
+        input_directories: impl FnOnce() -> anyhow::Result<PartitionedInputs<ActionSharedDirectory>>,
+    ) -> anyhow::Result<Option<DepFileState>> {
+        let cas_digest_config = digest_config.cas_digest_config();
+
+        if declared_dep_files.resolved_paths(fs)? != self.dep_files {
+            return Ok(None);
+        }
+
+        let mut outputs = IndexMap::with_capacity(declared_outputs.len());
+        for output in declared_outputs {
+            let path = output.get_path();
+            let persisted = match self.outputs.get(path.path().as_str()) {
+                Some(persisted) => persisted,
+                None => return Ok(None),
+            };
+            outputs.insert(
+                path.dupe(),
+                ArtifactValue::file(FileMetadata {
+                    digest: parse_tracked_digest(&persisted.digest, cas_digest_config)?,
+                    is_executable: persisted.is_executable,
+                }),
+            );
+        }
+
+        let cli = hex::decode(&self.cli_digest)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid command line digest"))?;
+        let directory = parse_digest(&self.directory_digest, cas_digest_config)?;
+
+        let input_signatures = match &self.fingerprints {
+            Some(fingerprints) => DepFileStateInputSignatures::Computed(
+                StoredFingerprints::Digests(fingerprints.restore(cas_digest_config)?),
+            ),
+            None => {
+                // Without fingerprints, we can only use this state if the inputs haven't changed,
+                // in which case the current inputs are the ones we'd have fingerprinted.
+                if digests.directory != directory {
+                    return Ok(None);
+                }
+                DepFileStateInputSignatures::Deferred(Some(input_directories()?))
+            }
+        };
+
+        Ok(Some(DepFileState {
+            digests: CommandDigests {
+                cli: ExpandedCommandLineDigest::from_bytes(cli),
+                directory,
+            },
+            input_signatures: Mutex::new(input_signatures),
+            declared_dep_files: declared_dep_files.clone(),
+            result: ActionOutputs::new(outputs),
+        }))
+    }
+}
+
+/// A write to the dep files table.
+enum DepFilesTableWrite {
+    Insert {
+        key: String,
+        value: String,
+    },
+    /// Mark the state persisted for `key` (if any) as most recently used.
+    Touch {
+        key: String,
+    },
+    Delete {
+        key: String,
+    },
+    DeleteAll,
+}
+
+/// The dep files table, along with the positions of the keys it holds, so that we can keep it to
+/// `max_entries` rows by evicting the least recently used ones. A key's position is bumped whenever
+/// it is inserted or touched.
+struct BoundedDepFilesTable {
+    table: OrderedKeyValueSqliteTable,
+    positions: HashMap<String, i64>,
+    /// The keys in `positions`, least recently used first.
+    keys: BTreeMap<i64, String>,
+    next_position: i64,
+    max_entries: usize,
+}
+
+impl BoundedDepFilesTable {
+    /// Read the dep file state persisted in `table`. Rows we can't parse (and the least recently
+    /// used rows past `max_entries`) are deleted.
+    fn load(
+        table: OrderedKeyValueSqliteTable,
+        max_entries: usize,
+    ) -> anyhow::Result<(Self, HashMap<String, PersistedDepFileState>)> {
+        let mut persisted = HashMap::new();
+        let mut positions = HashMap::new();
+        let mut keys = BTreeMap::new();
+        let mut next_position = 0;
+        for (key, value, position) in table.read_all()? {
+            next_position = position + 1;
+            match serde_json::from_str(&value) {
+                Ok(state) => {
+                    positions.insert(key.clone(), position);
+                    keys.insert(position, key.clone());
+                    persisted.insert(key, state);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Ignoring invalid persisted dep file state for `{}`: {}",
+                        key,
+                        e
+                    );
+                    table.delete(&key)?;
+                }
+            }
+        }
+
+        let mut this = Self {
+            table,
+            positions,
+            keys,
+            next_position,
+            max_entries,
+        };
+        for key in this.evict()? {
+            persisted.remove(&key);
+        }
+
+        Ok((this, persisted))
+    }
+
+    fn apply(&mut self, write: DepFilesTableWrite) -> anyhow::Result<()> {
+        match write {
+            DepFilesTableWrite::Insert { key, value } => {
+                let position = self.bump(&key);
+                self.table.insert(&key, &value, position)?;
+                self.evict()?;
+            }
+            DepFilesTableWrite::Touch { key } => {
+                if self.positions.contains_key(&key) {
+                    let position = self.bump(&key);
+                    self.table.set_position(&key, position)?;
+                }
+            }
+            DepFilesTableWrite::Delete { key } => {
+                self.table.delete(&key)?;
+                if let Some(position) = self.positions.remove(&key) {
+                    self.keys.remove(&position);
+                }
+            }
+            DepFilesTableWrite::DeleteAll => {
+                self.table.delete_all()?;
+                self.positions.clear();
+                self.keys.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Make `key` the most recently used key, and return its new position.
+    fn bump(&mut self, key: &str) -> i64 {
+        let position = self.next_position;
+        self.next_position += 1;
+        if let Some(previous) = self.positions.insert(key.to_owned(), position) {
+            self.keys.remove(&previous);
+        }
+        self.keys.insert(position, key.to_owned());
+        position
+    }
+
+    /// Delete the least recently used rows if we have more than `max_entries`, and return their
+    /// keys.
+    fn evict(&mut self) -> anyhow::Result<Vec<String>> {
+        if self.keys.len() <= self.max_entries {
+            return Ok(Vec::new());
+        }
+
+        // Evict an extra tenth of the table, so that we don't evict on every insert.
+        let count = self.keys.len() - self.max_entries + self.max_entries / 10;
+        let mut evicted = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (_, key) = match self.keys.pop_first() {
+                Some(entry) => entry,
+                None => break,
+            };
+            self.positions.remove(&key);
+            self.table.delete(&key)?;
+            evicted.push(key);
+        }
+        Ok(evicted)
+    }
+}
+
+/// Applies writes to the dep files table on a dedicated thread. The table shares its sqlite
+/// connection with the materializer, so we don't want actions to wait on it.
+struct DepFilesTableWriter {
+    sender: mpsc::UnboundedSender<DepFilesTableWrite>,
+}
+
+impl DepFilesTableWriter {
+    fn spawn(mut table: BoundedDepFilesTable) -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        std::thread::Builder::new()
+            .name("buck2-dep-files".to_owned())
+            .spawn(move || {
+                for write in futures::executor::block_on_stream(receiver) {
+                    if let Err(e) = table.apply(write) {
+                        tracing::warn!("Error writing persisted dep files: {:#}", e);
+                    }
+                }
+            })
+            .context("Cannot start dep files writer thread")?;
+
+        Ok(Self { sender })
+    }
+
+    fn write(&self, write: DepFilesTableWrite) -> anyhow::Result<()> {
+        self.sender
+            .unbounded_send(write)
+            .map_err(|_| anyhow::anyhow!("Dep files writer thread has exited"))
+    }
+}
+
 /// Inputs partitioned by tag. `D` is the representation of the set of inputs.
 #[derive(Clone, PartialEq, Eq, Allocative)]
 pub struct PartitionedInputs<D> {
@@ -627,7 +1131,7 @@ struct DeclaredDepFile {
 }
 
 /// All the dep files declared by a command;
-#[derive(Default, Debug, Allocative)]
+#[derive(Default, Debug, Clone, Allocative)]
 pub(crate) struct DeclaredDepFiles {
     tagged: HashMap<ArtifactTag, DeclaredDepFile>,
 }
@@ -763,6 +1267,18 @@ impl DeclaredDepFiles {
         Ok(Some(ConcreteDepFiles { contents }))
     }
 
+    /// Map the label of each dep file to the path it is written to. Like
+    /// `declares_same_dep_files`, this ignores tags.
+    fn resolved_paths(&self, fs: &ArtifactFs) -> anyhow::Result<BTreeMap<String, String>> {
+        self.tagged
+            .values()
+            .map(|declared_dep_file| {
+                let path = declared_dep_file.output.resolve_path(fs)?;
+                anyhow::Ok((declared_dep_file.label.to_string(), path.to_string()))
+            })
+            .collect()
+    }
+
     /// Returns whether two DeclaredDepFile instances have the same dep files. This ignores the tag
     /// identity, but it requires the same paths declared using the same name. This is a
     /// pre-requisite for being able to reuse dep files from a previous invocation.
@@ -822,13 +1338,22 @@ impl CommandLineArtifactVisitor for DepFilesCommandLineVisitor<'_> {
 
 #[cfg(test)]
 mod test {
+    use anyhow::Context;
     use buck2_build_api::actions::artifact::artifact_type::testing::BuildArtifactTestingExt;
     use buck2_build_api::deferred::types::testing::DeferredIdExt;
     use buck2_build_api::deferred::types::DeferredId;
+    use buck2_core::buck_path::resolver::BuckPathResolver;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::CellResolver;
     use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::buck_out_path::BuckOutPathResolver;
     use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathBuf;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
     use buck2_core::target::label::ConfiguredTargetLabel;
     use maplit::hashmap;
+    use rusqlite::Connection;
 
     use super::*;
 
@@ -889,4 +1414,202 @@ mod test {
         assert!(!decl2.declares_same_dep_files(&decl3));
         assert!(!decl3.declares_same_dep_files(&decl4));
     }
+
+    #[test]
+    fn test_persisted_fingerprints() -> anyhow::Result<()> {
+        let config = CasDigestConfig::testing_default();
+
+        let fingerprints = PartitionedInputs {
+            untagged: TrackedFileDigest::from_content(b"untagged", config),
+            tagged: hashmap! {
+                Arc::from("foo") => TrackedFileDigest::from_content(b"foo", config),
+            },
+        };
+
+        let persisted = PersistedFingerprints::new(&fingerprints);
+        let persisted: PersistedFingerprints =
+            serde_json::from_str(&serde_json::to_string(&persisted)?)?;
+
+        assert!(persisted.restore(config)? == fingerprints);
+
+        Ok(())
+    }
+
+    fn open_table(temp: &ProjectRootTemp) -> anyhow::Result<OrderedKeyValueSqliteTable> {
+        let connection = Connection::open(
+            temp.path()
+                .resolve(ProjectRelativePath::unchecked_new("dep_files.db")),
+        )?;
+        Ok(OrderedKeyValueSqliteTable::new(
+            "dep_files".to_owned(),
+            Arc::new(Mutex::new(connection)),
+        ))
+    }
+
+    #[test]
+    fn test_persisted_dep_file_state_across_restart() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = ArtifactFs::new(
+            BuckPathResolver::new(CellResolver::testing_with_name_and_path(
+                CellName::testing_new("cell"),
+                CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new("cell".into())),
+            )),
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new("buck-out/v2".into())),
+            temp.path().dupe(),
+        );
+        let digest_config = DigestConfig::testing_default();
+        let config = digest_config.cas_digest_config();
+
+        let target =
+            ConfiguredTargetLabel::testing_parse("cell//pkg:foo", ConfigurationData::testing_new());
+        let build_artifact = |path: &str| {
+            BuildArtifact::testing_new(
+                target.dupe(),
+                ForwardRelativePathBuf::unchecked_new(path.to_owned()),
+                DeferredId::testing_new(0),
+            )
+        };
+        let declared_dep_files = |label: &str| DeclaredDepFiles {
+            tagged: hashmap! {
+                ArtifactTag::new() => DeclaredDepFile {
+                    label: Arc::from(label),
+                    output: Artifact::from(build_artifact("foo/dep_file")),
+                },
+            },
+        };
+
+        let output = build_artifact("foo/out");
+        let fingerprints = PartitionedInputs {
+            untagged: TrackedFileDigest::from_content(b"untagged", config),
+            tagged: hashmap! {
+                Arc::from("dep_file") => TrackedFileDigest::from_content(b"tagged", config),
+            },
+        };
+        let state = DepFileState {
+            digests: CommandDigests {
+                cli: ExpandedCommandLineDigest::from_bytes([1; 32]),
+                directory: FileDigest::from_content(b"inputs", config),
+            },
+            input_signatures: Mutex::new(DepFileStateInputSignatures::Computed(
+                StoredFingerprints::Digests(fingerprints.clone()),
+            )),
+            declared_dep_files: declared_dep_files("dep_file"),
+            result: ActionOutputs::from_single(
+                output.get_path().dupe(),
+                ArtifactValue::file(FileMetadata {
+                    digest: TrackedFileDigest::from_content(b"out", config),
+                    is_executable: false,
+                }),
+            ),
+        };
+
+        let table = open_table(&temp)?;
+        table.create_table()?;
+        let (mut table, persisted) = BoundedDepFilesTable::load(table, 10)?;
+        assert!(persisted.is_empty());
+        let persisted = PersistedDepFileState::new(&state, &fs)?.context("Not persisted")?;
+        table.apply(DepFilesTableWrite::Insert {
+            key: "key".to_owned(),
+            value: serde_json::to_string(&persisted)?,
+        })?;
+        drop(table);
+
+        // A new daemon reads the state back from the db.
+        let (_table, mut persisted) = BoundedDepFilesTable::load(open_table(&temp)?, 10)?;
+        let persisted = persisted.remove("key").context("Missing persisted state")?;
+
+        let restore = |outputs: &[BuildArtifact], dep_files: &DeclaredDepFiles| {
+            persisted.restore(
+                &state.digests,
+                outputs,
+                dep_files,
+                &fs,
+                digest_config,
+                || panic!("Fingerprints were persisted, inputs aren't needed"),
+            )
+        };
+
+        let restored = restore(&[output.dupe()], &declared_dep_files("dep_file"))?
+            .context("Persisted state was rejected")?;
+        assert_eq!(restored.digests.cli, state.digests.cli);
+        assert_eq!(restored.digests.directory, state.digests.directory);
+        assert_eq!(restored.result, state.result);
+        match &*restored.input_signatures.lock() {
+            DepFileStateInputSignatures::Computed(StoredFingerprints::Digests(restored)) => {
+                assert!(*restored == fingerprints)
+            }
+            _ => panic!("Fingerprints were not restored"),
+        }
+
+        // Actions that declare different dep files or outputs can't use this state.
+        assert!(restore(&[output.dupe()], &declared_dep_files("other"))?.is_none());
+        assert!(
+            restore(
+                &[build_artifact("foo/other")],
+                &declared_dep_files("dep_file")
+            )?
+            .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bounded_dep_files_table() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let table = open_table(&temp)?;
+        table.create_table()?;
+
+        let value = serde_json::to_string(&PersistedDepFileState {
+            cli_digest: String::new(),
+            directory_digest: String::new(),
+            dep_files: BTreeMap::new(),
+            outputs: BTreeMap::new(),
+            fingerprints: None,
+        })?;
+        let insert = |key: &str| DepFilesTableWrite::Insert {
+            key: key.to_owned(),
+            value: value.clone(),
+        };
+        let touch = |key: &str| DepFilesTableWrite::Touch {
+            key: key.to_owned(),
+        };
+        // The keys in the db, least recently used first.
+        let keys = |table: &BoundedDepFilesTable| -> anyhow::Result<Vec<String>> {
+            Ok(table
+                .table
+                .read_all()?
+                .into_iter()
+                .map(|(key, _, _)| key)
+                .collect())
+        };
+
+        let (mut table, _) = BoundedDepFilesTable::load(table, 2)?;
+        table.apply(insert("a"))?;
+        table.apply(insert("b"))?;
+        table.apply(touch("a"))?;
+        table.apply(insert("c"))?;
+        assert_eq!(keys(&table)?, vec!["a", "c"]);
+
+        // Touching a key we don't have does nothing.
+        table.apply(touch("b"))?;
+        assert_eq!(keys(&table)?, vec!["a", "c"]);
+
+        // A new daemon keeps evicting in the same order.
+        drop(table);
+        let (mut table, persisted) = BoundedDepFilesTable::load(open_table(&temp)?, 2)?;
+        assert_eq!(persisted.len(), 2);
+        table.apply(insert("d"))?;
+        assert_eq!(keys(&table)?, vec!["c", "d"]);
+
+        table.apply(DepFilesTableWrite::Delete {
+            key: "c".to_owned(),
+        })?;
+        assert_eq!(keys(&table)?, vec!["d"]);
+
+        table.apply(DepFilesTableWrite::DeleteAll)?;
+        assert!(keys(&table)?.is_empty());
+
+        Ok(())
+    }
 }
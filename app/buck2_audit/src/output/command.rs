@@ -103,6 +103,7 @@ async fn write_output(
     json: bool,
     output_attributes: &[String],
     cell_resolver: &CellResolver,
+    dice_ctx: &DiceComputations,
 ) -> anyhow::Result<()> {
     // Dot/DotCompact output format don't make sense here.
     let unstable_output_format = if json {
@@ -126,6 +127,7 @@ async fn write_output(
             QueryEvaluationValue::TargetSet(result),
             false,
             ShouldPrintProviders::No,
+            dice_ctx,
         )
         .await
 }
@@ -255,7 +257,7 @@ impl AuditSubcommand for AuditOutputCommand {
                     Some(result) => {
                         match result {
                             AuditOutputResult::Match(action) => {
-                                write_output(&mut stdout, action, self.json, &self.query_attributes.get()?, &cell_resolver).await?
+                                write_output(&mut stdout, action, self.json, &self.query_attributes.get()?, &cell_resolver, &dice_ctx).await?
                             },
                             AuditOutputResult::MaybeRelevant(label) => {
                                 writeln!(
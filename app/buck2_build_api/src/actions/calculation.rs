@@ -407,6 +407,10 @@ async fn command_details(
             queue_time: command.timing.re_queue_time.and_then(|d| d.try_into().ok()),
         }
         .into(),
+        CommandExecutionKind::LocalActionCache { digest } => buck2_data::LocalActionCacheHit {
+            action_digest: digest.to_string(),
+        }
+        .into(),
     });
 
     buck2_data::CommandExecutionDetails {
@@ -416,6 +420,16 @@ async fn command_details(
         command: command_data,
         signed_exit_code,
         execution_stats: command.timing.execution_stats,
+        input_root_digest: command
+            .inputs
+            .as_ref()
+            .map(|i| i.root_digest.to_string())
+            .unwrap_or_default(),
+        inputs: command
+            .inputs
+            .as_ref()
+            .map(|i| i.entries.clone())
+            .unwrap_or_default(),
     }
 }
 
@@ -926,6 +940,7 @@ mod tests {
                 stderr: "stderr".to_owned().into_bytes(),
             },
             exit_code: Some(1),
+            inputs: None,
         };
 
         let proto = command_details(&report, false).await;
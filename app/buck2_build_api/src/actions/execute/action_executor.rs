@@ -38,6 +38,7 @@ use buck2_execute::execute::kind::CommandExecutionKind;
 use buck2_execute::execute::manager::CommandExecutionManager;
 use buck2_execute::execute::request::CommandExecutionRequest;
 use buck2_execute::execute::request::OutputType;
+use buck2_execute::execute::result::CommandExecutionInputs;
 use buck2_execute::execute::result::CommandExecutionReport;
 use buck2_execute::execute::result::CommandExecutionResult;
 use buck2_execute::execute::result::CommandExecutionStatus;
@@ -384,8 +385,14 @@ impl ActionExecutionCtx for BuckActionExecutionContext<'_> {
             _ => Err(CommandExecutionErrorMarker.into()),
         };
 
-        self.command_reports.extend(rejected_execution.into_iter());
-        self.command_reports.push(report);
+        let inputs = Arc::new(CommandExecutionInputs::new(request.paths()));
+        for mut report in rejected_execution
+            .into_iter()
+            .chain(std::iter::once(report))
+        {
+            report.inputs = Some(inputs.dupe());
+            self.command_reports.push(report);
+        }
 
         res
     }
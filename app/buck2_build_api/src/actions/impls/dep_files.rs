@@ -7,12 +7,22 @@
  * of this source tree.
  */
 
+use buck2_common::sqlite::OrderedKeyValueSqliteTable;
 use buck2_util::late_binding::LateBinding;
 
 pub static FLUSH_DEP_FILES: LateBinding<fn()> = LateBinding::new("FLUSH_DEP_FILES");
 
+pub static INIT_DEP_FILES_TABLE: LateBinding<fn(OrderedKeyValueSqliteTable) -> anyhow::Result<()>> =
+    LateBinding::new("INIT_DEP_FILES_TABLE");
+
 /// Forget about all dep files. This isn't really meant to be commonly used, but if an invalid dep
 /// file was produced and the user wants unblocking, this will provide it.
 pub fn flush_dep_files() {
     (FLUSH_DEP_FILES.get().unwrap())();
 }
+
+/// Load the dep file state that was persisted to `table` by a previous daemon, and persist dep
+/// file state there from now on. Only the first call does anything.
+pub fn init_dep_files_table(table: OrderedKeyValueSqliteTable) -> anyhow::Result<()> {
+    (INIT_DEP_FILES_TABLE.get()?)(table)
+}
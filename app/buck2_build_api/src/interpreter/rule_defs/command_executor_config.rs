@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::path::Path;
 use std::sync::Arc;
 
 use allocative::Allocative;
@@ -17,6 +18,7 @@ use buck2_common::executor_config::CommandGenerationOptions;
 use buck2_common::executor_config::Executor;
 use buck2_common::executor_config::HybridExecutionLevel;
 use buck2_common::executor_config::LocalExecutorOptions;
+use buck2_common::executor_config::LocalSandboxOptions;
 use buck2_common::executor_config::PathSeparatorKind;
 use buck2_common::executor_config::RemoteEnabledExecutor;
 use buck2_common::executor_config::RemoteExecutorOptions;
@@ -81,6 +83,10 @@ pub fn register_command_executor_config(builder: &mut GlobalsBuilder) {
     /// * `max_cache_upload_mebibytes`: Maximum size to upload in cache uploads
     /// * `experimental_low_pass_filter`: Whether to use the experimental low pass filter
     /// * `remote_output_paths`: How to express output paths to RE
+    /// * `use_local_sandbox`: Whether to run local actions in a sandbox where only the directories
+    /// of their declared inputs, and their outputs, are visible (Linux only)
+    /// * `local_sandbox_toolchain_paths`: Absolute paths that sandboxed local actions can read in
+    /// addition to their inputs (defaults to the usual system directories, e.g. `/usr`)
     #[starlark(type = "command_executor_config")]
     fn CommandExecutorConfig<'v>(
         #[starlark(require = named)] local_enabled: bool,
@@ -103,6 +109,8 @@ pub fn register_command_executor_config(builder: &mut GlobalsBuilder) {
         >,
         #[starlark(default = false, require = named)] experimental_low_pass_filter: bool,
         #[starlark(default = NoneOr::None, require = named)] remote_output_paths: NoneOr<&str>,
+        #[starlark(default = false, require = named)] use_local_sandbox: bool,
+        #[starlark(require = named)] local_sandbox_toolchain_paths: Option<Vec<String>>,
     ) -> anyhow::Result<StarlarkCommandExecutorConfig> {
         let command_executor_config = {
             let remote_execution_max_input_files_mebibytes =
@@ -142,7 +150,24 @@ pub fn register_command_executor_config(builder: &mut GlobalsBuilder) {
             };
 
             let local_options = if local_enabled {
-                Some(LocalExecutorOptions {})
+                let sandbox = if use_local_sandbox {
+                    let toolchain_paths = local_sandbox_toolchain_paths.unwrap_or_else(|| {
+                        LocalSandboxOptions::DEFAULT_TOOLCHAIN_PATHS
+                            .iter()
+                            .map(|p| (*p).to_owned())
+                            .collect()
+                    });
+                    if !toolchain_paths.iter().all(|p| Path::new(p).is_absolute()) {
+                        return Err(CommandExecutorConfigErrors::InvalidField(
+                            "local_sandbox_toolchain_paths",
+                        )
+                        .into());
+                    }
+                    Some(Arc::new(LocalSandboxOptions { toolchain_paths }))
+                } else {
+                    None
+                };
+                Some(LocalExecutorOptions { sandbox })
             } else {
                 None
             };
@@ -10,6 +10,7 @@
 //! Implementation of common cquery/uquery pieces.
 
 use buck2_query::query::environment::QueryEnvironment;
+use buck2_query::query::environment::TargetSink;
 use buck2_query::query::syntax::simple::eval::evaluator::QueryEvaluator;
 use buck2_query::query::syntax::simple::eval::literals::extract_target_literals;
 use buck2_query::query::syntax::simple::eval::multi_query::process_multi_query;
@@ -27,6 +28,8 @@ enum EvalQueryError {
     ArgsWithoutPlaceholder(Vec<String>),
     #[error("Placeholder `%s` in query argument `{0}`")]
     PlaceholderInPattern(String),
+    #[error("Queries with a `%s` placeholder can't be streamed")]
+    StreamingWithPlaceholder,
 }
 
 pub async fn eval_query<
@@ -79,3 +82,25 @@ pub async fn eval_query<
         ))
     }
 }
+
+/// Like `eval_query`, but adds the resulting targets to `sink` as they are found. Queries with a
+/// `%s` placeholder are not supported.
+pub async fn eval_query_streaming<
+    Env: QueryEnvironment,
+    Fut: Future<Output = anyhow::Result<Env>>,
+>(
+    functions: &DefaultQueryFunctionsModule<Env>,
+    query: &str,
+    sink: &mut dyn TargetSink<Env::Target>,
+    environment: impl FnOnce(Vec<String>) -> Fut,
+) -> anyhow::Result<()> {
+    if query.contains(QUERY_PERCENT_S_PLACEHOLDER) {
+        return Err(EvalQueryError::StreamingWithPlaceholder.into());
+    }
+    let mut literals = SmallSet::new();
+    extract_target_literals(functions, query, &mut literals)?;
+    let env = environment(literals.into_iter().collect()).await?;
+    QueryEvaluator::new(&env, functions)
+        .eval_query_streaming(query, sink)
+        .await
+}
@@ -14,9 +14,11 @@ use std::hash::Hash;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use buck2_common::package_listing::resolver::is_no_containing_package;
 use buck2_core::build_file_path::BuildFilePath;
 use buck2_core::cells::cell_path::CellPath;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::target::label::ConfiguredTargetLabel;
 use buck2_execute::artifact::fs::ExecutorFs;
 use buck2_query::query::compatibility::MaybeCompatible;
 use buck2_query::query::environment::LabeledNode;
@@ -63,6 +65,8 @@ impl ActionAttr {
 pub struct SetProjectionInputsData {
     key: TransitiveSetProjectionKey,
     direct: Vec<ActionKey>,
+    /// Paths of the artifacts in this projection (not including children).
+    paths: Vec<CellPath>,
     children: Vec<SetProjectionInputs>,
 }
 
@@ -78,18 +82,55 @@ impl SetProjectionInputs {
     pub fn new(
         key: TransitiveSetProjectionKey,
         direct: Vec<ActionKey>,
+        paths: Vec<CellPath>,
         children: Vec<SetProjectionInputs>,
     ) -> Self {
         Self {
             node: ArcIntern::new(SetProjectionInputsData {
                 key,
                 direct,
+                paths,
                 children,
             }),
         }
     }
 }
 
+/// Breadth-first iteration over a tset projection graph, visiting each node once.
+struct SetProjectionInputsIter<'a> {
+    visited: HashSet<&'a SetProjectionInputs>,
+    queue: VecDeque<&'a SetProjectionInputs>,
+}
+
+impl<'a> SetProjectionInputsIter<'a> {
+    fn new<From: Iterator<Item = &'a SetProjectionInputs>>(iter: From) -> Self {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        for it in iter {
+            if visited.insert(it) {
+                queue.push_back(it);
+            }
+        }
+        Self { visited, queue }
+    }
+}
+
+impl<'a> Iterator for SetProjectionInputsIter<'a> {
+    type Item = &'a SetProjectionInputs;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.pop_front().map(|node| {
+            for child in &*node.node.children {
+                if self.visited.insert(child) {
+                    self.queue.push_back(child);
+                }
+            }
+
+            node
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum ActionInput {
     ActionKey(ActionKey),
@@ -101,15 +142,27 @@ pub enum ActionInput {
 pub struct ActionQueryNode {
     action: Arc<RegisteredAction>,
     deps: Arc<Vec<ActionInput>>,
+    /// Paths of the artifacts this action reads directly (i.e. not through tsets).
+    input_paths: Arc<Vec<CellPath>>,
+    /// The buildfile of the target that owns this action, if it's owned by a target.
+    buildfile_path: Option<Arc<BuildFilePath>>,
     #[derivative(Debug = "ignore")]
     fs: Arc<ArtifactFs>,
 }
 
 impl ActionQueryNode {
-    pub fn new(action: Arc<RegisteredAction>, deps: Vec<ActionInput>, fs: Arc<ArtifactFs>) -> Self {
+    pub fn new(
+        action: Arc<RegisteredAction>,
+        deps: Vec<ActionInput>,
+        input_paths: Vec<CellPath>,
+        buildfile_path: Option<Arc<BuildFilePath>>,
+        fs: Arc<ArtifactFs>,
+    ) -> Self {
         Self {
             action,
             deps: Arc::new(deps),
+            input_paths: Arc::new(input_paths),
+            buildfile_path,
             fs,
         }
     }
@@ -124,6 +177,22 @@ impl ActionQueryNode {
     pub fn action(&self) -> Arc<RegisteredAction> {
         self.action.dupe()
     }
+
+    fn set_projection_inputs(&self) -> SetProjectionInputsIter<'_> {
+        SetProjectionInputsIter::new(self.deps.iter().filter_map(|input| match input {
+            ActionInput::ActionKey(..) => None,
+            ActionInput::IndirectInputs(val) => Some(val),
+        }))
+    }
+
+    /// Paths of all the artifacts this action reads, both source files and outputs of other
+    /// actions (in `buck-out`).
+    pub fn input_paths(&self) -> impl Iterator<Item = &CellPath> {
+        self.input_paths.iter().chain(
+            self.set_projection_inputs()
+                .flat_map(|v| v.node.paths.iter()),
+        )
+    }
 }
 
 impl LabeledNode for ActionQueryNode {
@@ -141,59 +210,19 @@ impl QueryTarget for ActionQueryNode {
         Cow::Owned(self.action.kind().variant_name().to_ascii_lowercase())
     }
 
-    /// Return the path to the buildfile that defines this target, e.g. `fbcode//foo/bar/TARGETS`
-    fn buildfile_path(&self) -> &BuildFilePath {
-        // TODO(cjhopman): In addition to implementing this, we should be able to return an anyhow::Error here rather than panicking.
-        unimplemented!("buildfile not yet implemented in aquery")
+    /// Return the path to the buildfile that defines the target that owns this action, e.g. `fbcode//foo/bar/TARGETS`
+    fn buildfile_path(&self) -> Option<&BuildFilePath> {
+        self.buildfile_path.as_deref()
     }
 
     // TODO(cjhopman): Use existential traits to remove the Box<> once they are stabilized.
     fn deps<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Self::NodeRef> + Send + 'a> {
-        struct Iter<'a> {
-            visited: HashSet<&'a SetProjectionInputs>,
-            queue: VecDeque<&'a SetProjectionInputs>,
-        }
-
-        impl<'a> Iter<'a> {
-            fn new<From: Iterator<Item = &'a SetProjectionInputs>>(iter: From) -> Self {
-                let mut visited = HashSet::new();
-                let mut queue = VecDeque::new();
-                for it in iter {
-                    if visited.insert(it) {
-                        queue.push_back(it);
-                    }
-                }
-                Self { visited, queue }
-            }
-        }
-
-        impl<'a> Iterator for Iter<'a> {
-            type Item = &'a SetProjectionInputs;
-
-            fn next(&mut self) -> Option<Self::Item> {
-                self.queue.pop_front().map(|node| {
-                    for child in &*node.node.children {
-                        if self.visited.insert(child) {
-                            self.queue.push_back(child);
-                        }
-                    }
-
-                    node
-                })
-            }
-        }
-
         let direct = self.deps.iter().filter_map(|input| match input {
             ActionInput::ActionKey(action_key) => Some(action_key),
             ActionInput::IndirectInputs(..) => None,
         });
 
-        let indirect = Iter::new(self.deps.iter().filter_map(|input| match input {
-            ActionInput::ActionKey(..) => None,
-            ActionInput::IndirectInputs(val) => Some(val),
-        }));
-
-        let indirect = Iter::new(indirect);
+        let indirect = self.set_projection_inputs();
 
         Box::new(direct.chain(indirect.flat_map(|v| v.node.direct.iter())))
     }
@@ -258,10 +287,12 @@ impl QueryTarget for ActionQueryNode {
 
     fn inputs_for_each<E, F: FnMut(CellPath) -> Result<(), E>>(
         &self,
-        mut _func: F,
+        mut func: F,
     ) -> Result<(), E> {
-        // TODO(cjhopman): In addition to implementing this, we should be able to return an anyhow::Error here rather than panicking.
-        unimplemented!("inputs not yet implemented in aquery")
+        for path in self.input_paths() {
+            func(path.clone())?;
+        }
+        Ok(())
     }
 
     fn call_stack(&self) -> Option<String> {
@@ -287,6 +318,12 @@ pub trait AqueryDelegate: Send + Sync {
     fn cquery_delegate(&self) -> &dyn CqueryDelegate;
 
     async fn get_node(&self, key: &ActionKey) -> anyhow::Result<ActionQueryNode>;
+
+    /// The actions registered by the analysis of `target` (or none if it's incompatible).
+    async fn get_target_actions(
+        &self,
+        target: &ConfiguredTargetLabel,
+    ) -> anyhow::Result<Vec<ActionQueryNode>>;
 }
 
 pub struct AqueryEnvironment<'c> {
@@ -305,6 +342,48 @@ impl<'c> AqueryEnvironment<'c> {
     async fn get_node(&self, label: &ActionKey) -> anyhow::Result<ActionQueryNode> {
         self.delegate.get_node(label).await
     }
+
+    /// The configured targets (for the default target platform) whose inputs include `path`.
+    async fn owner_targets(&self, path: &CellPath) -> anyhow::Result<Vec<ConfiguredTargetLabel>> {
+        let cquery_delegate = self.delegate.cquery_delegate();
+        let packages = match cquery_delegate
+            .uquery_delegate()
+            .get_enclosing_packages(path)
+            .await
+        {
+            Ok(packages) => packages,
+            Err(e) if is_no_containing_package(&e) => {
+                // Like in cquery, this is not an error: the file is just not owned by a target.
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e),
+        };
+
+        let uquery_delegate = cquery_delegate.uquery_delegate();
+        let eval_results = futures::future::try_join_all(
+            packages
+                .into_iter()
+                .map(|package| uquery_delegate.eval_build_file(package)),
+        )
+        .await?;
+
+        let owners = futures::future::try_join_all(
+            eval_results
+                .iter()
+                .flat_map(|eval_result| eval_result.targets().values())
+                .filter(|node| node.inputs().any(|input| &input == path))
+                .map(|node| cquery_delegate.get_node_for_target(node.label())),
+        )
+        .await?;
+
+        Ok(owners
+            .into_iter()
+            .filter_map(|node| match node {
+                MaybeCompatible::Compatible(node) => Some(node.label().dupe()),
+                MaybeCompatible::Incompatible(_) => None,
+            })
+            .collect())
+    }
 }
 
 #[async_trait]
@@ -368,7 +447,312 @@ impl<'c> QueryEnvironment for AqueryEnvironment<'c> {
         async_depth_limited_traversal(self, root.iter_names(), delegate, depth).await
     }
 
-    async fn owner(&self, _paths: &FileSet) -> anyhow::Result<TargetSet<Self::Target>> {
-        Err(QueryError::NotAvailableInContext("owner").into())
+    /// The actions that read the given files. Only the actions of the targets that own the files
+    /// are considered (as in cquery, these are the targets that have the files as inputs).
+    async fn owner(&self, paths: &FileSet) -> anyhow::Result<TargetSet<Self::Target>> {
+        let owners = futures::future::try_join_all(paths.iter().map(|path| async move {
+            let targets = self.owner_targets(path).await?;
+            let actions = futures::future::try_join_all(
+                targets
+                    .iter()
+                    .map(|target| self.delegate.get_target_actions(target)),
+            )
+            .await?;
+            anyhow::Ok(
+                actions
+                    .into_iter()
+                    .flatten()
+                    .filter(|action| action.input_paths().any(|input| input == path))
+                    .collect::<Vec<_>>(),
+            )
+        }))
+        .await?;
+
+        let mut result = TargetSet::new();
+        for action in owners.into_iter().flatten() {
+            result.insert(action);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use buck2_common::executor_config::CommandExecutorConfig;
+    use buck2_common::pattern::resolve::ResolvedPattern;
+    use buck2_core::buck_path::resolver::BuckPathResolver;
+    use buck2_core::build_file_path::BuildFilePath;
+    use buck2_core::bzl::ImportPath;
+    use buck2_core::category::Category;
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::paths::CellRelativePath;
+    use buck2_core::cells::paths::CellRelativePathBuf;
+    use buck2_core::cells::CellResolver;
+    use buck2_core::configuration::data::ConfigurationData;
+    use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+    use buck2_core::fs::buck_out_path::BuckOutPathResolver;
+    use buck2_core::fs::paths::file_name::FileNameBuf;
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+    use buck2_core::package::PackageLabel;
+    use buck2_core::pattern::pattern_type::TargetPatternExtra;
+    use buck2_core::target::label::ConfiguredTargetLabel;
+    use buck2_core::target::label::TargetLabel;
+    use buck2_core::target::name::TargetNameRef;
+    use buck2_node::nodes::configured::ConfiguredTargetNode;
+    use buck2_node::nodes::eval_result::EvaluationResult;
+    use buck2_node::nodes::targets_map::TargetsMap;
+    use buck2_query::query::compatibility::MaybeCompatible;
+    use buck2_query::query::environment::QueryEnvironment;
+    use buck2_query::query::syntax::simple::eval::file_set::FileNode;
+    use buck2_query::query::syntax::simple::eval::file_set::FileSet;
+    use buck2_query::query::syntax::simple::eval::set::TargetSet;
+    use dupe::Dupe;
+    use indexmap::indexset;
+    use indexmap::IndexSet;
+
+    use crate::actions::key::ActionKey;
+    use crate::actions::testings::SimpleAction;
+    use crate::actions::RegisteredAction;
+    use crate::deferred::base_deferred_key::BaseDeferredKey;
+    use crate::deferred::types::testing::DeferredDataExt;
+    use crate::deferred::types::testing::DeferredIdExt;
+    use crate::deferred::types::DeferredData;
+    use crate::deferred::types::DeferredId;
+    use crate::deferred::types::DeferredKey;
+    use crate::query::aquery::environment::ActionQueryNode;
+    use crate::query::aquery::environment::AqueryDelegate;
+    use crate::query::aquery::environment::AqueryEnvironment;
+    use crate::query::cquery::environment::CqueryDelegate;
+    use crate::query::uquery::environment::QueryLiterals;
+    use crate::query::uquery::environment::UqueryDelegate;
+
+    #[derive(Default)]
+    struct TestDelegate {
+        /// Number of times a buildfile was evaluated.
+        evaluated: AtomicUsize,
+        enclosing_packages_error: Option<String>,
+    }
+
+    #[async_trait]
+    impl UqueryDelegate for TestDelegate {
+        async fn eval_build_file(
+            &self,
+            package: PackageLabel,
+        ) -> anyhow::Result<Arc<EvaluationResult>> {
+            self.evaluated.fetch_add(1, Ordering::SeqCst);
+            Ok(Arc::new(EvaluationResult::new(
+                Arc::new(BuildFilePath::new(
+                    package,
+                    FileNameBuf::unchecked_new("BUCK"),
+                )),
+                Vec::new(),
+                TargetsMap::new(),
+            )))
+        }
+
+        async fn eval_module_imports(&self, _path: &ImportPath) -> anyhow::Result<Vec<ImportPath>> {
+            Ok(Vec::new())
+        }
+
+        fn get_buildfile_names_by_cell(&self) -> anyhow::Result<HashMap<CellName, &[FileNameBuf]>> {
+            Ok(HashMap::new())
+        }
+
+        async fn resolve_target_patterns(
+            &self,
+            _pattern: &[&str],
+        ) -> anyhow::Result<ResolvedPattern<TargetPatternExtra>> {
+            Ok(ResolvedPattern::new())
+        }
+
+        async fn eval_file_literal(&self, literal: &str) -> anyhow::Result<FileSet> {
+            Ok(FileSet::new(indexset![FileNode(cell_path(literal))]))
+        }
+
+        async fn get_enclosing_packages(
+            &self,
+            _path: &CellPath,
+        ) -> anyhow::Result<Vec<PackageLabel>> {
+            match &self.enclosing_packages_error {
+                Some(e) => Err(anyhow::anyhow!("{}", e)),
+                None => Ok(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CqueryDelegate for TestDelegate {
+        fn uquery_delegate(&self) -> &dyn UqueryDelegate {
+            self
+        }
+
+        async fn get_node_for_target(
+            &self,
+            _target: &TargetLabel,
+        ) -> anyhow::Result<MaybeCompatible<ConfiguredTargetNode>> {
+            unreachable!("There are no targets in these tests")
+        }
+
+        async fn get_node_for_configured_target(
+            &self,
+            _target: &ConfiguredTargetLabel,
+        ) -> anyhow::Result<ConfiguredTargetNode> {
+            unreachable!("There are no targets in these tests")
+        }
+
+        async fn get_configured_target(
+            &self,
+            _target: &TargetLabel,
+        ) -> anyhow::Result<ConfiguredTargetLabel> {
+            unreachable!("There are no targets in these tests")
+        }
+
+        async fn get_node_for_default_configured_target(
+            &self,
+            _target: &TargetLabel,
+        ) -> anyhow::Result<MaybeCompatible<ConfiguredTargetNode>> {
+            unreachable!("There are no targets in these tests")
+        }
+    }
+
+    #[async_trait]
+    impl AqueryDelegate for TestDelegate {
+        fn cquery_delegate(&self) -> &dyn CqueryDelegate {
+            self
+        }
+
+        async fn get_node(&self, key: &ActionKey) -> anyhow::Result<ActionQueryNode> {
+            Err(anyhow::anyhow!("Unknown action `{}`", key))
+        }
+
+        async fn get_target_actions(
+            &self,
+            _target: &ConfiguredTargetLabel,
+        ) -> anyhow::Result<Vec<ActionQueryNode>> {
+            unreachable!("There are no targets in these tests")
+        }
+    }
+
+    #[async_trait]
+    impl QueryLiterals<ActionQueryNode> for TestDelegate {
+        async fn eval_literals(
+            &self,
+            _literals: &[&str],
+        ) -> anyhow::Result<TargetSet<ActionQueryNode>> {
+            Ok(TargetSet::new())
+        }
+    }
+
+    fn environment(delegate: &Arc<TestDelegate>) -> AqueryEnvironment<'static> {
+        AqueryEnvironment::new(delegate.dupe(), delegate.dupe())
+    }
+
+    fn action_node(fs: &Arc<ArtifactFs>, package: &str, id: u32) -> ActionQueryNode {
+        let pkg = PackageLabel::new(
+            CellName::testing_new("cell"),
+            CellRelativePath::unchecked_new(package),
+        );
+        let label = TargetLabel::new(pkg.dupe(), TargetNameRef::unchecked_new("foo"))
+            .configure(ConfigurationData::testing_new());
+        let action = RegisteredAction::new(
+            ActionKey::new(DeferredData::testing_new(DeferredKey::Base(
+                BaseDeferredKey::TargetLabel(label),
+                DeferredId::testing_new(id),
+            ))),
+            Box::new(SimpleAction::new(
+                IndexSet::new(),
+                IndexSet::new(),
+                Vec::new(),
+                Category::try_from("fake_action").unwrap(),
+                None,
+            )),
+            CommandExecutorConfig::testing_local(),
+        );
+        ActionQueryNode::new(
+            Arc::new(action),
+            Vec::new(),
+            Vec::new(),
+            Some(Arc::new(BuildFilePath::new(
+                pkg,
+                FileNameBuf::unchecked_new("BUCK"),
+            ))),
+            fs.dupe(),
+        )
+    }
+
+    fn cell_path(path: &str) -> CellPath {
+        CellPath::new(
+            CellName::testing_new("cell"),
+            CellRelativePathBuf::unchecked_new(path.to_owned()),
+        )
+    }
+
+    fn artifact_fs(temp: &ProjectRootTemp) -> Arc<ArtifactFs> {
+        let cells = CellResolver::testing_with_name_and_path(
+            CellName::testing_new("cell"),
+            CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new("cell_path".into())),
+        );
+        Arc::new(ArtifactFs::new(
+            BuckPathResolver::new(cells),
+            BuckOutPathResolver::new(ProjectRelativePathBuf::unchecked_new(
+                "cell/buck-out/v2".into(),
+            )),
+            temp.path().dupe(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_buildfile() -> anyhow::Result<()> {
+        let temp = ProjectRootTemp::new()?;
+        let fs = artifact_fs(&temp);
+        let delegate = Arc::new(TestDelegate::default());
+        let env = environment(&delegate);
+
+        let mut targets = TargetSet::new();
+        targets.insert(action_node(&fs, "a", 0));
+        targets.insert(action_node(&fs, "a", 1));
+        targets.insert(action_node(&fs, "b", 2));
+
+        let buildfiles = env.buildfile(&targets).await?;
+        assert_eq!(
+            buildfiles.iter().cloned().collect::<Vec<_>>(),
+            vec![cell_path("a/BUCK"), cell_path("b/BUCK")]
+        );
+        // The nodes know their buildfiles, so nothing needs evaluating.
+        assert_eq!(delegate.evaluated.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_owner_propagates_errors() -> anyhow::Result<()> {
+        let delegate = Arc::new(TestDelegate {
+            enclosing_packages_error: Some("read_dir failed".to_owned()),
+            ..Default::default()
+        });
+        let env = environment(&delegate);
+
+        let files = FileSet::new(indexset![FileNode(cell_path("a/src.c"))]);
+        let err = env.owner(&files).await.unwrap_err();
+        assert_eq!(err.to_string(), "read_dir failed");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_owner_without_owning_package() -> anyhow::Result<()> {
+        let delegate = Arc::new(TestDelegate::default());
+        let env = environment(&delegate);
+
+        let files = FileSet::new(indexset![FileNode(cell_path("a/src.c"))]);
+        assert!(env.owner(&files).await?.is_empty());
+        Ok(())
     }
 }
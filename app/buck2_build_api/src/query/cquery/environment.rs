@@ -26,6 +26,7 @@ use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use buck2_query::query::syntax::simple::functions::HasModuleDescription;
 use buck2_query::query::traversal::async_depth_first_postorder_traversal;
 use buck2_query::query::traversal::async_depth_limited_traversal;
+use buck2_query::query::traversal::async_unordered_traversal;
 use buck2_query::query::traversal::AsyncNodeLookup;
 use buck2_query::query::traversal::AsyncTraversalDelegate;
 use dupe::Dupe;
@@ -237,6 +238,14 @@ impl<'c> QueryEnvironment for CqueryEnvironment<'c> {
         async_depth_limited_traversal(self, root.iter_names(), delegate, depth).await
     }
 
+    async fn unordered_traversal(
+        &self,
+        root: &TargetSet<Self::Target>,
+        delegate: &mut dyn AsyncTraversalDelegate<Self::Target>,
+    ) -> anyhow::Result<()> {
+        async_unordered_traversal(self, root.iter_names(), delegate).await
+    }
+
     async fn allbuildfiles(&self, universe: &TargetSet<Self::Target>) -> anyhow::Result<FileSet> {
         return allbuildfiles(universe, self.delegate.uquery_delegate()).await;
     }
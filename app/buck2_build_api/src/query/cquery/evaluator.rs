@@ -17,6 +17,7 @@ use buck2_core::target::label::TargetLabel;
 use buck2_events::dispatch::console_message;
 use buck2_node::configured_universe::CqueryUniverse;
 use buck2_node::nodes::configured::ConfiguredTargetNode;
+use buck2_query::query::environment::TargetSink;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use dice::DiceComputations;
@@ -26,6 +27,7 @@ use futures::StreamExt;
 use gazebo::prelude::*;
 
 use crate::query::analysis::evaluator::eval_query;
+use crate::query::analysis::evaluator::eval_query_streaming;
 use crate::query::cquery::environment::CqueryEnvironment;
 use crate::query::cquery::environment::CqueryOwnerBehavior;
 use crate::query::dice::get_dice_query_delegate;
@@ -40,7 +42,7 @@ pub struct CqueryEvaluator<'c> {
     owner_behavior: CqueryOwnerBehavior,
 }
 
-impl CqueryEvaluator<'_> {
+impl<'c> CqueryEvaluator<'c> {
     pub async fn eval_query<A: AsRef<str>, U: AsRef<str>>(
         &self,
         query: &str,
@@ -48,34 +50,56 @@ impl CqueryEvaluator<'_> {
         target_universe: Option<&[U]>,
     ) -> anyhow::Result<QueryEvaluationResult<ConfiguredTargetNode>> {
         eval_query(&self.functions, query, query_args, async move |literals| {
-            let (universe, resolved_literals) = match target_universe {
-                None => {
-                    if literals.is_empty() {
-                        console_message(
-                            "Query has no target literals and `--target-universe` is not specified.\n\
-                            Such query is correct, but the result is always empty.\n\
-                            Consider specifying `--target-universe` for this query\n\
-                            or using `uquery` instead of `cquery`".to_owned());
-                    }
-                    // In the absence of a user-provided target universe, we use the target
-                    // literals in the cquery as the universe.
-                    resolve_literals_in_universe(&self.dice_query_delegate, &literals, &literals)
-                        .await?
-                }
-                Some(universe) => {
-                    resolve_literals_in_universe(&self.dice_query_delegate, &literals, universe)
-                        .await?
-                }
-            };
-            Ok(CqueryEnvironment::new(
-                self.dice_query_delegate.dupe(),
-                Arc::new(resolved_literals),
-                Some(universe),
-                self.owner_behavior,
-            ))
+            self.environment(literals, target_universe).await
         })
         .await
     }
+
+    /// Evaluates a query without `%s` placeholders, adding targets to `sink` as they are found.
+    pub async fn eval_query_streaming<U: AsRef<str>>(
+        &self,
+        query: &str,
+        target_universe: Option<&[U]>,
+        sink: &mut dyn TargetSink<ConfiguredTargetNode>,
+    ) -> anyhow::Result<()> {
+        eval_query_streaming(&self.functions, query, sink, async move |literals| {
+            self.environment(literals, target_universe).await
+        })
+        .await
+    }
+
+    async fn environment<U: AsRef<str>>(
+        &self,
+        literals: Vec<String>,
+        target_universe: Option<&[U]>,
+    ) -> anyhow::Result<CqueryEnvironment<'c>> {
+        let (universe, resolved_literals) = match target_universe {
+            None => {
+                if literals.is_empty() {
+                    console_message(
+                        "Query has no target literals and `--target-universe` is not specified.\n\
+                        Such query is correct, but the result is always empty.\n\
+                        Consider specifying `--target-universe` for this query\n\
+                        or using `uquery` instead of `cquery`"
+                            .to_owned(),
+                    );
+                }
+                // In the absence of a user-provided target universe, we use the target
+                // literals in the cquery as the universe.
+                resolve_literals_in_universe(&self.dice_query_delegate, &literals, &literals)
+                    .await?
+            }
+            Some(universe) => {
+                resolve_literals_in_universe(&self.dice_query_delegate, &literals, universe).await?
+            }
+        };
+        Ok(CqueryEnvironment::new(
+            self.dice_query_delegate.dupe(),
+            Arc::new(resolved_literals),
+            Some(universe),
+            self.owner_behavior,
+        ))
+    }
 }
 
 async fn preresolve_literals_and_build_universe(
@@ -7,14 +7,19 @@
  * of this source tree.
  */
 
+use std::any;
 use std::hash::Hash;
 use std::sync::Arc;
 
 use anyhow::Context;
 use async_trait::async_trait;
+use buck2_common::dice::cells::HasCellResolver;
 use buck2_common::result::SharedResult;
+use buck2_core::cells::cell_path::CellPath;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
 use buck2_core::pattern::ParsedPattern;
+use buck2_core::target::label::ConfiguredTargetLabel;
+use buck2_interpreter_for_build::interpreter::calculation::InterpreterCalculation;
 use buck2_query::query::compatibility::MaybeCompatible;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use dashmap::DashMap;
@@ -28,16 +33,19 @@ use futures::stream::FuturesOrdered;
 use futures::Future;
 use futures::StreamExt;
 use gazebo::prelude::*;
+use indexmap::IndexSet;
 use itertools::Either;
 use itertools::Itertools;
 use thiserror::Error;
 
+use crate::actions::artifact::provide_outputs::ProvideOutputs;
 use crate::actions::calculation::ActionCalculation;
 use crate::actions::key::ActionKey;
 use crate::analysis::calculation::RuleAnalysisCalculation;
 use crate::artifact_groups::ArtifactGroup;
 use crate::artifact_groups::TransitiveSetProjectionKey;
 use crate::calculation::Calculation;
+use crate::deferred::base_deferred_key::BaseDeferredKey;
 use crate::deferred::calculation::DeferredCalculation;
 use crate::query::aquery::environment::ActionInput;
 use crate::query::aquery::environment::ActionQueryNode;
@@ -111,7 +119,8 @@ pub struct DiceAqueryDelegate<'c> {
 }
 
 /// Converts artifact inputs into aquery's ActionInput. This is mostly a matter of resolving the indirect
-/// `TransitiveSetProjectionKey` to our direct shadow tset graph node `SetProjectionInputs`.
+/// `TransitiveSetProjectionKey` to our direct shadow tset graph node `SetProjectionInputs`. Also returns
+/// the paths of the artifacts that aren't in tsets.
 // TODO(cjhopman): I think we should change ArtifactGroup to hold a `(TransitiveSet, ProjectionIndex)` rather
 // than `(TransitiveSetKey, ProjectionIndex)`. We already have that information when constructing it and the
 // artifact side of it holds a starlark ref. That would allow someone with an ArtifactGroup to synchronously
@@ -119,35 +128,48 @@ pub struct DiceAqueryDelegate<'c> {
 async fn convert_inputs<'a, Iter: IntoIterator<Item = &'a ArtifactGroup>>(
     ctx: &DiceComputations,
     node_cache: DiceAqueryNodesCache,
+    fs: &Arc<ArtifactFs>,
     inputs: Iter,
-) -> anyhow::Result<Vec<ActionInput>> {
+) -> anyhow::Result<(Vec<ActionInput>, Vec<CellPath>)> {
     let (artifacts, projections): (Vec<_>, Vec<_>) = Itertools::partition_map(
-        inputs.into_iter().filter_map(|input| match input {
-            ArtifactGroup::Artifact(a) => a.action_key().map(Either::Left),
-            ArtifactGroup::TransitiveSetProjection(key) => Some(Either::Right(key)),
+        inputs.into_iter().map(|input| match input {
+            ArtifactGroup::Artifact(a) => Either::Left(a),
+            ArtifactGroup::TransitiveSetProjection(key) => Either::Right(key),
         }),
         |v| v,
     );
-    let mut deps = artifacts.into_map(|a| ActionInput::ActionKey(a.dupe()));
+
+    let cell_resolver = ctx.get_cell_resolver().await?;
+    let paths = artifacts.try_map(|a| {
+        let path = a.get_path().resolve(fs)?;
+        cell_resolver.get_cell_path(&path)
+    })?;
+
+    let mut deps: Vec<_> = artifacts
+        .iter()
+        .filter_map(|a| a.action_key())
+        .map(|a| ActionInput::ActionKey(a.dupe()))
+        .collect();
     let mut projection_deps: FuturesOrdered<_> = projections
         .into_iter()
         .map(|key| {
             let key = key.dupe();
             let node_cache = node_cache.dupe();
-            get_tset_node(node_cache, ctx, key)
+            get_tset_node(node_cache, ctx, key, fs.dupe())
         })
         .collect();
 
     while let Some(node) = tokio::task::unconstrained(projection_deps.next()).await {
         deps.push(ActionInput::IndirectInputs(node?));
     }
-    Ok(deps)
+    Ok((deps, paths))
 }
 
 fn compute_tset_node(
     node_cache: DiceAqueryNodesCache,
     ctx: DiceTransaction,
     key: TransitiveSetProjectionKey,
+    fs: Arc<ArtifactFs>,
 ) -> BoxFuture<'static, SharedResult<SetProjectionInputs>> {
     async move {
         let set = ctx
@@ -159,14 +181,19 @@ fn compute_tset_node(
             .as_transitive_set()?
             .get_projection_sub_inputs(key.projection)?;
 
-        let inputs = convert_inputs(&ctx, node_cache, sub_inputs.iter()).await?;
+        let (inputs, paths) = convert_inputs(&ctx, node_cache, &fs, sub_inputs.iter()).await?;
 
         let (direct, children) = inputs.into_iter().partition_map(|v| match v {
             ActionInput::ActionKey(action_key) => Either::Left(action_key),
             ActionInput::IndirectInputs(projection) => Either::Right(projection),
         });
 
-        Ok(SetProjectionInputs::new(key.dupe(), direct, children))
+        Ok(SetProjectionInputs::new(
+            key.dupe(),
+            direct,
+            paths,
+            children,
+        ))
     }
     .boxed()
 }
@@ -175,13 +202,14 @@ async fn get_tset_node(
     node_cache: DiceAqueryNodesCache,
     ctx: &DiceComputations,
     key: TransitiveSetProjectionKey,
+    fs: Arc<ArtifactFs>,
 ) -> anyhow::Result<SetProjectionInputs> {
     let copied_node_cache = node_cache.dupe();
     Ok(node_cache
         .tset_nodes
         .get_or_compute(key, move |key| {
             ctx.temporary_spawn(move |ctx, _cancellation| {
-                compute_tset_node(copied_node_cache, ctx, key).boxed()
+                compute_tset_node(copied_node_cache, ctx, key, fs).boxed()
             })
         })
         .await?)
@@ -195,8 +223,25 @@ fn compute_action_node(
 ) -> BoxFuture<'static, SharedResult<ActionQueryNode>> {
     async move {
         let action = ActionCalculation::get_action(&ctx, &key).await?;
-        let deps = convert_inputs(&ctx, node_cache, action.inputs()?.iter()).await?;
-        Ok(ActionQueryNode::new(action, deps, fs))
+        let (deps, input_paths) =
+            convert_inputs(&ctx, node_cache, &fs, action.inputs()?.iter()).await?;
+        // The owner was analyzed to get this action, so its package was already evaluated.
+        let buildfile_path = match key.owner() {
+            BaseDeferredKey::TargetLabel(label) => Some(
+                ctx.get_interpreter_results(label.pkg())
+                    .await?
+                    .buildfile_path()
+                    .dupe(),
+            ),
+            BaseDeferredKey::AnonTarget(_) | BaseDeferredKey::BxlLabel(_) => None,
+        };
+        Ok(ActionQueryNode::new(
+            action,
+            deps,
+            input_paths,
+            buildfile_path,
+            fs,
+        ))
     }
     .boxed()
 }
@@ -250,6 +295,28 @@ impl<'c> AqueryDelegate for DiceAqueryDelegate<'c> {
     async fn get_node(&self, key: &ActionKey) -> anyhow::Result<ActionQueryNode> {
         self.get_action_node(key).await
     }
+
+    async fn get_target_actions(
+        &self,
+        target: &ConfiguredTargetLabel,
+    ) -> anyhow::Result<Vec<ActionQueryNode>> {
+        let analysis = match self.base_delegate.ctx().get_analysis_result(target).await? {
+            MaybeCompatible::Incompatible(_) => return Ok(Vec::new()),
+            MaybeCompatible::Compatible(analysis) => analysis,
+        };
+
+        // Actions are deferreds that provide their outputs.
+        let mut keys = IndexSet::new();
+        for entry in analysis.iter_deferreds() {
+            if let Some(outputs) = any::request_value::<ProvideOutputs>(entry.as_complex()) {
+                for output in outputs.0? {
+                    keys.insert(output.action_key().dupe());
+                }
+            }
+        }
+
+        futures::future::try_join_all(keys.iter().map(|key| self.get_action_node(key))).await
+    }
 }
 
 #[async_trait]
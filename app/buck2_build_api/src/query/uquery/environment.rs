@@ -40,6 +40,7 @@ use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use buck2_query::query::syntax::simple::functions::HasModuleDescription;
 use buck2_query::query::traversal::async_depth_first_postorder_traversal;
 use buck2_query::query::traversal::async_depth_limited_traversal;
+use buck2_query::query::traversal::async_unordered_traversal;
 use buck2_query::query::traversal::AsyncNodeLookup;
 use buck2_query::query::traversal::AsyncTraversalDelegate;
 use buck2_query::query::traversal::ChildVisitor;
@@ -230,6 +231,14 @@ impl<'c> QueryEnvironment for UqueryEnvironment<'c> {
         async_depth_limited_traversal(self, root.iter_names(), delegate, depth).await
     }
 
+    async fn unordered_traversal(
+        &self,
+        root: &TargetSet<Self::Target>,
+        delegate: &mut dyn AsyncTraversalDelegate<Self::Target>,
+    ) -> anyhow::Result<()> {
+        async_unordered_traversal(self, root.iter_names(), delegate).await
+    }
+
     async fn allbuildfiles(&self, universe: &TargetSet<Self::Target>) -> anyhow::Result<FileSet> {
         return allbuildfiles(universe, &*self.delegate).await;
     }
@@ -304,11 +313,13 @@ pub(crate) async fn allbuildfiles<'c, T: QueryTarget>(
     let mut top_level_imports = Vec::<ImportPath>::new();
 
     for target in universe.iter() {
-        paths.insert(FileNode(target.dupe().buildfile_path().path()));
+        let buildfile_path = match target.buildfile_path() {
+            Some(buildfile_path) => buildfile_path,
+            None => continue,
+        };
+        paths.insert(FileNode(buildfile_path.path()));
 
-        let eval_result = delegate
-            .eval_build_file(target.buildfile_path().package())
-            .await?; // TODO: no longer use eval_build_file, just parse imports directly (will solve async issue too)
+        let eval_result = delegate.eval_build_file(buildfile_path.package()).await?; // TODO: no longer use eval_build_file, just parse imports directly (will solve async issue too)
 
         top_level_imports.extend(eval_result.imports().iter().cloned());
     }
@@ -13,12 +13,14 @@ use std::sync::Arc;
 use buck2_core::fs::project_rel_path::ProjectRelativePath;
 use buck2_core::target::label::TargetLabel;
 use buck2_node::nodes::unconfigured::TargetNode;
+use buck2_query::query::environment::TargetSink;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationResult;
 use buck2_query::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use dice::DiceComputations;
 use dupe::Dupe;
 
 use crate::query::analysis::evaluator::eval_query;
+use crate::query::analysis::evaluator::eval_query_streaming;
 use crate::query::dice::get_dice_query_delegate;
 use crate::query::dice::DiceQueryDelegate;
 use crate::query::uquery::environment::PreresolvedQueryLiterals;
@@ -29,22 +31,38 @@ pub struct UqueryEvaluator<'c> {
     functions: DefaultQueryFunctionsModule<UqueryEnvironment<'c>>,
 }
 
-impl UqueryEvaluator<'_> {
+impl<'c> UqueryEvaluator<'c> {
     pub async fn eval_query(
         &self,
         query: &str,
         query_args: &[String],
     ) -> anyhow::Result<QueryEvaluationResult<TargetNode>> {
         eval_query(&self.functions, query, query_args, async move |literals| {
-            let resolved_literals =
-                PreresolvedQueryLiterals::pre_resolve(&*self.dice_query_delegate, &literals).await;
-            Ok(UqueryEnvironment::new(
-                self.dice_query_delegate.dupe(),
-                Arc::new(resolved_literals),
-            ))
+            self.environment(literals).await
         })
         .await
     }
+
+    /// Evaluates a query without `%s` placeholders, adding targets to `sink` as they are found.
+    pub async fn eval_query_streaming(
+        &self,
+        query: &str,
+        sink: &mut dyn TargetSink<TargetNode>,
+    ) -> anyhow::Result<()> {
+        eval_query_streaming(&self.functions, query, sink, async move |literals| {
+            self.environment(literals).await
+        })
+        .await
+    }
+
+    async fn environment(&self, literals: Vec<String>) -> anyhow::Result<UqueryEnvironment<'c>> {
+        let resolved_literals =
+            PreresolvedQueryLiterals::pre_resolve(&*self.dice_query_delegate, &literals).await;
+        Ok(UqueryEnvironment::new(
+            self.dice_query_delegate.dupe(),
+            Arc::new(resolved_literals),
+        ))
+    }
 }
 
 /// Evaluates some query expression. TargetNodes are resolved via the interpreter from
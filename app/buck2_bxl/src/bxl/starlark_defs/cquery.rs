@@ -611,6 +611,10 @@ fn register_cquery(builder: &mut MethodsBuilder) {
     /// Evaluates some general query string. `query_args` can be a target_set of unconfigured nodes, or
     /// a list of strings.
     ///
+    /// Like with `--query-file` on the command line, the query can start with query function
+    /// definitions (`def name(param, ...) = expr;`), so definitions kept in a `.bzl` string can be
+    /// shared between queries.
+    ///
     /// Sample usage:
     /// ```text
     /// def _impl_eval(ctx):
@@ -709,7 +713,7 @@ fn register_cquery(builder: &mut MethodsBuilder) {
                     this.ctx,
                 )?;
 
-                Ok(this.functions.buildfile(targets))
+                this.functions.buildfile(&this.env, targets).await
             })
             .map(StarlarkFileSet::from)
     }
@@ -336,5 +336,6 @@ define_request!(AllocativeRequest, has(context));
 define_request!(CleanStaleRequest, has(context));
 define_request!(FileStatusRequest, has(context));
 define_request!(TraceIoRequest, has(context));
+define_request!(WaitForChangesRequest, has(context));
 
 define_request!(InstallRequest, has(context, build_options));
@@ -59,7 +59,7 @@ impl StreamingCommand for AqueryCommand {
         matches: &clap::ArgMatches,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let (query, query_args) = self.query_common.get_query();
+        let (query, query_args) = self.query_common.get_query()?;
         let unstable_output_format = self.query_common.output_format() as i32;
         let output_attributes = self.query_common.attributes.get()?;
         let context = ctx.client_context(
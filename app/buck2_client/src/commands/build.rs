@@ -28,6 +28,7 @@ use buck2_client_ctx::common::CommonBuildOptions;
 use buck2_client_ctx::common::CommonCommandOptions;
 use buck2_client_ctx::common::CommonConsoleOptions;
 use buck2_client_ctx::common::CommonDaemonCommandOptions;
+use buck2_client_ctx::common::CommonWatchOptions;
 use buck2_client_ctx::daemon::client::BuckdClientConnector;
 use buck2_client_ctx::daemon::client::NoPartialResultHandler;
 use buck2_client_ctx::exit_result::ExitResult;
@@ -55,6 +56,9 @@ pub struct BuildCommand {
     #[clap(flatten)]
     build_opts: CommonBuildOptions,
 
+    #[clap(flatten)]
+    watch_opts: CommonWatchOptions,
+
     #[clap(
         long,
         use_delimiter = true,
@@ -73,24 +77,28 @@ pub struct BuildCommand {
 
     #[clap(
         long = "show-output",
+        conflicts_with = "watch",
         help = "Print the path to the output for each of the built rules relative to the cell"
     )]
     show_output: bool,
 
     #[clap(
         long = "show-full-output",
+        conflicts_with = "watch",
         help = "Print the absolute path to the output for each of the built rules"
     )]
     show_full_output: bool,
 
     #[clap(
         long = "show-json-output",
+        conflicts_with = "watch",
         help = "Print the output paths relative to the cell, in JSON format"
     )]
     show_json_output: bool,
 
     #[clap(
         long = "show-full-json-output",
+        conflicts_with = "watch",
         help = "Print the output absolute paths, in JSON format"
     )]
     show_full_json_output: bool,
@@ -151,6 +159,7 @@ pub struct BuildCommand {
 
     #[clap(
         long = "out",
+        conflicts_with = "watch",
         help = "Copy the output of the built target to this path (`-` to stdout)"
     )]
     output_path: Option<OutputDestinationArg>,
@@ -255,6 +264,7 @@ impl StreamingCommand for BuildCommand {
                     build_opts: Some(self.build_opts.to_proto()),
                     final_artifact_materializations: self.materializations.to_proto() as i32,
                     target_universe: self.target_universe,
+                    watch: self.watch_opts.to_proto(),
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -60,6 +60,15 @@ the specified duration, without killing the daemon",
 
     #[clap(long = "tracked-only", requires = "stale")]
     tracked_only: bool,
+
+    #[clap(
+        long = "max-size",
+        requires = "stale",
+        help = "With --stale, also delete the least recently used artifacts from buck-out until
+the artifacts not in use by the daemon take less than the specified size (e.g. 50GB)",
+        value_name = "SIZE"
+    )]
+    max_size: Option<bytesize::ByteSize>,
 }
 
 impl CleanCommand {
@@ -68,6 +77,7 @@ impl CleanCommand {
             let cmd = CleanStaleCommand {
                 common_opts: self.common_opts,
                 keep_since_arg,
+                max_size: self.max_size.map(|s| s.as_u64()),
                 dry_run: self.dry_run,
                 tracked_only: self.tracked_only,
             };
@@ -34,6 +34,7 @@ use humantime;
 pub struct CleanStaleCommand {
     pub(crate) common_opts: CommonCommandOptions,
     pub keep_since_arg: KeepSinceArg,
+    pub max_size: Option<u64>,
     pub dry_run: bool,
     pub tracked_only: bool,
 }
@@ -122,6 +123,12 @@ impl StreamingCommand for CleanStaleCommand {
                 .single()
                 .context("Invalid timestamp")?,
         };
+        if let Some(max_size) = self.max_size {
+            buck2_client_ctx::eprintln!(
+                "Cleaning least recently used artifacts until those not in use are under {}",
+                bytesize::to_string(max_size, true),
+            )?;
+        }
 
         let context = ctx.client_context(
             &self.common_opts.config_opts,
@@ -136,6 +143,7 @@ impl StreamingCommand for CleanStaleCommand {
                     keep_since_time: keep_since_time.timestamp(),
                     dry_run: self.dry_run,
                     tracked_only: self.tracked_only,
+                    max_size: self.max_size,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -84,6 +84,13 @@ pub struct CqueryCommand {
     /// See this post https://fburl.com/1mf2d2xj for details.
     #[clap(long)]
     correct_owner: bool,
+
+    /// Print targets as they are found instead of once the query has finished. A top-level
+    /// `deps()` or `rdeps()` is evaluated incrementally, so results appear while the graph is
+    /// still loading, in no particular order. With `--json`, each target is printed as a separate
+    /// JSON value on its own line.
+    #[clap(long, conflicts_with = "show-providers")]
+    streaming: bool,
 }
 
 #[async_trait]
@@ -96,7 +103,7 @@ impl StreamingCommand for CqueryCommand {
         matches: &clap::ArgMatches,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let (query, query_args) = self.query_common.get_query();
+        let (query, query_args) = self.query_common.get_query()?;
         let unstable_output_format = self.query_common.output_format() as i32;
         let output_attributes = self.query_common.attributes.get()?;
         let context = ctx.client_context(
@@ -129,6 +136,7 @@ impl StreamingCommand for CqueryCommand {
                     unstable_output_format,
                     target_call_stacks: self.query_common.target_call_stacks,
                     correct_owner,
+                    streaming: self.streaming,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -0,0 +1,393 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::stream_value::StreamValue;
+use buck2_client_ctx::subscribers::event_log::read::EventLogPathBuf;
+use buck2_client_ctx::tokio_runtime_setup::client_tokio_runtime;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use indexmap::IndexMap;
+use tokio_stream::StreamExt;
+
+use crate::commands::log::options::DiffEventLogOptions;
+use crate::commands::log::LogCommandOutputFormat;
+
+/// Compares the actions executed by two commands, to explain why actions reran.
+///
+/// Actions are matched by their owner and name. The output lists the actions that ran in the
+/// second command but not in the first (`new`) and those whose action digest changed
+/// (`changed`), one record per difference: the digest itself, the command-line arguments and
+/// environment variables that differ, the input root digest, and the input files and symlinks
+/// that were added, removed or changed. Arguments and environment are only logged for local
+/// commands.
+///
+/// By default the most recent command is compared against the one before it.
+///
+/// The tabulated output has the following tab-delimited columns: change, action, kind, name,
+/// value in the first command, value in the second command.
+#[derive(Debug, clap::Parser)]
+pub struct DiffCommand {
+    #[clap(flatten)]
+    event_logs: DiffEventLogOptions,
+
+    #[clap(
+        long = "format",
+        help = "Which output format to use for this command",
+        default_value = "tabulated",
+        ignore_case = true,
+        arg_enum
+    )]
+    output: LogCommandOutputFormat,
+}
+
+impl DiffCommand {
+    pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let Self { event_logs, output } = self;
+
+        let rt = client_tokio_runtime()?;
+
+        rt.block_on(async move {
+            let (first, second) = event_logs.get(&ctx).await?;
+            let first = read_actions(&first).await?;
+            let second = read_actions(&second).await?;
+
+            for record in diff_actions(&first, &second) {
+                write_record(&output, &record)?;
+            }
+
+            anyhow::Ok(())
+        })?;
+
+        ExitResult::success()
+    }
+}
+
+/// What the log tells us about one executed action.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct ActionRecord {
+    digest: Option<String>,
+    /// Only available for local commands.
+    command: Option<LocalCommandRecord>,
+    /// Not available in logs written before inputs were recorded.
+    input_root_digest: Option<String>,
+    /// Input paths to their file digest or symlink target.
+    inputs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct LocalCommandRecord {
+    argv: Vec<String>,
+    env: BTreeMap<String, String>,
+}
+
+impl ActionRecord {
+    fn from_action_execution_end(action: &buck2_data::ActionExecutionEnd) -> Self {
+        use buck2_data::command_execution_details::Command;
+
+        // The command that should be shown to the user is always last.
+        let details = match action.commands.last().and_then(|c| c.details.as_ref()) {
+            Some(details) => details,
+            None => return Self::default(),
+        };
+
+        let (digest, command) = match &details.command {
+            Some(Command::LocalCommand(local)) => (
+                Some(local.action_digest.clone()),
+                Some(LocalCommandRecord {
+                    argv: local.argv.clone(),
+                    env: local
+                        .env
+                        .iter()
+                        .map(|e| (e.key.clone(), e.value.clone()))
+                        .collect(),
+                }),
+            ),
+            Some(Command::RemoteCommand(remote)) => (Some(remote.action_digest.clone()), None),
+            Some(Command::OmittedLocalCommand(omitted)) => {
+                (Some(omitted.action_digest.clone()), None)
+            }
+            Some(Command::LocalActionCacheHit(hit)) => (Some(hit.action_digest.clone()), None),
+            None => (None, None),
+        };
+
+        Self {
+            digest,
+            command,
+            input_root_digest: Some(details.input_root_digest.clone()).filter(|d| !d.is_empty()),
+            inputs: details
+                .inputs
+                .iter()
+                .map(|i| (i.path.clone(), describe_input(i)))
+                .collect(),
+        }
+    }
+}
+
+fn describe_input(input: &buck2_data::CommandInput) -> String {
+    use buck2_data::command_input::Entry;
+
+    match &input.entry {
+        Some(Entry::FileDigest(digest)) if input.is_executable => {
+            format!("{} (executable)", digest)
+        }
+        Some(Entry::FileDigest(digest)) => digest.clone(),
+        Some(Entry::SymlinkTarget(target)) => format!("-> {}", target),
+        None => String::new(),
+    }
+}
+
+/// Reads the actions executed by a command, keyed by their identity, in the order they finished.
+async fn read_actions(
+    log_path: &EventLogPathBuf,
+) -> anyhow::Result<IndexMap<String, ActionRecord>> {
+    let (invocation, mut events) = log_path.unpack_stream().await?;
+    buck2_client_ctx::eprintln!(
+        "Reading actions from: {}",
+        invocation.display_command_line()
+    )?;
+
+    let mut actions = IndexMap::new();
+    while let Some(event) = events.try_next().await? {
+        match event {
+            StreamValue::Event(event) => match event.data {
+                Some(buck2_data::buck_event::Data::SpanEnd(span)) => match &span.data {
+                    Some(buck2_data::span_end_event::Data::ActionExecution(action)) => {
+                        let identity = display::display_action_identity(
+                            action.key.as_ref(),
+                            action.name.as_ref(),
+                            TargetDisplayOptions::for_log(),
+                        )?;
+                        actions.insert(identity, ActionRecord::from_action_execution_end(action));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    Ok(actions)
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+struct DiffRecord<'a> {
+    /// `new` or `changed`.
+    change: &'static str,
+    action: &'a str,
+    /// `digest`, `argument`, `env`, `input_root` or `input`. Empty for new actions.
+    kind: &'static str,
+    /// The argument index, environment variable name or input path.
+    name: String,
+    before: Option<&'a str>,
+    after: Option<&'a str>,
+}
+
+fn diff_actions<'a>(
+    first: &'a IndexMap<String, ActionRecord>,
+    second: &'a IndexMap<String, ActionRecord>,
+) -> Vec<DiffRecord<'a>> {
+    let mut records = Vec::new();
+    for (action, after) in second {
+        let before = match first.get(action) {
+            Some(before) => before,
+            None => {
+                records.push(DiffRecord {
+                    change: "new",
+                    action,
+                    kind: "",
+                    name: String::new(),
+                    before: None,
+                    after: after.digest.as_deref(),
+                });
+                continue;
+            }
+        };
+
+        if before.digest == after.digest {
+            continue;
+        }
+
+        let changed = |kind, name, before, after| DiffRecord {
+            change: "changed",
+            action,
+            kind,
+            name,
+            before,
+            after,
+        };
+
+        records.push(changed(
+            "digest",
+            String::new(),
+            before.digest.as_deref(),
+            after.digest.as_deref(),
+        ));
+
+        if let (Some(before), Some(after)) = (&before.command, &after.command) {
+            for i in 0..std::cmp::max(before.argv.len(), after.argv.len()) {
+                let (b, a) = (before.argv.get(i), after.argv.get(i));
+                if b != a {
+                    records.push(changed(
+                        "argument",
+                        i.to_string(),
+                        b.map(|s| s.as_str()),
+                        a.map(|s| s.as_str()),
+                    ));
+                }
+            }
+            for (key, b, a) in changed_entries(&before.env, &after.env) {
+                records.push(changed("env", key.clone(), b, a));
+            }
+        }
+
+        if let (Some(before_root), Some(after_root)) =
+            (&before.input_root_digest, &after.input_root_digest)
+        {
+            if before_root != after_root {
+                records.push(changed(
+                    "input_root",
+                    String::new(),
+                    Some(before_root.as_str()),
+                    Some(after_root.as_str()),
+                ));
+                for (path, b, a) in changed_entries(&before.inputs, &after.inputs) {
+                    records.push(changed("input", path.clone(), b, a));
+                }
+            }
+        }
+    }
+    records
+}
+
+/// The keys whose values differ between two maps, with the value in each.
+fn changed_entries<'a>(
+    before: &'a BTreeMap<String, String>,
+    after: &'a BTreeMap<String, String>,
+) -> impl Iterator<Item = (&'a String, Option<&'a str>, Option<&'a str>)> {
+    before
+        .keys()
+        .chain(after.keys().filter(|k| !before.contains_key(*k)))
+        .filter_map(|key| {
+            let (b, a) = (before.get(key), after.get(key));
+            (b != a).then(|| (key, b.map(|s| s.as_str()), a.map(|s| s.as_str())))
+        })
+}
+
+fn write_record(output: &LogCommandOutputFormat, record: &DiffRecord<'_>) -> anyhow::Result<()> {
+    match output {
+        LogCommandOutputFormat::Tabulated => buck2_client_ctx::println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            record.change,
+            record.action,
+            record.kind,
+            record.name,
+            record.before.unwrap_or_default(),
+            record.after.unwrap_or_default(),
+        ),
+        LogCommandOutputFormat::Csv => buck2_client_ctx::stdio::print_with_writer(|w| {
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+            writer.serialize(record)
+        }),
+        LogCommandOutputFormat::Json => buck2_client_ctx::stdio::print_with_writer(|mut w| {
+            serde_json::to_writer(&mut w, record)?;
+            w.write(b"\n").map(|_| ())
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(digest: &str, argv: &[&str], env: &[(&str, &str)]) -> ActionRecord {
+        ActionRecord {
+            digest: Some(digest.to_owned()),
+            command: Some(LocalCommandRecord {
+                argv: argv.iter().map(|s| (*s).to_owned()).collect(),
+                env: env
+                    .iter()
+                    .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                    .collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn with_inputs(record: ActionRecord, root: &str, inputs: &[(&str, &str)]) -> ActionRecord {
+        ActionRecord {
+            input_root_digest: Some(root.to_owned()),
+            inputs: inputs
+                .iter()
+                .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                .collect(),
+            ..record
+        }
+    }
+
+    fn summary(records: &[DiffRecord<'_>]) -> Vec<String> {
+        records
+            .iter()
+            .map(|r| {
+                format!(
+                    "{} {} {} {} {:?} {:?}",
+                    r.change, r.action, r.kind, r.name, r.before, r.after
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_actions() {
+        let first = indexmap::indexmap! {
+            "same".to_owned() => local("1", &["cc", "a.c"], &[]),
+            "args".to_owned() => local("2", &["cc", "-O1", "b.c"], &[("A", "1")]),
+            "inputs".to_owned() => with_inputs(
+                local("3", &["cc", "c.c"], &[]),
+                "r1",
+                &[("c.c", "a:1"), ("c.h", "b:1"), ("d.h", "c:1")],
+            ),
+            "remote".to_owned() => ActionRecord { digest: Some("4".to_owned()), ..Default::default() },
+            "removed".to_owned() => local("5", &[], &[]),
+        };
+        let second = indexmap::indexmap! {
+            "same".to_owned() => local("1", &["cc", "a.c"], &[]),
+            "args".to_owned() => local("6", &["cc", "-O2", "b.c", "-g"], &[("A", "2"), ("B", "3")]),
+            "inputs".to_owned() => with_inputs(
+                local("7", &["cc", "c.c"], &[]),
+                "r2",
+                &[("c.c", "a:2"), ("d.h", "c:1"), ("e", "-> d.h")],
+            ),
+            "remote".to_owned() => ActionRecord { digest: Some("8".to_owned()), ..Default::default() },
+            "new".to_owned() => local("9", &[], &[]),
+        };
+
+        assert_eq!(
+            vec![
+                "changed args digest  Some(\"2\") Some(\"6\")",
+                "changed args argument 1 Some(\"-O1\") Some(\"-O2\")",
+                "changed args argument 3 None Some(\"-g\")",
+                "changed args env A Some(\"1\") Some(\"2\")",
+                "changed args env B None Some(\"3\")",
+                "changed inputs digest  Some(\"3\") Some(\"7\")",
+                "changed inputs input_root  Some(\"r1\") Some(\"r2\")",
+                "changed inputs input c.c Some(\"a:1\") Some(\"a:2\")",
+                "changed inputs input c.h Some(\"b:1\") None",
+                "changed inputs input e None Some(\"-> d.h\")",
+                "changed remote digest  Some(\"4\") Some(\"8\")",
+                "new new   None Some(\"9\")",
+            ],
+            summary(&diff_actions(&first, &second))
+        );
+    }
+}
@@ -10,9 +10,11 @@
 mod critical_path;
 pub(crate) mod debug_last_log;
 pub(crate) mod debug_what_ran;
+mod diff;
 pub(crate) mod options;
 pub(crate) mod path_log;
 mod show_log;
+mod trace;
 mod what_cmd;
 mod what_failed;
 mod what_materialized;
@@ -72,6 +74,12 @@ pub enum LogCommand {
 
     /// Shows how many bytes/digests were uploaded by a command.
     CriticalPath(critical_path::CriticalPathCommand),
+
+    /// Compares the actions executed by two commands, to explain why actions reran.
+    Diff(diff::DiffCommand),
+
+    /// Converts an event log into a Perfetto trace, to inspect a command after the fact.
+    Trace(trace::TraceCommand),
 }
 
 impl LogCommand {
@@ -86,6 +94,8 @@ impl LogCommand {
             Self::WhatMaterialized(cmd) => cmd.exec(matches, ctx),
             Self::WhatUploaded(cmd) => cmd.exec(matches, ctx),
             Self::CriticalPath(cmd) => cmd.exec(matches, ctx),
+            Self::Diff(cmd) => cmd.exec(matches, ctx),
+            Self::Trace(cmd) => cmd.exec(matches, ctx),
         }
     }
 }
@@ -62,93 +62,171 @@ impl EventLogOptions {
         &self,
         ctx: &ClientCommandContext<'_>,
     ) -> anyhow::Result<EventLogPathBuf> {
-        if let Some(path) = &self.path {
-            EventLogPathBuf::infer(path.resolve(&ctx.working_dir))
-        } else if let Some(id) = &self.trace_id {
-            if let Some(log_path) = find_log_by_trace_id(&ctx.paths()?.log_dir(), id)? {
-                Ok(log_path)
-            } else if self.allow_remote {
-                EventLogPathBuf::infer(self.download_remote_id(id, ctx).await?)
-            } else {
-                return Err(EventLogOptionsError::LogNotFoundLocally(id.dupe()).into());
-            }
-        } else {
-            retrieve_nth_recent_log(ctx, self.recent.unwrap_or(0))
-        }
+        get_event_log(
+            ctx,
+            self.path.as_ref(),
+            self.trace_id.as_ref(),
+            self.recent,
+            self.allow_remote,
+        )
+        .await
     }
+}
 
-    fn random_string() -> String {
-        let mut s = String::with_capacity(10);
-        for _ in 0..10 {
-            s.push(rand::thread_rng().gen_range('a'..='z'));
-        }
-        s
-    }
+/// Selects the two event logs compared by `buck2 log diff`. By default the most recent command is
+/// compared against the one before it.
+#[derive(Debug, clap::Parser)]
+#[clap(
+    group = clap::ArgGroup::with_name("first_event_log"),
+    group = clap::ArgGroup::with_name("second_event_log")
+)]
+pub(crate) struct DiffEventLogOptions {
+    /// Open the first event-log file from a recent command.
+    #[clap(long, group = "first_event_log", value_name = "NUMBER")]
+    recent1: Option<usize>,
+
+    /// Show the first log by trace id.
+    #[clap(long, group = "first_event_log", value_name = "ID")]
+    trace_id1: Option<TraceId>,
+
+    /// A path to the first event-log file to read from.
+    #[clap(long, group = "first_event_log", value_name = "PATH")]
+    path1: Option<PathArg>,
+
+    /// Open the second event-log file from a recent command.
+    #[clap(long, group = "second_event_log", value_name = "NUMBER")]
+    recent2: Option<usize>,
+
+    /// Show the second log by trace id.
+    #[clap(long, group = "second_event_log", value_name = "ID")]
+    trace_id2: Option<TraceId>,
+
+    /// A path to the second event-log file to read from.
+    #[clap(long, group = "second_event_log", value_name = "PATH")]
+    path2: Option<PathArg>,
+
+    /// Allow downloading the logs from manifold if they are not found locally.
+    #[clap(long)]
+    allow_remote: bool,
+}
 
-    async fn download_remote_id(
+impl DiffEventLogOptions {
+    pub(crate) async fn get(
         &self,
-        trace_id: &TraceId,
         ctx: &ClientCommandContext<'_>,
-    ) -> anyhow::Result<AbsPathBuf> {
-        let manifold_file_name = FileNameBuf::try_from(format!(
-            "{}{}",
-            trace_id,
-            // TODO(nga): hardcoded default, should at least use the same default buck2 uses,
-            //   or better enumerate all the possible suffixes.
-            Encoding::PROTO_ZSTD.extensions[0]
-        ))?;
-
-        let log_path = ctx
-            .paths()?
-            .log_dir()
-            .join(FileName::new(&format!("dl-{}", manifold_file_name))?);
-
-        if fs_util::try_exists(&log_path)? {
-            return Ok(log_path.into_abs_path_buf());
-        }
+    ) -> anyhow::Result<(EventLogPathBuf, EventLogPathBuf)> {
+        let first = get_event_log(
+            ctx,
+            self.path1.as_ref(),
+            self.trace_id1.as_ref(),
+            Some(self.recent1.unwrap_or(1)),
+            self.allow_remote,
+        )
+        .await?;
+        let second = get_event_log(
+            ctx,
+            self.path2.as_ref(),
+            self.trace_id2.as_ref(),
+            self.recent2,
+            self.allow_remote,
+        )
+        .await?;
+        Ok((first, second))
+    }
+}
 
-        let tmp_dir = ctx.paths()?.tmp_dir();
-        fs_util::create_dir_if_not_exists(&tmp_dir)?;
-        let temp_path = tmp_dir.join(FileName::new(&format!(
-            "dl.{}.{}.tmp",
-            manifold_file_name,
-            Self::random_string()
-        ))?);
-
-        // Delete the file on failure.
-        let temp_path = TempPath::new_path(temp_path);
-
-        let args = [
-            "get",
-            &format!("buck2_logs/flat/{}", manifold_file_name),
-            temp_path
-                .path()
-                .as_os_str()
-                .to_str()
-                .context("temp_path is not valid UTF-8")?,
-        ];
-        buck2_client_ctx::eprintln!("Spawning: manifold {}", args.join(" "))?;
-        let command = async_background_command("manifold")
-            .args(args)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        // No timeout here, just press Ctrl-C if you want it to cancel.
-        let result = command.wait_with_output().await?;
-        if !result.status.success() {
-            return Err(EventLogOptionsError::ManifoldFailed(
-                String::from_utf8_lossy(&result.stderr).into_owned(),
-            )
-            .into());
+async fn get_event_log(
+    ctx: &ClientCommandContext<'_>,
+    path: Option<&PathArg>,
+    trace_id: Option<&TraceId>,
+    recent: Option<usize>,
+    allow_remote: bool,
+) -> anyhow::Result<EventLogPathBuf> {
+    if let Some(path) = path {
+        EventLogPathBuf::infer(path.resolve(&ctx.working_dir))
+    } else if let Some(id) = trace_id {
+        if let Some(log_path) = find_log_by_trace_id(&ctx.paths()?.log_dir(), id)? {
+            Ok(log_path)
+        } else if allow_remote {
+            EventLogPathBuf::infer(download_remote_id(id, ctx).await?)
+        } else {
+            Err(EventLogOptionsError::LogNotFoundLocally(id.dupe()).into())
         }
+    } else {
+        retrieve_nth_recent_log(ctx, recent.unwrap_or(0))
+    }
+}
 
-        fs_util::rename(temp_path.path(), &log_path)?;
-        buck2_client_ctx::eprintln!("Downloaded event-log to `{}`", log_path.display())?;
+fn random_string() -> String {
+    let mut s = String::with_capacity(10);
+    for _ in 0..10 {
+        s.push(rand::thread_rng().gen_range('a'..='z'));
+    }
+    s
+}
 
-        temp_path.close()?;
+async fn download_remote_id(
+    trace_id: &TraceId,
+    ctx: &ClientCommandContext<'_>,
+) -> anyhow::Result<AbsPathBuf> {
+    let manifold_file_name = FileNameBuf::try_from(format!(
+        "{}{}",
+        trace_id,
+        // TODO(nga): hardcoded default, should at least use the same default buck2 uses,
+        //   or better enumerate all the possible suffixes.
+        Encoding::PROTO_ZSTD.extensions[0]
+    ))?;
+
+    let log_path = ctx
+        .paths()?
+        .log_dir()
+        .join(FileName::new(&format!("dl-{}", manifold_file_name))?);
+
+    if fs_util::try_exists(&log_path)? {
+        return Ok(log_path.into_abs_path_buf());
+    }
 
-        Ok(log_path.into_abs_path_buf())
+    let tmp_dir = ctx.paths()?.tmp_dir();
+    fs_util::create_dir_if_not_exists(&tmp_dir)?;
+    let temp_path = tmp_dir.join(FileName::new(&format!(
+        "dl.{}.{}.tmp",
+        manifold_file_name,
+        random_string()
+    ))?);
+
+    // Delete the file on failure.
+    let temp_path = TempPath::new_path(temp_path);
+
+    let args = [
+        "get",
+        &format!("buck2_logs/flat/{}", manifold_file_name),
+        temp_path
+            .path()
+            .as_os_str()
+            .to_str()
+            .context("temp_path is not valid UTF-8")?,
+    ];
+    buck2_client_ctx::eprintln!("Spawning: manifold {}", args.join(" "))?;
+    let command = async_background_command("manifold")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // No timeout here, just press Ctrl-C if you want it to cancel.
+    let result = command.wait_with_output().await?;
+    if !result.status.success() {
+        return Err(EventLogOptionsError::ManifoldFailed(
+            String::from_utf8_lossy(&result.stderr).into_owned(),
+        )
+        .into());
     }
+
+    fs_util::rename(temp_path.path(), &log_path)?;
+    buck2_client_ctx::eprintln!("Downloaded event-log to `{}`", log_path.display())?;
+
+    temp_path.close()?;
+
+    Ok(log_path.into_abs_path_buf())
 }
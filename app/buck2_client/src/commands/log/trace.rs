@@ -0,0 +1,726 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use buck2_client_ctx::client_ctx::ClientCommandContext;
+use buck2_client_ctx::exit_result::ExitResult;
+use buck2_client_ctx::path_arg::PathArg;
+use buck2_client_ctx::stream_value::StreamValue;
+use buck2_client_ctx::tokio_runtime_setup::client_tokio_runtime;
+use buck2_core::fs::fs_util;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_event_observer::re_state::ReState;
+use buck2_events::span::SpanId;
+use buck2_events::BuckEvent;
+use dupe::Dupe;
+use prost::Message;
+use tokio_stream::StreamExt;
+
+use crate::commands::log::options::EventLogOptions;
+
+/// Converts an event log into a Perfetto trace, to inspect a command after the fact.
+///
+/// Actions are shown on tracks grouped by how they were executed: locally, remotely, served from
+/// the action cache, or by buck2 itself. Their executor stages are nested within them.
+/// Materializations, uploads, and the loads and analyses computed by DICE have tracks of their
+/// own, and the RE statistics are shown as counters.
+///
+/// The trace can be opened with https://ui.perfetto.dev.
+#[derive(Debug, clap::Parser)]
+pub struct TraceCommand {
+    #[clap(flatten)]
+    event_log: EventLogOptions,
+
+    /// Where to write the trace. If a directory is passed, the filename of the event log is used
+    /// as a base filename.
+    #[clap(long, value_name = "PATH")]
+    trace_path: PathArg,
+}
+
+impl TraceCommand {
+    pub fn exec(self, _matches: &clap::ArgMatches, ctx: ClientCommandContext<'_>) -> ExitResult {
+        let Self {
+            event_log,
+            trace_path,
+        } = self;
+
+        let rt = client_tokio_runtime()?;
+
+        rt.block_on(async move {
+            let log_path = event_log.get(&ctx).await?;
+
+            let mut trace_path = trace_path.resolve(&ctx.working_dir);
+            if trace_path.is_dir() {
+                let file_name = log_path.path().file_name().with_context(|| {
+                    format!(
+                        "Could not determine filename from event log path: `{}`",
+                        log_path.path().display()
+                    )
+                })?;
+                trace_path.push(file_name);
+                trace_path.set_extension("perfetto-trace");
+            }
+
+            let (invocation, mut events) = log_path.unpack_stream().await?;
+            let mut builder = TraceBuilder::new(invocation.display_command_line());
+            while let Some(event) = events.try_next().await? {
+                match event {
+                    StreamValue::Event(event) => {
+                        let event = Arc::new(BuckEvent::try_from(event)?);
+                        builder
+                            .handle_event(&event)
+                            .with_context(|| display::InvalidBuckEvent(event.dupe()))?;
+                    }
+                    _ => {}
+                }
+            }
+
+            fs_util::write(&trace_path, builder.finish().encode_to_vec())?;
+            buck2_client_ctx::eprintln!("Trace written to: {}", trace_path.display())?;
+
+            anyhow::Ok(())
+        })?;
+
+        ExitResult::success()
+    }
+}
+
+/// The tracks spans are laid out on. Each group has as many tracks as it needs for its spans not
+/// to overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum TrackGroup {
+    Command,
+    Load,
+    Analysis,
+    LocalActions,
+    RemoteActions,
+    CachedActions,
+    OtherActions,
+    Materialization,
+    Upload,
+}
+
+impl TrackGroup {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Command => "command",
+            Self::Load => "load",
+            Self::Analysis => "analysis",
+            Self::LocalActions => "local actions",
+            Self::RemoteActions => "remote actions",
+            Self::CachedActions => "cached actions",
+            Self::OtherActions => "other actions",
+            Self::Materialization => "materialization",
+            Self::Upload => "upload",
+        }
+    }
+
+    fn for_action(action: &buck2_data::ActionExecutionEnd) -> Self {
+        match buck2_data::ActionExecutionKind::from_i32(action.execution_kind) {
+            Some(buck2_data::ActionExecutionKind::Local) => Self::LocalActions,
+            Some(buck2_data::ActionExecutionKind::Remote) => Self::RemoteActions,
+            Some(
+                buck2_data::ActionExecutionKind::ActionCache
+                | buck2_data::ActionExecutionKind::LocalActionCache,
+            ) => Self::CachedActions,
+            _ => Self::OtherActions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Bool(bool),
+    Uint(u64),
+    String(String),
+}
+
+struct OpenSpan {
+    name: String,
+    parent: Option<SpanId>,
+    /// `None` for spans shown within their parent, or whose group is only known when they end.
+    group: Option<TrackGroup>,
+    start: u64,
+    args: Vec<(&'static str, Arg)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ClosedSpan {
+    span_id: SpanId,
+    name: String,
+    parent: Option<SpanId>,
+    /// `None` for spans shown within their parent.
+    group: Option<TrackGroup>,
+    start: u64,
+    end: u64,
+    args: Vec<(&'static str, Arg)>,
+}
+
+/// Assigns each span a track within its group, returning the spans with their group and track
+/// index. Spans without a group are placed on the track of their parent, and are dropped if their
+/// parent isn't shown.
+fn assign_tracks(mut spans: Vec<ClosedSpan>) -> Vec<(ClosedSpan, TrackGroup, usize)> {
+    // Parents start before their children, or end after them if they start together.
+    spans.sort_by_key(|s| (s.start, Reverse(s.end)));
+
+    // The end of the last span on each track.
+    let mut track_ends: HashMap<TrackGroup, Vec<u64>> = HashMap::new();
+    let mut assigned: HashMap<SpanId, (TrackGroup, usize)> = HashMap::new();
+    let mut res = Vec::new();
+    for span in spans {
+        let track = match span.group {
+            Some(group) => {
+                let ends = track_ends.entry(group).or_default();
+                let index = match ends.iter().position(|end| *end <= span.start) {
+                    Some(index) => {
+                        ends[index] = span.end;
+                        index
+                    }
+                    None => {
+                        ends.push(span.end);
+                        ends.len() - 1
+                    }
+                };
+                (group, index)
+            }
+            None => match span.parent.and_then(|p| assigned.get(&p)) {
+                Some(track) => *track,
+                None => continue,
+            },
+        };
+        assigned.insert(span.span_id, track);
+        res.push((span, track.0, track.1));
+    }
+    res
+}
+
+fn nanos_since_epoch(timestamp: SystemTime) -> anyhow::Result<u64> {
+    Ok(timestamp.duration_since(SystemTime::UNIX_EPOCH)?.as_nanos() as u64)
+}
+
+struct TraceBuilder {
+    command_line: String,
+    open_spans: HashMap<SpanId, OpenSpan>,
+    closed_spans: Vec<ClosedSpan>,
+    re_state: ReState,
+    /// The samples of each counter, by counter name.
+    counters: BTreeMap<String, Vec<(u64, u64, bool)>>,
+}
+
+impl TraceBuilder {
+    /// All packets are written by a single sequence.
+    const SEQUENCE_ID: u32 = 1;
+    const PROCESS_UUID: u64 = 1;
+
+    fn new(command_line: String) -> Self {
+        Self {
+            command_line,
+            open_spans: HashMap::new(),
+            closed_spans: Vec::new(),
+            re_state: ReState::new(),
+            counters: BTreeMap::new(),
+        }
+    }
+
+    fn handle_event(&mut self, event: &BuckEvent) -> anyhow::Result<()> {
+        let timestamp = nanos_since_epoch(event.timestamp())?;
+        match event.data() {
+            buck2_data::buck_event::Data::SpanStart(start) => {
+                let span_id = match event.span_id() {
+                    Some(span_id) => span_id,
+                    None => return Ok(()),
+                };
+                if let Some((name, group)) = Self::categorize_start(start)? {
+                    self.open_spans.insert(
+                        span_id,
+                        OpenSpan {
+                            name,
+                            parent: event.parent_id(),
+                            group,
+                            start: timestamp,
+                            args: Vec::new(),
+                        },
+                    );
+                }
+            }
+            buck2_data::buck_event::Data::SpanEnd(end) => {
+                let span_id = match event.span_id() {
+                    Some(span_id) => span_id,
+                    None => return Ok(()),
+                };
+                if let Some(mut open) = self.open_spans.remove(&span_id) {
+                    Self::complete_end(&mut open, end);
+                    self.closed_spans.push(ClosedSpan {
+                        span_id,
+                        name: open.name,
+                        parent: open.parent,
+                        group: open.group,
+                        start: open.start,
+                        // Slices need a duration for their begin to be ordered before their end.
+                        end: std::cmp::max(timestamp, open.start + 1),
+                        args: open.args,
+                    });
+                }
+            }
+            buck2_data::buck_event::Data::Instant(instant) => {
+                if let Some(buck2_data::instant_event::Data::Snapshot(snapshot)) = &instant.data {
+                    self.re_state.update(event.timestamp(), snapshot);
+                    for counter in self.re_state.counters() {
+                        self.counters.entry(counter.name).or_default().push((
+                            timestamp,
+                            counter.value,
+                            counter.bytes,
+                        ));
+                    }
+                }
+            }
+            buck2_data::buck_event::Data::Record(_) => {}
+        }
+        Ok(())
+    }
+
+    /// The name and group of the spans shown in the trace.
+    fn categorize_start(
+        start: &buck2_data::SpanStartEvent,
+    ) -> anyhow::Result<Option<(String, Option<TrackGroup>)>> {
+        use buck2_data::span_start_event::Data;
+
+        Ok(Some(match start.data.as_ref() {
+            Some(Data::Command(_)) => ("command".to_owned(), Some(TrackGroup::Command)),
+            Some(Data::FileWatcher(_)) => {
+                ("file_watcher_sync".to_owned(), Some(TrackGroup::Command))
+            }
+            Some(Data::Load(load)) => (format!("load {}", load.module_id), Some(TrackGroup::Load)),
+            Some(Data::LoadPackage(load)) => (
+                format!("load_package {}", load.path),
+                Some(TrackGroup::Load),
+            ),
+            Some(Data::Analysis(analysis)) => (
+                format!(
+                    "analysis {}",
+                    display::display_analysis_target(
+                        analysis
+                            .target
+                            .as_ref()
+                            .context("AnalysisStart event missing 'target' field")?,
+                        TargetDisplayOptions::for_chrome_trace(),
+                    )?
+                ),
+                Some(TrackGroup::Analysis),
+            ),
+            // The group depends on how the action was executed, known when it ends.
+            Some(Data::ActionExecution(action)) => (
+                display::display_action_identity(
+                    action.key.as_ref(),
+                    action.name.as_ref(),
+                    TargetDisplayOptions::for_chrome_trace(),
+                )?,
+                None,
+            ),
+            Some(Data::ExecutorStage(stage)) => (
+                display::display_executor_stage(stage.stage.as_ref().context("expected stage")?)?
+                    .to_owned(),
+                None,
+            ),
+            Some(Data::Materialization(_)) => (
+                "materialization".to_owned(),
+                Some(TrackGroup::Materialization),
+            ),
+            Some(Data::FinalMaterialization(materialization)) => (
+                format!(
+                    "materialize {}",
+                    materialization
+                        .artifact
+                        .as_ref()
+                        .map_or("", |a| a.path.as_str())
+                ),
+                Some(TrackGroup::Materialization),
+            ),
+            Some(Data::ReUpload(_)) => ("re_upload".to_owned(), Some(TrackGroup::Upload)),
+            Some(Data::CacheUpload(upload)) => (
+                format!(
+                    "cache_upload {}",
+                    display::display_action_identity(
+                        upload.key.as_ref(),
+                        upload.name.as_ref(),
+                        TargetDisplayOptions::for_chrome_trace(),
+                    )?
+                ),
+                Some(TrackGroup::Upload),
+            ),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Records what is only known when a span ends.
+    fn complete_end(open: &mut OpenSpan, end: &buck2_data::SpanEndEvent) {
+        use buck2_data::span_end_event::Data;
+
+        match end.data.as_ref() {
+            Some(Data::ActionExecution(action)) => {
+                open.group = Some(TrackGroup::for_action(action));
+                open.args.push(("failed", Arg::Bool(action.failed)));
+                open.args
+                    .push(("output_size", Arg::Uint(action.output_size)));
+                if let Some(name) = &action.name {
+                    open.args
+                        .push(("category", Arg::String(name.category.clone())));
+                }
+            }
+            Some(Data::Materialization(materialization)) => {
+                open.name = format!("materialize {}", materialization.path);
+                open.args
+                    .push(("file_count", Arg::Uint(materialization.file_count)));
+                open.args
+                    .push(("total_bytes", Arg::Uint(materialization.total_bytes)));
+                open.args
+                    .push(("success", Arg::Bool(materialization.success)));
+            }
+            Some(Data::ReUpload(upload)) => {
+                if let Some(bytes) = upload.bytes_uploaded {
+                    open.args.push(("bytes_uploaded", Arg::Uint(bytes)));
+                }
+                if let Some(digests) = upload.digests_uploaded {
+                    open.args.push(("digests_uploaded", Arg::Uint(digests)));
+                }
+            }
+            Some(Data::CacheUpload(upload)) => {
+                open.args.push(("success", Arg::Bool(upload.success)));
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> perfetto::Trace {
+        let mut packets = Vec::new();
+        let mut next_uuid = Self::PROCESS_UUID + 1;
+        let mut track_descriptor =
+            |name: String, parent_uuid: u64, counter: Option<perfetto::CounterDescriptor>| {
+                let uuid = next_uuid;
+                next_uuid += 1;
+                packets.push(perfetto::TracePacket::track_descriptor(
+                    perfetto::TrackDescriptor {
+                        uuid: Some(uuid),
+                        name: Some(name),
+                        parent_uuid: Some(parent_uuid),
+                        counter,
+                        ..Default::default()
+                    },
+                ));
+                uuid
+            };
+
+        let spans = assign_tracks(self.closed_spans);
+
+        // Create the tracks in the order of their groups.
+        let mut tracks: BTreeMap<TrackGroup, Vec<u64>> = BTreeMap::new();
+        for (_, group, index) in &spans {
+            let count = tracks.entry(*group).or_default();
+            if count.len() <= *index {
+                count.resize(index + 1, 0);
+            }
+        }
+        for (group, uuids) in tracks.iter_mut() {
+            let group_uuid = track_descriptor(group.name().to_owned(), Self::PROCESS_UUID, None);
+            for (index, uuid) in uuids.iter_mut().enumerate() {
+                *uuid = track_descriptor(format!("{} {}", group.name(), index), group_uuid, None);
+            }
+        }
+
+        let counter_uuids: Vec<u64> = self
+            .counters
+            .iter()
+            .map(|(name, samples)| {
+                let unit = match samples.first() {
+                    Some((_, _, true)) => perfetto::CounterUnit::SizeBytes,
+                    _ => perfetto::CounterUnit::Count,
+                };
+                track_descriptor(
+                    format!("RE {}", name),
+                    Self::PROCESS_UUID,
+                    Some(perfetto::CounterDescriptor {
+                        unit: Some(unit as i32),
+                    }),
+                )
+            })
+            .collect();
+
+        // Ends sort before begins at the same time, and nested slices begin after and end
+        // before the slices enclosing them.
+        let mut events: Vec<((u64, u8, Reverse<u64>), perfetto::TrackEvent)> = Vec::new();
+        for (span, group, index) in spans {
+            let track_uuid = tracks[&group][index];
+            events.push((
+                (span.end, 0, Reverse(span.start)),
+                perfetto::TrackEvent {
+                    r#type: Some(perfetto::TrackEventType::SliceEnd as i32),
+                    track_uuid: Some(track_uuid),
+                    ..Default::default()
+                },
+            ));
+            events.push((
+                (span.start, 1, Reverse(span.end)),
+                perfetto::TrackEvent {
+                    r#type: Some(perfetto::TrackEventType::SliceBegin as i32),
+                    track_uuid: Some(track_uuid),
+                    name: Some(span.name),
+                    categories: vec![group.name().to_owned()],
+                    debug_annotations: span
+                        .args
+                        .into_iter()
+                        .map(|(name, value)| perfetto::DebugAnnotation::new(name, value))
+                        .collect(),
+                    ..Default::default()
+                },
+            ));
+        }
+        for ((_, samples), track_uuid) in self.counters.into_iter().zip(counter_uuids) {
+            for (timestamp, value, _) in samples {
+                events.push((
+                    (timestamp, 2, Reverse(0)),
+                    perfetto::TrackEvent {
+                        r#type: Some(perfetto::TrackEventType::Counter as i32),
+                        track_uuid: Some(track_uuid),
+                        counter_value: Some(value as i64),
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+        events.sort_by_key(|(key, _)| *key);
+
+        let mut trace = perfetto::Trace {
+            packet: vec![perfetto::TracePacket::track_descriptor(
+                perfetto::TrackDescriptor {
+                    uuid: Some(Self::PROCESS_UUID),
+                    process: Some(perfetto::ProcessDescriptor {
+                        pid: Some(1),
+                        process_name: Some(self.command_line),
+                    }),
+                    ..Default::default()
+                },
+            )],
+        };
+        trace.packet.extend(packets);
+        trace
+            .packet
+            .extend(
+                events
+                    .into_iter()
+                    .map(|((timestamp, _, _), event)| perfetto::TracePacket {
+                        timestamp: Some(timestamp),
+                        track_event: Some(event),
+                        ..perfetto::TracePacket::default()
+                    }),
+            );
+        for (i, packet) in trace.packet.iter_mut().enumerate() {
+            packet.trusted_packet_sequence_id = Some(Self::SEQUENCE_ID);
+            if i == 0 {
+                packet.sequence_flags = Some(perfetto::SEQ_INCREMENTAL_STATE_CLEARED);
+            }
+        }
+        trace
+    }
+}
+
+/// The subset of the Perfetto trace format (`perfetto/trace/trace.proto`) we write, using the
+/// same field numbers.
+mod perfetto {
+    use super::Arg;
+
+    pub(super) const SEQ_INCREMENTAL_STATE_CLEARED: u32 = 1;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Trace {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) packet: Vec<TracePacket>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct TracePacket {
+        #[prost(uint64, optional, tag = "8")]
+        pub(super) timestamp: Option<u64>,
+        #[prost(uint32, optional, tag = "10")]
+        pub(super) trusted_packet_sequence_id: Option<u32>,
+        #[prost(message, optional, tag = "11")]
+        pub(super) track_event: Option<TrackEvent>,
+        #[prost(uint32, optional, tag = "13")]
+        pub(super) sequence_flags: Option<u32>,
+        #[prost(message, optional, tag = "60")]
+        pub(super) track_descriptor: Option<TrackDescriptor>,
+    }
+
+    impl TracePacket {
+        pub(super) fn track_descriptor(descriptor: TrackDescriptor) -> Self {
+            Self {
+                track_descriptor: Some(descriptor),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct TrackDescriptor {
+        #[prost(uint64, optional, tag = "1")]
+        pub(super) uuid: Option<u64>,
+        #[prost(string, optional, tag = "2")]
+        pub(super) name: Option<String>,
+        #[prost(message, optional, tag = "3")]
+        pub(super) process: Option<ProcessDescriptor>,
+        #[prost(uint64, optional, tag = "5")]
+        pub(super) parent_uuid: Option<u64>,
+        #[prost(message, optional, tag = "8")]
+        pub(super) counter: Option<CounterDescriptor>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct ProcessDescriptor {
+        #[prost(int32, optional, tag = "1")]
+        pub(super) pid: Option<i32>,
+        #[prost(string, optional, tag = "6")]
+        pub(super) process_name: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct CounterDescriptor {
+        #[prost(enumeration = "CounterUnit", optional, tag = "3")]
+        pub(super) unit: Option<i32>,
+    }
+
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub(super) enum CounterUnit {
+        Unspecified = 0,
+        Count = 2,
+        SizeBytes = 3,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct TrackEvent {
+        #[prost(message, repeated, tag = "4")]
+        pub(super) debug_annotations: Vec<DebugAnnotation>,
+        #[prost(enumeration = "TrackEventType", optional, tag = "9")]
+        pub(super) r#type: Option<i32>,
+        #[prost(uint64, optional, tag = "11")]
+        pub(super) track_uuid: Option<u64>,
+        #[prost(string, repeated, tag = "22")]
+        pub(super) categories: Vec<String>,
+        #[prost(string, optional, tag = "23")]
+        pub(super) name: Option<String>,
+        #[prost(int64, optional, tag = "30")]
+        pub(super) counter_value: Option<i64>,
+    }
+
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub(super) enum TrackEventType {
+        Unspecified = 0,
+        SliceBegin = 1,
+        SliceEnd = 2,
+        Counter = 4,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct DebugAnnotation {
+        #[prost(bool, optional, tag = "2")]
+        pub(super) bool_value: Option<bool>,
+        #[prost(uint64, optional, tag = "3")]
+        pub(super) uint_value: Option<u64>,
+        #[prost(string, optional, tag = "6")]
+        pub(super) string_value: Option<String>,
+        #[prost(string, optional, tag = "10")]
+        pub(super) name: Option<String>,
+    }
+
+    impl DebugAnnotation {
+        pub(super) fn new(name: &str, value: Arg) -> Self {
+            let mut annotation = Self {
+                name: Some(name.to_owned()),
+                ..Default::default()
+            };
+            match value {
+                Arg::Bool(v) => annotation.bool_value = Some(v),
+                Arg::Uint(v) => annotation.uint_value = Some(v),
+                Arg::String(v) => annotation.string_value = Some(v),
+            }
+            annotation
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign_tracks() {
+        let ids: Vec<SpanId> = (0..8).map(|_| SpanId::new()).collect();
+        let span = |id: usize, parent: Option<usize>, group, start, end| ClosedSpan {
+            span_id: ids[id],
+            name: id.to_string(),
+            parent: parent.map(|p| ids[p]),
+            group,
+            start,
+            end,
+            args: Vec::new(),
+        };
+
+        let spans = vec![
+            span(1, None, Some(TrackGroup::LocalActions), 0, 10),
+            span(2, Some(1), None, 2, 5),
+            span(3, None, Some(TrackGroup::LocalActions), 5, 15),
+            span(4, None, Some(TrackGroup::LocalActions), 10, 20),
+            span(5, None, Some(TrackGroup::RemoteActions), 0, 10),
+            // The parent isn't shown.
+            span(6, Some(7), None, 0, 1),
+        ];
+
+        let assigned: Vec<_> = assign_tracks(spans)
+            .into_iter()
+            .map(|(s, group, index)| (s.name, group, index))
+            .collect();
+        assert_eq!(
+            vec![
+                ("1".to_owned(), TrackGroup::LocalActions, 0),
+                ("5".to_owned(), TrackGroup::RemoteActions, 0),
+                ("2".to_owned(), TrackGroup::LocalActions, 0),
+                ("3".to_owned(), TrackGroup::LocalActions, 1),
+                ("4".to_owned(), TrackGroup::LocalActions, 0),
+            ],
+            assigned
+        );
+    }
+}
@@ -10,6 +10,7 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
+use std::time::Instant;
 
 use anyhow::Context;
 use async_trait::async_trait;
@@ -17,6 +18,7 @@ use buck2_cli_proto::build_request::build_providers;
 use buck2_cli_proto::build_request::BuildProviders;
 use buck2_cli_proto::build_request::Materializations;
 use buck2_cli_proto::BuildRequest;
+use buck2_cli_proto::WaitForChangesRequest;
 use buck2_client_ctx::client_ctx::ClientCommandContext;
 use buck2_client_ctx::command_outcome::CommandOutcome;
 use buck2_client_ctx::common::CommonBuildConfigurationOptions;
@@ -24,6 +26,7 @@ use buck2_client_ctx::common::CommonBuildOptions;
 use buck2_client_ctx::common::CommonCommandOptions;
 use buck2_client_ctx::common::CommonConsoleOptions;
 use buck2_client_ctx::common::CommonDaemonCommandOptions;
+use buck2_client_ctx::common::CommonWatchOptions;
 use buck2_client_ctx::daemon::client::BuckdClientConnector;
 use buck2_client_ctx::daemon::client::NoPartialResultHandler;
 use buck2_client_ctx::exit_result::ExitResult;
@@ -32,6 +35,7 @@ use buck2_wrapper_common::BUCK2_WRAPPER_ENV_VAR;
 use buck2_wrapper_common::BUCK_WRAPPER_UUID_ENV_VAR;
 use serde::Serialize;
 use thiserror::Error;
+use tokio::process::Command;
 
 use crate::commands::build::print_build_result;
 
@@ -51,6 +55,9 @@ pub struct RunCommand {
     #[clap(flatten)]
     build_opts: CommonBuildOptions,
 
+    #[clap(flatten)]
+    watch_opts: CommonWatchOptions,
+
     #[clap(long = "providers", help = "Print the providers of each target")]
     print_providers: bool,
 
@@ -93,60 +100,16 @@ impl StreamingCommand for RunCommand {
         matches: &clap::ArgMatches,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let context = ctx.client_context(
-            &self.common_opts.config_opts,
-            matches,
-            self.sanitized_argv(),
-        )?;
-        // TODO(rafaelc): fail fast on the daemon if the target doesn't have RunInfo
-        let response = buckd
-            .with_flushing()
-            .build(
-                BuildRequest {
-                    context: Some(context),
-                    target_patterns: vec![buck2_data::TargetPattern {
-                        value: self.target.clone(),
-                    }],
-                    unstable_print_providers: self.print_providers,
-                    build_providers: Some(BuildProviders {
-                        default_info: build_providers::Action::Skip as i32,
-                        run_info: build_providers::Action::Build as i32,
-                        test_info: build_providers::Action::Skip as i32,
-                    }),
-                    response_options: None,
-                    build_opts: Some(self.build_opts.to_proto()),
-                    final_artifact_materializations: Materializations::Materialize as i32,
-                    target_universe: Vec::new(),
-                },
-                ctx.stdin()
-                    .console_interaction_stream(&self.common_opts.console_opts),
-                &mut NoPartialResultHandler,
-            )
-            .await;
-
-        let console = self.common_opts.console_opts.final_console();
-        let success = match &response {
-            Ok(CommandOutcome::Success(response)) => response.error_messages.is_empty(),
-            Ok(CommandOutcome::Failure(_)) => false,
-            Err(_) => false,
-        };
-        if !success {
-            console.print_error("BUILD FAILED")?;
+        if self.watch_opts.watch {
+            return self.exec_watch(buckd, matches, ctx).await;
         }
-        let response = response??;
-        print_build_result(&console, &response.error_messages)?;
 
-        if !success {
-            return ExitResult::failure();
-        }
-
-        // TODO(rafaelc): use absolute paths for artifacts in the cli
-        //      we should run the command from the current dir, not the project root
-        if response.build_targets.is_empty() || response.build_targets[0].run_args.is_empty() {
-            return ExitResult::err(RunCommandError::NonBinaryRule(self.target).into());
-        }
-        let mut run_args = response.build_targets[0].run_args.clone();
-        run_args.extend(self.extra_run_args);
+        let run_args = match self.build_run_args(buckd, matches, ctx).await? {
+            CommandOutcome::Success(run_args) => run_args,
+            CommandOutcome::Failure(status) => {
+                return status.map_or_else(ExitResult::failure, ExitResult::status);
+            }
+        };
 
         // Special case for recursive invocations of buck; `BUCK2_WRAPPER` is set by wrapper scripts that execute
         // Buck2. We're not a wrapper script, so we unset it to prevent `run` from inheriting it.
@@ -211,6 +174,164 @@ impl StreamingCommand for RunCommand {
     }
 }
 
+impl RunCommand {
+    /// Builds the target and returns the command to run it. Returns a failure, with the exit code
+    /// the daemon reported if any, if the build failed, which has then been reported to the user.
+    async fn build_run_args(
+        &self,
+        buckd: &mut BuckdClientConnector,
+        matches: &clap::ArgMatches,
+        ctx: &mut ClientCommandContext<'_>,
+    ) -> anyhow::Result<CommandOutcome<Vec<String>>> {
+        let context = ctx.client_context(
+            &self.common_opts.config_opts,
+            matches,
+            self.sanitized_argv(),
+        )?;
+        // TODO(rafaelc): fail fast on the daemon if the target doesn't have RunInfo
+        let response = buckd
+            .with_flushing()
+            .build(
+                BuildRequest {
+                    context: Some(context),
+                    target_patterns: vec![buck2_data::TargetPattern {
+                        value: self.target.clone(),
+                    }],
+                    unstable_print_providers: self.print_providers,
+                    build_providers: Some(BuildProviders {
+                        default_info: build_providers::Action::Skip as i32,
+                        run_info: build_providers::Action::Build as i32,
+                        test_info: build_providers::Action::Skip as i32,
+                    }),
+                    response_options: None,
+                    build_opts: Some(self.build_opts.to_proto()),
+                    final_artifact_materializations: Materializations::Materialize as i32,
+                    target_universe: Vec::new(),
+                    // Watching happens here, since the client runs the target.
+                    watch: None,
+                },
+                ctx.stdin()
+                    .console_interaction_stream(&self.common_opts.console_opts),
+                &mut NoPartialResultHandler,
+            )
+            .await;
+
+        let console = self.common_opts.console_opts.final_console();
+        let success = match &response {
+            Ok(CommandOutcome::Success(response)) => response.error_messages.is_empty(),
+            Ok(CommandOutcome::Failure(_)) => false,
+            Err(_) => false,
+        };
+        if !success {
+            console.print_error("BUILD FAILED")?;
+        }
+        let response = match response? {
+            CommandOutcome::Success(response) => response,
+            CommandOutcome::Failure(status) => return Ok(CommandOutcome::Failure(status)),
+        };
+        print_build_result(&console, &response.error_messages)?;
+
+        if !success {
+            return Ok(CommandOutcome::Failure(None));
+        }
+
+        // TODO(rafaelc): use absolute paths for artifacts in the cli
+        //      we should run the command from the current dir, not the project root
+        if response.build_targets.is_empty() || response.build_targets[0].run_args.is_empty() {
+            return Err(RunCommandError::NonBinaryRule(self.target.clone()).into());
+        }
+        let mut run_args = response.build_targets[0].run_args.clone();
+        run_args.extend(self.extra_run_args.iter().cloned());
+        Ok(CommandOutcome::Success(run_args))
+    }
+
+    /// Builds and runs the target, and does so again every time files change, killing the
+    /// previous process if it is still running.
+    async fn exec_watch(
+        self,
+        buckd: &mut BuckdClientConnector,
+        matches: &clap::ArgMatches,
+        ctx: &mut ClientCommandContext<'_>,
+    ) -> ExitResult {
+        if self.command_args_file.is_some() || self.emit_shell {
+            return ExitResult::err(RunCommandError::WatchWithoutRunning.into());
+        }
+
+        // See `exec_impl`.
+        std::env::remove_var(BUCK2_WRAPPER_ENV_VAR);
+        std::env::remove_var(BUCK_WRAPPER_UUID_ENV_VAR);
+
+        let console = self.common_opts.console_opts.final_console();
+        let mut iteration = 0;
+        loop {
+            iteration += 1;
+            let start = Instant::now();
+            let child = match self.build_run_args(buckd, matches, ctx).await? {
+                CommandOutcome::Success(run_args) => {
+                    console.print_success(&format!(
+                        "Iteration {}: built in {:.1}s, running `{}`",
+                        iteration,
+                        start.elapsed().as_secs_f64(),
+                        shlex::join(run_args.iter().map(|a| a.as_str())),
+                    ))?;
+                    let mut command = Command::new(&run_args[0]);
+                    command
+                        .args(&run_args[1..])
+                        .env("BUCK_RUN_BUILD_ID", ctx.trace_id.to_string())
+                        .kill_on_drop(true);
+                    if let Some(chdir) = &self.chdir {
+                        command.current_dir(chdir);
+                    }
+                    Some(
+                        command
+                            .spawn()
+                            .with_context(|| format!("Failed to run `{}`", run_args[0]))?,
+                    )
+                }
+                CommandOutcome::Failure(_) => {
+                    console.print_error(&format!("Iteration {}: build failed", iteration))?;
+                    None
+                }
+            };
+            buck2_client_ctx::eprintln!("Waiting for changes...")?;
+
+            let context = ctx.client_context(
+                &self.common_opts.config_opts,
+                matches,
+                self.sanitized_argv(),
+            )?;
+            let changes = buckd.with_flushing().wait_for_changes(
+                WaitForChangesRequest {
+                    context: Some(context),
+                    watch: self.watch_opts.to_proto(),
+                },
+                ctx.stdin()
+                    .console_interaction_stream(&self.common_opts.console_opts),
+                &mut NoPartialResultHandler,
+            );
+            futures::pin_mut!(changes);
+
+            match child {
+                Some(mut child) => {
+                    tokio::select! {
+                        res = &mut changes => {
+                            res??;
+                            child.kill().await.context("Failed to stop the previous run")?;
+                        }
+                        status = child.wait() => {
+                            buck2_client_ctx::eprintln!("Run finished: {}", status?)?;
+                            changes.await??;
+                        }
+                    }
+                }
+                None => {
+                    changes.await??;
+                }
+            }
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct CommandArgsFile {
     path: String,
@@ -228,4 +349,6 @@ pub enum RunCommandError {
     NonBinaryRule(String),
     #[error("`--emit-shell` is not supported on Windows")]
     EmitShellNotSupportedOnWindows,
+    #[error("`--watch` can't be used with `--command-args-file` or `--emit-shell`")]
+    WatchWithoutRunning,
 }
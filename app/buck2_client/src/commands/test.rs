@@ -18,6 +18,7 @@ use buck2_client_ctx::common::CommonBuildOptions;
 use buck2_client_ctx::common::CommonCommandOptions;
 use buck2_client_ctx::common::CommonConsoleOptions;
 use buck2_client_ctx::common::CommonDaemonCommandOptions;
+use buck2_client_ctx::common::CommonWatchOptions;
 use buck2_client_ctx::daemon::client::BuckdClientConnector;
 use buck2_client_ctx::daemon::client::NoPartialResultHandler;
 use buck2_client_ctx::exit_result::ExitResult;
@@ -26,7 +27,9 @@ use buck2_client_ctx::output_destination_arg::OutputDestinationArg;
 use buck2_client_ctx::path_arg::PathArg;
 use buck2_client_ctx::stdio::eprint_line;
 use buck2_client_ctx::streaming::StreamingCommand;
+use buck2_client_ctx::subscribers::subscriber::EventSubscriber;
 use buck2_client_ctx::subscribers::superconsole::test::TestCounterColumn;
+use buck2_client_ctx::subscribers::test_report::TestReportWriter;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::working_dir::WorkingDir;
 use gazebo::prelude::*;
@@ -73,6 +76,9 @@ pub struct TestCommand {
     #[clap(flatten)]
     build_opts: CommonBuildOptions,
 
+    #[clap(flatten)]
+    watch_opts: CommonWatchOptions,
+
     #[clap(
         long = "exclude",
         multiple_values = true,
@@ -147,6 +153,19 @@ If include patterns are present, regardless of whether exclude patterns are pres
     #[clap(long)]
     test_executor_stderr: Option<OutputDestinationArg>,
 
+    /// Writes a JUnit XML report of the test results to the provided path.
+    ///
+    /// The report has one test suite per target, and is written whichever test runner is used.
+    #[clap(long, value_name = "PATH")]
+    junit_xml: Option<PathArg>,
+
+    /// Writes a JSON report of the test results to the provided path.
+    ///
+    /// The report lists every test with its target, status, duration, details and number of
+    /// attempts.
+    #[clap(long, value_name = "PATH")]
+    json_report: Option<PathArg>,
+
     #[clap(
         name = "TEST_EXECUTOR_ARGS",
         help = "Additional arguments passed to the test executor",
@@ -192,6 +211,7 @@ impl StreamingCommand for TestCommand {
                         force_use_project_relative_paths: self.unstable_allow_all_tests_on_re,
                         force_run_from_project_root: self.unstable_allow_all_tests_on_re,
                     }),
+                    watch: self.watch_opts.to_proto(),
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
@@ -291,4 +311,19 @@ impl StreamingCommand for TestCommand {
     fn common_opts(&self) -> &CommonBuildConfigurationOptions {
         &self.common_opts.config_opts
     }
+
+    fn extra_subscribers(
+        &self,
+        ctx: &ClientCommandContext,
+    ) -> anyhow::Result<Vec<Box<dyn EventSubscriber>>> {
+        if self.junit_xml.is_none() && self.json_report.is_none() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Box::new(TestReportWriter::new(
+            self.junit_xml.as_ref().map(|p| p.resolve(&ctx.working_dir)),
+            self.json_report
+                .as_ref()
+                .map(|p| p.resolve(&ctx.working_dir)),
+        ))])
+    }
 }
@@ -57,6 +57,13 @@ pub struct UqueryCommand {
 
     #[clap(flatten)]
     query_common: CommonQueryArgs,
+
+    /// Print targets as they are found instead of once the query has finished. A top-level
+    /// `deps()` or `rdeps()` is evaluated incrementally, so results appear while the graph is
+    /// still loading, in no particular order. With `--json`, each target is printed as a separate
+    /// JSON value on its own line.
+    #[clap(long)]
+    streaming: bool,
 }
 
 #[async_trait]
@@ -69,7 +76,7 @@ impl StreamingCommand for UqueryCommand {
         matches: &clap::ArgMatches,
         ctx: &mut ClientCommandContext<'_>,
     ) -> ExitResult {
-        let (query, query_args) = self.query_common.get_query();
+        let (query, query_args) = self.query_common.get_query()?;
         let unstable_output_format = self.query_common.output_format() as i32;
         let output_attributes = self.query_common.attributes.get()?;
         let context = ctx.client_context(
@@ -88,6 +95,7 @@ impl StreamingCommand for UqueryCommand {
                     output_attributes,
                     unstable_output_format,
                     target_call_stacks: self.query_common.target_call_stacks,
+                    streaming: self.streaming,
                 },
                 ctx.stdin()
                     .console_interaction_stream(&self.common_opts.console_opts),
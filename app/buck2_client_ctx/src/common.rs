@@ -428,6 +428,35 @@ impl CommonBuildOptions {
     }
 }
 
+/// Defines the options for running commands again when files change (build, test, run).
+#[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize, Default)]
+pub struct CommonWatchOptions {
+    /// Keep running, and run again every time files that aren't ignored change. Stop with Ctrl-C.
+    #[clap(long)]
+    pub watch: bool,
+
+    /// How long to wait for more changes once files have changed, before running again.
+    #[clap(
+        long,
+        value_name = "MILLISECONDS",
+        default_value = "200",
+        requires = "watch"
+    )]
+    pub watch_debounce_ms: u64,
+}
+
+impl CommonWatchOptions {
+    pub fn to_proto(&self) -> Option<buck2_cli_proto::WatchOptions> {
+        if self.watch {
+            Some(buck2_cli_proto::WatchOptions {
+                debounce_ms: self.watch_debounce_ms,
+            })
+        } else {
+            None
+        }
+    }
+}
+
 /// Defines common console options for commands.
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize)]
 pub struct CommonConsoleOptions {
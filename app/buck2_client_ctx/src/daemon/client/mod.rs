@@ -518,6 +518,12 @@ impl<'a> FlushingBuckdClient<'a> {
         GenericResponse,
         NoPartialResult
     );
+    stream_method!(
+        wait_for_changes,
+        WaitForChangesRequest,
+        GenericResponse,
+        NoPartialResult
+    );
     stream_method!(
         unstable_docs,
         UnstableDocsRequest,
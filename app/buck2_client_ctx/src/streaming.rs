@@ -88,7 +88,7 @@ fn default_subscribers<T: StreamingCommand>(
     )? {
         subscribers.push(recorder);
     }
-    subscribers.extend(cmd.extra_subscribers());
+    subscribers.extend(cmd.extra_subscribers(ctx)?);
     Ok(subscribers)
 }
 
@@ -137,8 +137,11 @@ pub trait StreamingCommand: Sized + Send + Sync {
 
     fn common_opts(&self) -> &CommonBuildConfigurationOptions;
 
-    fn extra_subscribers(&self) -> Vec<Box<dyn EventSubscriber>> {
-        vec![]
+    fn extra_subscribers(
+        &self,
+        _ctx: &ClientCommandContext,
+    ) -> anyhow::Result<Vec<Box<dyn EventSubscriber>>> {
+        Ok(vec![])
     }
 
     fn sanitized_argv(&self) -> Vec<String> {
@@ -125,6 +125,9 @@ fn eprint_command_details(
                 remote_command.action_digest
             )?;
         }
+        Some(Command::LocalActionCacheHit(hit)) => {
+            echo!("Local action cache hit: {}", hit.action_digest)?;
+        }
         Some(Command::OmittedLocalCommand(..)) | None => {
             // Nothing to show in this case.
         }
@@ -560,6 +563,18 @@ where
         Ok(())
     }
 
+    async fn handle_watch_iteration_end(
+        &mut self,
+        end: &buck2_data::WatchIterationEnd,
+        _event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        for line in display::format_watch_iteration_end(end)? {
+            echo!("{}", line.to_unstyled())?;
+        }
+        self.notify_printed();
+        Ok(())
+    }
+
     async fn tick(&mut self, _: &Tick) -> anyhow::Result<()> {
         self.detect_hangs().await?;
         if self.verbosity.print_status() && self.last_print_time.elapsed() > KEEPALIVE_TIME_LIMIT {
@@ -710,6 +710,25 @@ impl UnpackingEventSubscriber for StatefulSuperConsole {
         Ok(())
     }
 
+    async fn handle_watch_iteration_end(
+        &mut self,
+        end: &buck2_data::WatchIterationEnd,
+        event: &BuckEvent,
+    ) -> anyhow::Result<()> {
+        match &mut self.super_console {
+            Some(super_console) => {
+                super_console.emit(display::format_watch_iteration_end(end)?);
+                Ok(())
+            }
+            None => {
+                self.state
+                    .simple_console
+                    .handle_watch_iteration_end(end, event)
+                    .await
+            }
+        }
+    }
+
     async fn handle_console_preferences(
         &mut self,
         prefs: &buck2_data::ConsolePreferences,
@@ -785,7 +804,7 @@ fn lines_for_command_details(
                 )]));
             }
         }
-        Some(Command::OmittedLocalCommand(..)) | None => {
+        Some(Command::OmittedLocalCommand(..)) | Some(Command::LocalActionCacheHit(..)) | None => {
             // Nothing to show in this case.
         }
     };
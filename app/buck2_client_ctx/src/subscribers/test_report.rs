@@ -0,0 +1,414 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Reports of the results of `buck2 test`, independent of the test runner.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use buck2_common::convert::ProstDurationExt;
+use buck2_core::fs::paths::abs_path::AbsPathBuf;
+use buck2_event_observer::display;
+use buck2_event_observer::display::TargetDisplayOptions;
+use buck2_events::BuckEvent;
+use buck2_test_api::data::TestStatus;
+use serde::Serialize;
+use serde::Serializer;
+
+use crate::subscribers::subscriber::EventSubscriber;
+
+/// Collects the results of every test and writes them as JUnit XML and JSON when the command
+/// ends. In `--watch` mode the reports are rewritten after every run.
+pub struct TestReportWriter {
+    junit_xml: Option<AbsPathBuf>,
+    json: Option<AbsPathBuf>,
+    report: TestReport,
+}
+
+impl TestReportWriter {
+    pub fn new(junit_xml: Option<AbsPathBuf>, json: Option<AbsPathBuf>) -> Self {
+        Self {
+            junit_xml,
+            json,
+            report: TestReport::default(),
+        }
+    }
+
+    async fn write(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.junit_xml {
+            tokio::fs::write(path, self.report.to_junit_xml()).await?;
+        }
+        if let Some(path) = &self.json {
+            tokio::fs::write(path, serde_json::to_vec_pretty(&self.report)?).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for TestReportWriter {
+    async fn handle_events(&mut self, events: &[Arc<BuckEvent>]) -> anyhow::Result<()> {
+        for event in events {
+            if event.command_start()?.is_some() {
+                self.report = TestReport::default();
+                continue;
+            }
+            match event.data() {
+                buck2_data::buck_event::Data::Instant(instant) => {
+                    if let Some(buck2_data::instant_event::Data::TestResult(result)) = &instant.data
+                    {
+                        self.report.ingest(result)?;
+                    }
+                }
+                buck2_data::buck_event::Data::SpanEnd(end) => {
+                    if let Some(buck2_data::span_end_event::Data::Command(..)) = &end.data {
+                        self.write().await?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize)]
+struct TestReport {
+    tests: Vec<TestCaseReport>,
+    #[serde(skip)]
+    index: HashMap<(String, String, String), usize>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct TestCaseReport {
+    target: String,
+    configuration: String,
+    name: String,
+    #[serde(serialize_with = "serialize_status")]
+    status: TestStatus,
+    duration_secs: Option<f64>,
+    msg: Option<String>,
+    details: String,
+    /// How many times the test ran, including reruns.
+    attempts: u32,
+}
+
+impl TestReport {
+    /// Records a result. Results for a test that already ran are retries: the latest final status
+    /// wins, and `RERUN` results only count as attempts.
+    fn ingest(&mut self, result: &buck2_data::TestResult) -> anyhow::Result<()> {
+        let status = TestStatus::try_from(result.status)?;
+        let is_rerun = status == TestStatus::RERUN;
+        let (target, configuration) = match &result.target_label {
+            Some(label) => (
+                display::display_configured_target_label(
+                    label,
+                    TargetDisplayOptions::for_console(false),
+                )?,
+                label
+                    .configuration
+                    .as_ref()
+                    .map(|c| c.full_name.clone())
+                    .unwrap_or_default(),
+            ),
+            None => (String::new(), String::new()),
+        };
+        let case = TestCaseReport {
+            target,
+            configuration,
+            name: result.name.clone(),
+            status,
+            duration_secs: result
+                .duration
+                .as_ref()
+                .and_then(|d| d.try_into_duration().ok())
+                .map(|d| d.as_secs_f64()),
+            msg: result.msg.as_ref().map(|m| m.msg.clone()),
+            details: result.details.clone(),
+            attempts: 1,
+        };
+
+        let key = (
+            case.target.clone(),
+            case.configuration.clone(),
+            case.name.clone(),
+        );
+        match self.index.get(&key) {
+            Some(&i) => {
+                let existing = &mut self.tests[i];
+                let attempts = existing.attempts + 1;
+                if !is_rerun || existing.status == TestStatus::RERUN {
+                    *existing = case;
+                }
+                existing.attempts = attempts;
+            }
+            None => {
+                self.index.insert(key, self.tests.len());
+                self.tests.push(case);
+            }
+        }
+        Ok(())
+    }
+
+    /// One test suite per target. Listings that succeeded are not tests, so they are left out.
+    fn to_junit_xml(&self) -> String {
+        let mut suites: Vec<(&str, Vec<&TestCaseReport>)> = Vec::new();
+        for case in self
+            .tests
+            .iter()
+            .filter(|c| c.status != TestStatus::LISTING_SUCCESS)
+        {
+            match suites.iter_mut().find(|(target, _)| *target == case.target) {
+                Some((_, cases)) => cases.push(case),
+                None => suites.push((&case.target, vec![case])),
+            }
+        }
+
+        let mut totals = JUnitCounts::default();
+        let mut body = String::new();
+        for (target, cases) in &suites {
+            let counts = JUnitCounts::of(cases.iter().copied());
+            totals.add(&counts);
+            writeln!(
+                body,
+                "  <testsuite name=\"{}\"{}>",
+                xml_escape(target),
+                counts.attributes()
+            )
+            .unwrap();
+            for case in cases {
+                write_junit_case(&mut body, case);
+            }
+            body.push_str("  </testsuite>\n");
+        }
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites{}>\n{}</testsuites>\n",
+            totals.attributes(),
+            body
+        )
+    }
+}
+
+enum JUnitOutcome {
+    Passed,
+    Failure,
+    Error,
+    Skipped,
+}
+
+impl JUnitOutcome {
+    fn of(case: &TestCaseReport) -> Self {
+        match case.status {
+            TestStatus::PASS | TestStatus::LISTING_SUCCESS => Self::Passed,
+            TestStatus::FAIL | TestStatus::TIMEOUT | TestStatus::RERUN => Self::Failure,
+            TestStatus::SKIP | TestStatus::OMITTED => Self::Skipped,
+            TestStatus::FATAL | TestStatus::UNKNOWN | TestStatus::LISTING_FAILED => Self::Error,
+        }
+    }
+}
+
+#[derive(Default)]
+struct JUnitCounts {
+    tests: u64,
+    failures: u64,
+    errors: u64,
+    skipped: u64,
+    time: f64,
+}
+
+impl JUnitCounts {
+    fn of<'a>(cases: impl IntoIterator<Item = &'a TestCaseReport>) -> Self {
+        let mut counts = Self::default();
+        for case in cases {
+            counts.tests += 1;
+            counts.time += case.duration_secs.unwrap_or_default();
+            match JUnitOutcome::of(case) {
+                JUnitOutcome::Passed => {}
+                JUnitOutcome::Failure => counts.failures += 1,
+                JUnitOutcome::Error => counts.errors += 1,
+                JUnitOutcome::Skipped => counts.skipped += 1,
+            }
+        }
+        counts
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.tests += other.tests;
+        self.failures += other.failures;
+        self.errors += other.errors;
+        self.skipped += other.skipped;
+        self.time += other.time;
+    }
+
+    fn attributes(&self) -> String {
+        format!(
+            " tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+            self.tests, self.failures, self.errors, self.skipped, self.time
+        )
+    }
+}
+
+fn write_junit_case(out: &mut String, case: &TestCaseReport) {
+    write!(
+        out,
+        "    <testcase classname=\"{}\" name=\"{}\"",
+        xml_escape(&case.target),
+        xml_escape(&case.name)
+    )
+    .unwrap();
+    if let Some(duration) = case.duration_secs {
+        write!(out, " time=\"{:.3}\"", duration).unwrap();
+    }
+    out.push_str(">\n");
+
+    if case.attempts > 1 {
+        writeln!(
+            out,
+            "      <properties><property name=\"attempts\" value=\"{}\"/></properties>",
+            case.attempts
+        )
+        .unwrap();
+    }
+
+    let status = status_name(&case.status);
+    let message = xml_escape(case.msg.as_deref().unwrap_or(&status));
+    let details = xml_escape(&case.details);
+    match JUnitOutcome::of(case) {
+        JUnitOutcome::Passed => {
+            if !case.details.is_empty() {
+                writeln!(out, "      <system-out>{}</system-out>", details).unwrap();
+            }
+        }
+        JUnitOutcome::Failure => writeln!(
+            out,
+            "      <failure message=\"{}\" type=\"{}\">{}</failure>",
+            message, status, details
+        )
+        .unwrap(),
+        JUnitOutcome::Error => writeln!(
+            out,
+            "      <error message=\"{}\" type=\"{}\">{}</error>",
+            message, status, details
+        )
+        .unwrap(),
+        JUnitOutcome::Skipped => writeln!(out, "      <skipped message=\"{}\"/>", message).unwrap(),
+    }
+    out.push_str("    </testcase>\n");
+}
+
+/// The name of a status in the reports, e.g. `PASS`.
+fn status_name(status: &TestStatus) -> String {
+    format!("{:?}", status)
+}
+
+fn serialize_status<S: Serializer>(status: &TestStatus, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&status_name(status))
+}
+
+/// Escapes text for use in XML content and attributes, dropping characters XML can't represent.
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use dupe::Dupe;
+
+    use super::*;
+
+    fn result(name: &str, status: buck2_data::TestStatus, details: &str) -> buck2_data::TestResult {
+        buck2_data::TestResult {
+            name: name.to_owned(),
+            status: status as i32,
+            msg: None,
+            duration: Some(prost_types::Duration {
+                seconds: 1,
+                nanos: 500_000_000,
+            }),
+            details: details.to_owned(),
+            target_label: Some(buck2_data::ConfiguredTargetLabel {
+                label: Some(buck2_data::TargetLabel {
+                    package: "root//foo".to_owned(),
+                    name: "bar".to_owned(),
+                }),
+                configuration: Some(buck2_data::Configuration {
+                    full_name: "cfg".to_owned(),
+                }),
+                execution_configuration: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_ingest_retries() -> anyhow::Result<()> {
+        let mut report = TestReport::default();
+        report.ingest(&result("a", buck2_data::TestStatus::Rerun, "first"))?;
+        report.ingest(&result("a", buck2_data::TestStatus::Pass, ""))?;
+        report.ingest(&result("b", buck2_data::TestStatus::Fail, "boom"))?;
+        report.ingest(&result("b", buck2_data::TestStatus::Rerun, "second"))?;
+
+        let summary: Vec<_> = report
+            .tests
+            .iter()
+            .map(|c| (c.name.as_str(), c.status.dupe(), c.attempts))
+            .collect();
+        assert_eq!(
+            vec![("a", TestStatus::PASS, 2), ("b", TestStatus::FAIL, 2)],
+            summary
+        );
+        assert_eq!("root//foo:bar", report.tests[0].target);
+        assert_eq!("cfg", report.tests[0].configuration);
+        assert_eq!("PASS", serde_json::to_value(&report.tests[0])?["status"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_junit_xml() -> anyhow::Result<()> {
+        let mut report = TestReport::default();
+        report.ingest(&result("ok", buck2_data::TestStatus::Pass, ""))?;
+        report.ingest(&result("bad", buck2_data::TestStatus::Fail, "1 < 2"))?;
+        report.ingest(&result("skip", buck2_data::TestStatus::Skip, ""))?;
+        report.ingest(&result("list", buck2_data::TestStatus::ListingSuccess, ""))?;
+
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="3" failures="1" errors="0" skipped="1" time="4.500">
+  <testsuite name="root//foo:bar" tests="3" failures="1" errors="0" skipped="1" time="4.500">
+    <testcase classname="root//foo:bar" name="ok" time="1.500">
+    </testcase>
+    <testcase classname="root//foo:bar" name="bad" time="1.500">
+      <failure message="FAIL" type="FAIL">1 &lt; 2</failure>
+    </testcase>
+    <testcase classname="root//foo:bar" name="skip" time="1.500">
+      <skipped message="SKIP"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#,
+            report.to_junit_xml()
+        );
+        Ok(())
+    }
+}
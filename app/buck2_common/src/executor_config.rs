@@ -20,8 +20,25 @@ use internment_tweaks::Intern;
 use internment_tweaks::StaticInterner;
 use once_cell::sync::Lazy;
 
-#[derive(Debug, Eq, Hash, PartialEq, Clone, Dupe, Allocative)]
-pub struct LocalExecutorOptions {}
+#[derive(Debug, Default, Eq, Hash, PartialEq, Clone, Dupe, Allocative)]
+pub struct LocalExecutorOptions {
+    /// If set, run local actions in a sandbox where only the directories of their declared inputs,
+    /// their outputs (and the paths listed in the sandbox options) are visible.
+    pub sandbox: Option<Arc<LocalSandboxOptions>>,
+}
+
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Allocative)]
+pub struct LocalSandboxOptions {
+    /// Absolute paths (typically toolchains and system libraries) that actions can read in
+    /// addition to their inputs.
+    pub toolchain_paths: Vec<String>,
+}
+
+impl LocalSandboxOptions {
+    /// The paths that are visible in the sandbox when the execution platform doesn't list any.
+    pub const DEFAULT_TOOLCHAIN_PATHS: &'static [&'static str] =
+        &["/bin", "/etc", "/lib", "/lib64", "/sbin", "/usr"];
+}
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Dupe, Display, Allocative)]
 pub struct RemoteExecutorUseCase(Intern<String>);
@@ -184,7 +201,7 @@ pub enum HybridExecutionLevel {
 impl CommandExecutorConfig {
     pub fn testing_local() -> Arc<CommandExecutorConfig> {
         Arc::new(CommandExecutorConfig {
-            executor: Executor::Local(LocalExecutorOptions {}),
+            executor: Executor::Local(LocalExecutorOptions::default()),
             options: CommandGenerationOptions {
                 path_separator: PathSeparatorKind::system_default(),
                 output_paths_behavior: Default::default(),
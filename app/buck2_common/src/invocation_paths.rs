@@ -72,17 +72,32 @@ pub struct InvocationPaths {
 
 impl InvocationPaths {
     pub fn daemon_dir(&self) -> anyhow::Result<DaemonDir> {
+        Ok(DaemonDir {
+            path: self.home_buck_project_dir("buckd")?,
+        })
+    }
+
+    /// The local action cache. This is outside of buck-out and of the daemon dir, so it survives
+    /// `buck2 clean`.
+    pub fn local_action_cache_dir(&self) -> anyhow::Result<AbsNormPathBuf> {
+        self.home_buck_project_dir("local_action_cache")
+    }
+
+    /// `$HOME/.buck/<prefix>/<projectroot>/<isolationdir>`.
+    fn home_buck_project_dir(&self, prefix: &str) -> anyhow::Result<AbsNormPathBuf> {
         #[cfg(windows)]
         let root_relative: Cow<ForwardRelativePath> = {
             use buck2_core::fs::paths::forward_rel_path::ForwardRelativePathNormalizer;
 
             // Get drive letter, network share name, etc.
             // Network share contains '\' therefore it needs to be normalized.
-            let prefix = self.roots.project_root.root().windows_prefix()?;
+            let windows_prefix = self.roots.project_root.root().windows_prefix()?;
             let stripped_path = ForwardRelativePathNormalizer::normalize_path(
                 self.roots.project_root.root().strip_windows_prefix()?,
             )?;
-            Cow::Owned(ForwardRelativePathNormalizer::normalize_path(&prefix)?.join(stripped_path))
+            Cow::Owned(
+                ForwardRelativePathNormalizer::normalize_path(&windows_prefix)?.join(stripped_path),
+            )
         };
         #[cfg(not(windows))]
         let root_relative: Cow<ForwardRelativePath> = self
@@ -107,8 +122,6 @@ impl InvocationPaths {
         // output directories between different buckd instances.
         let home_buck_dir = home_buck_dir()?;
 
-        let prefix = "buckd";
-
         let mut ret = AbsNormPathBuf::with_capacity(
             home_buck_dir.as_os_str().len()
                 + 1
@@ -124,7 +137,7 @@ impl InvocationPaths {
         ret.push(root_relative.as_ref());
         ret.push(&self.isolation);
 
-        Ok(DaemonDir { path: ret })
+        Ok(ret)
     }
 
     pub fn cell_root(&self) -> &AbsNormPath {
@@ -257,6 +270,22 @@ mod tests {
             .as_os_str()
         );
 
+        let expected_path = if cfg!(windows) {
+            ".buck\\local_action_cache\\C\\my\\project\\isolation"
+        } else {
+            ".buck/local_action_cache/my/project/isolation"
+        };
+        assert_eq!(
+            paths.local_action_cache_dir().unwrap().as_os_str(),
+            AbsNormPathBuf::try_from(
+                dirs::home_dir().expect("Expected a HOME directory to be available")
+            )
+            .expect("Expected an absolute HOME directory")
+            .join_normalized(ForwardRelativePath::unchecked_new(expected_path))
+            .unwrap()
+            .as_os_str()
+        );
+
         let expected_path = if cfg!(windows) {
             "C:\\my\\project\\root\\cell"
         } else {
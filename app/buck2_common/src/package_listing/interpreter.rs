@@ -33,7 +33,7 @@ use crate::package_listing::resolver::PackageListingResolver;
 use crate::result::SharedResult;
 
 #[derive(Debug, Error)]
-enum PackageListingError {
+pub(crate) enum PackageListingError {
     #[error("Expected `{0}` to be a package directory, but there was no buildfile there, expected one of `{}`", .1.join("`, `"))]
     NoBuildFile(CellPath, Vec<FileNameBuf>),
     #[error("Expected `{0}` to be within a package directory, but there was no buildfile in any parent directories. Expected one of `{}`", .1.join("`, `"))]
@@ -11,6 +11,7 @@ use async_trait::async_trait;
 use buck2_core::cells::cell_path::CellPathRef;
 use buck2_core::package::PackageLabel;
 
+use crate::package_listing::interpreter::PackageListingError;
 use crate::package_listing::listing::PackageListing;
 use crate::result::SharedResult;
 
@@ -29,3 +30,40 @@ pub trait PackageListingResolver: Send + Sync {
         enclosing_path: CellPathRef<'async_trait>,
     ) -> anyhow::Result<Vec<PackageLabel>>;
 }
+
+/// Whether `error` is the one returned by `get_enclosing_package(s)` when the path is not within
+/// any package (as opposed to e.g. an IO error while looking for buildfiles).
+pub fn is_no_containing_package(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<PackageListingError>(),
+        Some(PackageListingError::NoContainingPackage(..))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::cells::cell_path::CellPath;
+    use buck2_core::cells::name::CellName;
+    use buck2_core::cells::paths::CellRelativePathBuf;
+
+    use crate::package_listing::interpreter::PackageListingError;
+    use crate::package_listing::resolver::is_no_containing_package;
+
+    #[test]
+    fn test_is_no_containing_package() {
+        let path = CellPath::new(
+            CellName::testing_new("cell"),
+            CellRelativePathBuf::unchecked_new("foo/bar".to_owned()),
+        );
+        let err = anyhow::Error::from(PackageListingError::NoContainingPackage(
+            path.clone(),
+            Vec::new(),
+        ));
+        assert!(is_no_containing_package(&err));
+        assert!(is_no_containing_package(&err.context("Resolving owner")));
+
+        let err = anyhow::Error::from(PackageListingError::NoBuildFile(path, Vec::new()));
+        assert!(!is_no_containing_package(&err));
+        assert!(!is_no_containing_package(&anyhow::anyhow!("IO error")));
+    }
+}
@@ -16,6 +16,7 @@ use parking_lot::Mutex;
 use rusqlite::Connection;
 
 /// A generic sqlite table for storing string key-value pairs.
+#[derive(Clone)]
 pub struct KeyValueSqliteTable {
     table_name: String,
     connection: Arc<Mutex<Connection>>,
@@ -65,6 +66,41 @@ impl KeyValueSqliteTable {
         Ok(())
     }
 
+    pub fn insert(&self, key: &str, value: &str) -> anyhow::Result<()> {
+        let sql = format!(
+            "INSERT OR REPLACE INTO {} (key, value) VALUES (?, ?)",
+            self.table_name
+        );
+        tracing::trace!(sql = %sql, key = %key, "inserting into table");
+        self.connection
+            .lock()
+            .execute(&sql, [key, value])
+            .with_context(|| {
+                format!("inserting `{}` into sqlite table {}", key, self.table_name)
+            })?;
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let sql = format!("DELETE FROM {} WHERE key = ?", self.table_name);
+        tracing::trace!(sql = %sql, key = %key, "deleting from table");
+        self.connection
+            .lock()
+            .execute(&sql, [key])
+            .with_context(|| format!("deleting `{}` from sqlite table {}", key, self.table_name))?;
+        Ok(())
+    }
+
+    pub fn delete_all(&self) -> anyhow::Result<()> {
+        let sql = format!("DELETE FROM {}", self.table_name);
+        tracing::trace!(sql = %sql, "deleting all from table");
+        self.connection
+            .lock()
+            .execute(&sql, [])
+            .with_context(|| format!("deleting from sqlite table {}", self.table_name))?;
+        Ok(())
+    }
+
     pub fn read_all(&self) -> anyhow::Result<HashMap<String, String>> {
         let sql = format!("SELECT key, value FROM {}", self.table_name);
         tracing::trace!(sql = %sql, "read all from table");
@@ -91,6 +127,101 @@ impl KeyValueSqliteTable {
     }
 }
 
+/// A sqlite table for storing string key-value pairs along with a position, for callers that need
+/// to order the rows (e.g. to evict the least recently used ones).
+#[derive(Clone)]
+pub struct OrderedKeyValueSqliteTable {
+    table_name: String,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl OrderedKeyValueSqliteTable {
+    pub fn new(table_name: String, connection: Arc<Mutex<Connection>>) -> Self {
+        Self {
+            table_name,
+            connection,
+        }
+    }
+
+    pub fn create_table(&self) -> anyhow::Result<()> {
+        let sql = format!(
+            "CREATE TABLE {} (
+                key         TEXT PRIMARY KEY NOT NULL,
+                value       TEXT NOT NULL,
+                position    INTEGER NOT NULL
+            )",
+            self.table_name
+        );
+        tracing::trace!(sql = %sql, "creating table");
+        self.connection
+            .lock()
+            .execute(&sql, [])
+            .with_context(|| format!("creating sqlite table {}", self.table_name))?;
+        Ok(())
+    }
+
+    pub fn insert(&self, key: &str, value: &str, position: i64) -> anyhow::Result<()> {
+        let sql = format!(
+            "INSERT OR REPLACE INTO {} (key, value, position) VALUES (?, ?, ?)",
+            self.table_name
+        );
+        tracing::trace!(sql = %sql, key = %key, position = %position, "inserting into table");
+        self.connection
+            .lock()
+            .execute(&sql, rusqlite::params![key, value, position])
+            .with_context(|| {
+                format!("inserting `{}` into sqlite table {}", key, self.table_name)
+            })?;
+        Ok(())
+    }
+
+    pub fn set_position(&self, key: &str, position: i64) -> anyhow::Result<()> {
+        let sql = format!("UPDATE {} SET position = ? WHERE key = ?", self.table_name);
+        tracing::trace!(sql = %sql, key = %key, position = %position, "updating table");
+        self.connection
+            .lock()
+            .execute(&sql, rusqlite::params![position, key])
+            .with_context(|| format!("updating `{}` in sqlite table {}", key, self.table_name))?;
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let sql = format!("DELETE FROM {} WHERE key = ?", self.table_name);
+        tracing::trace!(sql = %sql, key = %key, "deleting from table");
+        self.connection
+            .lock()
+            .execute(&sql, [key])
+            .with_context(|| format!("deleting `{}` from sqlite table {}", key, self.table_name))?;
+        Ok(())
+    }
+
+    pub fn delete_all(&self) -> anyhow::Result<()> {
+        let sql = format!("DELETE FROM {}", self.table_name);
+        tracing::trace!(sql = %sql, "deleting all from table");
+        self.connection
+            .lock()
+            .execute(&sql, [])
+            .with_context(|| format!("deleting from sqlite table {}", self.table_name))?;
+        Ok(())
+    }
+
+    /// Read all the rows, as `(key, value, position)`, ordered by position.
+    pub fn read_all(&self) -> anyhow::Result<Vec<(String, String, i64)>> {
+        let sql = format!(
+            "SELECT key, value, position FROM {} ORDER BY position",
+            self.table_name
+        );
+        tracing::trace!(sql = %sql, "read all from table");
+        let connection = self.connection.lock();
+        let mut stmt = connection.prepare(&sql)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("reading from sqlite table {}", self.table_name))?;
+        Ok(rows)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -124,5 +255,56 @@ mod tests {
 
         assert_eq!(table.get("foo").unwrap().as_deref(), Some("foo"));
         assert_eq!(table.get("baz").unwrap(), None);
+
+        table.insert("foo", "foo2").unwrap();
+        table.insert("baz", "baz").unwrap();
+        assert_eq!(table.get("foo").unwrap().as_deref(), Some("foo2"));
+        assert_eq!(table.get("baz").unwrap().as_deref(), Some("baz"));
+
+        table.delete("foo").unwrap();
+        assert_eq!(table.get("foo").unwrap(), None);
+        assert_eq!(table.read_all().unwrap().len(), 2);
+
+        table.delete_all().unwrap();
+        assert!(table.read_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ordered_key_value_sqlite_table() {
+        let fs = ProjectRootTemp::new().unwrap();
+        let connection = Connection::open(
+            fs.path()
+                .resolve(ProjectRelativePath::unchecked_new("test.db")),
+        )
+        .unwrap();
+        let table =
+            OrderedKeyValueSqliteTable::new("ordered".to_owned(), Arc::new(Mutex::new(connection)));
+
+        table.create_table().unwrap();
+
+        table.insert("foo", "foo", 1).unwrap();
+        table.insert("bar", "bar", 0).unwrap();
+        table.insert("baz", "baz", 2).unwrap();
+        assert_eq!(
+            table.read_all().unwrap(),
+            vec![
+                ("bar".to_owned(), "bar".to_owned(), 0),
+                ("foo".to_owned(), "foo".to_owned(), 1),
+                ("baz".to_owned(), "baz".to_owned(), 2),
+            ]
+        );
+
+        table.set_position("bar", 3).unwrap();
+        table.delete("foo").unwrap();
+        assert_eq!(
+            table.read_all().unwrap(),
+            vec![
+                ("baz".to_owned(), "baz".to_owned(), 2),
+                ("bar".to_owned(), "bar".to_owned(), 3),
+            ]
+        );
+
+        table.delete_all().unwrap();
+        assert!(table.read_all().unwrap().is_empty());
     }
 }
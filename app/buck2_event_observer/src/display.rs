@@ -482,6 +482,33 @@ pub fn format_test_result(test_result: &buck2_data::TestResult) -> anyhow::Resul
     Ok(Some(Lines(lines)))
 }
 
+/// Summarizes one run of a command in `--watch` mode.
+pub fn format_watch_iteration_end(end: &buck2_data::WatchIterationEnd) -> anyhow::Result<Lines> {
+    let status = if end.success {
+        Span::new_styled(format!("Iteration {} succeeded", end.iteration).green())
+    } else {
+        Span::new_styled(format!("Iteration {} failed", end.iteration).red())
+    }?;
+    let mut line = Line::from_iter([status]);
+    if let Some(duration) = &end.duration {
+        if let Ok(duration) = Duration::try_from(duration.clone()) {
+            line.push(Span::new_unstyled(format!(
+                " in {}",
+                duration_as_secs_elapsed(duration, 1.0)
+            ))?);
+        }
+    }
+    if let Some(error) = &end.error {
+        line.push(Span::new_unstyled(format!(": {}", error))?);
+    }
+    line.push(Span::new_unstyled(". Waiting for changes...")?);
+    let mut lines = vec![line];
+    for message in &end.error_messages {
+        lines.extend(Lines::from_multiline_string(message, Default::default()).0);
+    }
+    Ok(Lines(lines))
+}
+
 pub struct ActionErrorDisplay<'a> {
     pub action_id: String,
     pub reason: String,
@@ -552,7 +579,9 @@ fn failure_reason_for_command_execution(
 
     let locality = match command.command {
         Some(Command::RemoteCommand(..)) => "Remote ",
-        Some(Command::LocalCommand(..)) | Some(Command::OmittedLocalCommand(..)) => "Local ",
+        Some(Command::LocalCommand(..))
+        | Some(Command::OmittedLocalCommand(..))
+        | Some(Command::LocalActionCacheHit(..)) => "Local ",
         None => "",
     };
 
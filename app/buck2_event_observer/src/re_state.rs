@@ -17,6 +17,15 @@ use crate::humanized_bytes::HumanizedBytes;
 use crate::humanized_bytes::HumanizedBytesPerSecond;
 use crate::two_snapshots::TwoSnapshots;
 
+/// A numeric RE statistic, see `ReState::counters`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReCounter {
+    pub name: String,
+    pub value: u64,
+    /// Whether the value is a number of bytes, otherwise it is a count.
+    pub bytes: bool,
+}
+
 pub struct ReState {
     session_id: Option<String>,
     two_snapshots: TwoSnapshots,
@@ -117,52 +126,64 @@ impl ReState {
     fn render_detailed(&self) -> anyhow::Result<Vec<Line>> {
         let mut r = Vec::new();
         if let Some((_, last)) = &self.two_snapshots.last {
-            r.extend(self.render_detailed_items(
-                "uploads",
-                last.re_uploads_started,
-                last.re_uploads_finished_successfully,
-                last.re_uploads_finished_with_error,
-            )?);
-            r.extend(self.render_detailed_items(
-                "downloads",
-                last.re_downloads_started,
-                last.re_downloads_finished_successfully,
-                last.re_downloads_finished_with_error,
-            )?);
-            r.extend(self.render_detailed_items(
-                "action_cache",
-                last.re_action_cache_started,
-                last.re_action_cache_finished_successfully,
-                last.re_action_cache_finished_with_error,
-            )?);
-            r.extend(self.render_detailed_items(
-                "executes",
-                last.re_executes_started,
-                last.re_executes_finished_successfully,
-                last.re_executes_finished_with_error,
-            )?);
-            r.extend(self.render_detailed_items(
-                "materializes",
-                last.re_materializes_started,
-                last.re_materializes_finished_successfully,
-                last.re_materializes_finished_with_error,
-            )?);
-            r.extend(self.render_detailed_items(
-                "write_action_results",
-                last.re_write_action_results_started,
-                last.re_write_action_results_finished_successfully,
-                last.re_write_action_results_finished_with_error,
-            )?);
-            r.extend(self.render_detailed_items(
-                "get_digest_expirations",
-                last.re_get_digest_expirations_started,
-                last.re_get_digest_expirations_finished_successfully,
-                last.re_get_digest_expirations_finished_with_error,
-            )?);
+            for (name, started, finished_successfully, finished_with_error) in detailed_items(last)
+            {
+                r.extend(self.render_detailed_items(
+                    name,
+                    started,
+                    finished_successfully,
+                    finished_with_error,
+                )?);
+            }
         }
         Ok(r)
     }
 
+    /// The RE statistics of the last snapshot as numeric counters, for exporting as a timeseries.
+    pub fn counters(&self) -> Vec<ReCounter> {
+        let mut r = Vec::new();
+        if let Some((_, last)) = &self.two_snapshots.last {
+            r.push(ReCounter {
+                name: "upload_bytes".to_owned(),
+                value: last.re_upload_bytes,
+                bytes: true,
+            });
+            r.push(ReCounter {
+                name: "download_bytes".to_owned(),
+                value: last.re_download_bytes,
+                bytes: true,
+            });
+            r.push(ReCounter {
+                name: "upload_bytes_per_second".to_owned(),
+                value: self
+                    .two_snapshots
+                    .re_upload_bytes_per_second()
+                    .unwrap_or_default(),
+                bytes: true,
+            });
+            r.push(ReCounter {
+                name: "download_bytes_per_second".to_owned(),
+                value: self
+                    .two_snapshots
+                    .re_download_bytes_per_second()
+                    .unwrap_or_default(),
+                bytes: true,
+            });
+            for (name, started, finished_successfully, finished_with_error) in detailed_items(last)
+            {
+                let in_progress = started
+                    .saturating_sub(finished_successfully)
+                    .saturating_sub(finished_with_error);
+                r.push(ReCounter {
+                    name: format!("{name}_in_progress"),
+                    value: in_progress.into(),
+                    bytes: false,
+                });
+            }
+        }
+        r
+    }
+
     pub fn render(&self, detailed: bool, draw_mode: DrawMode) -> anyhow::Result<Lines> {
         let header = match self.render_header(draw_mode) {
             Some(header) => header,
@@ -175,3 +196,52 @@ impl ReState {
         Ok(Lines(lines))
     }
 }
+
+/// The kinds of RE requests, with the number started, finished successfully and finished with an
+/// error.
+fn detailed_items(last: &buck2_data::Snapshot) -> [(&'static str, u32, u32, u32); 7] {
+    [
+        (
+            "uploads",
+            last.re_uploads_started,
+            last.re_uploads_finished_successfully,
+            last.re_uploads_finished_with_error,
+        ),
+        (
+            "downloads",
+            last.re_downloads_started,
+            last.re_downloads_finished_successfully,
+            last.re_downloads_finished_with_error,
+        ),
+        (
+            "action_cache",
+            last.re_action_cache_started,
+            last.re_action_cache_finished_successfully,
+            last.re_action_cache_finished_with_error,
+        ),
+        (
+            "executes",
+            last.re_executes_started,
+            last.re_executes_finished_successfully,
+            last.re_executes_finished_with_error,
+        ),
+        (
+            "materializes",
+            last.re_materializes_started,
+            last.re_materializes_finished_successfully,
+            last.re_materializes_finished_with_error,
+        ),
+        (
+            "write_action_results",
+            last.re_write_action_results_started,
+            last.re_write_action_results_finished_successfully,
+            last.re_write_action_results_finished_with_error,
+        ),
+        (
+            "get_digest_expirations",
+            last.re_get_digest_expirations_started,
+            last.re_get_digest_expirations_finished_successfully,
+            last.re_get_digest_expirations_finished_with_error,
+        ),
+    ]
+}
@@ -180,7 +180,13 @@ impl<'a> CommandReproducer<'a> {
     pub fn executor(&self) -> String {
         match self {
             Self::CacheQuery(..) => "cache_query".to_owned(),
-            Self::CacheHit(..) => "cache".to_owned(),
+            Self::CacheHit(cache_hit) => {
+                if cache_hit.local {
+                    "local_cache".to_owned()
+                } else {
+                    "cache".to_owned()
+                }
+            }
             Self::ReExecute(execute) => executor_with_platform(execute),
             Self::LocalExecute(..) => "local".to_owned(),
         }
@@ -0,0 +1,1168 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A Sink for publishing events to a Build Event Service (BES), the service Bazel uses to stream
+//! its Build Event Protocol (BEP) to tools such as BuildBuddy or EngFlow.
+//!
+//! Every command is published as a separate invocation, identified by its trace id. Only the
+//! events that have a BEP equivalent are published: the start and end of the command, configured
+//! top-level targets, executed actions and test results.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use buck2_bes_proto::build_event_stream as bep;
+use buck2_bes_proto::google::devtools::build::v1 as bes;
+use buck2_bes_proto::google::devtools::build::v1::publish_build_event_client::PublishBuildEventClient;
+use buck2_wrapper_common::invocation_id::TraceId;
+use dupe::Dupe;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use gazebo::variants::VariantName;
+use prost::Message;
+use tonic::metadata::MetadataMap;
+use tonic::transport::Channel;
+use tonic::transport::ClientTlsConfig;
+use tonic::transport::Endpoint;
+
+use crate::BuckEvent;
+use crate::ControlEvent;
+use crate::EventSink;
+use crate::EventSinkStats;
+
+/// Where and how to publish events.
+#[derive(Debug, Clone, Default)]
+pub struct BesConfig {
+    /// `grpc://`, `grpcs://`, `http://` or `https://` URL of the service.
+    pub endpoint: String,
+    /// Sent with every event, some services use it to pick the project to report to.
+    pub project_id: Option<String>,
+    /// Headers sent with every stream, e.g. for authentication.
+    pub headers: Vec<(String, String)>,
+}
+
+/// How many events of a command can be waiting to be sent before further events are dropped, so
+/// that a slow service can't make the daemon run out of memory. The events that end the stream are
+/// never dropped.
+const MAX_QUEUED_EVENTS: usize = 10000;
+
+#[derive(Default)]
+struct BesCounters {
+    sent: AtomicU64,
+    acknowledged: AtomicU64,
+    failures: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// A stream of events of one command to the service.
+struct InvocationStream {
+    sender: mpsc::UnboundedSender<bes::PublishBuildToolEventStreamRequest>,
+    /// How many events were queued but not yet taken by the publishing task.
+    queued: Arc<AtomicUsize>,
+    translator: BepTranslator,
+    sequence_number: i64,
+    /// Whether the stream was ended, events that arrive after that are ignored.
+    finished: bool,
+}
+
+pub struct BesSink {
+    client: PublishBuildEventClient<Channel>,
+    config: BesConfig,
+    metadata: MetadataMap,
+    runtime: tokio::runtime::Handle,
+    /// The global lock is only held to find a stream, every stream has its own lock so that
+    /// commands don't wait on each other while their events are translated.
+    streams: Mutex<HashMap<TraceId, Arc<Mutex<InvocationStream>>>>,
+    counters: Arc<BesCounters>,
+}
+
+impl BesSink {
+    /// Creates a sink that connects to the service lazily. Must be called within a Tokio runtime,
+    /// which is used to publish the events.
+    pub fn new(config: BesConfig) -> anyhow::Result<BesSink> {
+        let uri = if let Some(rest) = config.endpoint.strip_prefix("grpc://") {
+            format!("http://{}", rest)
+        } else if let Some(rest) = config.endpoint.strip_prefix("grpcs://") {
+            format!("https://{}", rest)
+        } else {
+            config.endpoint.clone()
+        };
+        let mut endpoint = Endpoint::from_shared(uri.clone())
+            .with_context(|| format!("Invalid BES endpoint `{}`", config.endpoint))?;
+        if uri.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+
+        let mut metadata = MetadataMap::new();
+        for (name, value) in &config.headers {
+            metadata.insert(
+                tonic::metadata::MetadataKey::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid BES header name `{}`", name))?,
+                value
+                    .parse()
+                    .with_context(|| format!("Invalid value for BES header `{}`", name))?,
+            );
+        }
+
+        Ok(BesSink {
+            client: PublishBuildEventClient::new(endpoint.connect_lazy()),
+            config,
+            metadata,
+            runtime: tokio::runtime::Handle::try_current()
+                .context("The BES sink must be created within a Tokio runtime")?,
+            streams: Mutex::new(HashMap::new()),
+            counters: Arc::new(BesCounters::default()),
+        })
+    }
+
+    /// Starts publishing a new invocation.
+    fn start_stream(&self, trace_id: TraceId) -> InvocationStream {
+        let (sender, receiver) = mpsc::unbounded();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let receiver = receiver.inspect({
+            let queued = queued.dupe();
+            move |_| {
+                queued.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+        let mut request = tonic::Request::new(receiver);
+        *request.metadata_mut() = self.metadata.clone();
+
+        let mut client = self.client.clone();
+        let counters = self.counters.dupe();
+        self.runtime.spawn(async move {
+            let mut acknowledgements = match client.publish_build_tool_event_stream(request).await {
+                Ok(response) => response.into_inner(),
+                Err(e) => {
+                    tracing::warn!("Error publishing events to BES: {}", e);
+                    counters.failures.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            };
+            loop {
+                match acknowledgements.message().await {
+                    Ok(Some(_)) => {
+                        counters.acknowledged.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::warn!("Error publishing events to BES: {}", e);
+                        counters.failures.fetch_add(1, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            }
+        });
+
+        InvocationStream {
+            sender,
+            queued,
+            translator: BepTranslator::new(trace_id),
+            sequence_number: 0,
+            finished: false,
+        }
+    }
+
+    /// Queues an event to be sent. Events are dropped if too many are queued already, unless they
+    /// are `terminal`: the service needs those to consider the invocation complete.
+    fn publish(
+        &self,
+        stream: &mut InvocationStream,
+        timestamp: SystemTime,
+        event: bes::build_event::Event,
+        terminal: bool,
+    ) {
+        if !terminal && stream.queued.load(Ordering::Relaxed) >= MAX_QUEUED_EVENTS {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        // Dropped events don't use up a sequence number, the service expects them to be contiguous.
+        let sequence_number = stream.sequence_number + 1;
+        let trace_id = stream.translator.trace_id.clone();
+        let request = bes::PublishBuildToolEventStreamRequest {
+            ordered_build_event: Some(bes::OrderedBuildEvent {
+                stream_id: Some(bes::StreamId {
+                    build_id: trace_id.clone(),
+                    component: bes::stream_id::BuildComponent::Tool as i32,
+                    invocation_id: trace_id,
+                }),
+                sequence_number,
+                event: Some(bes::BuildEvent {
+                    event_time: Some(timestamp.into()),
+                    event: Some(event),
+                }),
+            }),
+            notification_keywords: Vec::new(),
+            project_id: self.config.project_id.clone().unwrap_or_default(),
+        };
+        // Counted before sending, the publishing task may take the event right away.
+        stream.queued.fetch_add(1, Ordering::Relaxed);
+        match stream.sender.unbounded_send(request) {
+            Ok(()) => {
+                stream.sequence_number = sequence_number;
+                self.counters.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                stream.queued.fetch_sub(1, Ordering::Relaxed);
+                self.counters.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl EventSink for BesSink {
+    fn send(&self, event: BuckEvent) {
+        let trace_id = match event.trace_id() {
+            Ok(trace_id) => trace_id,
+            Err(_) => return,
+        };
+        let started = if event.command_start().ok().flatten().is_some() {
+            Some(Arc::new(Mutex::new(self.start_stream(trace_id.clone()))))
+        } else {
+            None
+        };
+
+        let stream = {
+            let mut streams = self.streams.lock().unwrap();
+            if let Some(started) = started {
+                streams.insert(trace_id.clone(), started);
+            }
+            match streams.get(&trace_id) {
+                Some(stream) => stream.dupe(),
+                // Not part of a command, or the command already ended.
+                None => return,
+            }
+        };
+        let mut stream = stream.lock().unwrap();
+        if stream.finished {
+            return;
+        }
+
+        let build_events = stream.translator.translate(&event);
+        // Everything that ends the stream is sent, even if the queue is full.
+        let last = build_events.iter().any(|e| e.last_message);
+        for build_event in build_events {
+            self.publish(
+                &mut stream,
+                event.timestamp(),
+                bes::build_event::Event::BazelEvent(prost_types::Any {
+                    type_url: "type.googleapis.com/build_event_stream.BuildEvent".to_owned(),
+                    value: build_event.encode_to_vec(),
+                }),
+                last,
+            );
+        }
+
+        if last {
+            self.publish(
+                &mut stream,
+                event.timestamp(),
+                bes::build_event::Event::ComponentStreamFinished(
+                    bes::build_event::BuildComponentStreamFinished {
+                        r#type:
+                            bes::build_event::build_component_stream_finished::FinishType::Finished
+                                as i32,
+                    },
+                ),
+                true,
+            );
+            stream.finished = true;
+            // Closing the sender ends the stream.
+            stream.sender.close_channel();
+            drop(stream);
+            self.streams.lock().unwrap().remove(&trace_id);
+        }
+    }
+
+    fn send_control(&self, _control_event: ControlEvent) {}
+
+    fn stats(&self) -> Option<EventSinkStats> {
+        let sent = self.counters.sent.load(Ordering::Relaxed);
+        let acknowledged = self.counters.acknowledged.load(Ordering::Relaxed);
+        Some(EventSinkStats {
+            successes: acknowledged,
+            failures: self.counters.failures.load(Ordering::Relaxed),
+            buffered: sent.saturating_sub(acknowledged),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Translates the events of one command to BEP events.
+struct BepTranslator {
+    trace_id: String,
+    /// The test cases of every test target, with how many attempts of each were reported. BEP
+    /// identifies test results by target, shard and attempt, so every test case is reported as a
+    /// shard of its target.
+    test_cases: HashMap<String, Vec<(String, i32)>>,
+    /// The target patterns of the command, only the targets they match are reported as
+    /// configured: BEP consumers expect the top-level targets there, not all their dependencies.
+    patterns: Vec<String>,
+    /// The targets that were reported as configured, BEP reports a target once whatever the number
+    /// of configurations it is analyzed in.
+    configured: HashSet<String>,
+    /// How many progress events were sent. Every event is announced by the progress event sent
+    /// right before it, which also announces the next progress event.
+    progress_count: i32,
+}
+
+impl BepTranslator {
+    fn new(trace_id: TraceId) -> Self {
+        Self {
+            trace_id: trace_id.to_string(),
+            test_cases: HashMap::new(),
+            patterns: Vec::new(),
+            configured: HashSet::new(),
+            progress_count: 0,
+        }
+    }
+
+    fn translate(&mut self, event: &BuckEvent) -> Vec<bep::BuildEvent> {
+        use buck2_data::buck_event::Data;
+
+        let timestamp: prost_types::Timestamp = event.timestamp().into();
+        let build_event = match event.data() {
+            Data::SpanStart(start) => match &start.data {
+                Some(buck2_data::span_start_event::Data::Command(command)) => {
+                    Some(self.build_started(command, timestamp))
+                }
+                _ => None,
+            },
+            Data::SpanEnd(end) => match &end.data {
+                Some(buck2_data::span_end_event::Data::Command(command)) => {
+                    Some(build_finished(command, timestamp))
+                }
+                Some(buck2_data::span_end_event::Data::ActionExecution(action)) => {
+                    action_executed(action, end.duration.as_ref(), event.timestamp())
+                }
+                Some(buck2_data::span_end_event::Data::Analysis(analysis)) => {
+                    self.target_configured(analysis)
+                }
+                _ => None,
+            },
+            Data::Instant(instant) => match &instant.data {
+                Some(buck2_data::instant_event::Data::TestResult(result)) => {
+                    self.test_result(result, timestamp)
+                }
+                Some(buck2_data::instant_event::Data::TargetPatterns(patterns)) => {
+                    self.patterns
+                        .extend(patterns.target_patterns.iter().map(|p| p.value.clone()));
+                    None
+                }
+                _ => None,
+            },
+            Data::Record(_) => None,
+        };
+
+        let build_event = match build_event {
+            Some(build_event) => build_event,
+            None => return Vec::new(),
+        };
+        if let Some(bep::build_event::Payload::Started(..)) = build_event.payload {
+            // Announces itself the first progress event.
+            return vec![build_event];
+        }
+        // The end of the build is announced by its start, so the last progress event announces
+        // nothing.
+        let children = if build_event.last_message {
+            Vec::new()
+        } else {
+            build_event.id.clone().into_iter().collect()
+        };
+        vec![self.progress(children), build_event]
+    }
+
+    /// A progress event announcing `children`, and the next progress event if there are any.
+    fn progress(&mut self, mut children: Vec<bep::BuildEventId>) -> bep::BuildEvent {
+        let id = progress_id(self.progress_count);
+        self.progress_count += 1;
+        if !children.is_empty() {
+            children.push(progress_id(self.progress_count));
+        }
+        bep::BuildEvent {
+            id: Some(id),
+            children,
+            last_message: false,
+            payload: Some(bep::build_event::Payload::Progress(bep::Progress::default())),
+        }
+    }
+
+    fn build_started(
+        &self,
+        command: &buck2_data::CommandStart,
+        timestamp: prost_types::Timestamp,
+    ) -> bep::BuildEvent {
+        bep::BuildEvent {
+            id: Some(bep::BuildEventId {
+                id: Some(bep::build_event_id::Id::Started(
+                    bep::build_event_id::BuildStartedId {},
+                )),
+            }),
+            children: vec![
+                progress_id(self.progress_count),
+                bep::BuildEventId {
+                    id: Some(bep::build_event_id::Id::BuildFinished(
+                        bep::build_event_id::BuildFinishedId {},
+                    )),
+                },
+            ],
+            last_message: false,
+            payload: Some(bep::build_event::Payload::Started(bep::BuildStarted {
+                uuid: self.trace_id.clone(),
+                build_tool_version: buck2_build_info::revision().unwrap_or_default().to_owned(),
+                command: command
+                    .data
+                    .as_ref()
+                    .map_or("", |d| d.variant_name())
+                    .to_lowercase(),
+                server_pid: std::process::id().into(),
+                start_time: Some(timestamp),
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn test_result(
+        &mut self,
+        result: &buck2_data::TestResult,
+        timestamp: prost_types::Timestamp,
+    ) -> Option<bep::BuildEvent> {
+        use buck2_data::TestStatus;
+
+        let (label, configuration) = configured_label(result.target_label.as_ref()?)?;
+        let cases = self.test_cases.entry(label.clone()).or_default();
+        let shard = match cases.iter().position(|(name, _)| *name == result.name) {
+            Some(i) => i,
+            None => {
+                cases.push((result.name.clone(), 0));
+                cases.len() - 1
+            }
+        };
+        let attempt = &mut cases[shard].1;
+        *attempt += 1;
+
+        let status = match TestStatus::from_i32(result.status)? {
+            TestStatus::Pass | TestStatus::ListingSuccess => bep::TestStatus::Passed,
+            TestStatus::Fail | TestStatus::ListingFailed => bep::TestStatus::Failed,
+            TestStatus::Timeout => bep::TestStatus::Timeout,
+            TestStatus::Fatal => bep::TestStatus::Incomplete,
+            // A failed attempt of a test that is run again, the next attempt has the final status.
+            TestStatus::Rerun => bep::TestStatus::Failed,
+            TestStatus::Skip
+            | TestStatus::Omitted
+            | TestStatus::Unknown
+            | TestStatus::NotSetTestStatus => bep::TestStatus::NoStatus,
+        };
+        let status_details = match &result.msg {
+            Some(msg) => format!("{}: {}", result.name, msg.msg),
+            None => result.name.clone(),
+        };
+
+        Some(bep::BuildEvent {
+            id: Some(bep::BuildEventId {
+                id: Some(bep::build_event_id::Id::TestResult(
+                    bep::build_event_id::TestResultId {
+                        label,
+                        run: 1,
+                        shard: shard as i32 + 1,
+                        attempt: *attempt,
+                        configuration: Some(bep::build_event_id::ConfigurationId {
+                            id: configuration,
+                        }),
+                    },
+                )),
+            }),
+            children: Vec::new(),
+            last_message: false,
+            payload: Some(bep::build_event::Payload::TestResult(bep::TestResult {
+                status: status as i32,
+                status_details,
+                test_attempt_start: Some(timestamp),
+                test_attempt_duration: result.duration.clone(),
+            })),
+        })
+    }
+
+    fn target_configured(&mut self, analysis: &buck2_data::AnalysisEnd) -> Option<bep::BuildEvent> {
+        let target = match analysis.target.as_ref()? {
+            buck2_data::analysis_end::Target::StandardTarget(label) => label.label.as_ref()?,
+            _ => return None,
+        };
+        if !self.patterns.iter().any(|p| pattern_matches(p, target)) {
+            return None;
+        }
+        let label = format!("{}:{}", target.package, target.name);
+        if !self.configured.insert(label.clone()) {
+            return None;
+        }
+        Some(bep::BuildEvent {
+            id: Some(bep::BuildEventId {
+                id: Some(bep::build_event_id::Id::TargetConfigured(
+                    bep::build_event_id::TargetConfiguredId {
+                        label,
+                        aspect: String::new(),
+                    },
+                )),
+            }),
+            children: Vec::new(),
+            last_message: false,
+            payload: Some(bep::build_event::Payload::Configured(
+                bep::TargetConfigured {
+                    target_kind: analysis.rule.clone(),
+                    tag: Vec::new(),
+                },
+            )),
+        })
+    }
+}
+
+fn progress_id(opaque_count: i32) -> bep::BuildEventId {
+    bep::BuildEventId {
+        id: Some(bep::build_event_id::Id::Progress(
+            bep::build_event_id::ProgressId { opaque_count },
+        )),
+    }
+}
+
+/// Whether a target pattern, as logged in `ResolvedTargetPatterns`, matches a target.
+fn pattern_matches(pattern: &str, target: &buck2_data::TargetLabel) -> bool {
+    // Target patterns may be followed by providers and a configuration, e.g.
+    // `root//foo:bar[baz] (cfg)`.
+    let pattern = pattern.split(['[', ' ']).next().unwrap_or_default();
+    if let Some(prefix) = pattern.strip_suffix("/...") {
+        // `root///...` matches every package of the cell.
+        (prefix.ends_with("//") && target.package.starts_with(prefix))
+            || target.package == prefix
+            || target
+                .package
+                .strip_prefix(prefix)
+                .map_or(false, |rest| rest.starts_with('/'))
+    } else if let Some(package) = pattern.strip_suffix(':') {
+        target.package == package
+    } else {
+        pattern.split_once(':').map_or(false, |(package, name)| {
+            target.package == package && target.name == name
+        })
+    }
+}
+
+fn build_finished(
+    command: &buck2_data::CommandEnd,
+    timestamp: prost_types::Timestamp,
+) -> bep::BuildEvent {
+    // These are the names and codes Bazel uses.
+    let exit_code = if command.is_success {
+        bep::build_finished::ExitCode {
+            name: "SUCCESS".to_owned(),
+            code: 0,
+        }
+    } else {
+        bep::build_finished::ExitCode {
+            name: "BUILD_FAILURE".to_owned(),
+            code: 1,
+        }
+    };
+    bep::BuildEvent {
+        id: Some(bep::BuildEventId {
+            id: Some(bep::build_event_id::Id::BuildFinished(
+                bep::build_event_id::BuildFinishedId {},
+            )),
+        }),
+        children: Vec::new(),
+        last_message: true,
+        payload: Some(bep::build_event::Payload::Finished(bep::BuildFinished {
+            exit_code: Some(exit_code),
+            finish_time: Some(timestamp),
+        })),
+    }
+}
+
+fn action_executed(
+    action: &buck2_data::ActionExecutionEnd,
+    duration: Option<&prost_types::Duration>,
+    end_time: SystemTime,
+) -> Option<bep::BuildEvent> {
+    use buck2_data::command_execution_details::Command;
+
+    // Simple actions (writes, copies, ...) are not interesting to BES consumers.
+    if action.execution_kind == buck2_data::ActionExecutionKind::Simple as i32 {
+        return None;
+    }
+
+    let (label, configuration) = match action.key.as_ref()?.owner.as_ref()? {
+        buck2_data::action_key::Owner::TargetLabel(label)
+        | buck2_data::action_key::Owner::TestTargetLabel(label) => configured_label(label)?,
+        _ => return None,
+    };
+    let name = action.name.as_ref()?;
+    let primary_output = if name.identifier.is_empty() {
+        name.category.clone()
+    } else {
+        format!("{} {}", name.category, name.identifier)
+    };
+
+    let details = action.commands.last().and_then(|c| c.details.as_ref());
+    let command_line = match details.and_then(|d| d.command.as_ref()) {
+        Some(Command::LocalCommand(local)) => local.argv.clone(),
+        _ => Vec::new(),
+    };
+    let output = |name: &str, contents: &str| {
+        if contents.is_empty() {
+            None
+        } else {
+            Some(bep::File {
+                name: name.to_owned(),
+                file: Some(bep::file::File::Contents(contents.as_bytes().to_vec())),
+            })
+        }
+    };
+    let start_time = duration
+        .and_then(|d| std::time::Duration::try_from(d.clone()).ok())
+        .and_then(|d| end_time.checked_sub(d));
+
+    Some(bep::BuildEvent {
+        id: Some(bep::BuildEventId {
+            id: Some(bep::build_event_id::Id::ActionCompleted(
+                bep::build_event_id::ActionCompletedId {
+                    primary_output,
+                    label: label.clone(),
+                    configuration: Some(bep::build_event_id::ConfigurationId { id: configuration }),
+                },
+            )),
+        }),
+        children: Vec::new(),
+        last_message: false,
+        payload: Some(bep::build_event::Payload::Action(bep::ActionExecuted {
+            success: !action.failed,
+            exit_code: details.and_then(|d| d.signed_exit_code).unwrap_or_default(),
+            stdout: details.and_then(|d| output("stdout", &d.stdout)),
+            stderr: details.and_then(|d| output("stderr", &d.stderr)),
+            label,
+            r#type: name.category.clone(),
+            command_line,
+            start_time: start_time.map(|t| t.into()),
+            end_time: Some(end_time.into()),
+        })),
+    })
+}
+
+/// The label and configuration of a configured target.
+fn configured_label(label: &buck2_data::ConfiguredTargetLabel) -> Option<(String, String)> {
+    let target = label.label.as_ref()?;
+    Some((
+        format!("{}:{}", target.package, target.name),
+        label
+            .configuration
+            .as_ref()
+            .map(|c| c.full_name.clone())
+            .unwrap_or_default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::time::Duration;
+
+    use buck2_bes_proto::google::devtools::build::v1::publish_build_event_server::PublishBuildEvent;
+    use buck2_bes_proto::google::devtools::build::v1::publish_build_event_server::PublishBuildEventServer;
+    use futures::Stream;
+    use futures::StreamExt;
+    use tonic::Status;
+    use tonic::Streaming;
+
+    use super::*;
+    use crate::span::SpanId;
+
+    fn target_label() -> buck2_data::ConfiguredTargetLabel {
+        buck2_data::ConfiguredTargetLabel {
+            label: Some(buck2_data::TargetLabel {
+                package: "root//foo".to_owned(),
+                name: "bar".to_owned(),
+            }),
+            configuration: Some(buck2_data::Configuration {
+                full_name: "cfg".to_owned(),
+            }),
+            execution_configuration: None,
+        }
+    }
+
+    fn command_start(trace_id: &TraceId) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            Some(SpanId::new()),
+            None,
+            buck2_data::SpanStartEvent {
+                data: Some(
+                    buck2_data::CommandStart {
+                        data: Some(buck2_data::BuildCommandStart {}.into()),
+                        metadata: HashMap::new(),
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        )
+    }
+
+    fn action_end(trace_id: &TraceId) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            Some(SpanId::new()),
+            None,
+            buck2_data::SpanEndEvent {
+                duration: Some(prost_types::Duration {
+                    seconds: 2,
+                    nanos: 0,
+                }),
+                data: Some(
+                    buck2_data::ActionExecutionEnd {
+                        key: Some(buck2_data::ActionKey {
+                            owner: Some(buck2_data::action_key::Owner::TargetLabel(target_label())),
+                            ..Default::default()
+                        }),
+                        name: Some(buck2_data::ActionName {
+                            category: "cxx_compile".to_owned(),
+                            identifier: "bar.cpp".to_owned(),
+                        }),
+                        failed: true,
+                        execution_kind: buck2_data::ActionExecutionKind::Local as i32,
+                        commands: vec![buck2_data::CommandExecution {
+                            details: Some(buck2_data::CommandExecutionDetails {
+                                signed_exit_code: Some(1),
+                                stderr: "error: oops".to_owned(),
+                                command: Some(
+                                    buck2_data::command_execution_details::Command::LocalCommand(
+                                        buck2_data::LocalCommand {
+                                            argv: vec!["clang++".to_owned(), "bar.cpp".to_owned()],
+                                            ..Default::default()
+                                        },
+                                    ),
+                                ),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            }
+            .into(),
+        )
+    }
+
+    fn test_result(trace_id: &TraceId, name: &str, status: buck2_data::TestStatus) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            None,
+            None,
+            buck2_data::InstantEvent {
+                data: Some(
+                    buck2_data::TestResult {
+                        name: name.to_owned(),
+                        status: status as i32,
+                        target_label: Some(target_label()),
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        )
+    }
+
+    fn command_end(trace_id: &TraceId) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            Some(SpanId::new()),
+            None,
+            buck2_data::SpanEndEvent {
+                data: Some(
+                    buck2_data::CommandEnd {
+                        is_success: false,
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            }
+            .into(),
+        )
+    }
+
+    fn target_patterns(trace_id: &TraceId, patterns: &[&str]) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            None,
+            None,
+            buck2_data::InstantEvent {
+                data: Some(
+                    buck2_data::ResolvedTargetPatterns {
+                        target_patterns: patterns
+                            .iter()
+                            .map(|p| buck2_data::TargetPattern {
+                                value: (*p).to_owned(),
+                            })
+                            .collect(),
+                    }
+                    .into(),
+                ),
+            }
+            .into(),
+        )
+    }
+
+    fn analysis_end(trace_id: &TraceId, package: &str, name: &str) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            Some(SpanId::new()),
+            None,
+            buck2_data::SpanEndEvent {
+                data: Some(
+                    buck2_data::AnalysisEnd {
+                        target: Some(buck2_data::analysis_end::Target::StandardTarget(
+                            buck2_data::ConfiguredTargetLabel {
+                                label: Some(buck2_data::TargetLabel {
+                                    package: package.to_owned(),
+                                    name: name.to_owned(),
+                                }),
+                                ..target_label()
+                            },
+                        )),
+                        rule: "cxx_library".to_owned(),
+                        ..Default::default()
+                    }
+                    .into(),
+                ),
+                ..Default::default()
+            }
+            .into(),
+        )
+    }
+
+    fn payload(event: &bep::BuildEvent) -> &bep::build_event::Payload {
+        event.payload.as_ref().unwrap()
+    }
+
+    #[test]
+    fn test_translate() {
+        let trace_id = TraceId::new();
+        let mut translator = BepTranslator::new(trace_id.clone());
+
+        let started = translator.translate(&command_start(&trace_id));
+        match payload(&started[0]) {
+            bep::build_event::Payload::Started(started) => {
+                assert_eq!(trace_id.to_string(), started.uuid);
+                assert_eq!("build", started.command);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+
+        let action = translator.translate(&action_end(&trace_id));
+        match payload(&action[1]) {
+            bep::build_event::Payload::Action(action) => {
+                assert!(!action.success);
+                assert_eq!(1, action.exit_code);
+                assert_eq!("root//foo:bar", action.label);
+                assert_eq!("cxx_compile", action.r#type);
+                assert_eq!(vec!["clang++", "bar.cpp"], action.command_line);
+                assert!(action.stdout.is_none());
+                assert_eq!(
+                    Some(bep::file::File::Contents(b"error: oops".to_vec())),
+                    action.stderr.as_ref().unwrap().file
+                );
+                let start = action.start_time.as_ref().unwrap();
+                let end = action.end_time.as_ref().unwrap();
+                assert_eq!(2, end.seconds - start.seconds);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+
+        // Every test case of a target is a shard, and every result of it an attempt.
+        let rerun = buck2_data::TestStatus::Rerun;
+        let pass = buck2_data::TestStatus::Pass;
+        let first = translator.translate(&test_result(&trace_id, "Suite.Case", rerun));
+        let second = translator.translate(&test_result(&trace_id, "Suite.Case", pass));
+        let other = translator.translate(&test_result(&trace_id, "Suite.Other", pass));
+        for (event, name, shard, attempt, status) in [
+            (&first[1], "Suite.Case", 1, 1, bep::TestStatus::Failed),
+            (&second[1], "Suite.Case", 1, 2, bep::TestStatus::Passed),
+            (&other[1], "Suite.Other", 2, 1, bep::TestStatus::Passed),
+        ] {
+            match event.id.as_ref().unwrap().id.as_ref().unwrap() {
+                bep::build_event_id::Id::TestResult(id) => {
+                    assert_eq!("root//foo:bar", id.label);
+                    assert_eq!(1, id.run);
+                    assert_eq!(shard, id.shard);
+                    assert_eq!(attempt, id.attempt);
+                    assert_eq!("cfg", id.configuration.as_ref().unwrap().id);
+                }
+                id => panic!("unexpected id: {:?}", id),
+            }
+            match payload(event) {
+                bep::build_event::Payload::TestResult(result) => {
+                    assert_eq!(status as i32, result.status);
+                    assert_eq!(name, result.status_details);
+                }
+                p => panic!("unexpected payload: {:?}", p),
+            }
+        }
+
+        let finished = translator.translate(&command_end(&trace_id));
+        assert!(finished[1].last_message);
+        match payload(&finished[1]) {
+            bep::build_event::Payload::Finished(finished) => {
+                assert_eq!(1, finished.exit_code.as_ref().unwrap().code);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let target = buck2_data::TargetLabel {
+            package: "root//foo/bar".to_owned(),
+            name: "baz".to_owned(),
+        };
+        for pattern in [
+            "root//foo/bar:baz",
+            "root//foo/bar:baz[providers] (cfg)",
+            "root//foo/bar:",
+            "root//foo/bar/...",
+            "root//foo/...",
+            "root///...",
+        ] {
+            assert!(pattern_matches(pattern, &target), "{}", pattern);
+        }
+        for pattern in [
+            "root//foo/bar:qux",
+            "root//foo:",
+            "root//fo/...",
+            "root//foo/bar/baz/...",
+            "other///...",
+        ] {
+            assert!(!pattern_matches(pattern, &target), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_translate_announces_children() {
+        let trace_id = TraceId::new();
+        let mut translator = BepTranslator::new(trace_id.clone());
+
+        let mut events = Vec::new();
+        events.extend(translator.translate(&command_start(&trace_id)));
+        events.extend(translator.translate(&target_patterns(&trace_id, &["root//foo:bar"])));
+        // Only the top-level targets are configured, and each of them once.
+        events.extend(translator.translate(&analysis_end(&trace_id, "root//foo", "bar")));
+        events.extend(translator.translate(&analysis_end(&trace_id, "root//foo", "bar")));
+        events.extend(translator.translate(&analysis_end(&trace_id, "root//foo", "dep")));
+        events.extend(translator.translate(&action_end(&trace_id)));
+        events.extend(translator.translate(&command_end(&trace_id)));
+
+        let configured: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e.id.as_ref()?.id.as_ref()? {
+                bep::build_event_id::Id::TargetConfigured(id) => Some(id.label.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["root//foo:bar"], configured);
+
+        // Every event but the first is announced by exactly one event before it.
+        let mut announced = HashSet::new();
+        for (i, event) in events.iter().enumerate() {
+            let id = event.id.clone().unwrap();
+            if i > 0 {
+                assert!(announced.remove(&id.encode_to_vec()), "{:?}", id);
+            }
+            for child in &event.children {
+                assert!(announced.insert(child.encode_to_vec()), "{:?}", child);
+            }
+        }
+        assert!(announced.is_empty(), "{:?}", announced);
+        assert!(events.last().unwrap().last_message);
+    }
+
+    /// Records the events it receives and acknowledges each of them.
+    #[derive(Default, Clone)]
+    struct FakeBes {
+        requests: Arc<Mutex<Vec<bes::PublishBuildToolEventStreamRequest>>>,
+    }
+
+    #[tonic::async_trait]
+    impl PublishBuildEvent for FakeBes {
+        type PublishBuildToolEventStreamStream = Pin<
+            Box<dyn Stream<Item = Result<bes::PublishBuildToolEventStreamResponse, Status>> + Send>,
+        >;
+
+        async fn publish_build_tool_event_stream(
+            &self,
+            request: tonic::Request<Streaming<bes::PublishBuildToolEventStreamRequest>>,
+        ) -> Result<tonic::Response<Self::PublishBuildToolEventStreamStream>, Status> {
+            let requests = self.requests.dupe();
+            let responses = request.into_inner().map(move |request| {
+                let request = request?;
+                let event = request.ordered_build_event.clone().unwrap_or_default();
+                requests.lock().unwrap().push(request);
+                Ok(bes::PublishBuildToolEventStreamResponse {
+                    stream_id: event.stream_id,
+                    sequence_number: event.sequence_number,
+                })
+            });
+            Ok(tonic::Response::new(Box::pin(responses)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_drops_events_when_full() -> anyhow::Result<()> {
+        let sink = BesSink::new(BesConfig {
+            endpoint: "grpc://127.0.0.1:1".to_owned(),
+            ..Default::default()
+        })?;
+        // Nothing is received, so only one more event fits.
+        let (sender, _receiver) = mpsc::unbounded();
+        let mut stream = InvocationStream {
+            sender,
+            queued: Arc::new(AtomicUsize::new(MAX_QUEUED_EVENTS - 1)),
+            translator: BepTranslator::new(TraceId::new()),
+            sequence_number: 0,
+            finished: false,
+        };
+        for _ in 0..3 {
+            sink.publish(
+                &mut stream,
+                SystemTime::now(),
+                bes::build_event::Event::BazelEvent(Default::default()),
+                false,
+            );
+        }
+        // The end of the stream is sent whatever the size of the queue.
+        sink.publish(
+            &mut stream,
+            SystemTime::now(),
+            bes::build_event::Event::ComponentStreamFinished(Default::default()),
+            true,
+        );
+
+        assert_eq!(2, stream.sequence_number);
+        assert_eq!(MAX_QUEUED_EVENTS + 1, stream.queued.load(Ordering::Relaxed));
+        let stats = sink.stats().unwrap();
+        assert_eq!(2, stats.buffered);
+        assert_eq!(2, stats.dropped);
+        assert_eq!(0, stats.failures);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_publish() -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = FakeBes::default();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(PublishBuildEventServer::new(server.clone()))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let sink = BesSink::new(BesConfig {
+            endpoint: format!("grpc://{}", addr),
+            project_id: Some("project".to_owned()),
+            headers: vec![("x-api-key".to_owned(), "secret".to_owned())],
+        })?;
+        let trace_id = TraceId::new();
+        // Events outside of a command are not published.
+        sink.send(test_result(
+            &TraceId::new(),
+            "Suite.Case",
+            buck2_data::TestStatus::Pass,
+        ));
+        sink.send(command_start(&trace_id));
+        sink.send(action_end(&trace_id));
+        sink.send(test_result(
+            &trace_id,
+            "Suite.Case",
+            buck2_data::TestStatus::Fail,
+        ));
+        sink.send(command_end(&trace_id));
+
+        // Started, action, test result and finished, each but the first announced by a progress
+        // event, and the end of the stream.
+        let expected = 8;
+        for _ in 0..100 {
+            if server.requests.lock().unwrap().len() == expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let requests = server.requests.lock().unwrap().clone();
+        assert_eq!(expected, requests.len());
+        for (i, request) in requests.iter().enumerate() {
+            assert_eq!("project", request.project_id);
+            let event = request.ordered_build_event.as_ref().unwrap();
+            assert_eq!(i as i64 + 1, event.sequence_number);
+            assert_eq!(
+                trace_id.to_string(),
+                event.stream_id.as_ref().unwrap().invocation_id
+            );
+        }
+        let bazel_events: Vec<_> = requests
+            .iter()
+            .filter_map(|r| {
+                match r
+                    .ordered_build_event
+                    .as_ref()?
+                    .event
+                    .as_ref()?
+                    .event
+                    .as_ref()?
+                {
+                    bes::build_event::Event::BazelEvent(any) => {
+                        Some(bep::BuildEvent::decode(any.value.as_slice()).unwrap())
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        assert_eq!(7, bazel_events.len());
+        assert!(bazel_events[6].last_message);
+        assert!(matches!(
+            requests[7]
+                .ordered_build_event
+                .as_ref()
+                .unwrap()
+                .event
+                .as_ref()
+                .unwrap()
+                .event,
+            Some(bes::build_event::Event::ComponentStreamFinished(..))
+        ));
+
+        let stats = sink.stats().unwrap();
+        assert_eq!(0, stats.failures);
+        Ok(())
+    }
+}
@@ -0,0 +1,687 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! A Sink for exporting spans as OpenTelemetry traces to a collector, using OTLP over gRPC.
+//!
+//! Every command is a trace whose id is the command's trace id, and every pair of `SpanStart` and
+//! `SpanEnd` events is a span of that trace. Spans are exported once they end, in batches.
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use buck2_otlp_proto::opentelemetry::proto::collector::trace::v1 as collector;
+use buck2_otlp_proto::opentelemetry::proto::collector::trace::v1::trace_service_client::TraceServiceClient;
+use buck2_otlp_proto::opentelemetry::proto::common::v1 as common;
+use buck2_otlp_proto::opentelemetry::proto::resource::v1 as resource;
+use buck2_otlp_proto::opentelemetry::proto::trace::v1 as trace;
+use buck2_wrapper_common::invocation_id::TraceId;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use dupe::Dupe;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use gazebo::variants::VariantName;
+use tonic::transport::Channel;
+use tonic::transport::ClientTlsConfig;
+use tonic::transport::Endpoint;
+
+use crate::span::SpanId;
+use crate::BuckEvent;
+use crate::ControlEvent;
+use crate::EventSink;
+use crate::EventSinkStats;
+
+/// The most spans sent in a single export request.
+const MAX_EXPORT_BATCH_SIZE: usize = 512;
+
+/// How many spans can be waiting to be exported before further spans are dropped, so that a slow
+/// collector can't make the daemon run out of memory.
+const MAX_QUEUED_SPANS: usize = 10000;
+
+/// How long dropping the sink waits for the queued spans to be exported.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct OtlpCounters {
+    /// Spans that ended and were queued for export.
+    queued: AtomicU64,
+    exported: AtomicU64,
+    failures: AtomicU64,
+    /// Spans that ended while the queue was full.
+    dropped: AtomicU64,
+}
+
+/// A span that started and did not end yet.
+struct OpenSpan {
+    parent_span_id: Vec<u8>,
+    name: String,
+    start_time: SystemTime,
+}
+
+/// The spans of a trace that did not end yet.
+struct TraceSpans {
+    trace_id: Vec<u8>,
+    open_spans: HashMap<SpanId, OpenSpan>,
+    /// Every trace has its own sender, so that commands don't contend on a shared one.
+    sender: mpsc::Sender<trace::Span>,
+}
+
+pub struct OtlpSink {
+    /// Sharded, so that concurrent commands rarely wait on each other.
+    traces: DashMap<TraceId, TraceSpans>,
+    sender: mpsc::Sender<trace::Span>,
+    counters: Arc<OtlpCounters>,
+    /// Receives a message once the export task exported the last span of a closed channel.
+    flushed: Mutex<std::sync::mpsc::Receiver<()>>,
+}
+
+impl OtlpSink {
+    /// Creates a sink exporting to the collector at `endpoint`, e.g. `http://localhost:4317`. The
+    /// connection is established lazily. Must be called within a Tokio runtime, which is used to
+    /// export the spans.
+    pub fn new(endpoint: &str) -> anyhow::Result<OtlpSink> {
+        let mut channel = Endpoint::from_shared(endpoint.to_owned())
+            .with_context(|| format!("Invalid OTLP endpoint `{}`", endpoint))?;
+        if endpoint.starts_with("https://") {
+            channel = channel.tls_config(ClientTlsConfig::new())?;
+        }
+        let client = TraceServiceClient::new(channel.connect_lazy());
+        let runtime = tokio::runtime::Handle::try_current()
+            .context("The OTLP sink must be created within a Tokio runtime")?;
+
+        let (sender, receiver) = mpsc::channel(MAX_QUEUED_SPANS);
+        let (flushed_sender, flushed) = std::sync::mpsc::channel();
+        let counters = Arc::new(OtlpCounters::default());
+        let export_counters = counters.dupe();
+        runtime.spawn(async move {
+            export(client, receiver, export_counters).await;
+            let _ignored = flushed_sender.send(());
+        });
+
+        Ok(OtlpSink {
+            traces: DashMap::new(),
+            sender,
+            counters,
+            flushed: Mutex::new(flushed),
+        })
+    }
+
+    /// Whether every span that was queued was exported, or failed to be.
+    fn is_flushed(&self) -> bool {
+        let queued = self.counters.queued.load(Ordering::Relaxed);
+        let exported = self.counters.exported.load(Ordering::Relaxed);
+        let failures = self.counters.failures.load(Ordering::Relaxed);
+        exported + failures >= queued
+    }
+}
+
+impl Drop for OtlpSink {
+    fn drop(&mut self) {
+        // The export task stops once it exported the spans that are still queued, e.g. when the
+        // daemon shuts down. Don't wait on it if there are none, it may not get to run anymore.
+        self.sender.close_channel();
+        if !self.is_flushed() {
+            let _ignored = self.flushed.get_mut().unwrap().recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+/// Exports the spans received on `receiver` until the channel is closed.
+async fn export(
+    mut client: TraceServiceClient<Channel>,
+    receiver: mpsc::Receiver<trace::Span>,
+    counters: Arc<OtlpCounters>,
+) {
+    let resource = resource::Resource {
+        attributes: vec![
+            attribute("service.name", "buck2".to_owned()),
+            attribute(
+                "service.version",
+                buck2_build_info::revision().unwrap_or_default().to_owned(),
+            ),
+        ],
+        dropped_attributes_count: 0,
+    };
+
+    let mut batches = receiver.ready_chunks(MAX_EXPORT_BATCH_SIZE);
+    while let Some(spans) = batches.next().await {
+        let count = spans.len() as u64;
+        let request = collector::ExportTraceServiceRequest {
+            resource_spans: vec![trace::ResourceSpans {
+                resource: Some(resource.clone()),
+                scope_spans: vec![trace::ScopeSpans {
+                    scope: Some(common::InstrumentationScope {
+                        name: "buck2".to_owned(),
+                        ..Default::default()
+                    }),
+                    spans,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        match client.export(request).await {
+            Ok(response) => {
+                let rejected = response
+                    .into_inner()
+                    .partial_success
+                    .map_or(0, |p| p.rejected_spans.max(0) as u64)
+                    .min(count);
+                counters
+                    .exported
+                    .fetch_add(count - rejected, Ordering::Relaxed);
+                counters.failures.fetch_add(rejected, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::warn!("Error exporting spans to OTLP collector: {}", e);
+                counters.failures.fetch_add(count, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl EventSink for OtlpSink {
+    fn send(&self, event: BuckEvent) {
+        let span_id = match event.span_id() {
+            Some(span_id) => span_id,
+            None => return,
+        };
+        let trace_id = match event.trace_id() {
+            Ok(trace_id) => trace_id,
+            Err(_) => return,
+        };
+
+        if let Some(start) = event.span_start_event() {
+            let mut trace = match self.traces.entry(trace_id) {
+                Entry::Occupied(entry) => entry.into_ref(),
+                Entry::Vacant(entry) => {
+                    let trace_id = match uuid::Uuid::parse_str(&entry.key().to_string()) {
+                        Ok(trace_id) => trace_id.as_bytes().to_vec(),
+                        Err(_) => return,
+                    };
+                    entry.insert(TraceSpans {
+                        trace_id,
+                        open_spans: HashMap::new(),
+                        sender: self.sender.clone(),
+                    })
+                }
+            };
+            trace.open_spans.insert(
+                span_id,
+                OpenSpan {
+                    parent_span_id: event
+                        .parent_id()
+                        .map_or_else(Vec::new, |p| span_id_bytes(p).to_vec()),
+                    name: start_span_name(start),
+                    start_time: event.timestamp(),
+                },
+            );
+        } else if let Some(end) = event.span_end_event() {
+            let mut trace = match self.traces.get_mut(&trace_id) {
+                Some(trace) => trace,
+                None => return,
+            };
+            let open = match trace.open_spans.remove(&span_id) {
+                Some(open) => open,
+                None => return,
+            };
+            let span = to_otlp_span(
+                trace.trace_id.clone(),
+                span_id,
+                open,
+                end,
+                event.timestamp(),
+            );
+            match trace.sender.try_send(span) {
+                Ok(()) => {
+                    self.counters.queued.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) if e.is_full() => {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(_) => {
+                    self.counters.failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            if let Some(buck2_data::span_end_event::Data::Command(..)) = &end.data {
+                // Spans that never ended, e.g. because the command was cancelled, won't end now.
+                drop(trace);
+                self.traces.remove(&trace_id);
+            }
+        }
+    }
+
+    fn send_control(&self, _control_event: ControlEvent) {}
+
+    fn stats(&self) -> Option<EventSinkStats> {
+        let queued = self.counters.queued.load(Ordering::Relaxed);
+        let exported = self.counters.exported.load(Ordering::Relaxed);
+        let failures = self.counters.failures.load(Ordering::Relaxed);
+        Some(EventSinkStats {
+            successes: exported,
+            failures,
+            buffered: queued.saturating_sub(exported + failures),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        })
+    }
+}
+
+fn span_id_bytes(span_id: SpanId) -> [u8; 8] {
+    span_id.0.get().to_be_bytes()
+}
+
+fn start_span_name(start: &buck2_data::SpanStartEvent) -> String {
+    match &start.data {
+        Some(buck2_data::span_start_event::Data::Command(command)) => format!(
+            "buck2 {}",
+            command
+                .data
+                .as_ref()
+                .map_or("", |d| d.variant_name())
+                .to_lowercase()
+        ),
+        Some(data) => data.variant_name().to_owned(),
+        None => "Unknown".to_owned(),
+    }
+}
+
+fn to_otlp_span(
+    trace_id: Vec<u8>,
+    span_id: SpanId,
+    open: OpenSpan,
+    end: &buck2_data::SpanEndEvent,
+    end_time: SystemTime,
+) -> trace::Span {
+    let mut name = open.name;
+    let mut attributes = Vec::new();
+    let mut error = None;
+
+    match &end.data {
+        Some(buck2_data::span_end_event::Data::Command(command)) => {
+            if let Some(data) = &command.data {
+                attributes.push(attribute(
+                    "buck2.command",
+                    data.variant_name().to_lowercase(),
+                ));
+            }
+            if !command.is_success {
+                error = Some("Command failed".to_owned());
+            }
+        }
+        Some(buck2_data::span_end_event::Data::ActionExecution(action)) => {
+            if let Some(
+                buck2_data::action_key::Owner::TargetLabel(label)
+                | buck2_data::action_key::Owner::TestTargetLabel(label),
+            ) = action.key.as_ref().and_then(|k| k.owner.as_ref())
+            {
+                push_target_attributes(&mut attributes, label);
+            }
+            if let Some(action_name) = &action.name {
+                attributes.push(attribute(
+                    "buck2.action.category",
+                    action_name.category.clone(),
+                ));
+                attributes.push(attribute(
+                    "buck2.action.identifier",
+                    action_name.identifier.clone(),
+                ));
+                // Makes actions recognizable in trace viewers, which show little more than names.
+                name = format!("{} {}", action_name.category, action_name.identifier)
+                    .trim()
+                    .to_owned();
+            }
+            let kind = buck2_data::ActionExecutionKind::from_i32(action.execution_kind)
+                .unwrap_or(buck2_data::ActionExecutionKind::NotSet);
+            attributes.push(attribute("buck2.executor", executor_name(kind).to_owned()));
+            attributes.push(common::KeyValue {
+                key: "buck2.cache_hit".to_owned(),
+                value: Some(common::AnyValue {
+                    value: Some(common::any_value::Value::BoolValue(
+                        kind == buck2_data::ActionExecutionKind::ActionCache
+                            || kind == buck2_data::ActionExecutionKind::LocalActionCache,
+                    )),
+                }),
+            });
+            if action.failed {
+                error = Some("Action failed".to_owned());
+            }
+        }
+        Some(buck2_data::span_end_event::Data::Analysis(analysis)) => {
+            if let Some(buck2_data::analysis_end::Target::StandardTarget(label)) = &analysis.target
+            {
+                push_target_attributes(&mut attributes, label);
+                if let Some(target) = &label.label {
+                    name = format!("analysis {}:{}", target.package, target.name);
+                }
+            }
+            attributes.push(attribute("buck2.rule", analysis.rule.clone()));
+        }
+        _ => {}
+    }
+
+    trace::Span {
+        trace_id,
+        span_id: span_id_bytes(span_id).to_vec(),
+        trace_state: String::new(),
+        parent_span_id: open.parent_span_id,
+        name,
+        kind: trace::span::SpanKind::Internal as i32,
+        start_time_unix_nano: unix_nanos(open.start_time),
+        end_time_unix_nano: unix_nanos(end_time),
+        attributes,
+        dropped_attributes_count: 0,
+        status: Some(match error {
+            Some(message) => trace::Status {
+                message,
+                code: trace::status::StatusCode::Error as i32,
+            },
+            None => trace::Status {
+                message: String::new(),
+                code: trace::status::StatusCode::Unset as i32,
+            },
+        }),
+    }
+}
+
+fn push_target_attributes(
+    attributes: &mut Vec<common::KeyValue>,
+    label: &buck2_data::ConfiguredTargetLabel,
+) {
+    if let Some(target) = &label.label {
+        attributes.push(attribute(
+            "buck2.target",
+            format!("{}:{}", target.package, target.name),
+        ));
+    }
+    if let Some(configuration) = &label.configuration {
+        attributes.push(attribute(
+            "buck2.configuration",
+            configuration.full_name.clone(),
+        ));
+    }
+}
+
+fn executor_name(kind: buck2_data::ActionExecutionKind) -> &'static str {
+    match kind {
+        buck2_data::ActionExecutionKind::NotSet => "unknown",
+        buck2_data::ActionExecutionKind::Local => "local",
+        buck2_data::ActionExecutionKind::Remote => "remote",
+        buck2_data::ActionExecutionKind::ActionCache => "action_cache",
+        buck2_data::ActionExecutionKind::Simple => "simple",
+        buck2_data::ActionExecutionKind::Skipped => "skipped",
+        buck2_data::ActionExecutionKind::Deferred => "deferred",
+        buck2_data::ActionExecutionKind::LocalActionCache => "local_action_cache",
+    }
+}
+
+fn attribute(key: &str, value: String) -> common::KeyValue {
+    common::KeyValue {
+        key: key.to_owned(),
+        value: Some(common::AnyValue {
+            value: Some(common::any_value::Value::StringValue(value)),
+        }),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_otlp_proto::opentelemetry::proto::collector::trace::v1::trace_service_server::TraceService;
+    use buck2_otlp_proto::opentelemetry::proto::collector::trace::v1::trace_service_server::TraceServiceServer;
+    use tonic::Status;
+
+    use super::*;
+
+    fn start(
+        trace_id: &TraceId,
+        span_id: SpanId,
+        parent_id: Option<SpanId>,
+        data: buck2_data::span_start_event::Data,
+    ) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            Some(span_id),
+            parent_id,
+            buck2_data::SpanStartEvent { data: Some(data) }.into(),
+        )
+    }
+
+    fn end(
+        trace_id: &TraceId,
+        span_id: SpanId,
+        parent_id: Option<SpanId>,
+        data: buck2_data::span_end_event::Data,
+    ) -> BuckEvent {
+        BuckEvent::new(
+            SystemTime::now(),
+            trace_id.clone(),
+            Some(span_id),
+            parent_id,
+            buck2_data::SpanEndEvent {
+                data: Some(data),
+                ..Default::default()
+            }
+            .into(),
+        )
+    }
+
+    /// Sends the events of a build with a single action that was an action cache hit.
+    fn send_build(sink: &impl EventSink, trace_id: &TraceId) -> (SpanId, SpanId) {
+        let command = SpanId::new();
+        let action = SpanId::new();
+        sink.send(start(
+            trace_id,
+            command,
+            None,
+            buck2_data::CommandStart {
+                data: Some(buck2_data::BuildCommandStart {}.into()),
+                metadata: HashMap::new(),
+            }
+            .into(),
+        ));
+        sink.send(start(
+            trace_id,
+            action,
+            Some(command),
+            buck2_data::ActionExecutionStart::default().into(),
+        ));
+        sink.send(end(
+            trace_id,
+            action,
+            Some(command),
+            buck2_data::ActionExecutionEnd {
+                key: Some(buck2_data::ActionKey {
+                    owner: Some(buck2_data::action_key::Owner::TargetLabel(
+                        buck2_data::ConfiguredTargetLabel {
+                            label: Some(buck2_data::TargetLabel {
+                                package: "root//foo".to_owned(),
+                                name: "bar".to_owned(),
+                            }),
+                            configuration: Some(buck2_data::Configuration {
+                                full_name: "cfg".to_owned(),
+                            }),
+                            execution_configuration: None,
+                        },
+                    )),
+                    ..Default::default()
+                }),
+                name: Some(buck2_data::ActionName {
+                    category: "cxx_compile".to_owned(),
+                    identifier: "bar.cpp".to_owned(),
+                }),
+                execution_kind: buck2_data::ActionExecutionKind::ActionCache as i32,
+                ..Default::default()
+            }
+            .into(),
+        ));
+        sink.send(end(
+            trace_id,
+            command,
+            None,
+            buck2_data::CommandEnd {
+                is_success: true,
+                data: Some(buck2_data::BuildCommandEnd::default().into()),
+                ..Default::default()
+            }
+            .into(),
+        ));
+        (command, action)
+    }
+
+    fn string_attribute<'a>(span: &'a trace::Span, key: &str) -> Option<&'a str> {
+        span.attributes.iter().find(|a| a.key == key).and_then(|a| {
+            match a.value.as_ref()?.value.as_ref()? {
+                common::any_value::Value::StringValue(s) => Some(s.as_str()),
+                _ => None,
+            }
+        })
+    }
+
+    /// Records the spans it receives.
+    #[derive(Default, Clone)]
+    struct FakeCollector {
+        spans: Arc<Mutex<Vec<trace::Span>>>,
+    }
+
+    #[tonic::async_trait]
+    impl TraceService for FakeCollector {
+        async fn export(
+            &self,
+            request: tonic::Request<collector::ExportTraceServiceRequest>,
+        ) -> Result<tonic::Response<collector::ExportTraceServiceResponse>, Status> {
+            let mut spans = self.spans.lock().unwrap();
+            for resource_spans in request.into_inner().resource_spans {
+                for scope_spans in resource_spans.scope_spans {
+                    spans.extend(scope_spans.spans);
+                }
+            }
+            Ok(tonic::Response::new(
+                collector::ExportTraceServiceResponse::default(),
+            ))
+        }
+    }
+
+    /// Starts a collector, returns it with its endpoint.
+    async fn start_collector() -> anyhow::Result<(FakeCollector, String)> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let collector = FakeCollector::default();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(TraceServiceServer::new(collector.clone()))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+        Ok((collector, format!("http://{}", addr)))
+    }
+
+    #[test]
+    fn test_drop_spans_when_full() {
+        // Nothing is received, so only one span fits.
+        let (sender, _receiver) = mpsc::channel(0);
+        // There is no export task, so dropping the sink doesn't wait for it.
+        let (_, flushed) = std::sync::mpsc::channel();
+        let sink = OtlpSink {
+            traces: DashMap::new(),
+            sender,
+            counters: Arc::new(OtlpCounters::default()),
+            flushed: Mutex::new(flushed),
+        };
+        send_build(&sink, &TraceId::new());
+
+        let stats = sink.stats().unwrap();
+        assert_eq!(1, stats.buffered);
+        assert_eq!(1, stats.dropped);
+        assert_eq!(0, stats.failures);
+    }
+
+    #[tokio::test]
+    async fn test_export() -> anyhow::Result<()> {
+        let (collector, endpoint) = start_collector().await?;
+        let sink = OtlpSink::new(&endpoint)?;
+        let trace_id = TraceId::new();
+        let (command, action) = send_build(&sink, &trace_id);
+
+        // Waits for the acknowledgements rather than the spans, so that dropping the sink doesn't
+        // wait for the export task.
+        for _ in 0..100 {
+            if sink.stats().unwrap().successes == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let spans = collector.spans.lock().unwrap().clone();
+        assert_eq!(2, spans.len());
+        let (action_span, command_span) = (&spans[0], &spans[1]);
+
+        let trace_id = uuid::Uuid::parse_str(&trace_id.to_string())?;
+        assert_eq!(trace_id.as_bytes().as_slice(), command_span.trace_id);
+        assert_eq!(command_span.trace_id, action_span.trace_id);
+        assert_eq!(span_id_bytes(command).as_slice(), command_span.span_id);
+        assert!(command_span.parent_span_id.is_empty());
+        assert_eq!(span_id_bytes(action).as_slice(), action_span.span_id);
+        assert_eq!(command_span.span_id, action_span.parent_span_id);
+
+        assert_eq!("buck2 build", command_span.name);
+        assert_eq!(
+            Some("build"),
+            string_attribute(command_span, "buck2.command")
+        );
+
+        assert_eq!("cxx_compile bar.cpp", action_span.name);
+        assert_eq!(
+            Some("root//foo:bar"),
+            string_attribute(action_span, "buck2.target")
+        );
+        assert_eq!(
+            Some("cxx_compile"),
+            string_attribute(action_span, "buck2.action.category")
+        );
+        assert_eq!(
+            Some("action_cache"),
+            string_attribute(action_span, "buck2.executor")
+        );
+        assert!(
+            action_span
+                .attributes
+                .iter()
+                .any(|a| a.key == "buck2.cache_hit"
+                    && a.value.as_ref().unwrap().value
+                        == Some(common::any_value::Value::BoolValue(true)))
+        );
+        assert!(action_span.start_time_unix_nano <= action_span.end_time_unix_nano);
+
+        assert_eq!(0, sink.stats().unwrap().failures);
+        Ok(())
+    }
+
+    // Dropping the sink blocks until the spans are exported, which needs another thread.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_flush_on_drop() -> anyhow::Result<()> {
+        let (collector, endpoint) = start_collector().await?;
+        let sink = OtlpSink::new(&endpoint)?;
+        send_build(&sink, &TraceId::new());
+        drop(sink);
+
+        assert_eq!(2, collector.spans.lock().unwrap().len());
+        Ok(())
+    }
+}
@@ -100,6 +100,7 @@ impl CommandExecutionManagerLike for CommandExecutionManager {
                 timing,
                 std_streams,
                 exit_code,
+                inputs: None,
             },
             rejected_execution: None,
             did_cache_upload: false,
@@ -162,6 +163,7 @@ impl CommandExecutionManagerLike for CommandExecutionManagerWithClaim {
                 timing,
                 std_streams,
                 exit_code,
+                inputs: None,
             },
             rejected_execution: None,
             did_cache_upload: false,
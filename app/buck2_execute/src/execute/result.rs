@@ -11,18 +11,26 @@ use std::convert::Infallible;
 use std::fmt::Display;
 use std::ops::ControlFlow;
 use std::ops::FromResidual;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
 
+use buck2_common::file_ops::TrackedFileDigest;
+use buck2_core::directory::Directory;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::directory::DirectoryIterator;
+use buck2_core::directory::FingerprintedDirectory;
 use buck2_core::fs::artifact_path_resolver::ArtifactFs;
 use dupe::Dupe;
 use indexmap::IndexMap;
 
 use crate::artifact_value::ArtifactValue;
+use crate::directory::ActionDirectoryMember;
 use crate::execute::claim::Claim;
 use crate::execute::kind::CommandExecutionKind;
 use crate::execute::output::CommandStdStreams;
 use crate::execute::request::CommandExecutionOutput;
+use crate::execute::request::CommandExecutionPaths;
 use crate::execute::request::ResolvedCommandExecutionOutput;
 use crate::output_size::OutputSize;
 
@@ -158,6 +166,53 @@ pub struct CommandExecutionReport {
     /// No exit_code means the command did not finish executing. Signals get mapped into this as
     /// 128 + SIGNUM, which is the convention shells follow.
     pub exit_code: Option<i32>,
+    /// The inputs the command ran with. Only set for commands run by actions.
+    pub inputs: Option<Arc<CommandExecutionInputs>>,
+}
+
+/// The input root of a command, flattened so it can be logged and compared across builds.
+#[derive(Debug)]
+pub struct CommandExecutionInputs {
+    pub root_digest: TrackedFileDigest,
+    /// Every file and symlink in the input root, in path order.
+    pub entries: Vec<buck2_data::CommandInput>,
+}
+
+impl CommandExecutionInputs {
+    pub fn new(paths: &CommandExecutionPaths) -> Self {
+        use buck2_data::command_input::Entry;
+
+        let input_directory = paths.input_directory();
+
+        let entries = input_directory
+            .ordered_walk()
+            .with_paths()
+            .filter_map(|(path, entry)| {
+                let (entry, is_executable) = match entry {
+                    DirectoryEntry::Dir(..) => return None,
+                    DirectoryEntry::Leaf(ActionDirectoryMember::File(f)) => {
+                        (Entry::FileDigest(f.digest.to_string()), f.is_executable)
+                    }
+                    DirectoryEntry::Leaf(ActionDirectoryMember::Symlink(s)) => {
+                        (Entry::SymlinkTarget(s.to_string()), false)
+                    }
+                    DirectoryEntry::Leaf(ActionDirectoryMember::ExternalSymlink(s)) => {
+                        (Entry::SymlinkTarget(s.target_str().to_owned()), false)
+                    }
+                };
+                Some(buck2_data::CommandInput {
+                    path: path.to_string(),
+                    entry: Some(entry),
+                    is_executable,
+                })
+            })
+            .collect();
+
+        Self {
+            root_digest: input_directory.fingerprint().dupe(),
+            entries,
+        }
+    }
 }
 
 /// Implement FromResidual so that it's easier to refactor functions returning a CommandExecutionResult
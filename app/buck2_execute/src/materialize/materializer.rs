@@ -661,9 +661,13 @@ pub trait DeferredMaterializerExtensions: Send + Sync {
 
     async fn get_ttl_refresh_log(&self) -> anyhow::Result<String>;
 
+    /// Clean artifacts last accessed before `keep_since_time`. If `max_size` is set, then also
+    /// evict the least recently accessed artifacts until the remaining ones fit in `max_size`
+    /// bytes.
     async fn clean_stale_artifacts(
         &self,
         keep_since_time: DateTime<Utc>,
+        max_size: Option<u64>,
         dry_run: bool,
         tracked_only: bool,
     ) -> anyhow::Result<buck2_cli_proto::CleanStaleResponse>;
@@ -166,6 +166,7 @@ impl RemoteExecutionClient {
         static_metadata: Arc<RemoteExecutionStaticMetadata>,
         logs_dir_path: Option<&str>,
         buck_out_path: &str,
+        digest_config: DigestConfig,
     ) -> anyhow::Result<Self> {
         let client = RemoteExecutionClientImpl::new(
             fb,
@@ -173,6 +174,7 @@ impl RemoteExecutionClient {
             static_metadata,
             logs_dir_path,
             buck_out_path,
+            digest_config,
         )
         .await?;
 
@@ -203,6 +205,7 @@ impl RemoteExecutionClient {
         static_metadata: Arc<RemoteExecutionStaticMetadata>,
         logs_dir_path: Option<&str>,
         buck_out_path: &str,
+        digest_config: DigestConfig,
     ) -> anyhow::Result<Self> {
         // Loop happens times-1 times at most
         for i in 1..times {
@@ -212,6 +215,7 @@ impl RemoteExecutionClient {
                 static_metadata.dupe(),
                 logs_dir_path,
                 buck_out_path,
+                digest_config,
             )
             .await
             {
@@ -232,6 +236,7 @@ impl RemoteExecutionClient {
             static_metadata,
             logs_dir_path,
             buck_out_path,
+            digest_config,
         )
         .await
     }
@@ -497,6 +502,7 @@ impl RemoteExecutionClientImpl {
         static_metadata: Arc<RemoteExecutionStaticMetadata>,
         maybe_logs_dir_path: Option<&str>,
         buck_out_path: &str,
+        digest_config: DigestConfig,
     ) -> anyhow::Result<Self> {
         let res: anyhow::Result<Self> = try {
             static DOWNLOAD_CONCURRENCY: EnvHelper<usize> =
@@ -516,6 +522,9 @@ impl RemoteExecutionClientImpl {
                 use remote_execution::EmbeddedCASDaemonClientCfg;
                 use remote_execution::RichClientMode;
 
+                // The internal client validates digests itself.
+                let _unused = digest_config;
+
                 let mut re_client_config = create_default_config();
                 re_client_config.action_cache_client_config.connection_count =
                     static_metadata.action_cache_connection_count;
@@ -622,7 +631,15 @@ impl RemoteExecutionClientImpl {
             let client = {
                 let _unused = (fb, maybe_logs_dir_path, buck_out_path);
 
-                REClientBuilder::build_and_connect(&static_metadata.0).await?
+                let client = REClientBuilder::build_and_connect(&static_metadata.0).await?;
+                client.check_digest_function(
+                    &digest_config
+                        .cas_digest_config()
+                        .preferred_algorithm()
+                        .kind()
+                        .to_string(),
+                )?;
+                client
             };
 
             Self {
@@ -86,6 +86,7 @@ struct RemoteExecutionConfig {
     static_metadata: Arc<RemoteExecutionStaticMetadata>,
     logs_dir_path: Option<String>,
     buck_out_path: String,
+    digest_config: DigestConfig,
 }
 
 impl RemoteExecutionConfig {
@@ -97,6 +98,7 @@ impl RemoteExecutionConfig {
             self.static_metadata.dupe(),
             self.logs_dir_path.as_deref(),
             &self.buck_out_path,
+            self.digest_config,
         )
         .await
     }
@@ -198,6 +200,7 @@ impl ReConnectionManager {
         static_metadata: Arc<RemoteExecutionStaticMetadata>,
         logs_dir_path: Option<String>,
         buck_out_path: String,
+        digest_config: DigestConfig,
     ) -> Self {
         Self {
             data: RwLock::new(Weak::new()),
@@ -208,6 +211,7 @@ impl ReConnectionManager {
                 static_metadata,
                 logs_dir_path,
                 buck_out_path,
+                digest_config,
             },
         }
     }
@@ -350,9 +350,10 @@ impl Uploader {
                     UploadRequest {
                         files_with_digest: Some(upload_files),
                         inlined_blobs_with_digest: Some(upload_blobs),
-                        // all find missing checks are done previously
-                        // and we can skip them and upload all digests
-                        upload_only_missing: false,
+                        // `find_missing` relies on the TTLs the client reports, which
+                        // can be stale or conservative, so have the client check what
+                        // the CAS is actually missing before uploading.
+                        upload_only_missing: true,
                         ..Default::default()
                     },
                 )
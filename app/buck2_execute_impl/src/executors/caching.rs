@@ -89,6 +89,7 @@ impl CachingExecutor {
         let action_cache_response = executor_stage_async(
             buck2_data::CacheQuery {
                 action_digest: action_digest.to_string(),
+                local: false,
             },
             re_client.action_cache(action_digest.dupe(), self.re_use_case),
         )
@@ -139,6 +140,7 @@ impl CachingExecutor {
             // TODO (torozco): We should deduplicate this and ActionExecutionKind.
             buck2_data::CacheHit {
                 action_digest: action_digest.to_string(),
+                local: false,
             }
             .into(),
             request.paths(),
@@ -19,6 +19,8 @@ use std::time::SystemTime;
 
 use anyhow::Context as _;
 use async_trait::async_trait;
+use buck2_common::executor_config::LocalExecutorOptions;
+use buck2_common::executor_config::LocalSandboxOptions;
 use buck2_common::liveliness_observer::LivelinessObserver;
 use buck2_common::liveliness_observer::LivelinessObserverExt;
 use buck2_common::local_resource_state::LocalResourceHolder;
@@ -75,6 +77,9 @@ use more_futures::cancellation::CancellationContext;
 use thiserror::Error;
 use tracing::info;
 
+use crate::local_sandbox::LocalSandbox;
+use crate::local_sandbox::SANDBOX_FAILURE_NOTE;
+
 #[derive(Debug, Error)]
 enum LocalExecutionError {
     #[error("Args list was empty")]
@@ -82,6 +87,9 @@ enum LocalExecutionError {
 
     #[error("Trying to execute a remote-only action on a local executor")]
     RemoteOnlyAction,
+
+    #[error("Sandboxed local execution requires the forkserver (see `buck2.forkserver`)")]
+    SandboxUnavailable,
 }
 
 #[derive(Clone)]
@@ -95,6 +103,7 @@ pub struct LocalExecutor {
     forkserver: Option<ForkserverClient>,
     #[allow(unused)]
     knobs: ExecutorGlobalKnobs,
+    sandbox: Option<Arc<LocalSandboxOptions>>,
 }
 
 impl LocalExecutor {
@@ -106,6 +115,7 @@ impl LocalExecutor {
         root: AbsNormPathBuf,
         forkserver: Option<ForkserverClient>,
         knobs: ExecutorGlobalKnobs,
+        options: &LocalExecutorOptions,
     ) -> Self {
         Self {
             artifact_fs,
@@ -115,6 +125,7 @@ impl LocalExecutor {
             root,
             forkserver,
             knobs,
+            sandbox: options.sandbox.dupe(),
         }
     }
 
@@ -130,6 +141,7 @@ impl LocalExecutor {
         env_inheritance: Option<&'a EnvironmentInheritance>,
         liveliness_observer: impl LivelinessObserver + 'static,
         disable_miniperf: bool,
+        sandbox: Option<&'a LocalSandbox>,
     ) -> impl futures::future::Future<
         Output = anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)>,
     > + Send
@@ -156,17 +168,20 @@ impl LocalExecutor {
                             env_inheritance,
                             liveliness_observer,
                             self.knobs.enable_miniperf && !disable_miniperf,
+                            sandbox,
                         )
                         .await
                     }
 
                     #[cfg(not(unix))]
                     {
-                        let _unused = (forkserver, disable_miniperf);
+                        let _unused = (forkserver, disable_miniperf, sandbox);
                         Err(anyhow::anyhow!("Forkserver is not supported off-UNIX"))
                     }
                 }
 
+                None if sandbox.is_some() => Err(LocalExecutionError::SandboxUnavailable.into()),
+
                 None => {
                     let mut cmd = background_command(exe);
                     cmd.current_dir(working_directory);
@@ -208,6 +223,13 @@ impl LocalExecutor {
             return manager.error("no_args", LocalExecutionError::NoArgs);
         }
 
+        if self.sandbox.is_some() && self.forkserver.is_none() {
+            return manager.error(
+                "local_sandbox_unavailable",
+                LocalExecutionError::SandboxUnavailable,
+            );
+        }
+
         match executor_stage_async(
             buck2_data::LocalStage {
                 stage: Some(buck2_data::LocalMaterializeInputs {}.into()),
@@ -250,6 +272,18 @@ impl LocalExecutor {
                 .resolve_scratch(tmpdir)
         });
 
+        let sandbox = match &self.sandbox {
+            Some(options) => {
+                match LocalSandbox::new(options, &self.artifact_fs, request, scratch_dir.as_deref())
+                {
+                    Ok(sandbox) => Some(sandbox),
+                    Err(e) => return manager.error("local_sandbox_prepare", e),
+                }
+            }
+            None => None,
+        };
+        let sandbox = sandbox.as_ref();
+
         let scratch_dir = &scratch_dir; // So it doesn't move in the block below.
 
         if let Err(e) = executor_stage_async(
@@ -382,6 +416,7 @@ impl LocalExecutor {
                         request.local_environment_inheritance(),
                         liveliness_observer,
                         request.disable_miniperf(),
+                        sandbox,
                     )
                     .await;
 
@@ -411,6 +446,15 @@ impl LocalExecutor {
             Err(e) => return manager.error("exec_failed", e), // TODO (torozco): Can this take CommandExecutionKind? Should this be a failure?
         };
 
+        let mut stderr = stderr;
+        if sandbox.is_some() {
+            if let GatherOutputStatus::Finished { exit_code, .. } = &status {
+                if *exit_code != 0 {
+                    stderr.extend_from_slice(SANDBOX_FAILURE_NOTE.as_bytes());
+                }
+            }
+        }
+
         let std_streams = CommandStdStreams::Local { stdout, stderr };
 
         match status {
@@ -878,6 +922,7 @@ mod unix {
         env_inheritance: Option<&EnvironmentInheritance>,
         liveliness_observer: impl LivelinessObserver + 'static,
         enable_miniperf: bool,
+        sandbox: Option<&LocalSandbox>,
     ) -> anyhow::Result<(GatherOutputStatus, Vec<u8>, Vec<u8>)> {
         let exe = exe.as_ref();
 
@@ -893,6 +938,7 @@ mod unix {
             env: vec![],
             timeout: command_timeout.try_map(|d| d.try_into())?,
             enable_miniperf,
+            sandbox: sandbox.map(|s| s.to_proto()),
         };
         apply_local_execution_environment(&mut req, working_directory, env, env_inheritance);
         forkserver
@@ -1077,6 +1123,7 @@ mod tests {
             temp.path().root().to_buf(),
             None,
             ExecutorGlobalKnobs::default(),
+            &LocalExecutorOptions::default(),
         );
 
         Ok((executor, temp.path().root().to_buf(), temp))
@@ -1097,6 +1144,7 @@ mod tests {
                 None,
                 NoopLivelinessObserver::create(),
                 false,
+                None,
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
@@ -1133,6 +1181,7 @@ mod tests {
                 Some(&EnvironmentInheritance::empty()),
                 NoopLivelinessObserver::create(),
                 false,
+                None,
             )
             .await?;
         assert!(matches!(status, GatherOutputStatus::Finished { exit_code, .. } if exit_code == 0));
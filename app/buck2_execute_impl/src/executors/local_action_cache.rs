@@ -0,0 +1,272 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_execute::execute::blocking::BlockingExecutor;
+use buck2_execute::execute::executor_stage_async;
+use buck2_execute::execute::kind::CommandExecutionKind;
+use buck2_execute::execute::manager::CommandExecutionManager;
+use buck2_execute::execute::manager::CommandExecutionManagerExt;
+use buck2_execute::execute::output::CommandStdStreams;
+use buck2_execute::execute::prepared::PreparedCommand;
+use buck2_execute::execute::prepared::PreparedCommandExecutor;
+use buck2_execute::execute::request::CommandExecutionOutput;
+use buck2_execute::execute::request::CommandExecutionRequest;
+use buck2_execute::execute::request::ExecutorPreference;
+use buck2_execute::execute::result::CommandExecutionMetadata;
+use buck2_execute::execute::result::CommandExecutionResult;
+use buck2_execute::execute::result::CommandExecutionStatus;
+use buck2_execute::materialize::materializer::Materializer;
+use dupe::Dupe;
+use indexmap::IndexMap;
+use more_futures::cancellation::CancellationContext;
+use tracing::info;
+
+use crate::executors::local::create_output_dirs;
+use crate::local_action_cache::LocalActionCache;
+
+/// A PreparedCommandExecutor that will check the local action cache before executing any actions
+/// using the underlying executor, and store the results of actions that ran locally in it.
+pub struct LocalActionCacheExecutor {
+    pub inner: Arc<dyn PreparedCommandExecutor>,
+    pub cache: Arc<LocalActionCache>,
+    pub artifact_fs: ArtifactFs,
+    pub materializer: Arc<dyn Materializer>,
+    pub blocking_executor: Arc<dyn BlockingExecutor>,
+    pub skip_cache_read: bool,
+    pub skip_cache_write: bool,
+}
+
+impl LocalActionCacheExecutor {
+    /// Whether the outputs of this request only depend on its action digest. Actions that reuse
+    /// their previous outputs or that use local resources (e.g. simulators) might not be.
+    fn is_cacheable(request: &CommandExecutionRequest) -> bool {
+        request.outputs_cleanup() && request.required_local_resources().is_empty()
+    }
+
+    async fn try_local_action_cache_fetch(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> ControlFlow<CommandExecutionResult, CommandExecutionManager> {
+        let request = command.request;
+        let action_digest = &command.prepared_action.action;
+        let digest_config = command.digest_config;
+
+        let cached = executor_stage_async(
+            buck2_data::CacheQuery {
+                action_digest: action_digest.to_string(),
+                local: true,
+            },
+            self.blocking_executor
+                .execute_io_inline(|| self.cache.lookup(action_digest, digest_config)),
+        )
+        .await;
+
+        let cached = match cached {
+            Ok(Some(cached)) => cached,
+            Ok(None) => return ControlFlow::Continue(manager),
+            Err(e) => {
+                // The cache is only an optimization, so don't fail the build over it.
+                tracing::warn!(
+                    "Local action cache lookup for `{}` failed: {:#}",
+                    action_digest,
+                    e
+                );
+                return ControlFlow::Continue(manager);
+            }
+        };
+
+        info!(
+            "Action result is cached locally, skipping execution of:\n```\n$ {}\n```\n for action `{}`",
+            request.args().join(" "),
+            action_digest,
+        );
+
+        let start_time = SystemTime::now();
+        let start = Instant::now();
+
+        // Validate and restore the cached outputs before claiming, so that if anything goes wrong
+        // we can still fall back to running the action.
+        let outputs = executor_stage_async(
+            buck2_data::CacheHit {
+                action_digest: action_digest.to_string(),
+                local: true,
+            },
+            async {
+                let mut outputs = IndexMap::new();
+                let mut to_declare = Vec::new();
+                for output in request.outputs() {
+                    let path = output.resolve(&self.artifact_fs).into_path();
+                    if let Some(value) = cached.artifact_value(&path, digest_config)? {
+                        to_declare.push((path, value.dupe()));
+                        outputs.insert(output.cloned(), value);
+                    }
+                }
+
+                create_output_dirs(
+                    &self.artifact_fs,
+                    request,
+                    self.materializer.dupe(),
+                    self.blocking_executor.dupe(),
+                    cancellations,
+                )
+                .await?;
+
+                self.blocking_executor
+                    .execute_io_inline(|| self.cache.restore(self.artifact_fs.fs(), &cached))
+                    .await?;
+
+                anyhow::Ok((outputs, to_declare))
+            },
+        )
+        .await;
+
+        let (outputs, to_declare) = match outputs {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                tracing::warn!(
+                    "Restoring `{}` from the local action cache failed: {:#}",
+                    action_digest,
+                    e
+                );
+                return ControlFlow::Continue(manager);
+            }
+        };
+
+        let manager = manager.claim().await;
+
+        if let Err(e) = self.materializer.declare_existing(to_declare).await {
+            return ControlFlow::Break(manager.error("local_action_cache", e));
+        }
+
+        let wall_time = start.elapsed();
+        let timing = CommandExecutionMetadata {
+            wall_time,
+            re_queue_time: None,
+            execution_time: wall_time,
+            start_time,
+            execution_stats: None,
+        };
+
+        ControlFlow::Break(manager.success(
+            CommandExecutionKind::LocalActionCache {
+                digest: action_digest.dupe(),
+            },
+            outputs,
+            CommandStdStreams::Local {
+                stdout: cached.stdout,
+                stderr: cached.stderr,
+            },
+            timing,
+        ))
+    }
+
+    /// Store the result of an action in the local action cache, if it was successful and ran
+    /// locally. Returns whether the result was stored.
+    async fn maybe_store(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        result: &CommandExecutionResult,
+    ) -> anyhow::Result<bool> {
+        match &result.report.status {
+            CommandExecutionStatus::Success {
+                execution_kind: CommandExecutionKind::Local { .. },
+            } => {}
+            _ => return Ok(false),
+        }
+
+        let (stdout, stderr) = match &result.report.std_streams {
+            CommandStdStreams::Local { stdout, stderr } => (stdout, stderr),
+            _ => return Ok(false),
+        };
+
+        let mut outputs = Vec::with_capacity(result.outputs.len());
+        for (output, value) in &result.outputs {
+            match output {
+                CommandExecutionOutput::BuildArtifact { .. } => {
+                    outputs.push((
+                        output.as_ref().resolve(&self.artifact_fs).into_path(),
+                        value.dupe(),
+                    ));
+                }
+                CommandExecutionOutput::TestPath { .. } => {
+                    // Those aren't declared in the materializer, and tests are expected to run
+                    // every time they are requested anyway.
+                    return Ok(false);
+                }
+            }
+        }
+
+        self.blocking_executor
+            .execute_io_inline(|| {
+                self.cache.store(
+                    &command.prepared_action.action,
+                    self.artifact_fs.fs(),
+                    &outputs,
+                    stdout,
+                    stderr,
+                )
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl PreparedCommandExecutor for LocalActionCacheExecutor {
+    async fn exec_cmd(
+        &self,
+        command: &PreparedCommand<'_, '_>,
+        manager: CommandExecutionManager,
+        cancellations: &CancellationContext,
+    ) -> CommandExecutionResult {
+        let cacheable = Self::is_cacheable(command.request);
+
+        let manager = if cacheable && !self.skip_cache_read {
+            self.try_local_action_cache_fetch(command, manager, cancellations)
+                .await?
+        } else {
+            manager
+        };
+
+        let res = self.inner.exec_cmd(command, manager, cancellations).await;
+
+        if cacheable && !self.skip_cache_write {
+            match self.maybe_store(command, &res).await {
+                Ok(stored) => {
+                    tracing::debug!(
+                        "Local action cache store for `{}`: {}",
+                        command.prepared_action.action,
+                        if stored { "stored" } else { "skipped" }
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Local action cache store for `{}` failed: {:#}",
+                        command.prepared_action.action,
+                        e
+                    );
+                }
+            }
+        }
+
+        res
+    }
+
+    fn is_local_execution_possible(&self, executor_preference: ExecutorPreference) -> bool {
+        self.inner.is_local_execution_possible(executor_preference)
+    }
+}
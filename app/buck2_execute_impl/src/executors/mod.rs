@@ -10,4 +10,5 @@
 pub mod caching;
 pub mod hybrid;
 pub mod local;
+pub mod local_action_cache;
 pub mod re;
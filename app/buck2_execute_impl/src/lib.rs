@@ -12,6 +12,8 @@
 #![feature(try_trait_v2)]
 
 pub mod executors;
+pub mod local_action_cache;
+mod local_sandbox;
 pub mod low_pass_filter;
 pub mod materializers;
 pub mod re;
@@ -0,0 +1,743 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! An on-disk action cache for actions that ran locally, for builds that don't have a remote
+//! action cache to fall back on.
+//!
+//! Results are keyed by action digest, just like the remote action cache. The outputs are stored
+//! in a content-addressed directory of blobs, and a sqlite db records which blobs each action
+//! produced, as well as when each action was last used. Once the blobs exceed the configured size,
+//! we evict the least recently used actions (and whatever blobs no other action needs).
+//!
+//! This lives outside of buck-out (see `InvocationPaths::local_action_cache_dir`), so it survives
+//! `buck2 clean`.
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use allocative::Allocative;
+use anyhow::Context;
+use buck2_common::file_ops::FileDigest;
+use buck2_common::file_ops::FileMetadata;
+use buck2_common::file_ops::TrackedFileDigest;
+use buck2_common::sqlite::KeyValueSqliteTable;
+use buck2_core::directory::Directory;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::directory::DirectoryIterator;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::paths::abs_norm_path::AbsNormPathBuf;
+use buck2_core::fs::paths::file_name::FileName;
+use buck2_core::fs::paths::forward_rel_path::ForwardRelativePath;
+use buck2_core::fs::project::ProjectRoot;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+use buck2_execute::artifact_value::ArtifactValue;
+use buck2_execute::digest_config::DigestConfig;
+use buck2_execute::directory::extract_artifact_value;
+use buck2_execute::directory::insert_file;
+use buck2_execute::directory::ActionDirectoryBuilder;
+use buck2_execute::directory::ActionDirectoryMember;
+use buck2_execute::execute::action_digest::ActionDigest;
+use chrono::Utc;
+use dupe::Dupe;
+use itertools::Itertools;
+use parking_lot::Mutex;
+use parking_lot::RwLock;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+
+/// Hand-maintained schema version for the local action cache db. Bump this when making a
+/// breaking change to the schema: the cache is thrown away when the version doesn't match.
+const DB_SCHEMA_VERSION: u64 = 1;
+
+const DB_FILENAME: &str = "db.sqlite";
+const BLOBS_DIR: &str = "blobs";
+
+const ACTIONS_TABLE_NAME: &str = "actions";
+const OUTPUTS_TABLE_NAME: &str = "outputs";
+const BLOBS_TABLE_NAME: &str = "blobs";
+
+/// Used to give unique names to blobs while we are writing them.
+static TMP_BLOB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// An entry in the outputs of a cached action. Directories are recorded so that we can restore
+/// empty ones.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CachedEntry {
+    Directory,
+    File(FileMetadata),
+}
+
+pub(crate) struct CachedActionResult {
+    /// All the entries in the outputs of the action, sorted by path, so that directories come
+    /// before their contents.
+    pub(crate) entries: Vec<(ProjectRelativePathBuf, CachedEntry)>,
+    pub(crate) stdout: Vec<u8>,
+    pub(crate) stderr: Vec<u8>,
+}
+
+impl CachedActionResult {
+    /// The value of the output at `path`, as it was when the action was cached.
+    pub(crate) fn artifact_value(
+        &self,
+        path: &ProjectRelativePath,
+        digest_config: DigestConfig,
+    ) -> anyhow::Result<Option<ArtifactValue>> {
+        let mut builder = ActionDirectoryBuilder::empty();
+        for (entry_path, entry) in &self.entries {
+            if !entry_path.starts_with(path) {
+                continue;
+            }
+            match entry {
+                CachedEntry::Directory => {
+                    let entry_path: &ProjectRelativePath = entry_path;
+                    builder.insert(
+                        entry_path,
+                        DirectoryEntry::Dir(ActionDirectoryBuilder::empty()),
+                    )?;
+                }
+                CachedEntry::File(meta) => insert_file(&mut builder, entry_path, meta.clone())?,
+            }
+        }
+        extract_artifact_value(&builder, path, digest_config)
+    }
+}
+
+#[derive(Allocative)]
+pub struct LocalActionCache {
+    root: AbsNormPathBuf,
+    max_bytes: u64,
+    #[allocative(skip)]
+    connection: Arc<Mutex<Connection>>,
+    /// Held for writing while we evict blobs, and for reading while we write or restore them, so
+    /// that we never delete a blob that is about to be referenced or read.
+    #[allocative(skip)]
+    eviction_lock: RwLock<()>,
+}
+
+impl LocalActionCache {
+    /// Open the cache in `root`, creating it if it doesn't exist. If the existing cache can't be
+    /// read (e.g. because it was created by a version of Buck2 that used a different schema), we
+    /// throw it away and start over.
+    pub fn open(root: AbsNormPathBuf, max_bytes: u64) -> anyhow::Result<Self> {
+        let db_path = root.join(FileName::unchecked_new(DB_FILENAME));
+        let versions =
+            HashMap::from([("schema_version".to_owned(), DB_SCHEMA_VERSION.to_string())]);
+
+        let existing: anyhow::Result<Arc<Mutex<Connection>>> = try {
+            if !db_path.exists() {
+                Err(anyhow::anyhow!("Path {} does not exist", db_path))?
+            }
+            let connection = open_connection(&db_path)?;
+            let versions_table = KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
+            let read_versions = versions_table.read_all()?;
+            if read_versions != versions {
+                Err(anyhow::anyhow!(
+                    "Expected versions {:?}. Found versions {:?} in sqlite db at {}",
+                    versions,
+                    read_versions,
+                    db_path
+                ))?
+            }
+            connection
+        };
+
+        let connection = match existing {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::debug!("Creating a new local action cache: {:#}", e);
+
+                // Delete the whole directory and not just the db, since without the db we don't
+                // know what the blobs are for.
+                if root.exists() {
+                    fs_util::remove_dir_all(&root)?;
+                }
+                fs_util::create_dir_all(&root)?;
+
+                let connection = open_connection(&db_path)?;
+                let versions_table =
+                    KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
+                create_tables(&connection.lock())?;
+                versions_table.create_table()?;
+                versions_table.insert_all(versions)?;
+                connection
+            }
+        };
+
+        Ok(Self {
+            root,
+            max_bytes,
+            connection,
+            eviction_lock: RwLock::new(()),
+        })
+    }
+
+    fn blob_path(&self, digest: &FileDigest) -> AbsNormPathBuf {
+        self.blob_path_for_hash(&digest.raw_digest().to_string())
+    }
+
+    fn blob_path_for_hash(&self, hash: &str) -> AbsNormPathBuf {
+        self.root
+            .join(ForwardRelativePath::unchecked_new(BLOBS_DIR))
+            .join(ForwardRelativePath::unchecked_new(&hash[..2]))
+            .join(FileName::unchecked_new(hash))
+    }
+
+    /// Find the result of `action_digest` in the cache. This counts as a use of this action for
+    /// the purposes of eviction.
+    pub(crate) fn lookup(
+        &self,
+        action_digest: &ActionDigest,
+        digest_config: DigestConfig,
+    ) -> anyhow::Result<Option<CachedActionResult>> {
+        let key = action_digest.to_string();
+        // Blobs we find here must not be evicted until we've checked the action's entries.
+        let _guard = self.eviction_lock.read();
+        let connection = self.connection.lock();
+
+        let streams = connection
+            .query_row(
+                &format!(
+                    "SELECT stdout, stderr FROM {} WHERE action_digest = ?1",
+                    ACTIONS_TABLE_NAME
+                ),
+                [&key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .with_context(|| format!("reading from sqlite table {}", ACTIONS_TABLE_NAME))?;
+
+        let (stdout, stderr) = match streams {
+            Some(streams) => streams,
+            None => return Ok(None),
+        };
+
+        let mut stmt = connection.prepare(&format!(
+            "SELECT path, file_digest, file_is_executable FROM {} WHERE action_digest = ?1 ORDER BY path",
+            OUTPUTS_TABLE_NAME
+        ))?;
+        let rows = stmt
+            .query_map([&key], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<bool>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("reading from sqlite table {}", OUTPUTS_TABLE_NAME))?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for (path, file_digest, file_is_executable) in rows {
+            let entry = match file_digest {
+                Some(file_digest) => {
+                    let (digest, _) =
+                        FileDigest::parse_digest(&file_digest, digest_config.cas_digest_config())
+                            .with_context(|| format!("Invalid digest: `{}`", file_digest))?;
+                    if !self.blob_path(&digest).exists() {
+                        // Someone deleted it from under us. Just treat this as a cache miss, and
+                        // we'll overwrite this entry.
+                        tracing::debug!("Missing blob `{}` in local action cache", digest);
+                        return Ok(None);
+                    }
+                    CachedEntry::File(FileMetadata {
+                        digest: TrackedFileDigest::new(digest, digest_config.cas_digest_config()),
+                        is_executable: file_is_executable.unwrap_or_default(),
+                    })
+                }
+                None => CachedEntry::Directory,
+            };
+            entries.push((ProjectRelativePathBuf::unchecked_new(path), entry));
+        }
+
+        connection
+            .execute(
+                &format!(
+                    "UPDATE {} SET last_access_time = ?1 WHERE action_digest = ?2",
+                    ACTIONS_TABLE_NAME
+                ),
+                rusqlite::params![Utc::now().timestamp_millis(), key],
+            )
+            .with_context(|| format!("updating sqlite table {}", ACTIONS_TABLE_NAME))?;
+
+        Ok(Some(CachedActionResult {
+            entries,
+            stdout,
+            stderr,
+        }))
+    }
+
+    /// Write the outputs of a cached action back to the project.
+    pub(crate) fn restore(
+        &self,
+        fs: &ProjectRoot,
+        result: &CachedActionResult,
+    ) -> anyhow::Result<()> {
+        let _guard = self.eviction_lock.read();
+
+        for (path, entry) in &result.entries {
+            let dest = fs.resolve(path);
+            match entry {
+                CachedEntry::Directory => fs_util::create_dir_all(&dest)?,
+                CachedEntry::File(meta) => {
+                    if let Some(parent) = dest.parent() {
+                        fs_util::create_dir_all(parent)?;
+                    }
+                    copy_file(&self.blob_path(meta.digest.data()), &dest)
+                        .with_context(|| format!("Error restoring `{}`", path))?;
+                    if meta.is_executable {
+                        fs_util::set_executable(&dest)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store the outputs of an action that just ran, which must be present in `fs`. Returns
+    /// whether the action was cached: we don't cache actions whose outputs contain symlinks, since
+    /// their values depend on the action's inputs, and we don't cache actions whose outputs would
+    /// not fit in the cache.
+    pub(crate) fn store(
+        &self,
+        action_digest: &ActionDigest,
+        fs: &ProjectRoot,
+        outputs: &[(ProjectRelativePathBuf, ArtifactValue)],
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> anyhow::Result<bool> {
+        let entries = match collect_entries(outputs) {
+            Some(entries) => entries,
+            None => return Ok(false),
+        };
+
+        let output_bytes: u64 = entries
+            .iter()
+            .filter_map(|(_, entry)| match entry {
+                CachedEntry::File(meta) => Some(meta.digest.size()),
+                CachedEntry::Directory => None,
+            })
+            .sum();
+        if output_bytes > self.max_bytes {
+            return Ok(false);
+        }
+
+        {
+            let _guard = self.eviction_lock.read();
+
+            for (path, entry) in &entries {
+                if let CachedEntry::File(meta) = entry {
+                    self.write_blob(meta.digest.data(), &fs.resolve(path))
+                        .with_context(|| format!("Error caching `{}`", path))?;
+                }
+            }
+
+            let key = action_digest.to_string();
+            let mut connection = self.connection.lock();
+            let tx = connection.transaction()?;
+            tx.execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {} (action_digest, stdout, stderr, last_access_time) VALUES (?1, ?2, ?3, ?4)",
+                    ACTIONS_TABLE_NAME
+                ),
+                rusqlite::params![key, stdout, stderr, Utc::now().timestamp_millis()],
+            )?;
+            tx.execute(
+                &format!(
+                    "DELETE FROM {} WHERE action_digest = ?1",
+                    OUTPUTS_TABLE_NAME
+                ),
+                [&key],
+            )?;
+            for (path, entry) in &entries {
+                let (file_digest, file_is_executable) = match entry {
+                    CachedEntry::File(meta) => {
+                        tx.execute(
+                            &format!(
+                                "INSERT OR IGNORE INTO {} (file_digest, size) VALUES (?1, ?2)",
+                                BLOBS_TABLE_NAME
+                            ),
+                            rusqlite::params![meta.digest.to_string(), meta.digest.size()],
+                        )?;
+                        (Some(meta.digest.to_string()), Some(meta.is_executable))
+                    }
+                    CachedEntry::Directory => (None, None),
+                };
+                tx.execute(
+                    &format!(
+                        "INSERT INTO {} (action_digest, path, file_digest, file_is_executable) VALUES (?1, ?2, ?3, ?4)",
+                        OUTPUTS_TABLE_NAME
+                    ),
+                    rusqlite::params![key, path.as_str(), file_digest, file_is_executable],
+                )?;
+            }
+            tx.commit()
+                .with_context(|| format!("Error caching action `{}`", action_digest))?;
+        }
+
+        self.evict()?;
+
+        Ok(true)
+    }
+
+    fn write_blob(&self, digest: &FileDigest, src: &AbsNormPathBuf) -> anyhow::Result<()> {
+        let blob_path = self.blob_path(digest);
+        if blob_path.exists() {
+            return Ok(());
+        }
+
+        let blob_dir = blob_path.parent().context("Blob has no parent")?;
+        fs_util::create_dir_all(blob_dir)?;
+
+        // Write to a temporary file first so that we never have a partially written blob.
+        let tmp_path = blob_dir.join(FileName::new(&format!(
+            "{}.{}.{}.tmp",
+            digest.raw_digest(),
+            std::process::id(),
+            TMP_BLOB_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ))?);
+        copy_file(src, &tmp_path)?;
+        fs_util::rename(&tmp_path, &blob_path)?;
+
+        Ok(())
+    }
+
+    /// The total size of the blobs in the cache.
+    fn size_bytes(&self) -> anyhow::Result<u64> {
+        let size: i64 = self
+            .connection
+            .lock()
+            .query_row(
+                &format!("SELECT COALESCE(SUM(size), 0) FROM {}", BLOBS_TABLE_NAME),
+                [],
+                |row| row.get(0),
+            )
+            .with_context(|| format!("reading from sqlite table {}", BLOBS_TABLE_NAME))?;
+        Ok(size as u64)
+    }
+
+    /// Evict the least recently used actions, one at a time, until the cache fits in `max_bytes`.
+    fn evict(&self) -> anyhow::Result<()> {
+        if self.size_bytes()? <= self.max_bytes {
+            return Ok(());
+        }
+
+        let _guard = self.eviction_lock.write();
+
+        while self.size_bytes()? > self.max_bytes {
+            let unreferenced = {
+                let mut connection = self.connection.lock();
+                let tx = connection.transaction()?;
+
+                let action = tx
+                    .query_row(
+                        &format!(
+                            "SELECT action_digest FROM {} ORDER BY last_access_time, rowid LIMIT 1",
+                            ACTIONS_TABLE_NAME
+                        ),
+                        [],
+                        |row| row.get::<_, String>(0),
+                    )
+                    .optional()?;
+
+                let action = match action {
+                    Some(action) => action,
+                    None => break,
+                };
+
+                tracing::debug!("Evicting `{}` from local action cache", action);
+
+                for table in [OUTPUTS_TABLE_NAME, ACTIONS_TABLE_NAME] {
+                    tx.execute(
+                        &format!("DELETE FROM {} WHERE action_digest = ?1", table),
+                        [&action],
+                    )?;
+                }
+
+                let unreferenced = tx
+                    .prepare(&format!(
+                        "SELECT file_digest FROM {} WHERE file_digest NOT IN (SELECT file_digest FROM {} WHERE file_digest IS NOT NULL)",
+                        BLOBS_TABLE_NAME, OUTPUTS_TABLE_NAME
+                    ))?
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for chunk in unreferenced.chunks(100) {
+                    tx.execute(
+                        &format!(
+                            "DELETE FROM {} WHERE file_digest IN ({})",
+                            BLOBS_TABLE_NAME,
+                            itertools::repeat_n("?", chunk.len()).join(",")
+                        ),
+                        rusqlite::params_from_iter(chunk),
+                    )?;
+                }
+
+                tx.commit()
+                    .context("Error evicting from local action cache")?;
+                unreferenced
+            };
+
+            for file_digest in unreferenced {
+                let hash = file_digest
+                    .split_once(':')
+                    .map_or(file_digest.as_str(), |(hash, _)| hash);
+                let blob_path = self.blob_path_for_hash(hash);
+                match std::fs::remove_file(&blob_path) {
+                    // Someone deleted it from under us, which is what we wanted anyway.
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    res => res.with_context(|| format!("Error evicting `{}`", blob_path))?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn open_connection(path: &AbsNormPathBuf) -> anyhow::Result<Arc<Mutex<Connection>>> {
+    let connection = Connection::open(path)?;
+    // TODO: make this work on Windows too
+    if cfg!(unix) {
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+    }
+    // Like the materializer state, we'd rather lose the cache on power loss than `fsync` during
+    // builds.
+    connection.pragma_update(None, "synchronous", "OFF")?;
+    Ok(Arc::new(Mutex::new(connection)))
+}
+
+fn create_tables(connection: &Connection) -> anyhow::Result<()> {
+    for sql in [
+        format!(
+            "CREATE TABLE {} (
+                action_digest           TEXT NOT NULL PRIMARY KEY,
+                stdout                  BLOB NOT NULL,
+                stderr                  BLOB NOT NULL,
+                last_access_time        INTEGER NOT NULL
+            )",
+            ACTIONS_TABLE_NAME
+        ),
+        format!(
+            "CREATE TABLE {} (
+                action_digest           TEXT NOT NULL,
+                path                    TEXT NOT NULL,
+                file_digest             TEXT NULL DEFAULT NULL,
+                file_is_executable      INTEGER NULL DEFAULT NULL,
+                PRIMARY KEY (action_digest, path)
+            )",
+            OUTPUTS_TABLE_NAME
+        ),
+        format!(
+            "CREATE INDEX {0}_file_digest ON {0} (file_digest)",
+            OUTPUTS_TABLE_NAME
+        ),
+        format!(
+            "CREATE TABLE {} (
+                file_digest             TEXT NOT NULL PRIMARY KEY,
+                size                    INTEGER NOT NULL
+            )",
+            BLOBS_TABLE_NAME
+        ),
+    ] {
+        tracing::trace!(sql = %sql, "creating table");
+        connection
+            .execute(&sql, [])
+            .with_context(|| format!("Error initializing local action cache: `{}`", sql))?;
+    }
+    Ok(())
+}
+
+/// Flatten the outputs of an action, or return `None` if they contain symlinks.
+fn collect_entries(
+    outputs: &[(ProjectRelativePathBuf, ArtifactValue)],
+) -> Option<Vec<(ProjectRelativePathBuf, CachedEntry)>> {
+    let mut entries = Vec::new();
+
+    for (path, value) in outputs {
+        match value.entry() {
+            DirectoryEntry::Leaf(ActionDirectoryMember::File(meta)) => {
+                entries.push((path.clone(), CachedEntry::File(meta.clone())));
+            }
+            DirectoryEntry::Dir(d) => {
+                entries.push((path.clone(), CachedEntry::Directory));
+                for (entry_path, entry) in d.ordered_walk().with_paths() {
+                    let entry_path = path.join(entry_path);
+                    match entry {
+                        DirectoryEntry::Dir(..) => {
+                            entries.push((entry_path, CachedEntry::Directory));
+                        }
+                        DirectoryEntry::Leaf(ActionDirectoryMember::File(meta)) => {
+                            entries.push((entry_path, CachedEntry::File(meta.clone())));
+                        }
+                        DirectoryEntry::Leaf(..) => return None,
+                    }
+                }
+            }
+            DirectoryEntry::Leaf(..) => return None,
+        }
+    }
+
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Some(entries)
+}
+
+/// Copy a file without carrying its permissions over, since blobs are shared by outputs that
+/// may or may not be executable.
+fn copy_file(src: &AbsNormPathBuf, dest: &AbsNormPathBuf) -> anyhow::Result<()> {
+    let mut reader = fs_util::open_file(src)?;
+    let mut writer = fs_util::create_file(dest)?;
+    std::io::copy(&mut reader, &mut writer)
+        .with_context(|| format!("Error copying `{}` to `{}`", src, dest))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use buck2_core::fs::project::ProjectRootTemp;
+    use buck2_execute::directory::insert_entry;
+    use buck2_execute::entry::build_entry_from_disk;
+
+    use super::*;
+
+    fn output_value(
+        fs: &ProjectRoot,
+        path: &ProjectRelativePath,
+        digest_config: DigestConfig,
+    ) -> anyhow::Result<ArtifactValue> {
+        let mut builder = ActionDirectoryBuilder::empty();
+        let entry =
+            build_entry_from_disk(fs.resolve(path), digest_config)?.context("missing output")?;
+        insert_entry(&mut builder, path, entry)?;
+        extract_artifact_value(&builder, path, digest_config)?.context("missing value")
+    }
+
+    fn cache_in(fs: &ProjectRoot, max_bytes: u64) -> anyhow::Result<LocalActionCache> {
+        LocalActionCache::open(
+            fs.resolve(ProjectRelativePath::unchecked_new("cache")),
+            max_bytes,
+        )
+    }
+
+    #[test]
+    fn test_store_and_restore() -> anyhow::Result<()> {
+        let digest_config = DigestConfig::testing_default();
+        let src = ProjectRootTemp::new()?;
+        let dest = ProjectRootTemp::new()?;
+        let cache = cache_in(src.path(), 1024)?;
+
+        let file = ProjectRelativePath::unchecked_new("out/file");
+        let dir = ProjectRelativePath::unchecked_new("out/dir");
+        src.path().write_file(file, "file", true)?;
+        src.path()
+            .write_file(ProjectRelativePath::unchecked_new("out/dir/a"), "a", false)?;
+        fs_util::create_dir_all(
+            src.path()
+                .resolve(ProjectRelativePath::unchecked_new("out/dir/empty")),
+        )?;
+
+        let outputs = vec![
+            (
+                file.to_owned(),
+                output_value(src.path(), file, digest_config)?,
+            ),
+            (
+                dir.to_owned(),
+                output_value(src.path(), dir, digest_config)?,
+            ),
+        ];
+
+        let action_digest =
+            ActionDigest::from_content(b"action", digest_config.cas_digest_config());
+        assert!(cache.lookup(&action_digest, digest_config)?.is_none());
+        assert!(cache.store(&action_digest, src.path(), &outputs, b"out", b"err")?);
+
+        let cached = cache
+            .lookup(&action_digest, digest_config)?
+            .context("expected a hit")?;
+        assert_eq!(cached.stdout, b"out");
+        assert_eq!(cached.stderr, b"err");
+        for (path, value) in &outputs {
+            assert_eq!(
+                cached.artifact_value(path, digest_config)?.as_ref(),
+                Some(value)
+            );
+        }
+
+        cache.restore(dest.path(), &cached)?;
+        for (path, value) in &outputs {
+            assert_eq!(&output_value(dest.path(), path, digest_config)?, value);
+        }
+
+        // The cache persists across restarts.
+        drop(cache);
+        let cache = cache_in(src.path(), 1024)?;
+        assert!(cache.lookup(&action_digest, digest_config)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlink_outputs_are_not_cached() -> anyhow::Result<()> {
+        let digest_config = DigestConfig::testing_default();
+        let fs = ProjectRootTemp::new()?;
+        let cache = cache_in(fs.path(), 1024)?;
+
+        let path = ProjectRelativePath::unchecked_new("out/link");
+        fs_util::create_dir_all(fs.path().resolve(ProjectRelativePath::unchecked_new("out")))?;
+        fs_util::symlink("target", fs.path().resolve(path))?;
+        let outputs = vec![(
+            path.to_owned(),
+            output_value(fs.path(), path, digest_config)?,
+        )];
+
+        let action_digest =
+            ActionDigest::from_content(b"action", digest_config.cas_digest_config());
+        assert!(!cache.store(&action_digest, fs.path(), &outputs, b"", b"")?);
+        assert!(cache.lookup(&action_digest, digest_config)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction() -> anyhow::Result<()> {
+        let digest_config = DigestConfig::testing_default();
+        let fs = ProjectRootTemp::new()?;
+        // Enough for two of the outputs below, but not three.
+        let cache = cache_in(fs.path(), 25)?;
+
+        let actions = ["a", "b", "c"].map(|name| {
+            ActionDigest::from_content(name.as_bytes(), digest_config.cas_digest_config())
+        });
+
+        for (i, action_digest) in actions.iter().enumerate() {
+            let path = ProjectRelativePathBuf::unchecked_new(format!("out/{}", i));
+            fs.path()
+                .write_file(&path, &i.to_string().repeat(10), false)?;
+            let value = output_value(fs.path(), &path, digest_config)?;
+            assert!(cache.store(action_digest, fs.path(), &[(path, value)], b"", b"")?);
+
+            if i == 1 {
+                // Use the first action, so that the second one is the least recently used.
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                assert!(cache.lookup(&actions[0], digest_config)?.is_some());
+            }
+        }
+
+        assert!(cache.lookup(&actions[0], digest_config)?.is_some());
+        assert!(cache.lookup(&actions[1], digest_config)?.is_none());
+        assert!(cache.lookup(&actions[2], digest_config)?.is_some());
+        assert!(cache.size_bytes()? <= 25);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use buck2_common::executor_config::LocalSandboxOptions;
+use buck2_core::directory::DirectoryEntry;
+use buck2_core::fs::artifact_path_resolver::ArtifactFs;
+use buck2_core::fs::project_rel_path::ProjectRelativePath;
+use buck2_execute::execute::request::CommandExecutionInput;
+use buck2_execute::execute::request::CommandExecutionRequest;
+
+/// Appended to the stderr of commands that fail in the sandbox, since we can't tell whether they
+/// failed because of it.
+pub(crate) const SANDBOX_FAILURE_NOTE: &str = "\nNote: this command ran in the local execution sandbox, where only the directories of its inputs are visible. If it failed to find a file, that file is probably missing from the action's inputs.\n";
+
+/// The paths a local action is allowed to access when it runs in a sandbox: its inputs and the
+/// toolchain paths can be read, and its outputs and scratch directory can be written to.
+#[derive(Debug)]
+pub(crate) struct LocalSandbox {
+    readonly_paths: Vec<PathBuf>,
+    writable_paths: Vec<PathBuf>,
+}
+
+impl LocalSandbox {
+    pub(crate) fn new(
+        options: &LocalSandboxOptions,
+        artifact_fs: &ArtifactFs,
+        request: &CommandExecutionRequest,
+        scratch_dir: Option<&ProjectRelativePath>,
+    ) -> anyhow::Result<Self> {
+        let fs = artifact_fs.fs();
+
+        let mut readonly_paths: Vec<PathBuf> =
+            options.toolchain_paths.iter().map(PathBuf::from).collect();
+
+        // Actions tend to have many inputs in few directories, so we mount the directories that
+        // contain the inputs rather than every input: that keeps the number of mounts (and the
+        // work to set up the sandbox) down. This means siblings of inputs are visible too.
+        // Directories inside others we mount are skipped when the sandbox is set up.
+        let mut input_dirs = BTreeSet::new();
+        for input in request.inputs() {
+            match input {
+                CommandExecutionInput::Artifact(group) => {
+                    for (artifact, value) in group.iter() {
+                        let path = artifact.resolve_path(artifact_fs)?;
+                        let is_dir = matches!(value.entry(), DirectoryEntry::Dir(..));
+                        input_dirs.insert(input_dir(&path, is_dir).to_owned());
+                    }
+                }
+                CommandExecutionInput::ActionMetadata(metadata) => {
+                    let path = artifact_fs
+                        .buck_out_path_resolver()
+                        .resolve_gen(&metadata.path);
+                    input_dirs.insert(input_dir(&path, false).to_owned());
+                }
+            }
+        }
+        readonly_paths.extend(input_dirs.iter().map(|d| fs.resolve(d).into_path_buf()));
+
+        let mut writable_paths = Vec::new();
+        for output in request.outputs() {
+            if let Some(path) = output.resolve(artifact_fs).path_to_create() {
+                writable_paths.push(fs.resolve(path).into_path_buf());
+            }
+        }
+        if let Some(scratch_dir) = scratch_dir {
+            writable_paths.push(fs.resolve(scratch_dir).into_path_buf());
+        }
+
+        Ok(Self {
+            readonly_paths,
+            writable_paths,
+        })
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn to_proto(&self) -> buck2_forkserver_proto::Sandbox {
+        use std::os::unix::ffi::OsStrExt;
+
+        let to_bytes = |paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|p| p.as_os_str().as_bytes().to_vec())
+                .collect()
+        };
+
+        buck2_forkserver_proto::Sandbox {
+            readonly_paths: to_bytes(&self.readonly_paths),
+            writable_paths: to_bytes(&self.writable_paths),
+        }
+    }
+}
+
+/// What to mount to make the input at `path` visible: the directory it's in, unless that's the
+/// project root, which would expose everything.
+fn input_dir(path: &ProjectRelativePath, is_dir: bool) -> &ProjectRelativePath {
+    if is_dir {
+        return path;
+    }
+    match path.parent() {
+        Some(parent) if !parent.is_empty() => parent,
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_dir() {
+        let path = ProjectRelativePath::unchecked_new;
+        assert_eq!(input_dir(path("src/foo.h"), false), path("src"));
+        assert_eq!(input_dir(path("src/dir"), true), path("src/dir"));
+        assert_eq!(input_dir(path("foo.h"), false), path("foo.h"));
+    }
+}
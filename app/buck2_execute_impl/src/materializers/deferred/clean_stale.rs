@@ -24,6 +24,7 @@ use buck2_events::dispatch::EventDispatcher;
 use buck2_execute::digest_config::DigestConfig;
 use buck2_execute::execute::clean_output_paths::CleanOutputPaths;
 use chrono::DateTime;
+use chrono::TimeZone;
 use chrono::Utc;
 use derivative::Derivative;
 use dupe::Dupe;
@@ -39,6 +40,7 @@ use crate::materializers::deferred::join_all_existing_futs;
 use crate::materializers::deferred::ArtifactMaterializationData;
 use crate::materializers::deferred::ArtifactMaterializationStage;
 use crate::materializers::deferred::ArtifactTree;
+use crate::materializers::deferred::CleanStaleConfiguration;
 use crate::materializers::deferred::DefaultIoHandler;
 use crate::materializers::deferred::DeferredMaterializerCommandProcessor;
 use crate::materializers::sqlite::MaterializerStateSqliteDb;
@@ -47,6 +49,9 @@ use crate::materializers::sqlite::MaterializerStateSqliteDb;
 #[derivative(Debug)]
 pub struct CleanStaleArtifacts {
     pub keep_since_time: DateTime<Utc>,
+    /// If set, also evict the least recently accessed artifacts until the artifacts we keep that
+    /// aren't in use by this daemon fit in this many bytes.
+    pub max_size: Option<u64>,
     pub dry_run: bool,
     pub tracked_only: bool,
     #[derivative(Debug = "ignore")]
@@ -83,6 +88,7 @@ impl ExtensionCommand<DefaultIoHandler> for CleanStaleArtifacts {
                 gather_clean_futures_for_stale_artifacts(
                     &mut processor.tree,
                     self.keep_since_time,
+                    self.max_size,
                     self.dry_run,
                     self.tracked_only,
                     sqlite_db,
@@ -108,6 +114,84 @@ impl ExtensionCommand<DefaultIoHandler> for CleanStaleArtifacts {
     }
 }
 
+/// Sent periodically when `buck2.clean_stale_max_size` is set. If the artifacts tracked by the
+/// materializer take more than the configured maximum size, evict the least recently accessed ones
+/// until they fit in the configured target size.
+///
+/// Unlike `clean --stale`, this runs while builds might be in progress, so it only ever deletes
+/// tracked artifacts that aren't used by the current daemon.
+#[derive(Debug)]
+pub(super) struct CleanStaleInBackground {
+    pub(super) config: CleanStaleConfiguration,
+}
+
+impl ExtensionCommand<DefaultIoHandler> for CleanStaleInBackground {
+    fn execute(
+        self: Box<Self>,
+        processor: &mut DeferredMaterializerCommandProcessor<DefaultIoHandler>,
+    ) {
+        if !processor.defer_write_actions {
+            return;
+        }
+        let sqlite_db = match processor.sqlite_db.as_mut() {
+            Some(sqlite_db) => sqlite_db,
+            None => return,
+        };
+
+        let tracked_size: u64 = processor
+            .tree
+            .iter_without_paths()
+            .filter_map(|data| match &data.stage {
+                ArtifactMaterializationStage::Materialized {
+                    metadata,
+                    active: false,
+                    ..
+                } => Some(metadata.size()),
+                _ => None,
+            })
+            .sum();
+        if tracked_size <= self.config.max_size {
+            return;
+        }
+
+        tracing::info!(
+            tracked_size,
+            max_size = self.config.max_size,
+            target_size = self.config.target_size,
+            "buck-out is over its maximum size, evicting artifacts"
+        );
+
+        let res = gather_clean_futures_for_stale_artifacts(
+            &mut processor.tree,
+            // Only evict based on size.
+            Utc.timestamp_opt(0, 0).unwrap(),
+            Some(self.config.target_size),
+            false,
+            true,
+            sqlite_db,
+            &processor.io,
+            processor.digest_config,
+            processor.cancellations,
+            &EventDispatcher::null(),
+        );
+
+        match res {
+            Ok((fut, response)) => {
+                processor.rt.spawn(async move {
+                    match fut.await {
+                        Ok(()) => tracing::info!(
+                            stats = ?response.stats,
+                            "finished evicting artifacts from buck-out"
+                        ),
+                        Err(e) => error!("Error evicting artifacts from buck-out: {:#}", e),
+                    }
+                });
+            }
+            Err(e) => error!("Error evicting artifacts from buck-out: {:#}", e),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Error)]
 #[error("Internal error: materializer state exists (num db entries: {}) but no artifacts were found by clean ({:?}). Not cleaning untracked artifacts.", .db_size, .stats)]
 pub(crate) struct CleanStaleError {
@@ -118,6 +202,7 @@ pub(crate) struct CleanStaleError {
 fn gather_clean_futures_for_stale_artifacts(
     tree: &mut ArtifactTree,
     keep_since_time: DateTime<Utc>,
+    max_size: Option<u64>,
     dry_run: bool,
     tracked_only: bool,
     sqlite_db: &mut MaterializerStateSqliteDb,
@@ -141,9 +226,16 @@ fn gather_clean_futures_for_stale_artifacts(
     let mut stats = buck2_data::CleanStaleStats::default();
     let mut paths_to_remove = Vec::new();
     let mut paths_to_invalidate = Vec::new();
+    let mut eviction_candidates = Vec::new();
 
     if tracked_only {
-        find_stale_tracked_only(tree, keep_since_time, &mut stats, &mut paths_to_invalidate)?
+        find_stale_tracked_only(
+            tree,
+            keep_since_time,
+            &mut stats,
+            &mut paths_to_invalidate,
+            &mut eviction_candidates,
+        )?
     } else {
         let gen_subtree = tree
             .get_subtree(&mut gen_path.iter())
@@ -166,10 +258,19 @@ fn gather_clean_futures_for_stale_artifacts(
             stats: &mut stats,
             paths_to_remove: &mut paths_to_remove,
             paths_to_invalidate: &mut paths_to_invalidate,
+            eviction_candidates: &mut eviction_candidates,
         }
         .visit_recursively(gen_path, gen_subtree)?;
     };
 
+    if let Some(max_size) = max_size {
+        for path in select_for_eviction(eviction_candidates, max_size, &mut stats) {
+            tracing::trace!(path = %path, "evicting to fit in max size");
+            paths_to_invalidate.push(path.clone());
+            paths_to_remove.push(path);
+        }
+    }
+
     // If no stale or retained artifact founds, the db should be empty.
     if stats.stale_artifact_count + stats.retained_artifact_count == 0 {
         // Just need to know if any entries exist, could be a simpler query.
@@ -241,6 +342,40 @@ pub fn get_size(path: &AbsNormPath) -> anyhow::Result<u64> {
     Ok(result)
 }
 
+/// A retained artifact that can be evicted if buck-out is over its maximum size.
+struct EvictionCandidate {
+    path: ProjectRelativePathBuf,
+    last_access_time: DateTime<Utc>,
+    size: u64,
+}
+
+/// Pick the least recently accessed artifacts to evict until the candidates we keep fit in
+/// `max_size` bytes, and move them from retained to stale in `stats`. Artifacts in use by this
+/// daemon aren't candidates, and don't count towards `max_size`: we can't evict them, and
+/// evicting everything else to make room for them would just throw away the cache.
+fn select_for_eviction(
+    mut candidates: Vec<EvictionCandidate>,
+    max_size: u64,
+    stats: &mut buck2_data::CleanStaleStats,
+) -> Vec<ProjectRelativePathBuf> {
+    candidates.sort_by_key(|c| c.last_access_time);
+
+    let mut candidates_bytes: u64 = candidates.iter().map(|c| c.size).sum();
+    let mut evicted = Vec::new();
+    for candidate in candidates {
+        if candidates_bytes <= max_size {
+            break;
+        }
+        candidates_bytes -= candidate.size;
+        stats.retained_artifact_count -= 1;
+        stats.retained_bytes -= candidate.size;
+        stats.stale_artifact_count += 1;
+        stats.stale_bytes += candidate.size;
+        evicted.push(candidate.path);
+    }
+    evicted
+}
+
 struct StaleFinder<'a> {
     fs: &'a ProjectRoot,
     dispatcher: &'a EventDispatcher,
@@ -250,6 +385,8 @@ struct StaleFinder<'a> {
     paths_to_remove: &'a mut Vec<ProjectRelativePathBuf>,
     /// Those paths will be invalidated in the materiaizer.
     paths_to_invalidate: &'a mut Vec<ProjectRelativePathBuf>,
+    /// Those paths are retained but can be evicted if we're over the max size.
+    eviction_candidates: &'a mut Vec<EvictionCandidate>,
 }
 
 impl<'a> StaleFinder<'a> {
@@ -338,12 +475,24 @@ impl<'a> StaleFinder<'a> {
                     self.paths_to_remove.push(path);
                 }
                 ArtifactTree::Data(box ArtifactMaterializationData {
-                    stage: ArtifactMaterializationStage::Materialized { metadata, .. },
+                    stage:
+                        ArtifactMaterializationStage::Materialized {
+                            active,
+                            last_access_time,
+                            metadata,
+                        },
                     ..
                 }) => {
                     tracing::trace!(path = %path, file_type = ?file_type, "marking as retained");
                     self.stats.retained_artifact_count += 1;
                     self.stats.retained_bytes += metadata.size();
+                    if !active {
+                        self.eviction_candidates.push(EvictionCandidate {
+                            path,
+                            last_access_time: *last_access_time,
+                            size: metadata.size(),
+                        });
+                    }
                 }
                 _ => {
                     // What we have on disk does not match what we have in the materializer (which is
@@ -362,12 +511,13 @@ fn find_stale_tracked_only(
     keep_since_time: DateTime<Utc>,
     stats: &mut buck2_data::CleanStaleStats,
     paths_to_invalidate: &mut Vec<ProjectRelativePathBuf>,
+    eviction_candidates: &mut Vec<EvictionCandidate>,
 ) -> anyhow::Result<()> {
     for (f_path, v) in tree.iter_with_paths() {
         if let ArtifactMaterializationStage::Materialized {
             last_access_time,
             active,
-            ..
+            metadata,
         } = &v.stage
         {
             let path = ProjectRelativePathBuf::from(f_path);
@@ -378,8 +528,71 @@ fn find_stale_tracked_only(
             } else {
                 tracing::trace!(path = %path, "retaining artifact");
                 stats.retained_artifact_count += 1;
+                stats.retained_bytes += metadata.size();
+                if !active {
+                    eviction_candidates.push(EvictionCandidate {
+                        path,
+                        last_access_time: *last_access_time,
+                        size: metadata.size(),
+                    });
+                }
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, last_access_time: i64, size: u64) -> EvictionCandidate {
+        EvictionCandidate {
+            path: ProjectRelativePathBuf::unchecked_new(path.to_owned()),
+            last_access_time: Utc.timestamp_opt(last_access_time, 0).unwrap(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_select_for_eviction() {
+        let candidates = vec![
+            candidate("b", 20, 10),
+            candidate("a", 10, 10),
+            candidate("c", 30, 10),
+        ];
+        let mut stats = buck2_data::CleanStaleStats {
+            retained_artifact_count: 4,
+            retained_bytes: 40,
+            ..Default::default()
+        };
+
+        // One of the retained artifacts is in use, so it doesn't count.
+        let evicted = select_for_eviction(candidates, 15, &mut stats);
+
+        assert_eq!(
+            evicted,
+            vec![
+                ProjectRelativePathBuf::unchecked_new("a".to_owned()),
+                ProjectRelativePathBuf::unchecked_new("b".to_owned()),
+            ]
+        );
+        assert_eq!(stats.retained_artifact_count, 2);
+        assert_eq!(stats.retained_bytes, 20);
+        assert_eq!(stats.stale_artifact_count, 2);
+        assert_eq!(stats.stale_bytes, 20);
+    }
+
+    #[test]
+    fn test_select_for_eviction_under_max_size() {
+        let candidates = vec![candidate("a", 10, 10)];
+        let mut stats = buck2_data::CleanStaleStats {
+            retained_artifact_count: 1,
+            retained_bytes: 10,
+            ..Default::default()
+        };
+
+        assert!(select_for_eviction(candidates, 10, &mut stats).is_empty());
+        assert_eq!(stats.retained_bytes, 10);
+    }
+}
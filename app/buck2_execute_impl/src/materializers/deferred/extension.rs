@@ -331,6 +331,7 @@ impl DeferredMaterializerExtensions for DeferredMaterializer {
     async fn clean_stale_artifacts(
         &self,
         keep_since_time: DateTime<Utc>,
+        max_size: Option<u64>,
         dry_run: bool,
         tracked_only: bool,
     ) -> anyhow::Result<buck2_cli_proto::CleanStaleResponse> {
@@ -340,6 +341,7 @@ impl DeferredMaterializerExtensions for DeferredMaterializer {
             .send(MaterializerCommand::Extension(Box::new(
                 CleanStaleArtifacts {
                     keep_since_time,
+                    max_size,
                     dry_run,
                     tracked_only,
                     sender,
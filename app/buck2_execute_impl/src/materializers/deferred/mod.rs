@@ -95,6 +95,7 @@ use tokio::sync::oneshot::error::TryRecvError;
 use tokio::time::Interval;
 use tracing::instrument;
 
+use crate::materializers::deferred::clean_stale::CleanStaleInBackground;
 use crate::materializers::deferred::extension::ExtensionCommand;
 use crate::materializers::deferred::file_tree::FileTree;
 use crate::materializers::deferred::io_handler::DefaultIoHandler;
@@ -161,6 +162,8 @@ pub struct DeferredMaterializerConfigs {
     pub materialize_final_artifacts: bool,
     pub defer_write_actions: bool,
     pub ttl_refresh: TtlRefreshConfiguration,
+    /// If set, periodically evict artifacts to keep buck-out under a maximum size.
+    pub clean_stale: Option<CleanStaleConfiguration>,
 }
 
 pub struct TtlRefreshConfiguration {
@@ -169,6 +172,17 @@ pub struct TtlRefreshConfiguration {
     pub enabled: bool,
 }
 
+#[derive(Debug, Clone, Copy, Dupe)]
+pub struct CleanStaleConfiguration {
+    /// How often to check the size of buck-out.
+    pub frequency: std::time::Duration,
+    /// Start evicting artifacts when the artifacts we track (and don't use) take more than this
+    /// many bytes.
+    pub max_size: u64,
+    /// Evict artifacts until the artifacts we track (and don't use) take at most this many bytes.
+    pub target_size: u64,
+}
+
 #[derive(Copy, Dupe, Clone)]
 struct MaterializerCounters {
     sent: &'static AtomicUsize,
@@ -946,6 +960,28 @@ impl DeferredMaterializer {
             }
         };
 
+        if let Some(clean_stale) = configs.clean_stale {
+            let command_sender = command_sender.dupe();
+            Handle::current().spawn(async move {
+                let mut ticker = tokio::time::interval_at(
+                    tokio::time::Instant::now() + clean_stale.frequency,
+                    clean_stale.frequency,
+                );
+                loop {
+                    ticker.tick().await;
+                    let command = CleanStaleInBackground {
+                        config: clean_stale,
+                    };
+                    if command_sender
+                        .send(MaterializerCommand::Extension(Box::new(command)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
         let command_thread = std::thread::Builder::new()
             .name("buck2-dm".to_owned())
             .spawn({
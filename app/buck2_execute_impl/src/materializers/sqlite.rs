@@ -17,6 +17,7 @@ use buck2_common::file_ops::FileDigest;
 use buck2_common::file_ops::FileMetadata;
 use buck2_common::file_ops::TrackedFileDigest;
 use buck2_common::sqlite::KeyValueSqliteTable;
+use buck2_common::sqlite::OrderedKeyValueSqliteTable;
 use buck2_core::directory::DirectoryEntry;
 use buck2_core::fs::fs_util;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
@@ -52,9 +53,10 @@ pub struct MaterializerStateIdentity(String);
 /// materializer state sqlite db schema! If you forget to bump this version,
 /// then you can fix forward by bumping the `buck2.sqlite_materializer_state_version`
 /// buckconfig in the project root's .buckconfig.
-pub const DB_SCHEMA_VERSION: u64 = 6;
+pub const DB_SCHEMA_VERSION: u64 = 8;
 
 const STATE_TABLE_NAME: &str = "materializer_state";
+const DEP_FILES_TABLE_NAME: &str = "dep_files";
 const IDENTITY_KEY: &str = "timestamp_on_initialization";
 
 pub type MaterializerState = Vec<(ProjectRelativePathBuf, (ArtifactMetadata, DateTime<Utc>))>;
@@ -636,6 +638,12 @@ impl MaterializerStateSqliteDb {
     pub fn identity(&self) -> &MaterializerStateIdentity {
         &self.identity
     }
+
+    /// The table storing dep file state. It lives in this db so that it's thrown away along with
+    /// the materializer state: dep files are only useful if we also know what's in buck-out.
+    pub fn dep_files_table(&self) -> OrderedKeyValueSqliteTable {
+        self.tables.dep_files_table.clone()
+    }
 }
 
 struct MaterializerStateTables {
@@ -651,6 +659,8 @@ struct MaterializerStateTables {
     created_by_table: KeyValueSqliteTable,
     /// Table for logging metadata associated with the buck2 that last updated the db.
     last_read_by_table: KeyValueSqliteTable,
+    /// Table storing dep file state, so that it survives daemon restarts.
+    dep_files_table: OrderedKeyValueSqliteTable,
 }
 
 impl MaterializerStateTables {
@@ -686,13 +696,17 @@ impl MaterializerStateTables {
         let materializer_state_table = MaterializerStateSqliteTable::new(connection.dupe());
         let versions_table = KeyValueSqliteTable::new("versions".to_owned(), connection.dupe());
         let created_by_table = KeyValueSqliteTable::new("created_by".to_owned(), connection.dupe());
-        let last_read_by_table = KeyValueSqliteTable::new("last_read_by".to_owned(), connection);
+        let last_read_by_table =
+            KeyValueSqliteTable::new("last_read_by".to_owned(), connection.dupe());
+        let dep_files_table =
+            OrderedKeyValueSqliteTable::new(DEP_FILES_TABLE_NAME.to_owned(), connection);
 
         Ok(Self {
             materializer_state_table,
             versions_table,
             created_by_table,
             last_read_by_table,
+            dep_files_table,
         })
     }
 
@@ -701,6 +715,7 @@ impl MaterializerStateTables {
         self.versions_table.create_table()?;
         self.created_by_table.create_table()?;
         self.last_read_by_table.create_table()?;
+        self.dep_files_table.create_table()?;
         Ok(())
     }
 }
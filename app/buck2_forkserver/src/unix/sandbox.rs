@@ -0,0 +1,519 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Run commands in a mount, user & network namespace where only the paths the client lists are
+//! visible. Everything else the command might look for simply doesn't exist.
+//!
+//! The sandbox is set up in the child, between fork and exec. Nothing can allocate there, so
+//! everything it needs is computed upfront in a [`SandboxPlan`].
+
+use std::collections::BTreeSet;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+
+use anyhow::Context as _;
+use buck2_forkserver_proto::Sandbox;
+
+/// Those are needed by pretty much anything, so they're always visible (and writable, so that e.g.
+/// `/dev/null` works).
+const SYSTEM_PATHS: &[&str] = &["/dev", "/proc"];
+
+/// Where a fresh tmpfs is mounted in the sandbox.
+const TMP: &str = "/tmp";
+
+#[derive(Debug, PartialEq, Eq)]
+struct BindMount {
+    src: CString,
+    dst: CString,
+    /// Directories to create (parents first) right before mounting, including `dst` itself if
+    /// `src` is a directory. They're created once the mounts before this one are in place, since
+    /// `dst` might be inside one of them.
+    dirs: Vec<CString>,
+    /// Whether `dst` is a file to create before mounting.
+    file: bool,
+    /// For read-only mounts, the flags to remount with. Those need to include the flags of the
+    /// original mount, since the kernel won't let an unprivileged user drop them.
+    remount_flags: Option<libc::c_ulong>,
+}
+
+/// Everything the child needs to do to enter the sandbox.
+#[derive(Debug)]
+pub(crate) struct SandboxPlan {
+    /// An empty directory to build the sandbox's root filesystem in.
+    root: CString,
+    tmp: CString,
+    /// `tmp` and its parents that need creating on the sandbox root, parents first.
+    tmp_dirs: Vec<CString>,
+    cwd: CString,
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    /// Mounts, parents first.
+    mounts: Vec<BindMount>,
+    /// Directories to create (parents first) once everything is mounted, e.g. the working
+    /// directory.
+    dirs: Vec<CString>,
+}
+
+impl SandboxPlan {
+    pub(crate) fn new(root: &Path, sandbox: &Sandbox, cwd: &Path) -> anyhow::Result<Self> {
+        let mut paths = Vec::new();
+        for (path, writable) in sandbox
+            .readonly_paths
+            .iter()
+            .map(|p| (p, false))
+            .chain(sandbox.writable_paths.iter().map(|p| (p, true)))
+        {
+            let path = Path::new(OsStr::from_bytes(path));
+            if !path.is_absolute() {
+                return Err(anyhow::anyhow!(
+                    "Sandbox path is not absolute: `{}`",
+                    path.display()
+                ));
+            }
+            paths.push((path.to_owned(), writable));
+        }
+        paths.extend(SYSTEM_PATHS.iter().map(|p| (PathBuf::from(p), true)));
+
+        // Paths compare component-wise, so parents sort before their children (and a directory's
+        // children before its siblings). Writable paths sort before read-only ones.
+        paths.sort_by(|(p1, w1), (p2, w2)| p1.cmp(p2).then(w2.cmp(w1)));
+
+        let mut kept: Vec<(PathBuf, bool)> = Vec::new();
+        // Directories that will exist by the time a mount is made. The root and its parents
+        // exist already.
+        let mut created: BTreeSet<PathBuf> = root.ancestors().map(|p| p.to_owned()).collect();
+        let mut mounts = Vec::new();
+
+        // The tmpfs is mounted before anything else.
+        let tmp = sandbox_path(root, Path::new(TMP));
+        let tmp_dirs = dirs_to_create(&tmp, &mut created);
+
+        for (path, writable) in paths {
+            // Mounting this would be redundant if the closest parent we mount (or the same path)
+            // already gives it at least the same access.
+            let covered = kept
+                .iter()
+                .rev()
+                .find(|(p, _)| path.starts_with(p))
+                .map_or(false, |(_, w)| *w || !writable);
+            if covered {
+                continue;
+            }
+
+            let metadata = match std::fs::metadata(&path) {
+                Ok(m) => m,
+                // Toolchain paths are not expected to exist everywhere (e.g. `/lib64`).
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(anyhow::Error::from(e)
+                        .context(format!("Error accessing `{}`", path.display())));
+                }
+            };
+
+            let dst = sandbox_path(root, &path);
+            let file = !metadata.is_dir();
+            let dirs = if file {
+                dirs_to_create(dst.parent().unwrap_or(root), &mut created)
+            } else {
+                dirs_to_create(&dst, &mut created)
+            };
+
+            let remount_flags = if writable {
+                None
+            } else {
+                Some(readonly_remount_flags(&path)?)
+            };
+
+            mounts.push(BindMount {
+                src: cstring(&path)?,
+                dst: cstring(&dst)?,
+                dirs: dirs
+                    .iter()
+                    .map(|d| cstring(d))
+                    .collect::<anyhow::Result<_>>()?,
+                file,
+                remount_flags,
+            });
+            kept.push((path, writable));
+        }
+
+        let dirs = dirs_to_create(&sandbox_path(root, cwd), &mut created);
+
+        // SAFETY: Those can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+        Ok(Self {
+            root: cstring(root)?,
+            tmp: cstring(&tmp)?,
+            tmp_dirs: tmp_dirs
+                .iter()
+                .map(|d| cstring(d))
+                .collect::<anyhow::Result<_>>()?,
+            cwd: cstring(cwd)?,
+            uid_map: format!("{} {} 1\n", uid, uid).into_bytes(),
+            gid_map: format!("{} {} 1\n", gid, gid).into_bytes(),
+            mounts,
+            dirs: dirs
+                .iter()
+                .map(|d| cstring(d))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    /// Enter the sandbox. This is meant to be called in the child, before exec, so this must not
+    /// allocate.
+    pub(crate) fn enter(&self) -> io::Result<()> {
+        // SAFETY: All the pointers we pass are valid nul-terminated strings, and we only change
+        // the state of the current (single-threaded) process.
+        unsafe {
+            check(libc::unshare(
+                libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET,
+            ))?;
+
+            // Keep our own uid and gid in the namespace, so that outputs are owned by the user.
+            write_file(b"/proc/self/setgroups\0", b"deny")?;
+            write_file(b"/proc/self/uid_map\0", &self.uid_map)?;
+            write_file(b"/proc/self/gid_map\0", &self.gid_map)?;
+
+            // Don't propagate any of what follows back to the host.
+            check(libc::mount(
+                ptr::null(),
+                b"/\0".as_ptr().cast(),
+                ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                ptr::null(),
+            ))?;
+
+            mount_tmpfs(&self.root)?;
+
+            // This goes first since other paths (e.g. the working directory) might be in it.
+            for dir in &self.tmp_dirs {
+                mkdir(dir)?;
+            }
+            mount_tmpfs(&self.tmp)?;
+
+            for mount in &self.mounts {
+                // The mount point might be inside a mount made earlier in this loop, in which
+                // case it exists already (it was checked on the host), and creating it just
+                // reports that.
+                for dir in &mount.dirs {
+                    mkdir(dir)?;
+                }
+                if mount.file {
+                    // Not `O_WRONLY`, which fails on read-only mounts even if the file exists.
+                    let fd = libc::open(
+                        mount.dst.as_ptr(),
+                        libc::O_RDONLY | libc::O_CREAT | libc::O_CLOEXEC,
+                        0o644,
+                    );
+                    check(fd)?;
+                    libc::close(fd);
+                }
+
+                check(libc::mount(
+                    mount.src.as_ptr(),
+                    mount.dst.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    ptr::null(),
+                ))?;
+
+                if let Some(flags) = mount.remount_flags {
+                    check(libc::mount(
+                        ptr::null(),
+                        mount.dst.as_ptr(),
+                        ptr::null(),
+                        flags,
+                        ptr::null(),
+                    ))?;
+                }
+            }
+
+            for dir in &self.dirs {
+                mkdir(dir)?;
+            }
+
+            // Anything that was not mounted explicitly can't be written to either.
+            check(libc::mount(
+                ptr::null(),
+                self.root.as_ptr(),
+                ptr::null(),
+                libc::MS_REMOUNT
+                    | libc::MS_BIND
+                    | libc::MS_RDONLY
+                    | libc::MS_NOSUID
+                    | libc::MS_NODEV,
+                ptr::null(),
+            ))?;
+
+            check(libc::chdir(self.root.as_ptr()))?;
+            check(libc::chroot(b".\0".as_ptr().cast()))?;
+            check(libc::chdir(self.cwd.as_ptr()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where `path` lives once the sandbox root is mounted.
+fn sandbox_path(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// `dir` and those of its parents that are not in `created` yet, parents first. They're added to
+/// `created`.
+fn dirs_to_create(dir: &Path, created: &mut BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|d| !created.contains(*d))
+        .map(|d| d.to_owned())
+        .collect();
+    dirs.reverse();
+    created.extend(dirs.iter().cloned());
+    dirs
+}
+
+fn cstring(path: &Path) -> anyhow::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Invalid path: `{}`", path.display()))
+}
+
+fn readonly_remount_flags(path: &Path) -> anyhow::Result<libc::c_ulong> {
+    let c_path = cstring(path)?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: c_path is a valid nul-terminated string, and stat is only read if this succeeds.
+    let stat = unsafe {
+        check(libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()))
+            .with_context(|| format!("Error accessing `{}`", path.display()))?;
+        stat.assume_init()
+    };
+
+    let mut flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY;
+    for (st, ms) in [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if stat.f_flag & st != 0 {
+            flags |= ms;
+        }
+    }
+
+    Ok(flags)
+}
+
+unsafe fn mount_tmpfs(dst: &CString) -> io::Result<()> {
+    check(libc::mount(
+        b"tmpfs\0".as_ptr().cast(),
+        dst.as_ptr(),
+        b"tmpfs\0".as_ptr().cast(),
+        libc::MS_NOSUID | libc::MS_NODEV,
+        ptr::null(),
+    ))
+}
+
+unsafe fn mkdir(dir: &CString) -> io::Result<()> {
+    if libc::mkdir(dir.as_ptr(), 0o755) != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EEXIST) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+unsafe fn write_file(path: &[u8], data: &[u8]) -> io::Result<()> {
+    let fd = libc::open(path.as_ptr().cast(), libc::O_WRONLY | libc::O_CLOEXEC);
+    check(fd)?;
+    let written = libc::write(fd, data.as_ptr().cast(), data.len());
+    libc::close(fd);
+    if written < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn bytes(path: &Path) -> Vec<u8> {
+        path.as_os_str().as_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_plan() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let host = tempdir.path().join("host");
+        let root = tempdir.path().join("sandbox");
+        fs::create_dir_all(host.join("src/dir"))?;
+        fs::write(host.join("src/file"), "")?;
+        fs::write(host.join("src/dir/nested"), "")?;
+        fs::create_dir_all(host.join("out"))?;
+        fs::create_dir_all(&root)?;
+
+        let sandbox = Sandbox {
+            readonly_paths: vec![
+                bytes(&host.join("src/file")),
+                bytes(&host.join("src/dir")),
+                bytes(&host.join("src/dir/nested")),
+                bytes(&host.join("does_not_exist")),
+            ],
+            writable_paths: vec![bytes(&host.join("out"))],
+        };
+
+        let plan = SandboxPlan::new(&root, &sandbox, &host)?;
+
+        let mounts = plan
+            .mounts
+            .iter()
+            .map(|m| (m.src.clone(), m.remount_flags.is_some()))
+            .filter(|(src, _)| Path::new(OsStr::from_bytes(src.as_bytes())).starts_with(&host))
+            .collect::<Vec<_>>();
+
+        // The nested file is already visible through its parent, and paths that don't exist are
+        // skipped.
+        assert_eq!(
+            mounts,
+            vec![
+                (cstring(&host.join("out"))?, false),
+                (cstring(&host.join("src/dir"))?, true),
+                (cstring(&host.join("src/file"))?, true),
+            ]
+        );
+
+        let mount = |path: &Path| -> anyhow::Result<&BindMount> {
+            let src = cstring(path)?;
+            plan.mounts
+                .iter()
+                .find(|m| m.src == src)
+                .ok_or_else(|| anyhow::anyhow!("Missing mount"))
+        };
+
+        let file_mount = mount(&host.join("src/file"))?;
+        assert!(file_mount.file);
+        assert!(
+            !file_mount
+                .dirs
+                .contains(&cstring(&sandbox_path(&root, &host.join("src/file")))?)
+        );
+
+        let dir_mount = mount(&host.join("src/dir"))?;
+        assert!(!dir_mount.file);
+        assert_eq!(
+            dir_mount.dirs.last(),
+            Some(&cstring(&sandbox_path(&root, &host.join("src/dir")))?)
+        );
+
+        // Every directory is only created once, and never the root or its parents.
+        let all_dirs = plan
+            .mounts
+            .iter()
+            .flat_map(|m| m.dirs.iter())
+            .chain(plan.tmp_dirs.iter())
+            .chain(plan.dirs.iter())
+            .collect::<Vec<_>>();
+        let host_dst = cstring(&sandbox_path(&root, &host))?;
+        assert_eq!(1, all_dirs.iter().filter(|d| ***d == host_dst).count());
+        assert!(!all_dirs.contains(&&cstring(&root)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_nested_in_readonly() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let host = tempdir.path().join("host");
+        let root = tempdir.path().join("sandbox");
+        fs::create_dir_all(host.join("repo/buck-out/tmp"))?;
+        fs::create_dir_all(host.join("repo-other"))?;
+        fs::write(host.join("repo/buck-out/log"), "")?;
+        fs::create_dir_all(&root)?;
+
+        let sandbox = Sandbox {
+            readonly_paths: vec![bytes(&host.join("repo")), bytes(&host.join("repo-other"))],
+            writable_paths: vec![
+                bytes(&host.join("repo/buck-out/tmp")),
+                bytes(&host.join("repo/buck-out/log")),
+            ],
+        };
+
+        let plan = SandboxPlan::new(&root, &sandbox, &host.join("repo"))?;
+
+        let mounts = plan
+            .mounts
+            .iter()
+            .filter(|m| Path::new(OsStr::from_bytes(m.src.as_bytes())).starts_with(&host))
+            .collect::<Vec<_>>();
+
+        // The read-only parent is mounted first, then what's writable inside it.
+        assert_eq!(
+            mounts.iter().map(|m| m.src.clone()).collect::<Vec<_>>(),
+            vec![
+                cstring(&host.join("repo"))?,
+                cstring(&host.join("repo/buck-out/log"))?,
+                cstring(&host.join("repo/buck-out/tmp"))?,
+                cstring(&host.join("repo-other"))?,
+            ]
+        );
+
+        // The mount points inside the parent are only created once the parent is mounted.
+        assert_eq!(
+            mounts[0].dirs.last(),
+            Some(&cstring(&sandbox_path(&root, &host.join("repo")))?)
+        );
+        assert_eq!(
+            mounts[1].dirs,
+            vec![cstring(&sandbox_path(&root, &host.join("repo/buck-out")))?]
+        );
+        assert!(mounts[1].file);
+        assert_eq!(
+            mounts[2].dirs,
+            vec![cstring(&sandbox_path(
+                &root,
+                &host.join("repo/buck-out/tmp")
+            ))?]
+        );
+        assert!(!mounts[2].file);
+
+        // The working directory is inside a mount.
+        assert!(plan.dirs.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_rejects_relative_paths() -> anyhow::Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        let sandbox = Sandbox {
+            readonly_paths: vec![b"relative/path".to_vec()],
+            writable_paths: vec![],
+        };
+        assert!(SandboxPlan::new(tempdir.path(), &sandbox, tempdir.path()).is_err());
+        Ok(())
+    }
+}
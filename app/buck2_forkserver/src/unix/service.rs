@@ -9,6 +9,7 @@
 
 use std::ffi::OsStr;
 use std::fs::OpenOptions;
+use std::io;
 use std::io::Write;
 use std::os::unix::ffi::OsStrExt;
 use std::pin::Pin;
@@ -24,6 +25,7 @@ use buck2_core::logging::LogConfigurationReloadHandle;
 use buck2_forkserver_proto::forkserver_server::Forkserver;
 use buck2_forkserver_proto::CommandRequest;
 use buck2_forkserver_proto::RequestEvent;
+use buck2_forkserver_proto::Sandbox;
 use buck2_forkserver_proto::SetLogFilterRequest;
 use buck2_forkserver_proto::SetLogFilterResponse;
 use buck2_grpc::to_tonic;
@@ -57,6 +59,11 @@ pub struct UnixForkserverService {
 
     /// State for Miniperf.
     miniperf: Option<MiniperfContainer>,
+
+    /// An empty directory sandboxed commands build their root filesystem on (in their own mount
+    /// namespace, so they can all share it).
+    #[cfg_attr(not(target_os = "linux"), allow(unused))]
+    sandbox_root: AbsNormPathBuf,
 }
 
 impl UnixForkserverService {
@@ -66,11 +73,58 @@ impl UnixForkserverService {
     ) -> anyhow::Result<Self> {
         let miniperf = MiniperfContainer::new(state_dir)?;
 
+        let sandbox_root = state_dir.join(ForwardRelativePath::unchecked_new("sandbox"));
+        fs_util::remove_all(&sandbox_root)?;
+        fs_util::create_dir_all(&sandbox_root)?;
+
         Ok(Self {
             log_reload_handle,
             miniperf,
+            sandbox_root,
         })
     }
+
+    #[cfg(target_os = "linux")]
+    async fn apply_sandbox(
+        &self,
+        cmd: &mut std::process::Command,
+        sandbox: &Sandbox,
+        cwd: Option<&OsStr>,
+    ) -> anyhow::Result<()> {
+        use std::os::unix::process::CommandExt;
+        use std::path::PathBuf;
+
+        use super::sandbox::SandboxPlan;
+
+        let cwd = PathBuf::from(cwd.context("Sandboxed commands must have a working directory")?);
+        let sandbox_root = self.sandbox_root.clone();
+        let sandbox = sandbox.clone();
+        // Planning stats every path in the sandbox, so keep it off the runtime's threads.
+        let plan = tokio::task::spawn_blocking(move || {
+            SandboxPlan::new(sandbox_root.as_path(), &sandbox, &cwd)
+        })
+        .await
+        .context("Error planning the sandbox")??;
+
+        // SAFETY: Entering the sandbox doesn't allocate or touch anything but this process.
+        unsafe {
+            cmd.pre_exec(move || plan.enter());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn apply_sandbox(
+        &self,
+        _cmd: &mut std::process::Command,
+        _sandbox: &Sandbox,
+        _cwd: Option<&OsStr>,
+    ) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Sandboxed local execution is only supported on Linux"
+        ))
+    }
 }
 
 #[async_trait::async_trait]
@@ -109,6 +163,7 @@ impl Forkserver for UnixForkserverService {
                 cwd,
                 timeout,
                 enable_miniperf,
+                sandbox,
             } = msg;
 
             let exe = OsStr::from_bytes(&exe);
@@ -119,8 +174,9 @@ impl Forkserver for UnixForkserverService {
                 .transpose()
                 .context("Invalid timeout")?;
 
+            // Miniperf isn't visible in the sandbox.
             let (mut cmd, miniperf_output) = match (enable_miniperf, &self.miniperf) {
-                (true, Some(miniperf)) => {
+                (true, Some(miniperf)) if sandbox.is_none() => {
                     let mut cmd = background_command(miniperf.miniperf.as_path());
                     let output_path = miniperf.allocate_output_path();
                     cmd.arg(output_path.as_path());
@@ -153,8 +209,18 @@ impl Forkserver for UnixForkserverService {
                 }
             }
 
+            if let Some(sandbox) = &sandbox {
+                self.apply_sandbox(&mut cmd, sandbox, cwd).await?;
+            }
+
             let mut cmd = prepare_command(cmd);
-            let child = cmd.spawn();
+            let mut child = cmd.spawn();
+
+            if sandbox.is_some() {
+                child = child.map_err(|e| {
+                    io::Error::new(e.kind(), format!("{} (in the local execution sandbox)", e))
+                });
+            }
 
             let timeout = timeout_into_cancellation(timeout);
 
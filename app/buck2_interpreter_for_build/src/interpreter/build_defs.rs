@@ -136,6 +136,7 @@ pub fn configure_base_globals(
         LibraryExtension::Print,
         LibraryExtension::RecordType,
         LibraryExtension::ExperimentalRegex,
+        LibraryExtension::SetType,
         LibraryExtension::StructType,
     ];
     let mut global_env = GlobalsBuilder::extended_by(&starlark_extensions)
@@ -91,8 +91,8 @@ impl QueryTarget for ConfiguredGraphNodeRef {
         Cow::Borrowed(self.0.rule_type().name())
     }
 
-    fn buildfile_path(&self) -> &BuildFilePath {
-        self.0.buildfile_path()
+    fn buildfile_path(&self) -> Option<&BuildFilePath> {
+        Some(self.0.buildfile_path())
     }
 
     // TODO(cjhopman): Use existential traits to remove the Box<> once they are stabilized.
@@ -11,6 +11,7 @@ use std::borrow::Cow;
 
 use buck2_core::build_file_path::BuildFilePath;
 use buck2_core::cells::cell_path::CellPath;
+use buck2_core::provider::label::ConfiguredProvidersLabel;
 use buck2_core::target::label::ConfiguredTargetLabel;
 use buck2_query::query::environment::LabeledNode;
 use buck2_query::query::environment::QueryTarget;
@@ -19,6 +20,7 @@ use serde::Serializer;
 
 use crate::attrs::attr_type::any_matches::AnyMatches;
 use crate::attrs::configured_attr::ConfiguredAttr;
+use crate::attrs::configured_traversal::ConfiguredAttrTraversal;
 use crate::attrs::display::AttrDisplayWithContextExt;
 use crate::attrs::fmt_context::AttrFmtContext;
 use crate::attrs::inspect_options::AttrInspectOptions;
@@ -40,8 +42,8 @@ impl QueryTarget for ConfiguredTargetNode {
         Cow::Borrowed(ConfiguredTargetNode::rule_type(self).name())
     }
 
-    fn buildfile_path(&self) -> &BuildFilePath {
-        ConfiguredTargetNode::buildfile_path(self)
+    fn buildfile_path(&self) -> Option<&BuildFilePath> {
+        Some(ConfiguredTargetNode::buildfile_path(self))
     }
 
     // TODO(cjhopman): Use existential traits to remove the Box<> once they are stabilized.
@@ -78,6 +80,31 @@ impl QueryTarget for ConfiguredTargetNode {
         attr.any_matches(filter)
     }
 
+    fn attr_labels_for_each<F: FnMut(Self::NodeRef) -> anyhow::Result<()>>(
+        &self,
+        attr: &Self::Attr,
+        func: F,
+    ) -> anyhow::Result<()> {
+        struct LabelsCollector<F> {
+            func: F,
+        }
+
+        // Configuration deps aren't configured, so they're not included here.
+        impl<F: FnMut(ConfiguredTargetLabel) -> anyhow::Result<()>> ConfiguredAttrTraversal
+            for LabelsCollector<F>
+        {
+            fn dep(&mut self, dep: &ConfiguredProvidersLabel) -> anyhow::Result<()> {
+                (self.func)(dep.target().dupe())
+            }
+
+            fn label(&mut self, label: &ConfiguredProvidersLabel) -> anyhow::Result<()> {
+                (self.func)(label.target().dupe())
+            }
+        }
+
+        attr.traverse(self.label().pkg(), &mut LabelsCollector { func })
+    }
+
     fn attrs_for_each<E, F: FnMut(&str, &Self::Attr) -> Result<(), E>>(
         &self,
         mut func: F,
@@ -8,9 +8,13 @@
  */
 
 use std::borrow::Cow;
+use std::sync::Arc;
 
+use buck2_core::buck_path::path::BuckPathRef;
 use buck2_core::build_file_path::BuildFilePath;
 use buck2_core::cells::cell_path::CellPath;
+use buck2_core::configuration::transition::id::TransitionId;
+use buck2_core::provider::label::ProvidersLabel;
 use buck2_core::target::label::TargetLabel;
 use buck2_query::query::environment::LabeledNode;
 use buck2_query::query::environment::QueryTarget;
@@ -22,6 +26,7 @@ use crate::attrs::display::AttrDisplayWithContextExt;
 use crate::attrs::fmt_context::AttrFmtContext;
 use crate::attrs::inspect_options::AttrInspectOptions;
 use crate::attrs::serialize::AttrSerializeWithContext;
+use crate::attrs::traversal::CoercedAttrTraversal;
 use crate::nodes::unconfigured::TargetNode;
 
 impl LabeledNode for TargetNode {
@@ -39,8 +44,8 @@ impl QueryTarget for TargetNode {
         Cow::Borrowed(TargetNode::rule_type(self).name())
     }
 
-    fn buildfile_path(&self) -> &BuildFilePath {
-        TargetNode::buildfile_path(self)
+    fn buildfile_path(&self) -> Option<&BuildFilePath> {
+        Some(TargetNode::buildfile_path(self))
     }
 
     // TODO(cjhopman): Use existential traits to remove the Box<> once they are stabilized.
@@ -67,6 +72,68 @@ impl QueryTarget for TargetNode {
         attr.any_matches(filter)
     }
 
+    fn attr_labels_for_each<F: FnMut(Self::NodeRef) -> anyhow::Result<()>>(
+        &self,
+        attr: &Self::Attr,
+        func: F,
+    ) -> anyhow::Result<()> {
+        struct LabelsCollector<F> {
+            func: F,
+        }
+
+        impl<'a, F: FnMut(TargetLabel) -> anyhow::Result<()>> CoercedAttrTraversal<'a>
+            for LabelsCollector<F>
+        {
+            fn dep(&mut self, dep: &'a TargetLabel) -> anyhow::Result<()> {
+                (self.func)(dep.dupe())
+            }
+
+            fn exec_dep(&mut self, dep: &'a TargetLabel) -> anyhow::Result<()> {
+                (self.func)(dep.dupe())
+            }
+
+            fn toolchain_dep(&mut self, dep: &'a TargetLabel) -> anyhow::Result<()> {
+                (self.func)(dep.dupe())
+            }
+
+            fn transition_dep(
+                &mut self,
+                dep: &'a TargetLabel,
+                _tr: &Arc<TransitionId>,
+            ) -> anyhow::Result<()> {
+                (self.func)(dep.dupe())
+            }
+
+            fn split_transition_dep(
+                &mut self,
+                dep: &'a TargetLabel,
+                _tr: &Arc<TransitionId>,
+            ) -> anyhow::Result<()> {
+                (self.func)(dep.dupe())
+            }
+
+            // Configuration deps are skipped, like in cquery, where they aren't configured: the
+            // same query should return the same targets in both.
+            fn configuration_dep(&mut self, _dep: &'a TargetLabel) -> anyhow::Result<()> {
+                Ok(())
+            }
+
+            fn platform_dep(&mut self, _dep: &'a TargetLabel) -> anyhow::Result<()> {
+                Ok(())
+            }
+
+            fn input(&mut self, _path: BuckPathRef) -> anyhow::Result<()> {
+                Ok(())
+            }
+
+            fn label(&mut self, label: &'a ProvidersLabel) -> anyhow::Result<()> {
+                (self.func)(label.target().dupe())
+            }
+        }
+
+        attr.traverse(self.label().pkg(), &mut LabelsCollector { func })
+    }
+
     fn special_attrs_for_each<E, F: FnMut(&str, &Self::Attr) -> Result<(), E>>(
         &self,
         mut func: F,
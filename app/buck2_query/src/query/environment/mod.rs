@@ -90,8 +90,10 @@ pub trait QueryTarget: LabeledNode + Dupe + Send + Sync + 'static {
 
     fn rule_type(&self) -> Cow<str>;
 
-    /// Return the path to the buildfile that defines this target, e.g. `fbcode//foo/bar/TARGETS`
-    fn buildfile_path(&self) -> &BuildFilePath;
+    /// Return the path to the buildfile that defines this target, e.g. `fbcode//foo/bar/TARGETS`.
+    /// This is `None` for nodes that aren't defined in a buildfile (e.g. actions of a BXL script)
+    /// or that don't know it up front (aquery's actions, see `QueryEnvironment::buildfile`).
+    fn buildfile_path(&self) -> Option<&BuildFilePath>;
 
     // TODO(cjhopman): Use existential traits to remove the Box<> once they are stabilized.
     fn deps<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Self::NodeRef> + Send + 'a>;
@@ -119,6 +121,15 @@ pub trait QueryTarget: LabeledNode + Dupe + Send + Sync + 'static {
         filter: &dyn Fn(&str) -> anyhow::Result<bool>,
     ) -> anyhow::Result<bool>;
 
+    /// Calls `func` with the targets referenced by `attr`, one of the attributes of this node.
+    fn attr_labels_for_each<F: FnMut(Self::NodeRef) -> anyhow::Result<()>>(
+        &self,
+        _attr: &Self::Attr,
+        _func: F,
+    ) -> anyhow::Result<()> {
+        Err(QueryError::FunctionUnimplemented("labels").into())
+    }
+
     fn special_attrs_for_each<E, F: FnMut(&str, &Self::Attr) -> Result<(), E>>(
         &self,
         func: F,
@@ -140,6 +151,12 @@ pub trait TraversalFilter<T: QueryTarget>: Send + Sync {
     async fn get_children(&self, target: &T) -> anyhow::Result<TargetSet<T>>;
 }
 
+/// Receives the targets of a streaming query as they are discovered, rather than once the whole
+/// result has been computed. Each target is added at most once.
+pub trait TargetSink<T: QueryTarget>: Send + Sync {
+    fn add(&mut self, target: &T) -> anyhow::Result<()>;
+}
+
 /// The environment of a Buck query that can evaluate queries to produce a
 /// result.
 #[async_trait]
@@ -177,6 +194,17 @@ pub trait QueryEnvironment: Send + Sync {
         depth: u32,
     ) -> anyhow::Result<()>;
 
+    /// Performs a traversal that visits each node as soon as it is available, in no particular
+    /// order. Environments that load nodes lazily should override this, the default falls back
+    /// to `dfs_postorder`.
+    async fn unordered_traversal(
+        &self,
+        root: &TargetSet<Self::Target>,
+        delegate: &mut dyn AsyncTraversalDelegate<Self::Target>,
+    ) -> anyhow::Result<()> {
+        self.dfs_postorder(root, delegate).await
+    }
+
     async fn allpaths(
         &self,
         from: &TargetSet<Self::Target>,
@@ -246,6 +274,12 @@ pub trait QueryEnvironment: Send + Sync {
         Ok(delegate.path)
     }
 
+    /// The buildfiles that define the given targets. Environments whose nodes don't know their
+    /// buildfile up front (like aquery) can override this to resolve it on demand.
+    async fn buildfile(&self, targets: &TargetSet<Self::Target>) -> anyhow::Result<FileSet> {
+        Ok(targets.buildfile())
+    }
+
     async fn allbuildfiles(&self, _universe: &TargetSet<Self::Target>) -> anyhow::Result<FileSet> {
         Err(anyhow::anyhow!(QueryError::FunctionUnimplemented(
             "allbuildfiles() is implemented only for uquery and cquery.",
@@ -373,6 +407,23 @@ pub trait QueryEnvironment: Send + Sync {
         Ok(ret)
     }
 
+    async fn labels(
+        &self,
+        attr: &str,
+        targets: &TargetSet<Self::Target>,
+    ) -> anyhow::Result<TargetSet<Self::Target>> {
+        let labels = targets.labels(attr)?;
+
+        let nodes = futures::future::try_join_all(labels.iter().map(|label| async move {
+            self.get_node(label)
+                .await
+                .with_context(|| format!("Error getting target {} referenced by `{}`", label, attr))
+        }))
+        .await?;
+
+        Ok(nodes.into_iter().collect())
+    }
+
     async fn testsof_with_default_target_platform(
         &self,
         targets: &TargetSet<Self::Target>,
@@ -431,17 +482,46 @@ pub async fn deps<Env: QueryEnvironment + ?Sized>(
     targets: &TargetSet<Env::Target>,
     depth: Option<i32>,
     filter: Option<&dyn TraversalFilter<Env::Target>>,
+) -> anyhow::Result<TargetSet<Env::Target>> {
+    deps_impl(env, targets, depth, filter, None).await
+}
+
+/// Like `deps`, but adds each target to `sink` as soon as it is reached. Unbounded traversals
+/// don't wait for the whole graph to load, so the targets are not added in postorder.
+pub async fn deps_streaming<Env: QueryEnvironment + ?Sized>(
+    env: &Env,
+    targets: &TargetSet<Env::Target>,
+    depth: Option<i32>,
+    filter: Option<&dyn TraversalFilter<Env::Target>>,
+    sink: &mut dyn TargetSink<Env::Target>,
+) -> anyhow::Result<()> {
+    deps_impl(env, targets, depth, filter, Some(sink)).await?;
+    Ok(())
+}
+
+async fn deps_impl<Env: QueryEnvironment + ?Sized>(
+    env: &Env,
+    targets: &TargetSet<Env::Target>,
+    depth: Option<i32>,
+    filter: Option<&dyn TraversalFilter<Env::Target>>,
+    sink: Option<&mut dyn TargetSink<Env::Target>>,
 ) -> anyhow::Result<TargetSet<Env::Target>> {
     let mut deps = TargetSet::new();
 
     struct Delegate<'a, Q: QueryTarget> {
         deps: &'a mut TargetSet<Q>,
         filter: Option<&'a dyn TraversalFilter<Q>>,
+        sink: Option<&'a mut dyn TargetSink<Q>>,
     }
 
     #[async_trait]
     impl<'a, Q: QueryTarget> AsyncTraversalDelegate<Q> for Delegate<'a, Q> {
         fn visit(&mut self, target: Q) -> anyhow::Result<()> {
+            if let Some(sink) = &mut self.sink {
+                if !self.deps.contains(target.node_ref()) {
+                    sink.add(&target)?;
+                }
+            }
             self.deps.insert(target);
             Ok(())
         }
@@ -469,32 +549,133 @@ pub async fn deps<Env: QueryEnvironment + ?Sized>(
         }
     }
 
+    let streaming = sink.is_some();
+    let mut delegate = Delegate {
+        deps: &mut deps,
+        filter,
+        sink,
+    };
     match depth {
         // For unbounded traversals, buck1 recommends specifying a large value. We'll accept either a negative (like -1) or
         // a large value as unbounded. We can't just call it optional because args are positional only in the query syntax
         // and so to specify a filter you need to specify a depth.
         Some(v) if (0..1_000_000_000).contains(&v) => {
-            env.depth_limited_traversal(
-                targets,
-                &mut Delegate {
-                    deps: &mut deps,
-                    filter,
-                },
-                v as u32,
-            )
-            .await?;
+            env.depth_limited_traversal(targets, &mut delegate, v as u32)
+                .await?;
+        }
+        _ if streaming => {
+            env.unordered_traversal(targets, &mut delegate).await?;
         }
         _ => {
-            env.dfs_postorder(
-                targets,
-                &mut Delegate {
-                    deps: &mut deps,
-                    filter,
-                },
-            )
-            .await?;
+            env.dfs_postorder(targets, &mut delegate).await?;
         }
     }
 
     Ok(deps)
 }
+
+/// Computes the same targets as `QueryEnvironment::rdeps`, adding each one to `sink` as soon as
+/// it is known to be within `depth` of `from`.
+///
+/// The regular `rdeps` needs a postorder traversal of the whole universe before it can emit
+/// anything. Here nodes are visited as they load: each node records itself as a parent of its
+/// deps, and whenever a node's distance to `from` becomes known (or shrinks) the new distance is
+/// propagated to the parents that have been loaded so far.
+pub async fn rdeps_streaming<Env: QueryEnvironment + ?Sized>(
+    env: &Env,
+    universe: &TargetSet<Env::Target>,
+    from: &TargetSet<Env::Target>,
+    depth: Option<i32>,
+    sink: &mut dyn TargetSink<Env::Target>,
+) -> anyhow::Result<()> {
+    struct Delegate<'a, Q: QueryTarget> {
+        from: &'a TargetSet<Q>,
+        max_distance: Option<usize>,
+        sink: &'a mut dyn TargetSink<Q>,
+
+        result: TargetSet<Q>,
+        /// The best known distance to `from` of each node within `max_distance`.
+        distance: HashMap<Q::NodeRef, usize>,
+        /// The loaded parents of each node.
+        parents: HashMap<Q::NodeRef, Vec<Q>>,
+    }
+
+    impl<'a, Q: QueryTarget> Delegate<'a, Q> {
+        fn update(&mut self, target: Q, distance: usize) -> anyhow::Result<()> {
+            let mut work = vec![(target, distance)];
+            while let Some((target, distance)) = work.pop() {
+                if let Some(max_distance) = self.max_distance {
+                    if distance > max_distance {
+                        continue;
+                    }
+                }
+                let node_ref = target.node_ref();
+                match self.distance.get(node_ref) {
+                    Some(known) if *known <= distance => continue,
+                    _ => {}
+                }
+                self.distance.insert(node_ref.clone(), distance);
+                if let Some(parents) = self.parents.get(node_ref) {
+                    work.extend(parents.iter().map(|p| (p.dupe(), distance + 1)));
+                }
+                if !self.result.contains(node_ref) {
+                    self.sink.add(&target)?;
+                    self.result.insert(target);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl<'a, Q: QueryTarget> AsyncTraversalDelegate<Q> for Delegate<'a, Q> {
+        fn visit(&mut self, target: Q) -> anyhow::Result<()> {
+            let mut distance = None;
+            for dep in target.deps() {
+                self.parents
+                    .entry(dep.clone())
+                    .or_default()
+                    .push(target.dupe());
+                if let Some(dep_distance) = self.distance.get(dep) {
+                    distance = Some(match distance {
+                        Some(d) => std::cmp::min(d, *dep_distance + 1),
+                        None => *dep_distance + 1,
+                    });
+                }
+            }
+            if self.from.contains(target.node_ref()) {
+                distance = Some(0);
+            }
+            match distance {
+                Some(distance) => self.update(target, distance),
+                None => Ok(()),
+            }
+        }
+
+        async fn for_each_child(
+            &mut self,
+            target: &Q,
+            func: &mut dyn ChildVisitor<Q>,
+        ) -> anyhow::Result<()> {
+            let res: anyhow::Result<_> = try {
+                for dep in target.deps() {
+                    func.visit(dep.clone())?;
+                }
+            };
+            res.with_context(|| format!("Error traversing children of `{}`", target.node_ref()))
+        }
+    }
+
+    env.unordered_traversal(
+        universe,
+        &mut Delegate {
+            from,
+            max_distance: depth.map(|v| v as usize),
+            sink,
+            result: TargetSet::new(),
+            distance: HashMap::new(),
+            parents: HashMap::new(),
+        },
+    )
+    .await
+}
@@ -14,6 +14,8 @@ use std::fmt;
 use std::sync::Arc;
 
 use buck2_query::query::traversal::async_depth_first_postorder_traversal;
+use buck2_query::query::traversal::async_depth_limited_traversal;
+use buck2_query::query::traversal::async_unordered_traversal;
 use buck2_query::query::traversal::NodeLookup;
 use derive_more::Display;
 use derive_more::From;
@@ -64,7 +66,7 @@ impl QueryTarget for TestTarget {
         unimplemented!()
     }
 
-    fn buildfile_path(&self) -> &BuildFilePath {
+    fn buildfile_path(&self) -> Option<&BuildFilePath> {
         unimplemented!()
     }
 
@@ -113,8 +115,23 @@ impl QueryTarget for TestTarget {
         unimplemented!()
     }
 
-    fn map_attr<R, F: FnMut(Option<&Self::Attr>) -> R>(&self, _key: &str, _func: F) -> R {
-        unimplemented!()
+    fn attr_labels_for_each<F: FnMut(Self::NodeRef) -> anyhow::Result<()>>(
+        &self,
+        _attr: &Self::Attr,
+        mut func: F,
+    ) -> anyhow::Result<()> {
+        // The only attribute is `deps`.
+        for dep in self.deps.iter() {
+            func(*dep)?;
+        }
+        Ok(())
+    }
+
+    fn map_attr<R, F: FnMut(Option<&Self::Attr>) -> R>(&self, key: &str, mut func: F) -> R {
+        match key {
+            "deps" => func(Some(&TestTargetAttr)),
+            _ => func(None),
+        }
     }
 
     fn call_stack(&self) -> Option<String> {
@@ -185,11 +202,19 @@ impl QueryEnvironment for TestEnv {
 
     async fn depth_limited_traversal(
         &self,
-        _root: &TargetSet<Self::Target>,
-        _delegate: &mut dyn AsyncTraversalDelegate<Self::Target>,
-        _depth: u32,
+        root: &TargetSet<Self::Target>,
+        delegate: &mut dyn AsyncTraversalDelegate<Self::Target>,
+        depth: u32,
     ) -> anyhow::Result<()> {
-        unimplemented!()
+        async_depth_limited_traversal(self, root.iter_names(), delegate, depth).await
+    }
+
+    async fn unordered_traversal(
+        &self,
+        root: &TargetSet<Self::Target>,
+        delegate: &mut dyn AsyncTraversalDelegate<Self::Target>,
+    ) -> anyhow::Result<()> {
+        async_unordered_traversal(self, root.iter_names(), delegate).await
     }
 
     async fn owner(&self, _paths: &FileSet) -> anyhow::Result<TargetSet<Self::Target>> {
@@ -209,6 +234,32 @@ impl TestEnv {
     }
 }
 
+/// Collects the targets added by a streaming query.
+#[derive(Default)]
+struct TestSink {
+    targets: Vec<TestTargetId>,
+}
+
+impl TargetSink<TestTarget> for TestSink {
+    fn add(&mut self, target: &TestTarget) -> anyhow::Result<()> {
+        self.targets.push(target.id);
+        Ok(())
+    }
+}
+
+/// Sorts the targets of `set`, since streaming queries don't preserve the order.
+fn sorted_ids(set: &TargetSet<TestTarget>) -> Vec<u64> {
+    let mut ids: Vec<u64> = set.iter().map(|t| t.id.0).collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn sorted_sink(sink: TestSink) -> Vec<u64> {
+    let mut ids: Vec<u64> = sink.targets.iter().map(|t| t.0).collect();
+    ids.sort_unstable();
+    ids
+}
+
 #[derive(Default)]
 pub struct TestEnvBuilder {
     graph: HashMap<u64, IndexSet<u64>>,
@@ -338,3 +389,79 @@ async fn test_nested_paths() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_labels() -> anyhow::Result<()> {
+    let mut env = TestEnvBuilder::default();
+    env.edge(1, 2);
+    env.edge(1, 3);
+    env.edge(2, 3);
+    env.edge(3, 4);
+    let env = env.build();
+
+    let labels = env.labels("deps", &env.set("1,2")?).await?;
+    assert_eq!(labels, env.set("2,3")?);
+
+    let labels = env.labels("srcs", &env.set("1,2")?).await?;
+    assert_eq!(labels, TargetSet::new());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rdeps_streaming() -> anyhow::Result<()> {
+    let mut env = TestEnvBuilder::default();
+    env.edge(1, 2);
+    env.edge(2, 3);
+    env.edge(3, 4);
+    env.edge(1, 10);
+    env.edge(10, 4);
+    env.edge(20, 3);
+    env.edge(30, 31);
+    let env = env.build();
+    let universe = env.set("1,20,30")?;
+
+    for depth in [None, Some(0), Some(1), Some(2), Some(3)] {
+        let expected = env.rdeps(&universe, &env.set("4")?, depth).await?;
+        let mut sink = TestSink::default();
+        rdeps_streaming(&env, &universe, &env.set("4")?, depth, &mut sink).await?;
+        assert_eq!(
+            sorted_ids(&expected),
+            sorted_sink(sink),
+            "depth {:?}",
+            depth
+        );
+    }
+
+    let mut sink = TestSink::default();
+    rdeps_streaming(&env, &universe, &env.set("4")?, Some(1), &mut sink).await?;
+    assert_eq!(vec![3, 4, 10], sorted_sink(sink));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deps_streaming() -> anyhow::Result<()> {
+    let mut env = TestEnvBuilder::default();
+    env.edge(1, 2);
+    env.edge(2, 3);
+    env.edge(1, 3);
+    env.edge(3, 4);
+    env.edge(5, 6);
+    let env = env.build();
+    let targets = env.set("1")?;
+
+    for depth in [None, Some(0), Some(1), Some(2)] {
+        let expected = env.deps(&targets, depth, None).await?;
+        let mut sink = TestSink::default();
+        deps_streaming(&env, &targets, depth, None, &mut sink).await?;
+        assert_eq!(
+            sorted_ids(&expected),
+            sorted_sink(sink),
+            "depth {:?}",
+            depth
+        );
+    }
+
+    Ok(())
+}
@@ -54,6 +54,14 @@ pub enum QueryError {
         "Operation + requires either two set types, or one set and one string, got `{0}` and `{1}`"
     )]
     UnionIncompatibleTypes(&'static str, &'static str),
+    #[error("variable `${0}` is not defined")]
+    UndefinedVariable(String),
+    #[error("function `{0}` is already defined")]
+    FunctionAlreadyDefined(String),
+    #[error(
+        "function `{0}` is used before it is defined, user-defined functions can only call functions defined before them"
+    )]
+    FunctionUsedBeforeDefinition(String),
     /// Used to propagate up an inner error. The inner span will mark where the inner error was (which itself may be the
     /// propagation of another error). This error will end up in a Spanned that indicates where this error (the propagation) occurs.
     /// Since QueryError has an impl for `From<Spanned<QueryError>>`, just propagating inner eval errors via `?` will hit this case (and
@@ -9,31 +9,76 @@
 
 //! Implementation of the cli and query_* attr query language.
 
-use buck2_query_parser::parse_expr;
+use std::sync::Arc;
+
+use buck2_query_parser::parse_query;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::Expr;
+use buck2_query_parser::ParsedQuery;
 use futures::FutureExt;
 use gazebo::prelude::*;
 use gazebo::variants::VariantName;
 
 use crate::__derive_refs::indexmap::IndexSet;
 use crate::query::environment::QueryEnvironment;
+use crate::query::environment::TargetSink;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::file_set::FileNode;
 use crate::query::syntax::simple::eval::file_set::FileSet;
 use crate::query::syntax::simple::eval::set::TargetSet;
+use crate::query::syntax::simple::eval::streaming::eval_streaming;
 use crate::query::syntax::simple::eval::values::QueryEvaluationValue;
 use crate::query::syntax::simple::eval::values::QueryResult;
 use crate::query::syntax::simple::eval::values::QueryValue;
+use crate::query::syntax::simple::functions::defined::DefinedQueryFunctions;
+use crate::query::syntax::simple::functions::AugmentedQueryFunctions;
 use crate::query::syntax::simple::functions::QueryFunctions;
+
+/// A `$name` binding introduced by a `let` or by a call to a user-defined function. Bindings form
+/// a linked list so that nested scopes can share their parent scopes.
+struct QueryBinding<Env: QueryEnvironment> {
+    name: String,
+    value: QueryValue<Env::Target>,
+    parent: Option<Arc<QueryBinding<Env>>>,
+}
+
 pub struct QueryEvaluator<'e, Env: QueryEnvironment> {
     env: &'e Env,
     functions: &'e dyn QueryFunctions<Env = Env>,
+    bindings: Option<Arc<QueryBinding<Env>>>,
 }
 
 impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
     pub fn new(env: &'e Env, functions: &'e dyn QueryFunctions<Env = Env>) -> Self {
-        Self { env, functions }
+        Self {
+            env,
+            functions,
+            bindings: None,
+        }
+    }
+
+    /// Returns an evaluator that sees all of this evaluator's bindings plus `$name` bound to `value`.
+    pub(crate) fn bind(&self, name: &str, value: QueryValue<Env::Target>) -> Self {
+        Self {
+            env: self.env,
+            functions: self.functions,
+            bindings: Some(Arc::new(QueryBinding {
+                name: name.to_owned(),
+                value,
+                parent: self.bindings.clone(),
+            })),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&QueryValue<Env::Target>> {
+        let mut binding = self.bindings.as_deref();
+        while let Some(b) = binding {
+            if b.name == name {
+                return Some(&b.value);
+            }
+            binding = b.parent.as_deref();
+        }
+        None
     }
 
     pub fn env(&self) -> &Env {
@@ -105,6 +150,16 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
 
                 Ok(files.into())
             }
+            Expr::Let { name, value, body } => {
+                // The value is evaluated once, references to it in the body share the result.
+                let value = self.eval(value).await?.value;
+                let evaluator = self.bind(name, value);
+                Ok(evaluator.eval(body).await?.value)
+            }
+            Expr::Variable(name) => match self.lookup(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(QueryError::UndefinedVariable((*name.fragment()).to_owned())),
+            },
         }
     }
 
@@ -117,17 +172,66 @@ impl<'e, Env: QueryEnvironment> QueryEvaluator<'e, Env> {
         async move { expr.span(self.eval_internal(&expr.value).await) }.boxed()
     }
 
+    /// Evaluates a query, which may start with definitions of user-defined functions.
     pub async fn eval_query<'a>(
         &self,
         query: &str,
     ) -> anyhow::Result<QueryEvaluationValue<Env::Target>> {
-        let parsed_query = parse_expr(query)?;
-        match self.eval_parsed_query(&parsed_query).await {
+        let parsed_query = parse_query(query)?;
+        let defined_functions = self.defined_functions(&parsed_query, query)?;
+        let functions =
+            AugmentedQueryFunctions::augment(self.functions, Box::new(defined_functions));
+        match self
+            .with_functions(&functions)
+            .eval_parsed_query(&parsed_query.expr)
+            .await
+        {
             Ok(v) => Ok(v.value),
             Err(e) => Err(QueryError::convert_error(e, query)),
         }
     }
 
+    /// Evaluates a query, adding the resulting targets to `sink` as they are found. Only a
+    /// top-level `deps()` or `rdeps()` is actually evaluated incrementally, other queries add
+    /// their targets once the whole result is known.
+    pub async fn eval_query_streaming(
+        &self,
+        query: &str,
+        sink: &mut dyn TargetSink<Env::Target>,
+    ) -> anyhow::Result<()> {
+        let parsed_query = parse_query(query)?;
+        let defined_functions = self.defined_functions(&parsed_query, query)?;
+        let functions =
+            AugmentedQueryFunctions::augment(self.functions, Box::new(defined_functions));
+        match eval_streaming(&self.with_functions(&functions), &parsed_query.expr, sink).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(QueryError::convert_error(e, query)),
+        }
+    }
+
+    fn defined_functions<'a>(
+        &self,
+        parsed_query: &'a ParsedQuery<'a>,
+        query: &str,
+    ) -> anyhow::Result<DefinedQueryFunctions<'a, Env>> {
+        DefinedQueryFunctions::new(self.functions, &parsed_query.definitions)
+            .map_err(|e| QueryError::convert_error(e, query))
+    }
+
+    fn with_functions<'f>(
+        &self,
+        functions: &'f dyn QueryFunctions<Env = Env>,
+    ) -> QueryEvaluator<'f, Env>
+    where
+        'e: 'f,
+    {
+        QueryEvaluator {
+            env: self.env,
+            functions,
+            bindings: self.bindings.clone(),
+        }
+    }
+
     pub async fn eval_parsed_query<'a>(
         &self,
         expr: &Spanned<Expr<'a>>,
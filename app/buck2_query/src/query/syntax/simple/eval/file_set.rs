@@ -93,4 +93,8 @@ impl FileSet {
     pub fn len(&self) -> usize {
         self.files.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
 }
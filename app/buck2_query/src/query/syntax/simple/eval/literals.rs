@@ -9,23 +9,30 @@
 
 //! Implementation of the cli and query_* attr query language.
 
-use buck2_query_parser::parse_expr;
+use buck2_query_parser::parse_query;
 use buck2_query_parser::placeholder::QUERY_PERCENT_S_PLACEHOLDER;
 use starlark_map::small_set::SmallSet;
 
+use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::values::QueryResultExt;
+use crate::query::syntax::simple::functions::defined::DefinedQueryFunctions;
+use crate::query::syntax::simple::functions::AugmentedQueryFunctions;
 use crate::query::syntax::simple::functions::QueryFunctions;
 use crate::query::syntax::simple::functions::QueryFunctionsVisitLiterals;
 use crate::query::syntax::simple::functions::QueryLiteralVisitor;
 
-/// Look through the expression to find all the target literals.
+/// Look through the expression (and the bodies of any user-defined functions it calls) to find all
+/// the target literals.
 /// Adds those that are found to `result` set.
 pub fn extract_target_literals<F: QueryFunctions>(
     functions: &F,
     query: &str,
     result: &mut SmallSet<String>,
 ) -> anyhow::Result<()> {
-    let parsed = parse_expr(query)?;
+    let parsed = parse_query(query)?;
+    let defined_functions = DefinedQueryFunctions::<F::Env>::new(functions, &parsed.definitions)
+        .map_err(|e| QueryError::convert_error(e, query))?;
+    let functions = AugmentedQueryFunctions::augment(functions, Box::new(defined_functions));
     struct LiteralExtractor<'a> {
         literals: &'a mut SmallSet<String>,
     }
@@ -39,7 +46,7 @@ pub fn extract_target_literals<F: QueryFunctions>(
     }
     let mut visitor = LiteralExtractor { literals: result };
     functions
-        .visit_literals(&mut visitor, &parsed)
+        .visit_literals(&mut visitor, &parsed.expr)
         .into_anyhow(query)?;
     Ok(())
 }
@@ -14,5 +14,6 @@ pub mod label_indexed;
 pub mod literals;
 pub mod multi_query;
 pub mod set;
+mod streaming;
 pub mod tests;
 pub mod values;
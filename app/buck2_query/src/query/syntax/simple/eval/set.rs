@@ -18,7 +18,6 @@ use fancy_regex::Regex;
 use indexmap::IndexSet;
 
 use crate::query::environment::QueryTarget;
-use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::file_set::FileNode;
 use crate::query::syntax::simple::eval::file_set::FileSet;
 use crate::query::syntax::simple::eval::label_indexed;
@@ -67,7 +66,9 @@ impl<T: QueryTarget> TargetSet<T> {
     pub fn buildfile(&self) -> FileSet {
         let mut files = IndexSet::new();
         for target in self.targets.iter() {
-            files.insert(FileNode(target.buildfile_path().path()));
+            if let Some(buildfile_path) = target.buildfile_path() {
+                files.insert(FileNode(buildfile_path.path()));
+            }
         }
         FileSet::new(files)
     }
@@ -83,12 +84,20 @@ impl<T: QueryTarget> TargetSet<T> {
         Ok(FileSet::new(files))
     }
 
-    // TODO(cjhopman): Does this even make sense?
-    // TODO(cjhopman): I think this needs a heap to allocate values
-    pub fn labels(&self, _attr: &str) -> anyhow::Result<()> {
-        Err(anyhow::anyhow!(QueryError::FunctionUnimplemented(
-            "labels()"
-        )))
+    /// The targets referenced by the attribute `attr` of the targets in this set. Targets that
+    /// don't have this attribute are ignored.
+    pub fn labels(&self, attr: &str) -> anyhow::Result<IndexSet<T::NodeRef>> {
+        let mut labels = IndexSet::new();
+        for target in self.targets.iter() {
+            target.map_attr(attr, |val| match val {
+                None => Ok(()),
+                Some(v) => target.attr_labels_for_each(v, |label| {
+                    labels.insert(label);
+                    Ok(())
+                }),
+            })?;
+        }
+        Ok(labels)
     }
 
     pub fn union(&self, right: &TargetSet<T>) -> TargetSet<T> {
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Evaluation of queries whose results are streamed to a `TargetSink`.
+
+use std::marker::PhantomData;
+
+use buck2_query_parser::spanned::Spanned;
+use buck2_query_parser::Expr;
+
+use crate::query::environment::rdeps_streaming;
+use crate::query::environment::QueryEnvironment;
+use crate::query::environment::TargetSink;
+use crate::query::syntax::simple::eval::error::QueryError;
+use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::eval::set::TargetSet;
+use crate::query::syntax::simple::eval::values::QueryEvaluationValue;
+use crate::query::syntax::simple::eval::values::QueryResult;
+use crate::query::syntax::simple::functions::deps::DepsFunction;
+use crate::query::syntax::simple::functions::helpers::eval_arg;
+use crate::query::syntax::simple::functions::helpers::CapturedExpr;
+
+/// Evaluates `expr`, adding the targets to `sink`. A top-level `deps()` or `rdeps()` adds targets
+/// as the traversal reaches them, anything else is evaluated fully first.
+pub(crate) async fn eval_streaming<'a, Env: QueryEnvironment>(
+    evaluator: &QueryEvaluator<'a, Env>,
+    expr: &'a Spanned<Expr<'a>>,
+    sink: &mut dyn TargetSink<Env::Target>,
+) -> QueryResult<()> {
+    match &expr.value {
+        // Calls with too many args are left to the regular evaluation to report.
+        Expr::Function {
+            function_name,
+            args,
+        } if *function_name.fragment() == "deps" && args.len() <= 3 => {
+            expr.span(stream_deps(evaluator, args, sink).await)
+        }
+        Expr::Function {
+            function_name,
+            args,
+        } if *function_name.fragment() == "rdeps" && args.len() <= 3 => {
+            expr.span(stream_rdeps(evaluator, args, sink).await)
+        }
+        _ => {
+            let value = evaluator.eval_parsed_query(expr).await?;
+            expr.span(match value.value {
+                QueryEvaluationValue::TargetSet(targets) => targets
+                    .iter()
+                    .try_for_each(|t| sink.add(t))
+                    .map_err(QueryError::from),
+                QueryEvaluationValue::FileSet(_) => Err(QueryError::InvalidType {
+                    expected: "targets",
+                    actual: "FileSet",
+                }),
+            })
+        }
+    }
+}
+
+async fn stream_deps<'a, Env: QueryEnvironment>(
+    evaluator: &QueryEvaluator<'a, Env>,
+    args: &'a [Spanned<Expr<'a>>],
+    sink: &mut dyn TargetSink<Env::Target>,
+) -> Result<(), QueryError> {
+    let targets: TargetSet<Env::Target> = eval_arg("deps", evaluator, args, 0).await?;
+    let depth: Option<u64> = eval_arg("deps", evaluator, args, 1).await?;
+    let captured_expr: Option<CapturedExpr> = eval_arg("deps", evaluator, args, 2).await?;
+    DepsFunction::<Env> {
+        _marker: PhantomData,
+    }
+    .invoke_deps_streaming(
+        evaluator.env(),
+        evaluator.functions(),
+        &targets,
+        depth.map(|v| v as i32),
+        captured_expr.as_ref(),
+        sink,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn stream_rdeps<'a, Env: QueryEnvironment>(
+    evaluator: &QueryEvaluator<'a, Env>,
+    args: &'a [Spanned<Expr<'a>>],
+    sink: &mut dyn TargetSink<Env::Target>,
+) -> Result<(), QueryError> {
+    let universe: TargetSet<Env::Target> = eval_arg("rdeps", evaluator, args, 0).await?;
+    let targets: TargetSet<Env::Target> = eval_arg("rdeps", evaluator, args, 1).await?;
+    let depth: Option<u64> = eval_arg("rdeps", evaluator, args, 2).await?;
+    rdeps_streaming(
+        evaluator.env(),
+        &universe,
+        &targets,
+        depth.map(|v| v as i32),
+        sink,
+    )
+    .await?;
+    Ok(())
+}
@@ -18,10 +18,12 @@ use buck2_core::build_file_path::BuildFilePath;
 use buck2_core::cells::cell_path::CellPath;
 use buck2_query::query::environment::LabeledNode;
 use buck2_query_parser::parse_expr;
+use buck2_query_parser::parse_query;
 use derive_more::Display;
 use dupe::Dupe;
 use serde::Serialize;
 use serde::Serializer;
+use starlark_map::small_set::SmallSet;
 
 use crate::query::compatibility::MaybeCompatible;
 use crate::query::environment::NodeLabel;
@@ -30,7 +32,12 @@ use crate::query::environment::QueryTarget;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
 use crate::query::syntax::simple::eval::file_set::FileSet;
+use crate::query::syntax::simple::eval::literals::extract_target_literals;
 use crate::query::syntax::simple::eval::set::TargetSet;
+use crate::query::syntax::simple::eval::values::QueryResultExt;
+use crate::query::syntax::simple::eval::values::QueryValue;
+use crate::query::syntax::simple::functions::defined::DefinedQueryFunctions;
+use crate::query::syntax::simple::functions::AugmentedQueryFunctions;
 use crate::query::syntax::simple::functions::DefaultQueryFunctionsModule;
 use crate::query::traversal::AsyncTraversalDelegate;
 
@@ -63,7 +70,7 @@ impl QueryTarget for Target {
         unimplemented!()
     }
 
-    fn buildfile_path(&self) -> &BuildFilePath {
+    fn buildfile_path(&self) -> Option<&BuildFilePath> {
         unimplemented!()
     }
 
@@ -187,3 +194,157 @@ pub async fn test_missing_arg() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// Evaluates a query that may start with user-defined functions, without resolving a top-level literal.
+async fn eval_query_value(input: &str) -> anyhow::Result<QueryValue<Target>> {
+    let parsed = parse_query(input)?;
+    let builtins = DefaultQueryFunctionsModule::<Env>::new();
+    let defined = DefinedQueryFunctions::new(&builtins, &parsed.definitions)
+        .map_err(|e| QueryError::convert_error(e, input))?;
+    let functions = AugmentedQueryFunctions::augment(&builtins, Box::new(defined));
+    QueryEvaluator::new(&Env, &functions)
+        .eval(&parsed.expr)
+        .await
+        .into_anyhow(input)
+}
+
+async fn assert_eval_error(input: &str, expected: &str) -> anyhow::Result<()> {
+    match eval_query_value(input).await {
+        Ok(v) => panic!("expected `{}` to fail, got `{:?}`", input, v),
+        Err(err) => {
+            let msg = format!("{:#}", err);
+            if !msg.contains(expected) {
+                return Err(err.context(format!("Expected error to contain `{}`", expected)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_let() -> anyhow::Result<()> {
+    assert_eq!(
+        QueryValue::Integer(3),
+        eval_query_value("let x = 3 in let y = $x in $y").await?
+    );
+    assert_eq!(
+        QueryValue::String("a".to_owned()),
+        eval_query_value("let x = a in let x = $x in $x").await?
+    );
+    assert_eval_error("let x = 3 in $y", "variable `$y` is not defined").await?;
+    // Bindings are only visible within the body.
+    assert_eval_error("kind(let x = a in $x, $x)", "variable `$x` is not defined").await?;
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn test_defined_functions() -> anyhow::Result<()> {
+    assert_eq!(
+        QueryValue::Integer(5),
+        eval_query_value("def f(a, b) = $b; def g(a) = f(1, $a); g(5)").await?
+    );
+    assert_eq!(
+        QueryValue::Integer(2),
+        eval_query_value("def f(a) = $a; let x = 1 in f(2)").await?
+    );
+    // Function bodies don't see the bindings of their callers.
+    assert_eval_error(
+        "def f() = $x; let x = 1 in f()",
+        "variable `$x` is not defined",
+    )
+    .await?;
+    assert_eval_error(
+        "def f(a) = $a; f()",
+        "too few args. function `f` requires at least 1 args, got 0",
+    )
+    .await?;
+    assert_eval_error(
+        "def f(a) = $a; f(1, 2)",
+        "too many args. function `f` accepts maximum 1 args, got 2",
+    )
+    .await?;
+    assert_eval_error(
+        "def f() = g(); def g() = 1; f()",
+        "function `g` is used before it is defined",
+    )
+    .await?;
+    assert_eval_error(
+        "def f() = f(); f()",
+        "function `f` is used before it is defined",
+    )
+    .await?;
+    assert_eval_error(
+        "def f() = 1; def f() = 2; f()",
+        "function `f` is already defined",
+    )
+    .await?;
+    assert_eval_error(
+        "def deps(a) = $a; deps(1)",
+        "function `deps` is already defined",
+    )
+    .await?;
+    Ok(())
+}
+
+fn target_literals(query: &str) -> anyhow::Result<Vec<String>> {
+    let mut literals = SmallSet::new();
+    extract_target_literals(
+        &DefaultQueryFunctionsModule::<Env>::new(),
+        query,
+        &mut literals,
+    )?;
+    Ok(literals.into_iter().collect())
+}
+
+#[test]
+fn test_target_literals_through_variables() -> anyhow::Result<()> {
+    assert_eq!(vec!["//a:b"], target_literals("let x = //a:b in deps($x)")?);
+    assert_eq!(
+        vec!["//a:b"],
+        target_literals("let x = //a:b in let y = $x in deps($y)")?
+    );
+    // Strings bound to variables are only target literals where they're used as targets.
+    assert_eq!(
+        vec!["//a:b"],
+        target_literals("let x = rule in kind($x, //a:b)")?
+    );
+    assert_eq!(
+        vec!["//a:b"],
+        target_literals("let x = name in attrfilter($x, value, //a:b)")?
+    );
+    assert_eq!(
+        Vec::<String>::new(),
+        target_literals("let x = //a:b in kind($x, set())")?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_target_literals_through_defined_functions() -> anyhow::Result<()> {
+    assert_eq!(
+        vec!["//a:b"],
+        target_literals("def f(a, b) = kind($a, $b); f(rule, //a:b)")?
+    );
+    assert_eq!(
+        vec!["//c:d", "//a:b"],
+        target_literals("def f(a) = kind(rule, $a) + //c:d; def g(a) = f($a); g(//a:b)")?
+    );
+    // Whether a function's result is a target depends on where it's called.
+    assert_eq!(
+        vec!["//a:b"],
+        target_literals("def id(a) = $a; attrfilter(id(name), value, deps(id(//a:b)))")?
+    );
+    // Functions that are never called are never evaluated.
+    assert_eq!(
+        Vec::<String>::new(),
+        target_literals("def f() = //a:b; set()")?
+    );
+    // Each function body is only walked once, however many times it's called.
+    let mut query = "def f0(a) = deps($a);".to_owned();
+    for i in 1..64 {
+        query.push_str(&format!(" def f{0}(a) = f{1}($a) + f{1}($a);", i, i - 1));
+    }
+    query.push_str(" f63(//a:b)");
+    assert_eq!(vec!["//a:b"], target_literals(&query)?);
+    Ok(())
+}
@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Functions defined within a query with `def name(param, ...) = body;`.
+
+use std::fmt;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use buck2_query_parser::spanned::Spanned;
+use buck2_query_parser::BinaryOp;
+use buck2_query_parser::Expr;
+use buck2_query_parser::FunctionDefinition;
+use buck2_query_parser::SpannedExpr;
+
+use crate::query::environment::QueryEnvironment;
+use crate::query::syntax::simple::eval::error::QueryError;
+use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
+use crate::query::syntax::simple::eval::values::QueryValue;
+use crate::query::syntax::simple::functions::helpers::QueryArgType;
+use crate::query::syntax::simple::functions::helpers::QueryBinaryOp;
+use crate::query::syntax::simple::functions::helpers::QueryFunction;
+use crate::query::syntax::simple::functions::QueryFunctions;
+
+/// The functions defined at the start of a query. These are intended to be combined with the
+/// builtin functions with `AugmentedQueryFunctions`.
+pub struct DefinedQueryFunctions<'a, Env: QueryEnvironment> {
+    functions: Vec<DefinedQueryFunction<'a, Env>>,
+}
+
+impl<'a, Env: QueryEnvironment> Debug for DefinedQueryFunctions<'a, Env> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DefinedQueryFunctions")
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, Env: QueryEnvironment> DefinedQueryFunctions<'a, Env> {
+    /// Checks the definitions against each other and the `builtins`. A function may only call
+    /// functions defined before it, which also rules out recursion.
+    pub fn new(
+        builtins: &dyn QueryFunctions<Env = Env>,
+        definitions: &'a [Spanned<FunctionDefinition<'a>>],
+    ) -> Result<Self, Spanned<QueryError>> {
+        let mut functions: Vec<DefinedQueryFunction<'a, Env>> = Vec::new();
+        for definition in definitions {
+            let name = definition.value.name.fragment();
+            if builtins.get(name).is_some() || functions.iter().any(|f| f.name() == name) {
+                return Err(Spanned {
+                    position: definition.position.clone(),
+                    value: QueryError::FunctionAlreadyDefined(name.to_owned()),
+                });
+            }
+            check_calls(&definition.value.body, &mut |called| {
+                if definitions
+                    .iter()
+                    .any(|d| d.value.name.fragment() == called)
+                    && !functions.iter().any(|f| f.name() == called)
+                {
+                    Err(QueryError::FunctionUsedBeforeDefinition(called.to_owned()))
+                } else {
+                    Ok(())
+                }
+            })?;
+            functions.push(DefinedQueryFunction {
+                definition: &definition.value,
+                _marker: PhantomData,
+            });
+        }
+        Ok(Self { functions })
+    }
+}
+
+impl<'a, Env: QueryEnvironment> QueryFunctions for DefinedQueryFunctions<'a, Env> {
+    type Env = Env;
+
+    fn get(&self, name: &str) -> Option<&dyn QueryFunction<Env>> {
+        self.functions
+            .iter()
+            .find(|f| f.name() == name)
+            .map(|f| f as &dyn QueryFunction<Env>)
+    }
+
+    fn get_op(&self, _op: BinaryOp) -> Option<&dyn QueryBinaryOp<Env>> {
+        None
+    }
+}
+
+/// Calls `check` with the name of every function called within `expr`.
+fn check_calls(
+    expr: &SpannedExpr,
+    check: &mut dyn FnMut(&str) -> Result<(), QueryError>,
+) -> Result<(), Spanned<QueryError>> {
+    match &expr.value {
+        Expr::Function {
+            function_name,
+            args,
+        } => {
+            if let Err(e) = check(function_name.fragment()) {
+                return Err(Spanned {
+                    position: expr.position.clone(),
+                    value: e,
+                });
+            }
+            args.iter().try_for_each(|arg| check_calls(arg, check))
+        }
+        Expr::BinaryOpSequence(left, exprs) => {
+            check_calls(left, check)?;
+            exprs
+                .iter()
+                .try_for_each(|(_, right)| check_calls(right, check))
+        }
+        Expr::Let { value, body, .. } => {
+            check_calls(value, check)?;
+            check_calls(body, check)
+        }
+        Expr::String(..)
+        | Expr::Integer(..)
+        | Expr::Set(..)
+        | Expr::FileSet(..)
+        | Expr::Variable(..) => Ok(()),
+    }
+}
+
+struct DefinedQueryFunction<'a, Env: QueryEnvironment> {
+    definition: &'a FunctionDefinition<'a>,
+    _marker: PhantomData<Env>,
+}
+
+#[async_trait]
+impl<'a, Env: QueryEnvironment> QueryFunction<Env> for DefinedQueryFunction<'a, Env> {
+    fn name(&self) -> &str {
+        self.definition.name.fragment()
+    }
+
+    async fn invoke(
+        &self,
+        evaluator: &QueryEvaluator<Env>,
+        args: &[SpannedExpr<'_>],
+    ) -> Result<QueryValue<Env::Target>, QueryError> {
+        let params = &self.definition.params;
+        if args.len() > params.len() {
+            return Err(QueryError::TooManyArgs {
+                function: self.name().to_owned(),
+                max: params.len(),
+                actual: args.len(),
+            });
+        }
+        if args.len() < params.len() {
+            return Err(QueryError::TooFewArgs {
+                function: self.name().to_owned(),
+                min: params.len(),
+                actual: args.len(),
+            });
+        }
+
+        // Arguments are evaluated by the caller, but the body only sees the parameters.
+        let values =
+            futures::future::try_join_all(args.iter().map(|arg| evaluator.eval(arg))).await?;
+        let mut body_evaluator = QueryEvaluator::new(evaluator.env(), evaluator.functions());
+        for (param, value) in params.iter().zip(values) {
+            body_evaluator = body_evaluator.bind(param, value.value);
+        }
+        Ok(body_evaluator.eval(&self.definition.body).await?.value)
+    }
+
+    fn definition(&self) -> Option<&FunctionDefinition> {
+        Some(self.definition)
+    }
+
+    fn arg_type(&self, idx: usize) -> Result<QueryArgType, QueryError> {
+        if idx < self.definition.params.len() {
+            Ok(QueryArgType::Value)
+        } else {
+            Err(QueryError::TooManyArgs {
+                function: self.name().to_owned(),
+                max: self.definition.params.len(),
+                actual: idx + 1,
+            })
+        }
+    }
+}
@@ -15,8 +15,10 @@ use async_trait::async_trait;
 use buck2_query_derive::query_module;
 use gazebo::variants::VariantName;
 
+use crate::query::environment::deps_streaming;
 use crate::query::environment::QueryEnvironment;
 use crate::query::environment::QueryTarget;
+use crate::query::environment::TargetSink;
 use crate::query::environment::TraversalFilter;
 use crate::query::syntax::simple::eval::error::QueryError;
 use crate::query::syntax::simple::eval::evaluator::QueryEvaluator;
@@ -69,6 +71,36 @@ pub(crate) struct DepsFunction<Env: QueryEnvironment> {
     pub(crate) _marker: PhantomData<Env>,
 }
 
+struct DepsFilter<'a, Env: QueryEnvironment> {
+    inner_env: &'a Env,
+    functions: &'a dyn QueryFunctions<Env = Env>,
+    expr: &'a CapturedExpr<'a>,
+}
+
+#[async_trait]
+impl<'a, T: QueryTarget, Env: QueryEnvironment<Target = T>> TraversalFilter<T>
+    for DepsFilter<'a, Env>
+{
+    async fn get_children(&self, target: &T) -> anyhow::Result<TargetSet<T>> {
+        let augmented_functions = AugmentedQueryFunctions::augment(
+            self.functions,
+            Box::new(DepsContextFunctions { target }),
+        );
+        let evaluator = QueryEvaluator::new(self.inner_env, &augmented_functions);
+        match evaluator.eval_parsed_query(self.expr.expr).await {
+            Ok(v) => match v.value {
+                QueryEvaluationValue::TargetSet(v) => Ok(v),
+                v => Err(QueryError::InvalidType {
+                    expected: "targets",
+                    actual: v.variant_name(),
+                }
+                .into()),
+            },
+            Err(e) => Err(QueryError::drop_spans(e)),
+        }
+    }
+}
+
 impl<Env: QueryEnvironment> DepsFunction<Env> {
     pub(crate) async fn invoke_deps(
         &self,
@@ -78,49 +110,37 @@ impl<Env: QueryEnvironment> DepsFunction<Env> {
         depth: Option<i32>,
         captured_expr: Option<&CapturedExpr<'_>>,
     ) -> anyhow::Result<TargetSet<Env::Target>> {
-        let filter = match captured_expr {
-            Some(expr) => {
-                struct Filter<'a, Env: QueryEnvironment> {
-                    inner_env: &'a Env,
-                    functions: &'a dyn QueryFunctions<Env = Env>,
-                    expr: &'a CapturedExpr<'a>,
-                }
-
-                #[async_trait]
-                impl<'a, T: QueryTarget, Env: QueryEnvironment<Target = T>> TraversalFilter<T> for Filter<'a, Env> {
-                    async fn get_children(&self, target: &T) -> anyhow::Result<TargetSet<T>> {
-                        let augmented_functions = AugmentedQueryFunctions::augment(
-                            self.functions,
-                            Box::new(DepsContextFunctions { target }),
-                        );
-                        let evaluator = QueryEvaluator::new(self.inner_env, &augmented_functions);
-                        match evaluator.eval_parsed_query(self.expr.expr).await {
-                            Ok(v) => match v.value {
-                                QueryEvaluationValue::TargetSet(v) => Ok(v),
-                                v => Err(QueryError::InvalidType {
-                                    expected: "targets",
-                                    actual: v.variant_name(),
-                                }
-                                .into()),
-                            },
-                            Err(e) => Err(QueryError::drop_spans(e)),
-                        }
-                    }
-                }
+        let filter = captured_expr.map(|expr| DepsFilter {
+            inner_env: env,
+            functions,
+            expr,
+        });
+        let filter_ref = filter
+            .as_ref()
+            .map(|v| v as &dyn TraversalFilter<Env::Target>);
 
-                Some(Filter {
-                    inner_env: env,
-                    functions,
-                    expr,
-                })
-            }
-            None => None,
-        };
+        env.deps(targets, depth, filter_ref).await
+    }
 
+    /// Like `invoke_deps`, but adds the targets to `sink` as they are found.
+    pub(crate) async fn invoke_deps_streaming(
+        &self,
+        env: &Env,
+        functions: &dyn QueryFunctions<Env = Env>,
+        targets: &TargetSet<Env::Target>,
+        depth: Option<i32>,
+        captured_expr: Option<&CapturedExpr<'_>>,
+        sink: &mut dyn TargetSink<Env::Target>,
+    ) -> anyhow::Result<()> {
+        let filter = captured_expr.map(|expr| DepsFilter {
+            inner_env: env,
+            functions,
+            expr,
+        });
         let filter_ref = filter
             .as_ref()
             .map(|v| v as &dyn TraversalFilter<Env::Target>);
 
-        env.deps(targets, depth, filter_ref).await
+        deps_streaming(env, targets, depth, filter_ref, sink).await
     }
 }
@@ -14,6 +14,7 @@
 use async_trait::async_trait;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::Expr;
+use buck2_query_parser::FunctionDefinition;
 use buck2_query_parser::SpannedExpr;
 use enum_iterator::IntoEnumIterator;
 use gazebo::variants::VariantName;
@@ -112,7 +113,7 @@ impl QueryArgType {
 
 #[async_trait]
 pub trait QueryFunction<Env: QueryEnvironment>: Send + Sync {
-    fn name(&self) -> &'static str;
+    fn name(&self) -> &str;
 
     async fn invoke(
         &self,
@@ -121,6 +122,12 @@ pub trait QueryFunction<Env: QueryEnvironment>: Send + Sync {
     ) -> Result<QueryValue<Env::Target>, QueryError>;
 
     fn arg_type(&self, idx: usize) -> Result<QueryArgType, QueryError>;
+
+    /// The definition of a function defined within the query, whose body is looked through for
+    /// literals at each call.
+    fn definition(&self) -> Option<&FunctionDefinition> {
+        None
+    }
 }
 
 #[async_trait]
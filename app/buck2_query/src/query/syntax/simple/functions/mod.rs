@@ -7,6 +7,8 @@
  * of this source tree.
  */
 
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Debug;
 use std::marker::PhantomData;
@@ -14,9 +16,12 @@ use std::marker::PhantomData;
 use allocative::Allocative;
 use async_trait::async_trait;
 use buck2_query_derive::query_module;
+use buck2_query_parser::span::Span;
 use buck2_query_parser::spanned::Spanned;
 use buck2_query_parser::BinaryOp;
 use buck2_query_parser::Expr;
+use buck2_query_parser::FunctionDefinition;
+use buck2_query_parser::SpannedExpr;
 use gazebo::variants::VariantName;
 
 use crate::query::compatibility::MaybeCompatible;
@@ -36,6 +41,7 @@ use crate::query::syntax::simple::functions::helpers::QueryArgType;
 use crate::query::syntax::simple::functions::helpers::QueryBinaryOp;
 use crate::query::syntax::simple::functions::helpers::QueryFunction;
 
+pub mod defined;
 pub mod deps;
 pub mod docs;
 pub mod helpers;
@@ -71,82 +77,189 @@ impl<F: QueryFunctions> QueryFunctionsVisitLiterals for F {
         visitor: &mut dyn QueryLiteralVisitor,
         expr: &Spanned<Expr>,
     ) -> QueryResult<()> {
-        fn visit_literals_recurse<F: QueryFunctions>(
-            this: &F,
-            visitor: &mut dyn QueryLiteralVisitor,
-            expr: &Expr,
-        ) -> Result<(), QueryError> {
-            match expr {
-                Expr::Function {
-                    function_name,
-                    args,
-                } => match this.get(function_name) {
-                    Some(func) => {
-                        for (i, arg) in args.iter().enumerate() {
-                            visit_literals_item(
-                                this,
-                                visitor,
-                                arg,
-                                matches!(
-                                    func.arg_type(i)?,
-                                    QueryArgType::TargetSet
-                                        | QueryArgType::Set
-                                        | QueryArgType::Value
-                                ),
-                            )?;
-                        }
-                        Ok(())
-                    }
-                    None => Err(QueryError::UnknownFunction(
-                        (*function_name.fragment()).to_owned(),
-                    )),
-                },
-                Expr::BinaryOpSequence(left, exprs) => {
-                    visit_literals_item(this, visitor, left, true)?;
-                    // All binary ops are on targetsets currently.
-                    for (_, right) in exprs {
-                        visit_literals_item(this, visitor, right, true)?;
-                    }
-                    Ok(())
-                }
-                Expr::Set(args) => {
-                    for arg in args {
-                        visitor.target_pattern(arg)?;
-                    }
-                    Ok(())
+        LiteralWalker {
+            functions: self,
+            visitor,
+            params_used_as_target: HashMap::new(),
+        }
+        .visit_item(expr, None, true)
+    }
+}
+
+/// The variables in scope while looking for literals. A string bound to a variable is only a
+/// target literal if the variable is used where a target expression is expected, so the uses
+/// are recorded here and the bound expressions are visited once the whole scope has been seen.
+enum LiteralScope<'a> {
+    Let {
+        name: &'a str,
+        used_as_target: &'a Cell<bool>,
+        parent: Option<&'a LiteralScope<'a>>,
+    },
+    /// The parameters of a user-defined function. The body of the function doesn't see any
+    /// other bindings.
+    Params {
+        params: &'a [Span<'a>],
+        used_as_target: &'a [Cell<bool>],
+    },
+}
+
+impl<'a> LiteralScope<'a> {
+    /// Records that the variable `name` is used as a target expression.
+    fn use_as_target(&self, name: &str) {
+        match self {
+            LiteralScope::Let {
+                name: n,
+                used_as_target,
+                parent,
+            } => {
+                if *n == name {
+                    used_as_target.set(true);
+                } else if let Some(parent) = parent {
+                    parent.use_as_target(name);
                 }
-                Expr::FileSet(_args) => Ok(()),
-                Expr::String(..) | Expr::Integer(..) => {
-                    panic!(
-                        "This shouldn't be called with literals, they should be handled in the caller"
-                    )
+            }
+            LiteralScope::Params {
+                params,
+                used_as_target,
+            } => {
+                if let Some(i) = params.iter().position(|p| *p.fragment() == name) {
+                    used_as_target[i].set(true);
                 }
             }
         }
+    }
+}
+
+struct LiteralWalker<'v, F> {
+    functions: &'v F,
+    visitor: &'v mut dyn QueryLiteralVisitor,
+    /// Which parameters of each user-defined function are used as target expressions, keyed
+    /// by the function name and whether the call itself is a target expression. This means
+    /// each function body is walked at most twice, however many times it is called.
+    params_used_as_target: HashMap<(String, bool), Vec<bool>>,
+}
 
-        fn visit_literals_item<F: QueryFunctions>(
-            this: &F,
-            visitor: &mut dyn QueryLiteralVisitor,
-            expr: &Spanned<Expr>,
-            is_target_expr: bool,
-        ) -> QueryResult<()> {
-            expr.map_res(|value| -> Result<(), QueryError> {
-                match value {
-                    Expr::String(val) => {
-                        if is_target_expr {
-                            visitor.target_pattern(val)?;
+impl<'v, F: QueryFunctions> LiteralWalker<'v, F> {
+    fn visit_item(
+        &mut self,
+        expr: &SpannedExpr,
+        scope: Option<&LiteralScope>,
+        is_target_expr: bool,
+    ) -> QueryResult<()> {
+        let res = self.visit_value(expr, scope, is_target_expr);
+        expr.span(res)
+    }
+
+    fn visit_value(
+        &mut self,
+        expr: &SpannedExpr,
+        scope: Option<&LiteralScope>,
+        is_target_expr: bool,
+    ) -> Result<(), QueryError> {
+        match &expr.value {
+            Expr::String(val) => {
+                if is_target_expr {
+                    self.visitor.target_pattern(val)?;
+                }
+            }
+            Expr::Integer(..) => {
+                // ignored
+            }
+            Expr::Function {
+                function_name,
+                args,
+            } => {
+                let functions = self.functions;
+                match functions.get(function_name) {
+                    Some(func) => match func.definition() {
+                        Some(definition) => {
+                            // Arguments are only target literals if the body uses them as such.
+                            let params_used_as_target = self.params_used_as_target(
+                                function_name.fragment(),
+                                definition,
+                                is_target_expr,
+                            )?;
+                            for (i, arg) in args.iter().enumerate() {
+                                let used = params_used_as_target.get(i).copied();
+                                self.visit_item(arg, scope, used.unwrap_or(false))?;
+                            }
                         }
+                        None => {
+                            for (i, arg) in args.iter().enumerate() {
+                                self.visit_item(
+                                    arg,
+                                    scope,
+                                    matches!(
+                                        func.arg_type(i)?,
+                                        QueryArgType::TargetSet
+                                            | QueryArgType::Set
+                                            | QueryArgType::Value
+                                    ),
+                                )?;
+                            }
+                        }
+                    },
+                    None => {
+                        return Err(QueryError::UnknownFunction(
+                            (*function_name.fragment()).to_owned(),
+                        ));
                     }
-                    Expr::Integer(..) => {
-                        // ignored
-                    }
-                    _ => visit_literals_recurse(this, visitor, value)?,
                 }
-                Ok(())
-            })
+            }
+            Expr::BinaryOpSequence(left, exprs) => {
+                self.visit_item(left, scope, true)?;
+                // All binary ops are on targetsets currently.
+                for (_, right) in exprs {
+                    self.visit_item(right, scope, true)?;
+                }
+            }
+            Expr::Set(args) => {
+                for arg in args {
+                    self.visitor.target_pattern(arg)?;
+                }
+            }
+            Expr::FileSet(_args) => {}
+            Expr::Let { name, value, body } => {
+                let used_as_target = Cell::new(false);
+                let let_scope = LiteralScope::Let {
+                    name: name.fragment(),
+                    used_as_target: &used_as_target,
+                    parent: scope,
+                };
+                self.visit_item(body, Some(&let_scope), is_target_expr)?;
+                self.visit_item(value, scope, used_as_target.get())?;
+            }
+            Expr::Variable(name) => {
+                // Unknown variables are reported by evaluation.
+                if let (true, Some(scope)) = (is_target_expr, scope) {
+                    scope.use_as_target(name.fragment());
+                }
+            }
         }
-
-        visit_literals_item(self, visitor, expr, true)
+        Ok(())
+    }
+
+    /// Walks the body of the user-defined function `name`, if it hasn't been walked for
+    /// `is_target_expr` already, and returns which of its parameters are used as targets.
+    fn params_used_as_target(
+        &mut self,
+        name: &str,
+        definition: &FunctionDefinition,
+        is_target_expr: bool,
+    ) -> QueryResult<Vec<bool>> {
+        let key = (name.to_owned(), is_target_expr);
+        if let Some(used) = self.params_used_as_target.get(&key) {
+            return Ok(used.clone());
+        }
+        let used = vec![Cell::new(false); definition.params.len()];
+        let params = LiteralScope::Params {
+            params: &definition.params,
+            used_as_target: &used,
+        };
+        self.visit_item(&definition.body, Some(&params), is_target_expr)?;
+        let used: Vec<bool> = used.iter().map(Cell::get).collect();
+        self.params_used_as_target.insert(key, used.clone());
+        Ok(used)
     }
 }
 
@@ -263,8 +376,8 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
             .into())
     }
 
-    async fn buildfile(&self, targets: TargetSet<Env::Target>) -> QueryFuncResult<Env> {
-        Ok(self.implementation.buildfile(&targets).into())
+    async fn buildfile(&self, env: &Env, targets: TargetSet<Env::Target>) -> QueryFuncResult<Env> {
+        Ok(self.implementation.buildfile(env, &targets).await?.into())
     }
 
     async fn rbuildfiles(
@@ -332,8 +445,20 @@ impl<Env: QueryEnvironment> DefaultQueryFunctionsModule<Env> {
         Ok(self.implementation.kind(&regex, &targets)?.into())
     }
 
-    async fn labels(&self, attr: String, targets: TargetSet<Env::Target>) -> QueryFuncResult<Env> {
-        self.implementation.labels(&attr, &targets)
+    /// The `labels(attr, targets)` operator returns the targets referenced by the attribute `attr`
+    /// of the targets in `targets`, e.g. `labels(exported_deps, //foo:bar)`. Configuration deps
+    /// (e.g. in `select()` keys) are not included.
+    async fn labels(
+        &self,
+        env: &Env,
+        attr: String,
+        targets: TargetSet<Env::Target>,
+    ) -> QueryFuncResult<Env> {
+        Ok(self
+            .implementation
+            .labels(env, &attr, &targets)
+            .await?
+            .into())
     }
 
     async fn owner(&self, env: &Env, files: FileSet) -> QueryFuncResult<Env> {
@@ -462,8 +587,12 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         targets.attrregexfilter(attr, value)
     }
 
-    pub fn buildfile(&self, targets: &TargetSet<Env::Target>) -> FileSet {
-        targets.buildfile()
+    pub async fn buildfile(
+        &self,
+        env: &Env,
+        targets: &TargetSet<Env::Target>,
+    ) -> anyhow::Result<FileSet> {
+        env.buildfile(targets).await
     }
 
     pub async fn allbuildfiles(
@@ -522,12 +651,13 @@ impl<Env: QueryEnvironment> DefaultQueryFunctions<Env> {
         targets.kind(regex)
     }
 
-    pub fn labels(
+    pub async fn labels(
         &self,
-        _attr: &str,
-        _targets: &TargetSet<Env::Target>,
-    ) -> Result<QueryValue<Env::Target>, QueryError> {
-        Err(QueryError::FunctionUnimplemented("labels"))
+        env: &Env,
+        attr: &str,
+        targets: &TargetSet<Env::Target>,
+    ) -> anyhow::Result<TargetSet<Env::Target>> {
+        env.labels(attr, targets).await
     }
 
     pub async fn owner(
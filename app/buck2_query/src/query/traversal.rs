@@ -371,7 +371,7 @@ mod tests {
         fn rule_type(&self) -> Cow<str> {
             unimplemented!()
         }
-        fn buildfile_path(&self) -> &BuildFilePath {
+        fn buildfile_path(&self) -> Option<&BuildFilePath> {
             unimplemented!()
         }
 
@@ -8,6 +8,7 @@
  */
 
 use buck2_cli_proto::QueryOutputFormat;
+use buck2_core::fs::fs_util;
 use buck2_core::soft_error;
 use buck2_query_parser::placeholder::QUERY_PERCENT_SS_PLACEHOLDER;
 use dupe::Dupe;
@@ -120,6 +121,11 @@ pub struct CommonQueryArgs {
     #[clap(name = "QUERY", help = "the query to evaluate")]
     query: String,
 
+    /// File with query function definitions (`def name(param, ...) = expr;`) that can be
+    /// called from the query.
+    #[clap(long, value_name = "PATH")]
+    query_file: Option<String>,
+
     #[clap(flatten)]
     pub attributes: CommonAttributeArgs,
 
@@ -190,8 +196,8 @@ impl CommonQueryArgs {
         }
     }
 
-    pub fn get_query(&self) -> (String, Vec<String>) {
-        if self.query.contains(QUERY_PERCENT_SS_PLACEHOLDER) {
+    pub fn get_query(&self) -> anyhow::Result<(String, Vec<String>)> {
+        let (query, query_args) = if self.query.contains(QUERY_PERCENT_SS_PLACEHOLDER) {
             let replacement = Self::args_as_set(&self.query_args);
             (
                 self.query
@@ -200,6 +206,15 @@ impl CommonQueryArgs {
             )
         } else {
             (self.query.clone(), self.query_args.clone())
+        };
+        match &self.query_file {
+            // The definitions are evaluated as part of the query, so they are prepended to it. The
+            // query starts on its own line, as errors are reported against the line they're on.
+            Some(path) => {
+                let definitions = fs_util::read_to_string(path)?;
+                Ok((format!("{}\n{}", definitions, query), query_args))
+            }
+            None => Ok((query, query_args)),
         }
     }
 }
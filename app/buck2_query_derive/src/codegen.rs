@@ -251,7 +251,7 @@ fn gen_for_method(parsed: &Parsed, method: &Method) -> syn::Result<MethodCodegen
 
                 #[async_trait]
                 impl #impl_generics QueryFunction<#env_ident> for #func_ty #ty_generics #where_clause {
-                    fn name(&self) -> &'static str { stringify!(#func_ident) }
+                    fn name(&self) -> &str { stringify!(#func_ident) }
 
                     async fn invoke(
                         &self,
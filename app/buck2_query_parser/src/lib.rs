@@ -18,9 +18,16 @@
 //! ```text
 //!
 //! # note that set's args are space-separated, not comma-separated and so cannot be treated as a function
+//! QUERY ::= DEFINITION * EXPR
+//!
+//! # user-defined functions. Parameters are referred to as `$NAME` in the body.
+//! DEFINITION ::= 'def' IDENTIFIER '(' ( IDENTIFIER ( ',' IDENTIFIER ) * ) ? ')' '=' EXPR ';'
+//!
 //! EXPR ::=
 //!          WORD
 //!        | INTEGER
+//!        | '$' IDENTIFIER
+//!        | 'let' IDENTIFIER '=' EXPR 'in' EXPR
 //!        | '(' EXPR ')'
 //!        | 'set(' WORD * ')'
 //!        | FUNCTION_NAME '(' EXPR ( ',' EXPR ) * ')'
@@ -40,7 +47,9 @@
 //!
 //! INTEGER ::= "0" | ("1-9" "0-9"*)
 //!
-//! FUNCTION_NAME ::= "a-zA-Z_" "a-zA-Z0-9_" *
+//! FUNCTION_NAME ::= IDENTIFIER
+//!
+//! IDENTIFIER ::= "a-zA-Z_" "a-zA-Z0-9_" *
 //!
 //! ```
 
@@ -66,6 +75,7 @@ use nom::character::complete::multispace0;
 use nom::character::complete::multispace1;
 use nom::combinator::all_consuming;
 use nom::combinator::cut;
+use nom::combinator::not;
 use nom::combinator::recognize;
 use nom::error::context;
 use nom::error::convert_error;
@@ -84,8 +94,6 @@ use thiserror::Error;
 use crate::span::Span;
 use crate::spanned::Spanned;
 
-// TODO(cjhopman): Add `LET WORD = expr IN expr`
-
 // TODO(cjhopman): We should switch to our own error type here. VerboseError doesn't even allow us to construct
 // our own error messages (so, for example, we can't have a good error message for too large integers) and doesn't
 // support propagating anyhow or std errors (and since we can't do a custom message, we can't even capture them as a string).
@@ -123,6 +131,29 @@ pub enum Expr<'a> {
     BinaryOpSequence(Box<SpannedExpr<'a>>, Vec<(BinaryOp, SpannedExpr<'a>)>),
     Set(Vec<Span<'a>>),
     FileSet(Vec<Span<'a>>),
+    /// `let name = value in body`, `value` is visible as `$name` within `body`.
+    Let {
+        name: Span<'a>,
+        value: Box<SpannedExpr<'a>>,
+        body: Box<SpannedExpr<'a>>,
+    },
+    /// A reference to a let-bound variable or to a parameter of a user-defined function.
+    Variable(Span<'a>),
+}
+
+/// A user-defined function, `def name(param, ...) = body;`.
+#[derive(Debug)]
+pub struct FunctionDefinition<'a> {
+    pub name: Span<'a>,
+    pub params: Vec<Span<'a>>,
+    pub body: SpannedExpr<'a>,
+}
+
+/// A full query: the user-defined functions followed by the expression to evaluate.
+#[derive(Debug)]
+pub struct ParsedQuery<'a> {
+    pub definitions: Vec<Spanned<FunctionDefinition<'a>>>,
+    pub expr: SpannedExpr<'a>,
 }
 
 impl Display for Expr<'_> {
@@ -179,11 +210,28 @@ impl Display for Expr<'_> {
                 }
                 f.write_str(")")?;
             }
+            Expr::Let { name, value, body } => {
+                write!(f, "let {} = {} in {}", name.fragment(), value, body)?;
+            }
+            Expr::Variable(name) => write!(f, "${}", name.fragment())?,
         }
         Ok(())
     }
 }
 
+impl Display for FunctionDefinition<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "def {}(", self.name.fragment())?;
+        for (i, v) in self.params.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(v.fragment())?;
+        }
+        write!(f, ") = {};", self.body)
+    }
+}
+
 const INTERSECT: &str = "^";
 const EXCEPT: &str = "-";
 const UNION: &str = "+";
@@ -236,13 +284,27 @@ fn convert_to_str_error(err: VerboseError<Span>) -> VerboseError<&str> {
 
 /// Parses a query string into a SpannedExpr. Requires that the entire input is consumed.
 pub fn parse_expr(input: &str) -> anyhow::Result<SpannedExpr> {
+    parse_all(input, expr, expr)
+}
+
+/// Parses a query string that may start with user-defined function definitions. Requires that the
+/// entire input is consumed.
+pub fn parse_query(input: &str) -> anyhow::Result<ParsedQuery> {
+    parse_all(input, query, query)
+}
+
+/// Parses with fast error (`()`) first, and on error reparses again with `VerboseError` to get
+/// detailed errors. The same parser is passed twice so that it can be instantiated for both error types.
+fn parse_all<'a, O>(
+    input: &'a str,
+    fast: impl FnMut(Span<'a>) -> NomResult<'a, O, ()>,
+    verbose: impl FnMut(Span<'a>) -> NomResult<'a, O, VerboseError<Span<'a>>>,
+) -> anyhow::Result<O> {
     let span = Span::new(input);
-    // Parse with fast error (`()`) first,
-    // and on error reparse again with `VerboseError` to get detailed errors.
-    match all_consuming(expr)(span) {
+    match all_consuming(fast)(span) {
         Ok((_, value)) => Ok(value),
         Err(nom::Err::Failure(())) | Err(nom::Err::Error(())) => {
-            match all_consuming(expr)(span) {
+            match all_consuming(verbose)(span) {
                 Ok(..) => unreachable!(
                     "if fast parse didn't succeed, slow parse should not succeed as well"
                 ),
@@ -262,6 +324,43 @@ pub fn parse_expr(input: &str) -> anyhow::Result<SpannedExpr> {
     }
 }
 
+/// Parses the function definitions and the expression of a full query.
+fn query<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, ParsedQuery<'a>, E> {
+    let (input, definitions) = many0(delimited(
+        multispace0,
+        spanned(function_definition),
+        multispace0,
+    ))(input)?;
+    let (input, expr) = expr(input)?;
+    Ok((input, ParsedQuery { definitions, expr }))
+}
+
+/// Tries to parse a FunctionDefinition. Will fail if it detects an unfinished "def name("
+fn function_definition<'a, E: NomParseError<'a>>(
+    input: Span<'a>,
+) -> NomResult<'a, FunctionDefinition<'a>, E> {
+    fn params<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Vec<Span<'a>>, E> {
+        delimited(
+            multispace0,
+            separated_list0(delimited(multispace0, char(','), multispace0), identifier),
+            multispace0,
+        )(input)
+    }
+
+    let (input, _) = terminated(tag("def"), multispace1)(input)?;
+    let (input, name) = identifier(input)?;
+    let (input, _) = char('(')(input)?;
+    context(
+        "function definition",
+        cut(move |input| {
+            let (input, params) = terminated(params, char(')'))(input)?;
+            let (input, _) = delimited(multispace0, char('='), multispace0)(input)?;
+            let (input, body) = terminated(expr, char(';'))(input)?;
+            Ok((input, FunctionDefinition { name, params, body }))
+        }),
+    )(input)
+}
+
 // Parses a non-infix op expression. This is split out so that we can parse a sequence of infix operators without recursion.
 fn single_expr<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
     // The ordering here is a little important, the first three of these all have a pattern of identifying
@@ -272,12 +371,17 @@ fn single_expr<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spann
     //
     // The infix binary operators require left-recursion, so we can't just handle that like the others. Instead we
     // parse an expression from the beginning of the input and check after if there's a "trailing" infix operator.
+    //
+    // Similarly, `expr_let` needs to come before `expr_word` so that "let" isn't taken as a word and
+    // `expr_variable` needs to come before `expr_word` so that "$name" isn't taken as a word.
     let (input, left_expr) = alt((
         preceded(char('('), cut(terminated(expr, char(')')))),
+        expr_let,
         expr_set,
         expr_fileset,
         expr_function,
         expr_int,
+        expr_variable,
         expr_word,
     ))(input)?;
 
@@ -351,9 +455,52 @@ fn expr_int<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedE
     })(input)
 }
 
+/// Tries to parse an Expr::Variable. A `$` that is followed by more word characters than an
+/// identifier (like the regex `$name.*`) is left to be parsed as a word.
+fn expr_variable<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    spanned(|input| {
+        let (input, name) = preceded(char('$'), identifier)(input)?;
+        let (input, _) = not(word_char)(input)?;
+        Ok((input, Expr::Variable(name)))
+    })(input)
+}
+
+/// Tries to parse an Expr::Let. Will fail if it detects an unfinished "let name ="
+fn expr_let<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, SpannedExpr<'a>, E> {
+    spanned(|input| {
+        let (input, _) = terminated(tag("let"), multispace1)(input)?;
+        let (input, name) = identifier(input)?;
+        let (input, _) = preceded(multispace0, char('='))(input)?;
+        cut(move |input| {
+            let (input, value) = expr(input)?;
+            let (input, _) = terminated(tag("in"), multispace1)(input)?;
+            let (input, body) = expr(input)?;
+            Ok((
+                input,
+                Expr::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                },
+            ))
+        })(input)
+    })(input)
+}
+
+fn identifier<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
+    recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0(alt((alphanumeric1, tag("_")))),
+    ))(input)
+}
+
+fn word_char<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
+    alt((alphanumeric1, is_a("*/@.-_:$#%")))(input)
+}
+
 fn word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
     fn non_quoted_word<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Span<'a>, E> {
-        recognize(many1(alt((alphanumeric1, is_a("*/@.-_:$#%")))))(input)
+        recognize(many1(word_char))(input)
     }
 
     alt((
@@ -437,10 +584,7 @@ fn expr_function<'a, E: NomParseError<'a>>(input: Span<'a>) -> NomResult<'a, Spa
     }
 
     spanned(|input| {
-        let (input, function_name) = recognize(pair(
-            alt((alpha1, tag("_"))),
-            many0(alt((alphanumeric1, tag("_")))),
-        ))(input)?;
+        let (input, function_name) = identifier(input)?;
         let (input, _) = char('(')(input)?;
         cut(move |input| {
             let (input, args) = terminated(function_args, char(')'))(input)?;
@@ -621,6 +765,88 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_variable() -> anyhow::Result<()> {
+        run_tests(
+            expr_variable,
+            &["$x", "$some_name", "$_1"],
+            // Anything that continues as a word is left for `expr_word`.
+            &["x", "$", "$1", "$x.*", "$x:y", ""],
+            &[],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_let() -> anyhow::Result<()> {
+        run_tests(
+            expr_let,
+            &[
+                "let x = a in $x",
+                "let x=deps(a) in $x + b",
+                "let x = a in let y = $x in $y ^ $x",
+                "let x = let y = a in $y in $x",
+            ],
+            // As long as we don't match "let name =", it should be recoverable
+            &["let", "letx = a in $x", "let(a)", "let x in $x", ""],
+            // An error after "let name =" is non-recoverable
+            &["let x = ", "let x = a", "let x = a in", "let x = a $x"],
+        );
+
+        match parse_expr("deps(let x = a in $x) + b") {
+            Ok(Spanned {
+                value: Expr::BinaryOpSequence(..),
+                ..
+            }) => {}
+            v => panic!("expected union expr, got `{:?}`", v),
+        }
+
+        match parse_expr("let x = a in $x + b") {
+            Ok(Spanned {
+                value: Expr::Let { body, .. },
+                ..
+            }) => assert!(matches!(body.value, Expr::BinaryOpSequence(..))),
+            v => panic!("expected let expr, got `{:?}`", v),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query() -> anyhow::Result<()> {
+        run_tests(
+            query,
+            &[
+                "a",
+                "def f() = a; f()",
+                "def f(x, y) = $x + $y;\ndef g(x) = f($x, b);\ng(a)",
+                " def f( x ) = $x ; f(a)",
+            ],
+            &[],
+            // An error after "def name(" is non-recoverable
+            &[
+                "def f(",
+                "def f(x) $x; f(a)",
+                "def f(x) = $x f(a)",
+                "def f(,) = a; a",
+            ],
+        );
+
+        let parsed = parse_query("def f(x, y) = $x ^ $y; f(a, b)")?;
+        assert_eq!(1, parsed.definitions.len());
+        assert_eq!(
+            "def f(x, y) = ( $x ^ $y);",
+            parsed.definitions[0].value.to_string()
+        );
+        assert_eq!("f('a', 'b')", parsed.expr.to_string());
+
+        // `def` on its own is still a word.
+        assert!(parse_query("def")?.definitions.is_empty());
+        assert!(parse_expr("def f() = a; f()").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_integer() -> anyhow::Result<()> {
         run_tests(expr_int, &["0", "1234"], &["w123", ".1", ""], &["0123"]);
@@ -99,11 +99,19 @@ impl<T> Spanned<T> {
 
     // Constructs a 2-line string that prints the line where the span occurs and then a line below that identifying the span.
     pub fn get_err_context(&self, input: &str) -> String {
-        // TODO(cjhopman): This assumes that the input is a single line, it should (maybe) handle multi-line inputs.
+        // The input may have several lines, e.g. when the query is preceded by function definitions
+        // read from a file, so only the line where the span starts is shown.
         // TODO(cjhopman): This should cut off the beginning and/or end of long lines and focus around the span.
         // TODO(cjhopman): Consider using annotate-snippets like we do in starlark.
-        let (rest, end) = input.split_at(self.position.end);
-        let (start, inner) = rest.split_at(self.position.start);
+        let line_start = input[..self.position.start]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line_end = input[self.position.start..]
+            .find('\n')
+            .map_or(input.len(), |i| self.position.start + i);
+        let line = &input[line_start..line_end];
+        let (rest, end) = line.split_at(self.position.end.min(line_end) - line_start);
+        let (start, inner) = rest.split_at(self.position.start - line_start);
 
         let inner = truncate(inner, 80);
 
@@ -146,4 +154,37 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_span_on_later_line() {
+        let input = "def f(a) = deps($a);\ndef g() = f(1, 2);\ng()";
+        let start = input.find("f(1, 2)").unwrap();
+        let span = Spanned {
+            position: start..start + "f(1, 2)".len(),
+            value: false,
+        };
+        let context = span.get_err_context(input);
+        let context_lines: Vec<&str> = context.split('\n').collect();
+        assert_eq!(
+            context_lines,
+            ["", "    def g() = f(1, 2);", "              ^-----^", ""]
+        );
+
+        // Spans over several lines are only shown up to the end of the first line.
+        let span = Spanned {
+            position: 0..input.len(),
+            value: false,
+        };
+        let context = span.get_err_context(input);
+        let context_lines: Vec<&str> = context.split('\n').collect();
+        assert_eq!(
+            context_lines,
+            [
+                "",
+                "    def f(a) = deps($a);",
+                "    ^------------------^",
+                ""
+            ]
+        );
+    }
 }
@@ -14,6 +14,9 @@ use buck2_common::legacy_configs::LegacyBuckConfig;
 
 static BUCK2_RE_CLIENT_CFG_SECTION: &str = "buck2_re_client";
 
+/// Default for `cas_ttl_secs`. This is the same default Bazel uses.
+const DEFAULT_CAS_TTL_SECS: u64 = 3 * 60 * 60;
+
 /// We put functions here that both things need to implement for code that isn't gated behind a
 /// fbcode_build or not(fbcode_build)
 pub trait RemoteExecutionStaticMetadataImpl: Sized {
@@ -155,6 +158,44 @@ pub struct Buck2OssReConfiguration {
     /// This can contain environment variables using shell interpolation syntax (i.e. $VAR). They
     /// will be substituted before using the value.
     pub http_headers: Vec<HttpHeader>,
+    /// Compression to use when transferring blobs to and from the CAS. This is only used if the
+    /// server advertises support for it, otherwise blobs are transferred uncompressed.
+    pub compression: CasCompression,
+    /// The REAPI instance name to use in all requests. Empty by default.
+    pub instance_name: Option<String>,
+    /// Instance names to use instead of `instance_name` for specific use cases. This is a
+    /// comma-separated list of `use_case=instance_name` pairs. Execution platforms select their
+    /// use case with `remote_execution_use_case`, so this lets different platforms be routed to
+    /// different instances on the same server.
+    pub use_case_instance_names: Vec<UseCaseInstanceName>,
+    /// How long we assume blobs stay in the CAS after the server last told us it had them. The
+    /// REAPI does not let servers tell us this, so this should match the server's eviction
+    /// policy.
+    pub cas_ttl_secs: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Allocative)]
+pub enum CasCompression {
+    #[default]
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl FromStr for CasCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "zstd" => Ok(Self::Zstd),
+            "deflate" => Ok(Self::Deflate),
+            _ => Err(anyhow::anyhow!(
+                "Invalid compression (expected `none`, `zstd` or `deflate`): `{}`",
+                s
+            )),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Allocative)]
@@ -181,6 +222,29 @@ impl FromStr for HttpHeader {
     }
 }
 
+#[derive(Clone, Debug, Default, Allocative)]
+pub struct UseCaseInstanceName {
+    pub use_case: String,
+    pub instance_name: String,
+}
+
+impl FromStr for UseCaseInstanceName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((use_case, instance_name)) if !use_case.trim().is_empty() => Ok(Self {
+                use_case: use_case.trim().to_owned(),
+                instance_name: instance_name.trim().to_owned(),
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Invalid use case instance name (expected `use_case=instance_name`): `{}`",
+                s
+            )),
+        }
+    }
+}
+
 impl Buck2OssReConfiguration {
     pub fn from_legacy_config(legacy_config: &LegacyBuckConfig) -> anyhow::Result<Self> {
         Ok(Self {
@@ -196,6 +260,16 @@ impl Buck2OssReConfiguration {
             http_headers: legacy_config
                 .parse_list(BUCK2_RE_CLIENT_CFG_SECTION, "http_headers")?
                 .unwrap_or_default(), // Empty list is as good None.
+            compression: legacy_config
+                .parse(BUCK2_RE_CLIENT_CFG_SECTION, "compression")?
+                .unwrap_or_default(),
+            instance_name: legacy_config.parse(BUCK2_RE_CLIENT_CFG_SECTION, "instance_name")?,
+            use_case_instance_names: legacy_config
+                .parse_list(BUCK2_RE_CLIENT_CFG_SECTION, "use_case_instance_names")?
+                .unwrap_or_default(),
+            cas_ttl_secs: legacy_config
+                .parse(BUCK2_RE_CLIENT_CFG_SECTION, "cas_ttl_secs")?
+                .unwrap_or(DEFAULT_CAS_TTL_SECS),
         })
     }
 }
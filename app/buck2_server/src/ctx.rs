@@ -77,6 +77,7 @@ use buck2_execute::re::client::RemoteExecutionClient;
 use buck2_execute::re::manager::ReConnectionHandle;
 use buck2_execute::re::manager::ReConnectionManager;
 use buck2_execute::re::manager::ReConnectionObserver;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
 use buck2_execute_impl::low_pass_filter::LowPassFilter;
 use buck2_forkserver::client::ForkserverClient;
 use buck2_interpreter::dice::starlark_debug::SetStarlarkDebugger;
@@ -149,6 +150,8 @@ pub struct BaseServerCommandContext {
     pub materializer: Arc<dyn Materializer>,
     /// Forkserver connection, if any was started
     pub forkserver: Option<ForkserverClient>,
+    /// The local action cache, if enabled.
+    pub local_action_cache: Option<Arc<LocalActionCache>>,
     /// The event dispatcher for this command context.
     pub events: EventDispatcher,
     /// Removes this command from the set of active commands when dropped.
@@ -376,6 +379,7 @@ impl<'a> ServerCommandContext<'a> {
         let build_signals = self.build_signals.dupe();
 
         let forkserver = self.base_context.forkserver.dupe();
+        let local_action_cache = self.base_context.local_action_cache.dupe();
 
         let upload_all_actions = self
             .build_options
@@ -396,6 +400,7 @@ impl<'a> ServerCommandContext<'a> {
             re_connection,
             build_signals,
             forkserver,
+            local_action_cache,
             upload_all_actions,
             skip_cache_read,
             skip_cache_write,
@@ -495,6 +500,7 @@ struct DiceCommandDataProvider {
     re_connection: Arc<ReConnectionHandle>,
     build_signals: BuildSignalSender,
     forkserver: Option<ForkserverClient>,
+    local_action_cache: Option<Arc<LocalActionCache>>,
     upload_all_actions: bool,
     run_action_knobs: RunActionKnobs,
     skip_cache_read: bool,
@@ -583,6 +589,7 @@ impl DiceDataProvider for DiceCommandDataProvider {
             executor_global_knobs,
             self.upload_all_actions,
             self.forkserver.dupe(),
+            self.local_action_cache.dupe(),
             self.skip_cache_read,
             self.skip_cache_write,
             ctx.global_data()
@@ -37,7 +37,9 @@ use buck2_execute::re::manager::ReConnectionHandle;
 use buck2_execute_impl::executors::caching::CachingExecutor;
 use buck2_execute_impl::executors::hybrid::HybridExecutor;
 use buck2_execute_impl::executors::local::LocalExecutor;
+use buck2_execute_impl::executors::local_action_cache::LocalActionCacheExecutor;
 use buck2_execute_impl::executors::re::ReExecutor;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
 use buck2_execute_impl::low_pass_filter::LowPassFilter;
 use buck2_forkserver::client::ForkserverClient;
 use dupe::Dupe;
@@ -71,6 +73,7 @@ pub struct CommandExecutorFactory {
     pub executor_global_knobs: ExecutorGlobalKnobs,
     pub upload_all_actions: bool,
     pub forkserver: Option<ForkserverClient>,
+    pub local_action_cache: Option<Arc<LocalActionCache>>,
     pub skip_cache_read: bool,
     pub skip_cache_write: bool,
     project_root: ProjectRoot,
@@ -87,6 +90,7 @@ impl CommandExecutorFactory {
         executor_global_knobs: ExecutorGlobalKnobs,
         upload_all_actions: bool,
         forkserver: Option<ForkserverClient>,
+        local_action_cache: Option<Arc<LocalActionCache>>,
         skip_cache_read: bool,
         skip_cache_write: bool,
         project_root: ProjectRoot,
@@ -101,6 +105,7 @@ impl CommandExecutorFactory {
             executor_global_knobs,
             upload_all_actions,
             forkserver,
+            local_action_cache,
             skip_cache_read,
             skip_cache_write,
             project_root,
@@ -114,7 +119,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
         artifact_fs: &ArtifactFs,
         executor_config: &CommandExecutorConfig,
     ) -> anyhow::Result<CommandExecutorResponse> {
-        let local_executor_new = |_options| {
+        let local_executor_new = |options: &LocalExecutorOptions| {
             LocalExecutor::new(
                 artifact_fs.clone(),
                 self.materializer.dupe(),
@@ -123,9 +128,28 @@ impl HasCommandExecutor for CommandExecutorFactory {
                 self.project_root.root().to_owned(),
                 self.forkserver.dupe(),
                 self.executor_global_knobs.dupe(),
+                options,
             )
         };
 
+        // Executors that only ever run actions locally get the local action cache (if enabled),
+        // since they have no remote action cache to use instead.
+        let local_only_executor_new = |options| -> Arc<dyn PreparedCommandExecutor> {
+            let local = Arc::new(local_executor_new(options));
+            match &self.local_action_cache {
+                Some(cache) => Arc::new(LocalActionCacheExecutor {
+                    inner: local,
+                    cache: cache.dupe(),
+                    artifact_fs: artifact_fs.clone(),
+                    materializer: self.materializer.dupe(),
+                    blocking_executor: self.blocking_executor.dupe(),
+                    skip_cache_read: self.skip_cache_read,
+                    skip_cache_write: self.skip_cache_write,
+                }),
+                None => local,
+            }
+        };
+
         if !buck2_core::is_open_source() && !cfg!(fbcode_build) {
             static WARN: OnceCell<()> = OnceCell::new();
             WARN.get_or_init(|| {
@@ -140,7 +164,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
             }
 
             return Ok(CommandExecutorResponse {
-                executor: Arc::new(local_executor_new(&LocalExecutorOptions {})),
+                executor: local_only_executor_new(&LocalExecutorOptions::default()),
                 platform: Default::default(),
             });
         }
@@ -173,7 +197,7 @@ impl HasCommandExecutor for CommandExecutorFactory {
                     None
                 } else {
                     Some(CommandExecutorResponse {
-                        executor: Arc::new(local_executor_new(local)),
+                        executor: local_only_executor_new(local),
                         platform: Default::default(),
                     })
                 }
@@ -299,11 +323,11 @@ impl ExecutionStrategyExt for ExecutionStrategy {
 /// This is used when execution platforms are not configured.
 pub fn get_default_executor_config(host_platform: HostPlatformOverride) -> CommandExecutorConfig {
     let executor = if buck2_core::is_open_source() {
-        Executor::Local(LocalExecutorOptions {})
+        Executor::Local(LocalExecutorOptions::default())
     } else {
         Executor::RemoteEnabled {
             executor: RemoteEnabledExecutor::Hybrid {
-                local: LocalExecutorOptions {},
+                local: LocalExecutorOptions::default(),
                 remote: RemoteExecutorOptions::default(),
                 level: HybridExecutionLevel::Limited,
             },
@@ -12,6 +12,7 @@ use std::sync::Arc;
 
 use allocative::Allocative;
 use anyhow::Context;
+use buck2_build_api::actions::impls::dep_files::init_dep_files_table;
 use buck2_common::invocation_paths::InvocationPaths;
 use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_core::fs::fs_util;
@@ -32,7 +33,9 @@ use crate::daemon::server::BuckdServerInitPreferences;
 #[derive(Allocative)]
 pub struct DiskStateOptions {
     pub sqlite_materializer_state: bool,
-    // In future, this will include the config for dep files on disk
+    /// Persist dep file state in the materializer state sqlite db. This requires
+    /// `sqlite_materializer_state`.
+    pub sqlite_dep_files: bool,
 }
 
 impl DiskStateOptions {
@@ -48,8 +51,14 @@ impl DiskStateOptions {
             .parse::<RolloutPercentage>("buck2", "sqlite_materializer_state")?
             .unwrap_or_else(RolloutPercentage::never)
             .roll();
+        let sqlite_dep_files = sqlite_materializer_state
+            && root_config
+                .parse::<RolloutPercentage>("buck2", "sqlite_dep_files")?
+                .unwrap_or_else(RolloutPercentage::never)
+                .roll();
         Ok(Self {
             sqlite_materializer_state,
+            sqlite_dep_files,
         })
     }
 }
@@ -116,6 +125,32 @@ pub(crate) async fn maybe_initialize_materializer_sqlite_db(
     Ok((Some(db), materializer_state))
 }
 
+/// Load the dep file state persisted in the materializer state sqlite db, so that actions can reuse
+/// their dep files across daemon restarts. If persisting dep files is disabled, drop whatever a
+/// previous daemon persisted instead, since we won't keep it up to date.
+pub(crate) async fn maybe_initialize_dep_files_state(
+    options: &DiskStateOptions,
+    materializer_db: Option<&MaterializerStateSqliteDb>,
+    io_executor: Arc<dyn BlockingExecutor>,
+) -> anyhow::Result<()> {
+    let table = match materializer_db {
+        Some(db) => db.dep_files_table(),
+        None => return Ok(()),
+    };
+
+    if options.sqlite_dep_files {
+        io_executor
+            .execute_io_inline(|| init_dep_files_table(table))
+            .await
+            .context("Error loading persisted dep files")
+    } else {
+        io_executor
+            .execute_io_inline(|| table.delete_all())
+            .await
+            .context("Error deleting persisted dep files")
+    }
+}
+
 // Once we start storing disk state in the cache directory, we need to make sure
 // buck2 always deletes the cache directory if the cache is disabled.
 // Otherwise, buck-out state can diverge from the state of on-disk cache when
@@ -94,6 +94,8 @@ use crate::starlark_debug::run::run_dap_server_command;
 use crate::streaming_request_handler::StreamingRequestHandler;
 use crate::subscription::run_subscription_server_command;
 use crate::trace_io::trace_io_command;
+use crate::watch::run_watched;
+use crate::watch::wait_for_changes_command;
 
 // TODO(cjhopman): Figure out a reasonable value for this.
 static DEFAULT_KILL_TIMEOUT: Duration = Duration::from_millis(500);
@@ -912,6 +914,21 @@ impl DaemonApi for BuckdServer {
         .await
     }
 
+    type WaitForChangesStream = ResponseStream;
+    async fn wait_for_changes(
+        &self,
+        req: Request<WaitForChangesRequest>,
+    ) -> Result<Response<ResponseStream>, Status> {
+        self.run_streaming(
+            req,
+            DefaultCommandOptions,
+            |context, _: PartialResultDispatcher<NoPartialResult>, req| {
+                wait_for_changes_command(context, req).boxed()
+            },
+        )
+        .await
+    }
+
     type BuildStream = ResponseStream;
     async fn build(&self, req: Request<BuildRequest>) -> Result<Response<ResponseStream>, Status> {
         let callbacks = self.0.callbacks;
@@ -919,7 +936,17 @@ impl DaemonApi for BuckdServer {
             req,
             DefaultCommandOptions,
             |ctx, partial_result_dispatcher, req| {
-                callbacks.build(ctx, partial_result_dispatcher, req)
+                let watch = req.watch.clone();
+                run_watched(
+                    ctx,
+                    partial_result_dispatcher,
+                    req,
+                    watch,
+                    move |partial_result_dispatcher, req| {
+                        callbacks.build(ctx, partial_result_dispatcher, req)
+                    },
+                )
+                .boxed()
             },
         )
         .await
@@ -945,7 +972,17 @@ impl DaemonApi for BuckdServer {
             req,
             DefaultCommandOptions,
             |ctx, partial_result_dispatcher, req| {
-                callbacks.test(ctx, partial_result_dispatcher, req)
+                let watch = req.watch.clone();
+                run_watched(
+                    ctx,
+                    partial_result_dispatcher,
+                    req,
+                    watch,
+                    move |partial_result_dispatcher, req| {
+                        callbacks.test(ctx, partial_result_dispatcher, req)
+                    },
+                )
+                .boxed()
             },
         )
         .await
@@ -23,6 +23,7 @@ use buck2_common::ignores::ignore_set::IgnoreSet;
 use buck2_common::invocation_paths::InvocationPaths;
 use buck2_common::io::IoProvider;
 use buck2_common::legacy_configs::cells::BuckConfigBasedCells;
+use buck2_common::legacy_configs::LegacyBuckConfig;
 use buck2_common::result::SharedResult;
 use buck2_common::result::ToSharedResultExt;
 use buck2_core::cells::name::CellName;
@@ -33,6 +34,9 @@ use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
 use buck2_core::rollout_percentage::RolloutPercentage;
 use buck2_core::tag_result;
 use buck2_events::dispatch::EventDispatcher;
+use buck2_events::sink::bes::BesConfig;
+use buck2_events::sink::bes::BesSink;
+use buck2_events::sink::otlp::OtlpSink;
 use buck2_events::sink::scribe;
 use buck2_events::sink::tee::TeeSink;
 use buck2_events::EventSink;
@@ -43,6 +47,8 @@ use buck2_execute::execute::blocking::BuckBlockingExecutor;
 use buck2_execute::materialize::materializer::MaterializationMethod;
 use buck2_execute::materialize::materializer::Materializer;
 use buck2_execute::re::manager::ReConnectionManager;
+use buck2_execute_impl::local_action_cache::LocalActionCache;
+use buck2_execute_impl::materializers::deferred::CleanStaleConfiguration;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializer;
 use buck2_execute_impl::materializers::deferred::DeferredMaterializerConfigs;
 use buck2_execute_impl::materializers::deferred::TtlRefreshConfiguration;
@@ -58,6 +64,7 @@ use buck2_server_ctx::concurrency::DiceCleanup;
 use buck2_server_ctx::concurrency::NestedInvocation;
 use buck2_server_ctx::concurrency::ParallelInvocation;
 use buck2_wrapper_common::invocation_id::TraceId;
+use bytesize::ByteSize;
 use dupe::Dupe;
 use fbinit::FacebookInit;
 use gazebo::prelude::*;
@@ -68,6 +75,7 @@ use crate::active_commands::ActiveCommandDropGuard;
 use crate::ctx::BaseServerCommandContext;
 use crate::daemon::check_working_dir;
 use crate::daemon::disk_state::delete_unknown_disk_state;
+use crate::daemon::disk_state::maybe_initialize_dep_files_state;
 use crate::daemon::disk_state::maybe_initialize_materializer_sqlite_db;
 use crate::daemon::disk_state::DiskStateOptions;
 use crate::daemon::forkserver::maybe_launch_forkserver;
@@ -75,6 +83,9 @@ use crate::daemon::panic::DaemonStatePanicDiceDump;
 use crate::daemon::server::BuckdServerInitPreferences;
 use crate::file_watcher::FileWatcher;
 
+/// Default for `buck2.local_action_cache_max_mebibytes`.
+const DEFAULT_LOCAL_ACTION_CACHE_MAX_MEBIBYTES: u64 = 10 * 1024;
+
 /// For a buckd process there is a single DaemonState created at startup and never destroyed.
 #[derive(Allocative)]
 pub struct DaemonState {
@@ -120,9 +131,20 @@ pub struct DaemonStateData {
 
     pub(crate) forkserver: Option<ForkserverClient>,
 
+    /// The local action cache, if enabled. Like the materializer, this is shared by all commands.
+    pub(crate) local_action_cache: Option<Arc<LocalActionCache>>,
+
     #[allocative(skip)]
     pub scribe_sink: Option<Arc<dyn EventSink>>,
 
+    /// Publishes the events of every command to a Build Event Service, if one is configured.
+    #[allocative(skip)]
+    pub bes_sink: Option<Arc<dyn EventSink>>,
+
+    /// Exports the spans of every command to an OpenTelemetry collector, if one is configured.
+    #[allocative(skip)]
+    pub otlp_sink: Option<Arc<dyn EventSink>>,
+
     /// Whether or not to hash all commands
     pub hash_all_commands: bool,
 
@@ -303,6 +325,23 @@ impl DaemonState {
                 .unwrap_or_else(RolloutPercentage::never)
                 .roll();
 
+            // Keep buck-out under `clean_stale_max_size` by evicting the least recently used
+            // artifacts down to `clean_stale_target_size` (80% of the max size by default).
+            let clean_stale = parse_size_config(root_config, "clean_stale_max_size")?
+                .map(|max_size| {
+                    let frequency = root_config
+                        .parse("buck2", "clean_stale_frequency_seconds")?
+                        .unwrap_or(600);
+                    let target_size = parse_size_config(root_config, "clean_stale_target_size")?
+                        .unwrap_or(max_size / 5 * 4);
+                    anyhow::Ok(CleanStaleConfiguration {
+                        frequency: std::time::Duration::from_secs(frequency),
+                        max_size,
+                        target_size: std::cmp::min(target_size, max_size),
+                    })
+                })
+                .transpose()?;
+
             DeferredMaterializerConfigs {
                 materialize_final_artifacts: matches!(
                     materialization_method,
@@ -314,6 +353,7 @@ impl DaemonState {
                     min_ttl: chrono::Duration::seconds(ttl_refresh_min_ttl),
                     enabled: ttl_refresh_enabled,
                 },
+                clean_stale,
             }
         };
 
@@ -346,6 +386,13 @@ impl DaemonState {
 
         let materializer_state_identity = materializer_db.as_ref().map(|d| d.identity().clone());
 
+        maybe_initialize_dep_files_state(
+            &disk_state_options,
+            materializer_db.as_ref(),
+            blocking_executor.dupe() as Arc<dyn BlockingExecutor>,
+        )
+        .await?;
+
         let re_client_manager = Arc::new(ReConnectionManager::new(
             fb,
             false,
@@ -353,6 +400,7 @@ impl DaemonState {
             static_metadata,
             Some(paths.re_logs_dir().to_string()),
             paths.buck_out_dir().to_string(),
+            digest_config,
         ));
         let materializer = Self::create_materializer(
             fb,
@@ -372,6 +420,25 @@ impl DaemonState {
         let forkserver =
             maybe_launch_forkserver(root_config, &paths.forkserver_state_dir()).await?;
 
+        let local_action_cache = if root_config
+            .parse("buck2", "local_action_cache_enabled")?
+            .unwrap_or(false)
+        {
+            let max_bytes = root_config
+                .parse::<u64>("buck2", "local_action_cache_max_mebibytes")?
+                .unwrap_or(DEFAULT_LOCAL_ACTION_CACHE_MAX_MEBIBYTES)
+                * 1024
+                * 1024;
+            let local_action_cache_dir = paths.local_action_cache_dir()?;
+            let local_action_cache = (blocking_executor.dupe() as Arc<dyn BlockingExecutor>)
+                .execute_io_inline(|| LocalActionCache::open(local_action_cache_dir, max_bytes))
+                .await
+                .context("Error initializing the local action cache")?;
+            Some(Arc::new(local_action_cache))
+        } else {
+            None
+        };
+
         let dice = init_ctx
             .construct_dice(io.dupe(), digest_config, root_config)
             .await?;
@@ -436,6 +503,8 @@ impl DaemonState {
             message_batch_size,
         )
         .context("failed to init scribe sink")?;
+        let bes_sink = Self::init_bes_sink(root_config).context("failed to init BES sink")?;
+        let otlp_sink = Self::init_otlp_sink(root_config).context("failed to init OTLP sink")?;
 
         let critical_path_backend = root_config
             .parse("buck2", "critical_path_backend2")?
@@ -464,7 +533,10 @@ impl DaemonState {
             blocking_executor,
             materializer,
             forkserver,
+            local_action_cache,
             scribe_sink,
+            bes_sink,
+            otlp_sink,
             hash_all_commands,
             use_network_action_output_cache,
             disk_state_options,
@@ -569,8 +641,52 @@ impl DaemonState {
         .map(|maybe_scribe| maybe_scribe.map(|scribe| Arc::new(scribe) as _))
     }
 
+    /// Creates a sink publishing to the Build Event Service at `buck2.bes_endpoint`, if set.
+    /// `buck2.bes_http_headers` is a comma-separated list of `name:value` headers sent with every
+    /// stream, e.g. for authentication.
+    fn init_bes_sink(root_config: &LegacyBuckConfig) -> anyhow::Result<Option<Arc<dyn EventSink>>> {
+        let endpoint = match root_config.get("buck2", "bes_endpoint") {
+            Some(endpoint) if !endpoint.trim().is_empty() => endpoint.trim().to_owned(),
+            _ => return Ok(None),
+        };
+        let headers = root_config
+            .parse_list::<String>("buck2", "bes_http_headers")?
+            .unwrap_or_default()
+            .iter()
+            .filter(|h| !h.trim().is_empty())
+            .map(|header| match header.split_once(':') {
+                Some((name, value)) => Ok((name.trim().to_owned(), value.trim().to_owned())),
+                None => Err(anyhow::anyhow!(
+                    "Invalid BES header `{}`, expected `name:value`",
+                    header.trim()
+                )),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let sink = BesSink::new(BesConfig {
+            endpoint,
+            project_id: root_config
+                .get("buck2", "bes_project_id")
+                .map(str::to_owned),
+            headers,
+        })?;
+        Ok(Some(Arc::new(sink)))
+    }
+
+    /// Creates a sink exporting spans to the OpenTelemetry collector at `buck2.otlp_endpoint`, if set.
+    fn init_otlp_sink(
+        root_config: &LegacyBuckConfig,
+    ) -> anyhow::Result<Option<Arc<dyn EventSink>>> {
+        match root_config.get("buck2", "otlp_endpoint") {
+            Some(endpoint) if !endpoint.trim().is_empty() => {
+                Ok(Some(Arc::new(OtlpSink::new(endpoint.trim())?)))
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Prepares an event stream for a request by bootstrapping an event source and EventDispatcher pair. The given
-    /// EventDispatcher will log to the returned EventSource and (optionally) to Scribe if enabled via buckconfig.
+    /// EventDispatcher will log to the returned EventSource and (optionally) to Scribe, a Build Event Service and an
+    /// OpenTelemetry collector if enabled via buckconfig.
     pub async fn prepare_events(
         &self,
         trace_id: TraceId,
@@ -579,10 +695,22 @@ impl DaemonState {
         facebook_only();
         let (events, sink) = buck2_events::create_source_sink_pair();
         let data = self.data()?;
-        let dispatcher = if let Some(scribe_sink) = data.scribe_sink.dupe() {
-            EventDispatcher::new(trace_id, TeeSink::new(scribe_sink, sink))
-        } else {
+        let extra_sinks: Vec<Arc<dyn EventSink>> = [
+            data.scribe_sink.dupe(),
+            data.bes_sink.dupe(),
+            data.otlp_sink.dupe(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let dispatcher = if extra_sinks.is_empty() {
             EventDispatcher::new(trace_id, sink)
+        } else {
+            let mut tee: Arc<dyn EventSink> = Arc::new(sink);
+            for extra_sink in extra_sinks.into_iter().rev() {
+                tee = Arc::new(TeeSink::new(extra_sink, tee));
+            }
+            EventDispatcher::new(trace_id, tee)
         };
         Ok((events, dispatcher))
     }
@@ -627,6 +755,10 @@ impl DaemonState {
                 "sqlite-materializer-state:{}",
                 data.disk_state_options.sqlite_materializer_state
             ),
+            format!(
+                "sqlite-dep-files:{}",
+                data.disk_state_options.sqlite_dep_files
+            ),
         ];
 
         dispatcher.instant_event(buck2_data::TagEvent { tags });
@@ -649,6 +781,7 @@ impl DaemonState {
             file_watcher: data.file_watcher.dupe(),
             events: dispatcher,
             forkserver: data.forkserver.dupe(),
+            local_action_cache: data.local_action_cache.dupe(),
             hash_all_commands: data.hash_all_commands,
             use_network_action_output_cache: data.use_network_action_output_cache,
             _drop_guard: drop_guard,
@@ -709,3 +842,15 @@ impl DaemonState {
         Ok(())
     }
 }
+
+/// Parse a size in `buck2` config, e.g. `50GB`, into bytes.
+fn parse_size_config(root_config: &LegacyBuckConfig, key: &str) -> anyhow::Result<Option<u64>> {
+    root_config
+        .get("buck2", key)
+        .map(|v| {
+            v.parse::<ByteSize>()
+                .map(|s| s.as_u64())
+                .map_err(|e| anyhow::anyhow!("Invalid size for `buck2.{}`: {}", key, e))
+        })
+        .transpose()
+}
@@ -8,7 +8,9 @@
  */
 
 use std::collections::HashMap;
+use std::mem;
 use std::sync::Arc;
+use std::time::Duration;
 
 use allocative::Allocative;
 use anyhow::Context;
@@ -20,6 +22,7 @@ use buck2_core::cells::CellResolver;
 use buck2_core::fs::project::ProjectRoot;
 use buck2_core::is_open_source;
 use dice::DiceTransactionUpdater;
+use tokio::sync::watch;
 
 use crate::file_watcher::notify::NotifyFileWatcher;
 use crate::file_watcher::watchman::interface::WatchmanFileWatcher;
@@ -31,6 +34,78 @@ mod watchman;
 #[async_trait]
 pub trait FileWatcher: Allocative + Send + Sync + 'static {
     async fn sync(&self, dice: DiceTransactionUpdater) -> anyhow::Result<DiceTransactionUpdater>;
+
+    /// Subscribes to changes to files that aren't ignored, for commands that wait for files to
+    /// change. Unlike `sync`, this doesn't consume the changes, so any number of subscribers see
+    /// every change regardless of other commands syncing in the meantime. Changes that haven't
+    /// been synced yet count as changes for the new subscription.
+    async fn subscribe(&self) -> anyhow::Result<ChangeSubscription>;
+}
+
+/// Counts the changes a file watcher sees, for its subscribers.
+#[derive(Allocative)]
+pub struct ChangeNotifier {
+    #[allocative(skip)]
+    changes: watch::Sender<u64>,
+}
+
+impl Default for ChangeNotifier {
+    fn default() -> Self {
+        Self {
+            changes: watch::channel(0).0,
+        }
+    }
+}
+
+impl ChangeNotifier {
+    pub fn notify(&self, changes: u64) {
+        if changes != 0 {
+            self.changes.send_modify(|count| *count += changes);
+        }
+    }
+
+    /// `pending` is whether there already are changes the subscriber should see.
+    pub fn subscribe(&self, pending: bool) -> ChangeSubscription {
+        ChangeSubscription {
+            changes: self.changes.subscribe(),
+            pending,
+        }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.changes.receiver_count() != 0
+    }
+}
+
+pub struct ChangeSubscription {
+    changes: watch::Receiver<u64>,
+    /// Whether there are changes `wait_for_changes` hasn't returned for yet.
+    pending: bool,
+}
+
+impl ChangeSubscription {
+    /// Forgets about the changes so far, e.g. because a command that syncs them is about to run.
+    pub fn mark_seen(&mut self) {
+        self.changes.borrow_and_update();
+        self.pending = false;
+    }
+
+    /// Waits until there are changes, and then until no further changes arrive for `debounce`,
+    /// so that a burst of changes (e.g. a rebase) is picked up at once.
+    pub async fn wait_for_changes(&mut self, debounce: Duration) -> anyhow::Result<()> {
+        if !mem::take(&mut self.pending) {
+            self.changes
+                .changed()
+                .await
+                .context("File watcher has shut down")?;
+        }
+        loop {
+            match tokio::time::timeout(debounce, self.changes.changed()).await {
+                Ok(res) => res.context("File watcher has shut down")?,
+                Err(_) => return Ok(()),
+            }
+        }
+    }
 }
 
 impl dyn FileWatcher {
@@ -61,3 +136,84 @@ impl dyn FileWatcher {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use dupe::Dupe;
+
+    use super::*;
+
+    /// Notifies `notifier` of one change after each of `delays`.
+    fn notify_after(notifier: Arc<ChangeNotifier>, delays: &[u64]) {
+        let delays = delays.to_vec();
+        tokio::spawn(async move {
+            for delay in delays {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                notifier.notify(1);
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_changes_waits_for_changes() -> anyhow::Result<()> {
+        let notifier = Arc::new(ChangeNotifier::default());
+        let mut changes = notifier.subscribe(false);
+        notify_after(notifier.dupe(), &[50]);
+        let start = Instant::now();
+        changes.wait_for_changes(Duration::from_millis(1)).await?;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_changes_debounces() -> anyhow::Result<()> {
+        // Returns once no more changes arrive for the debounce period.
+        let notifier = Arc::new(ChangeNotifier::default());
+        let mut changes = notifier.subscribe(false);
+        notify_after(notifier.dupe(), &[1, 20, 20, 20]);
+        let start = Instant::now();
+        changes.wait_for_changes(Duration::from_millis(50)).await?;
+        assert!(start.elapsed() >= Duration::from_millis(110));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_changes_returns_for_pending_changes() -> anyhow::Result<()> {
+        let notifier = ChangeNotifier::default();
+        let mut changes = notifier.subscribe(true);
+        changes.wait_for_changes(Duration::from_millis(1)).await?;
+
+        notifier.notify(3);
+        changes.wait_for_changes(Duration::from_millis(1)).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mark_seen_forgets_changes() -> anyhow::Result<()> {
+        let notifier = Arc::new(ChangeNotifier::default());
+        let mut changes = notifier.subscribe(true);
+        notifier.notify(1);
+        changes.mark_seen();
+        notify_after(notifier.dupe(), &[50]);
+        let start = Instant::now();
+        changes.wait_for_changes(Duration::from_millis(1)).await?;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_changes_fails_once_the_watcher_is_gone() {
+        let notifier = ChangeNotifier::default();
+        let mut changes = notifier.subscribe(false);
+        assert!(notifier.has_subscribers());
+        drop(notifier);
+        assert!(
+            changes
+                .wait_for_changes(Duration::from_millis(1))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -36,6 +36,8 @@ use notify::Watcher;
 use tracing::info;
 
 use crate::file_watcher::stats::FileWatcherStats;
+use crate::file_watcher::ChangeNotifier;
+use crate::file_watcher::ChangeSubscription;
 use crate::file_watcher::FileWatcher;
 
 #[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq, Hash, Allocative)]
@@ -92,15 +94,17 @@ impl NotifyFileData {
         }
     }
 
+    /// Returns the number of changes that aren't ignored.
     fn process(
         &mut self,
         event: notify::Result<notify::Event>,
         root: &ProjectRoot,
         cells: &CellResolver,
         ignore_specs: &HashMap<CellName, IgnoreSet>,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<u64> {
         let event = event?;
         let change_type = ChangeType::new(event.kind);
+        let mut changes = 0;
         for path in event.paths {
             // Testing shows that we get absolute paths back from the `notify` library.
             // It's not documented though.
@@ -131,9 +135,10 @@ impl NotifyFileData {
                 self.ignored += 1;
             } else {
                 self.events.insert((cell_path, change_type));
+                changes += 1;
             }
         }
-        Ok(())
+        Ok(changes)
     }
 
     fn sync(self) -> (buck2_data::FileWatcherStats, FileChangeTracker) {
@@ -180,6 +185,7 @@ pub struct NotifyFileWatcher {
     #[allocative(skip)]
     watcher: RecommendedWatcher,
     data: Arc<Mutex<anyhow::Result<NotifyFileData>>>,
+    changes: Arc<ChangeNotifier>,
 }
 
 impl NotifyFileWatcher {
@@ -189,18 +195,29 @@ impl NotifyFileWatcher {
         ignore_specs: HashMap<CellName, IgnoreSet>,
     ) -> anyhow::Result<Self> {
         let data = Arc::new(Mutex::new(Ok(NotifyFileData::new())));
+        let changes = Arc::new(ChangeNotifier::default());
         let data2 = data.dupe();
+        let changes2 = changes.dupe();
         let root2 = root.dupe();
         let mut watcher = notify::recommended_watcher(move |event| {
             let mut guard = data2.lock().unwrap();
             if let Ok(state) = &mut *guard {
-                if let Err(e) = state.process(event, &root2, &cells, &ignore_specs) {
-                    *guard = Err(e);
+                match state.process(event, &root2, &cells, &ignore_specs) {
+                    Ok(count) => changes2.notify(count),
+                    Err(e) => {
+                        *guard = Err(e);
+                        // The next `sync` reports the error, so wake subscribers up to run it.
+                        changes2.notify(1);
+                    }
                 }
             }
         })?;
         watcher.watch(root.root().as_path(), notify::RecursiveMode::Recursive)?;
-        Ok(Self { watcher, data })
+        Ok(Self {
+            watcher,
+            data,
+            changes,
+        })
     }
 
     fn sync2(
@@ -232,4 +249,15 @@ impl FileWatcher for NotifyFileWatcher {
         )
         .await
     }
+
+    async fn subscribe(&self) -> anyhow::Result<ChangeSubscription> {
+        // Holding the lock means no changes are missed between looking at the data and
+        // subscribing.
+        let guard = self.data.lock().unwrap();
+        let pending = match &*guard {
+            Ok(data) => !data.events.is_empty(),
+            Err(_) => true,
+        };
+        Ok(self.changes.subscribe(pending))
+    }
 }
@@ -23,8 +23,16 @@ use serde::Deserialize;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
+use tokio::time::MissedTickBehavior;
 use watchman_client::prelude::*;
 
+use crate::file_watcher::ChangeNotifier;
+use crate::file_watcher::ChangeSubscription;
+
+/// Watchman only reports changes when queried, so while anyone is subscribed to changes, it is
+/// queried this often.
+const CHANGES_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
 // We use the "new" field. This is marked as deprecated, but buck1 uses it and
 // I'm unaware of issues due to its use there.
 //
@@ -176,6 +184,11 @@ pub trait SyncableQueryProcessor: Send + Sync {
         watchman_version: Option<String>,
     ) -> anyhow::Result<(Self::Output, Self::Payload)>;
 
+    /// The number of events that `process_events` would not ignore.
+    fn relevant_events(&self, events: &[WatchmanEvent]) -> u64 {
+        events.len() as u64
+    }
+
     /// Indicates that all derived data should be invalidated. This could happen, for example, if the watchman server restarts.
     async fn on_fresh_instance(
         &self,
@@ -188,6 +201,7 @@ pub trait SyncableQueryProcessor: Send + Sync {
 /// commands to be sent to the SyncableQueryHandler.
 enum SyncableQueryCommand<T, P> {
     Sync(P, oneshot::Sender<anyhow::Result<(T, P)>>),
+    Subscribe(oneshot::Sender<ChangeSubscription>),
 }
 
 /// A SyncableQuery is similar to a subscription. When created, it accepts a query expression
@@ -232,6 +246,11 @@ struct SyncableQueryHandler<T, P> {
     last_mergebase: Option<String>,
     mergebase_with: Option<String>,
     control_rx: UnboundedReceiver<SyncableQueryCommand<T, P>>,
+    changes: ChangeNotifier,
+    /// The clock the changes for subscribers have been counted up to. This is separate from
+    /// `last_clock` so that syncing doesn't hide changes from subscribers. Only kept while there
+    /// are subscribers.
+    changes_clock: Option<ClockSpec>,
 }
 
 impl<T, P> SyncableQueryHandler<T, P>
@@ -248,23 +267,49 @@ where
             tracing::warn!("Connecting to Watchman failed (will re-attempt): {:#}", e);
         };
 
+        let mut poll = tokio::time::interval(CHANGES_POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
-            match self.control_rx.recv().await {
-                Some(SyncableQueryCommand::Sync(dice, sync_tx)) => {
-                    let res = self.sync(dice, &mut client).await;
+            if !self.changes.has_subscribers() {
+                self.changes_clock = None;
+            }
 
-                    // NOTE: If the receiver is gone, then they won't be told we finished their
-                    // job. That's fine.
-                    let _ignore = sync_tx.send(res);
-                }
-                None => {
-                    // This indicates the controlling SyncableQuery has been dropped.
-                    return;
+            tokio::select! {
+                command = self.control_rx.recv() => match command {
+                    Some(SyncableQueryCommand::Sync(dice, sync_tx)) => {
+                        let res = self.sync(dice, &mut client).await;
+
+                        // NOTE: If the receiver is gone, then they won't be told we finished their
+                        // job. That's fine.
+                        let _ignore = sync_tx.send(res);
+                    }
+                    Some(SyncableQueryCommand::Subscribe(tx)) => {
+                        let subscription = self.changes.subscribe(false);
+                        if self.changes_clock.is_none() {
+                            // Counts the changes that haven't been synced yet, so the new
+                            // subscription sees them.
+                            self.poll_changes(&mut client).await;
+                        }
+                        let _ignore = tx.send(subscription);
+                    }
+                    None => {
+                        // This indicates the controlling SyncableQuery has been dropped.
+                        return;
+                    }
+                },
+                _ = poll.tick(), if self.changes_clock.is_some() => {
+                    self.poll_changes(&mut client).await;
                 }
             }
         }
     }
 
+    fn is_same_mergebase(&self, merge_base: &Option<String>) -> bool {
+        self.mergebase_with.is_none()
+            || self.last_mergebase.is_some() && self.last_mergebase == *merge_base
+    }
+
     /// sync() will send a since query to watchman and invoke the processor
     /// with either the received events or a fresh instance call.
     async fn sync(
@@ -284,9 +329,7 @@ where
                 clock,
                 watchman_version,
             } => {
-                if self.mergebase_with.is_none()
-                    || self.last_mergebase.is_some() && self.last_mergebase == merge_base
-                {
+                if self.is_same_mergebase(&merge_base) {
                     (
                         self.processor
                             .process_events(payload, events, &merge_base, watchman_version)
@@ -323,6 +366,44 @@ where
         Ok(res)
     }
 
+    /// Notifies subscribers of the relevant changes since the last time this was called, or
+    /// since the last sync if there have been no subscribers since.
+    async fn poll_changes(&mut self, client: &mut Option<WatchmanClient>) {
+        let (res, since_sync) = match self.changes_clock.take() {
+            Some(clock) => (self.query_since(client, Clock::Spec(clock)).await, false),
+            None => (self.sync_query(client).await, true),
+        };
+
+        match res {
+            Ok(WatchmanSyncResult::Events {
+                events,
+                merge_base,
+                clock,
+                ..
+            }) => {
+                // Like a fresh instance, a new mergebase invalidates everything on the next sync.
+                let changes = if since_sync && !self.is_same_mergebase(&merge_base) {
+                    1
+                } else {
+                    self.processor.relevant_events(&events)
+                };
+                self.changes.notify(changes);
+                self.changes_clock = Some(clock);
+            }
+            Ok(WatchmanSyncResult::FreshInstance { clock, .. }) => {
+                self.changes.notify(1);
+                self.changes_clock = Some(clock);
+            }
+            Err(e) => {
+                tracing::warn!("Querying Watchman for changes failed: {:#}", e);
+                // Reconnecting resets the clock, so the next poll sees a fresh instance.
+                if let Err(e) = self.reconnect(client).await {
+                    tracing::warn!("{:#}", e);
+                }
+            }
+        }
+    }
+
     async fn reconnect(&mut self, client: &mut Option<WatchmanClient>) -> anyhow::Result<()> {
         self.last_clock = Default::default();
         self.last_mergebase = None;
@@ -351,22 +432,32 @@ where
         &mut self,
         client: &mut Option<WatchmanClient>,
     ) -> anyhow::Result<WatchmanSyncResult> {
-        let client = client.as_mut().context("No Watchman connection")?;
-
-        let mut query = self.query.clone();
-        query.since = if let Some(mergebase_with) = self.mergebase_with.as_ref() {
-            Some(Clock::ScmAware(FatClockData {
+        let since = if let Some(mergebase_with) = self.mergebase_with.as_ref() {
+            Clock::ScmAware(FatClockData {
                 clock: self.last_clock.clone(),
                 scm: Some(ScmAwareClockData {
                     mergebase: self.last_mergebase.clone(),
                     mergebase_with: Some(mergebase_with.clone()),
                     saved_state: None,
                 }),
-            }))
+            })
         } else {
-            Some(Clock::Spec(self.last_clock.clone()))
+            Clock::Spec(self.last_clock.clone())
         };
 
+        self.query_since(client, since).await
+    }
+
+    async fn query_since(
+        &self,
+        client: &mut Option<WatchmanClient>,
+        since: Clock,
+    ) -> anyhow::Result<WatchmanSyncResult> {
+        let client = client.as_mut().context("No Watchman connection")?;
+
+        let mut query = self.query.clone();
+        query.since = Some(since);
+
         let QueryResult {
             version,
             is_fresh_instance,
@@ -447,6 +538,17 @@ where
         }
     }
 
+    /// Subscribes to the relevant changes watchman sees, including those not synced yet.
+    pub async fn subscribe(&self) -> anyhow::Result<ChangeSubscription> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.control_tx
+            .send(SyncableQueryCommand::Subscribe(tx))
+            .ok()
+            .context("SyncableQueryHandler has exited")?;
+        rx.await
+            .context("SyncableQueryHandler did not return a response for subscribe request")
+    }
+
     pub fn new(
         connector: Connector,
         path: impl AsRef<Path>,
@@ -486,6 +588,8 @@ where
                 mergebase_with,
                 processor,
                 control_rx,
+                changes: ChangeNotifier::default(),
+                changes_clock: None,
             };
             handler.run_loop().await
         });
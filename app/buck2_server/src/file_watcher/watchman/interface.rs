@@ -16,6 +16,7 @@ use async_trait::async_trait;
 use buck2_common::dice::file_ops::FileChangeTracker;
 use buck2_common::ignores::ignore_set::IgnoreSet;
 use buck2_common::legacy_configs::LegacyBuckConfig;
+use buck2_core::cells::cell_path::CellPath;
 use buck2_core::cells::name::CellName;
 use buck2_core::cells::CellResolver;
 use buck2_core::fs::paths::abs_norm_path::AbsNormPath;
@@ -35,6 +36,7 @@ use crate::file_watcher::watchman::core::SyncableQueryProcessor;
 use crate::file_watcher::watchman::core::WatchmanEvent;
 use crate::file_watcher::watchman::core::WatchmanEventType;
 use crate::file_watcher::watchman::core::WatchmanKind;
+use crate::file_watcher::ChangeSubscription;
 use crate::file_watcher::FileWatcher;
 
 struct WatchmanQueryProcessor {
@@ -51,6 +53,13 @@ enum ChangeEvent<'a> {
 }
 
 impl WatchmanQueryProcessor {
+    fn is_ignored(&self, cell_path: &CellPath) -> bool {
+        self.ignore_specs
+            .get(&cell_path.cell())
+            .expect("unexpected cell name mismatch")
+            .is_match(cell_path.path())
+    }
+
     async fn process_events_impl(
         &self,
         mut ctx: DiceTransactionUpdater,
@@ -99,11 +108,7 @@ impl WatchmanQueryProcessor {
     ) -> anyhow::Result<()> {
         let cell_path = self.cells.get_cell_path(path)?;
 
-        let ignore = self
-            .ignore_specs
-            .get(&cell_path.cell())
-            .expect("unexpected cell name mismatch")
-            .is_match(cell_path.path());
+        let ignore = self.is_ignored(&cell_path);
 
         info!("Watchman: {:?} (ignore = {})", ev, ignore);
 
@@ -217,6 +222,29 @@ impl SyncableQueryProcessor for WatchmanQueryProcessor {
             .await
     }
 
+    fn relevant_events(&self, events: &[WatchmanEvent]) -> u64 {
+        events
+            .iter()
+            .filter(|ev| {
+                // Directory modifications are ignored by `process_one_change`.
+                if matches!(
+                    (&ev.kind, &ev.event),
+                    (WatchmanKind::Directory, WatchmanEventType::Modify)
+                ) {
+                    return false;
+                }
+                match ProjectRelativePath::new(&ev.path)
+                    .ok()
+                    .and_then(|path| self.cells.get_cell_path(path).ok())
+                {
+                    Some(cell_path) => !self.is_ignored(&cell_path),
+                    // Invalid paths invalidate their parent directory.
+                    None => true,
+                }
+            })
+            .count() as u64
+    }
+
     async fn on_fresh_instance(
         &self,
         ctx: DiceTransactionUpdater,
@@ -311,4 +339,68 @@ impl FileWatcher for WatchmanFileWatcher {
         )
         .await
     }
+
+    async fn subscribe(&self) -> anyhow::Result<ChangeSubscription> {
+        self.query.subscribe().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use buck2_core::cells::cell_root_path::CellRootPathBuf;
+    use buck2_core::fs::project_rel_path::ProjectRelativePathBuf;
+
+    use super::*;
+
+    fn event(kind: WatchmanKind, event: WatchmanEventType, path: &str) -> WatchmanEvent {
+        WatchmanEvent {
+            kind,
+            event,
+            path: PathBuf::from(path),
+        }
+    }
+
+    #[test]
+    fn test_relevant_events() -> anyhow::Result<()> {
+        let root = CellName::testing_new("root");
+        let processor = WatchmanQueryProcessor {
+            cells: CellResolver::testing_with_name_and_path(
+                root,
+                CellRootPathBuf::new(ProjectRelativePathBuf::unchecked_new(String::new())),
+            ),
+            ignore_specs: HashMap::from([(root, IgnoreSet::from_ignore_spec("buck-out")?)]),
+            retain_dep_files_on_watchman_fresh_instance: false,
+        };
+
+        assert_eq!(
+            0,
+            processor.relevant_events(&[
+                event(WatchmanKind::Directory, WatchmanEventType::Modify, "src"),
+                event(WatchmanKind::File, WatchmanEventType::Create, "buck-out/a"),
+                event(WatchmanKind::File, WatchmanEventType::Modify, "buck-out"),
+            ])
+        );
+        assert_eq!(
+            4,
+            processor.relevant_events(&[
+                event(WatchmanKind::File, WatchmanEventType::Modify, "src/a.rs"),
+                event(WatchmanKind::Directory, WatchmanEventType::Create, "src/b"),
+                event(
+                    WatchmanKind::Symlink,
+                    WatchmanEventType::Delete,
+                    "buck-outs"
+                ),
+                // Invalid paths are counted, since they invalidate their parent directory.
+                event(WatchmanKind::File, WatchmanEventType::Create, "src/../c"),
+                event(
+                    WatchmanKind::Directory,
+                    WatchmanEventType::Modify,
+                    "buck-out/d"
+                ),
+            ])
+        );
+        Ok(())
+    }
 }
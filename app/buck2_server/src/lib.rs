@@ -31,3 +31,4 @@ mod starlark_debug;
 mod streaming_request_handler;
 mod subscription;
 mod trace_io;
+mod watch;
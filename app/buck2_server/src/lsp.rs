@@ -12,6 +12,7 @@ use std::future::Future;
 use std::io;
 use std::io::ErrorKind;
 use std::path::Path;
+use std::sync::Arc;
 use std::thread;
 
 use buck2_cli_proto::*;
@@ -57,6 +58,7 @@ use lsp_server::Connection;
 use lsp_server::Message;
 use lsp_types::Range;
 use lsp_types::Url;
+use starlark::collections::SmallMap;
 use starlark::docs::Doc;
 use starlark::docs::DocItem;
 use starlark::docs::Identifier;
@@ -170,6 +172,9 @@ struct DocsCache {
     global_urls: HashMap<String, LspUrl>,
     /// Mapping of starlark: urls to a synthesized starlark representation.
     native_starlark_files: HashMap<LspUrl, String>,
+    /// Documentation for all of the global symbols, e.g. rules and their attributes,
+    /// keyed by symbol name.
+    global_docs: Arc<SmallMap<String, Doc>>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -272,6 +277,12 @@ impl DocsCache {
         Ok(Self {
             global_urls,
             native_starlark_files,
+            global_docs: Arc::new(
+                builtin_symbols
+                    .iter()
+                    .map(|doc| (doc.id.name.clone(), doc.clone()))
+                    .collect(),
+            ),
         })
     }
 
@@ -284,6 +295,10 @@ impl DocsCache {
     fn url_for_symbol(&self, symbol: &str) -> Option<&LspUrl> {
         self.global_urls.get(symbol)
     }
+
+    fn global_docs(&self) -> &Arc<SmallMap<String, Doc>> {
+        &self.global_docs
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -734,6 +749,22 @@ impl<'a> LspContext for BuckLspContext<'a> {
                 Ok(docs_cache.url_for_symbol(symbol).cloned())
             }))
     }
+
+    fn get_global_symbol_docs(
+        &self,
+        _current_file: &LspUrl,
+    ) -> anyhow::Result<Arc<SmallMap<String, Doc>>> {
+        let dispatcher = self.server_ctx.events().dupe();
+        self.runtime
+            .block_on(with_dispatcher_async(dispatcher, async {
+                let docs_cache = self
+                    .with_dice_ctx(|dice_ctx| async {
+                        self.docs_cache_manager.get_cache(dice_ctx).await
+                    })
+                    .await?;
+                Ok(docs_cache.global_docs().dupe())
+            }))
+    }
 }
 
 pub(crate) async fn run_lsp_server_command(
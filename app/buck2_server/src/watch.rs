@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Running commands again every time files change, for `--watch`.
+
+use std::future::Future;
+use std::time::Duration;
+use std::time::Instant;
+
+use buck2_cli_proto::BuildResponse;
+use buck2_cli_proto::TestResponse;
+use buck2_events::dispatch::span_async;
+use buck2_server_ctx::command_end::command_end;
+use buck2_server_ctx::ctx::ServerCommandContextTrait;
+use buck2_server_ctx::partial_result_dispatcher::NoPartialResult;
+use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
+use dupe::Dupe;
+
+use crate::ctx::ServerCommandContext;
+
+/// The outcome of a command that is reported after every run in watch mode.
+pub(crate) trait WatchedResponse {
+    fn is_success(&self) -> bool;
+
+    fn error_messages(&self) -> &[String];
+}
+
+impl WatchedResponse for BuildResponse {
+    fn is_success(&self) -> bool {
+        self.error_messages.is_empty()
+    }
+
+    fn error_messages(&self) -> &[String] {
+        &self.error_messages
+    }
+}
+
+impl WatchedResponse for TestResponse {
+    fn is_success(&self) -> bool {
+        self.error_messages.is_empty() && self.exit_code == Some(0)
+    }
+
+    fn error_messages(&self) -> &[String] {
+        &self.error_messages
+    }
+}
+
+/// Runs `command` once, or, with `watch` set, again every time the file watcher reports changes.
+/// Every run is a separate command with its own DICE transaction, so it sees the changes. In watch
+/// mode this only returns if waiting for changes fails, otherwise it runs until the client
+/// disconnects; the outcome of every run is reported with a `WatchIterationEnd` event.
+pub(crate) async fn run_watched<Req, Res, F, Fut>(
+    ctx: &ServerCommandContext<'_>,
+    partial_result_dispatcher: PartialResultDispatcher<NoPartialResult>,
+    req: Req,
+    watch: Option<buck2_cli_proto::WatchOptions>,
+    mut command: F,
+) -> anyhow::Result<Res>
+where
+    Req: Clone,
+    Res: WatchedResponse,
+    F: FnMut(PartialResultDispatcher<NoPartialResult>, Req) -> Fut,
+    Fut: Future<Output = anyhow::Result<Res>>,
+{
+    let watch = match watch {
+        Some(watch) => watch,
+        None => return command(partial_result_dispatcher, req).await,
+    };
+    let debounce = Duration::from_millis(watch.debounce_ms);
+    let mut changes = ctx.base_context.file_watcher.subscribe().await?;
+
+    let mut partial_result_dispatcher = Some(partial_result_dispatcher);
+    let mut iteration = 0;
+    loop {
+        iteration += 1;
+        let partial_result_dispatcher = partial_result_dispatcher
+            .take()
+            .unwrap_or_else(|| PartialResultDispatcher::new(ctx.events().dupe()));
+
+        // The command syncs the changes so far, so it's only the ones after this that we wait for.
+        changes.mark_seen();
+
+        let start = Instant::now();
+        let result = command(partial_result_dispatcher, req.clone()).await;
+        let (success, error, error_messages) = match &result {
+            Ok(res) => (res.is_success(), None, res.error_messages().to_vec()),
+            Err(e) => (false, Some(format!("{:#}", e)), Vec::new()),
+        };
+        ctx.events().instant_event(buck2_data::WatchIterationEnd {
+            iteration,
+            success,
+            duration: start.elapsed().try_into().ok(),
+            error,
+            error_messages,
+        });
+
+        changes.wait_for_changes(debounce).await?;
+    }
+}
+
+/// Waits for files to change, for commands that run in watch mode on the client.
+pub(crate) async fn wait_for_changes_command(
+    context: &ServerCommandContext<'_>,
+    req: buck2_cli_proto::WaitForChangesRequest,
+) -> anyhow::Result<buck2_cli_proto::GenericResponse> {
+    let metadata = context.request_metadata().await?;
+    let start_event = buck2_data::CommandStart {
+        metadata: metadata.clone(),
+        data: Some(buck2_data::WaitForChangesCommandStart {}.into()),
+    };
+    span_async(start_event, async move {
+        let debounce = Duration::from_millis(req.watch.map_or(0, |w| w.debounce_ms));
+        let result = async {
+            // Changes that no command has synced yet count too: they were most likely made
+            // while the client was building.
+            let mut changes = context.base_context.file_watcher.subscribe().await?;
+            changes.wait_for_changes(debounce).await
+        }
+        .await
+        .map(|()| buck2_cli_proto::GenericResponse {});
+        let end_event = command_end(metadata, &result, buck2_data::WaitForChangesCommandEnd {});
+        (result, end_event)
+    })
+    .await
+}
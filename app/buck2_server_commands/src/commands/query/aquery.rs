@@ -98,12 +98,12 @@ async fn aquery(
     let result = match query_result {
         QueryEvaluationResult::Single(targets) => {
             output_configuration
-                .print_single_output(&mut stdout, targets, false, ShouldPrintProviders::No)
+                .print_single_output(&mut stdout, targets, false, ShouldPrintProviders::No, &*ctx)
                 .await
         }
         QueryEvaluationResult::Multiple(results) => {
             output_configuration
-                .print_multi_output(&mut stdout, results, false, ShouldPrintProviders::No)
+                .print_multi_output(&mut stdout, results, false, ShouldPrintProviders::No, &*ctx)
                 .await
         }
     };
@@ -36,6 +36,7 @@ use dupe::Dupe;
 use crate::commands::query::printer::ProviderLookUp;
 use crate::commands::query::printer::QueryResultPrinter;
 use crate::commands::query::printer::ShouldPrintProviders;
+use crate::commands::query::QueryCommandError;
 
 pub async fn cquery_command(
     ctx: &dyn ServerCommandContextTrait,
@@ -86,7 +87,7 @@ impl ServerCommandTemplate for CqueryServerCommand {
 
 async fn cquery(
     server_ctx: &dyn ServerCommandContextTrait,
-    mut stdout: impl Write,
+    mut stdout: impl Write + Send + Sync,
     ctx: DiceTransaction,
     request: &CqueryRequest,
 ) -> anyhow::Result<CqueryResponse> {
@@ -105,6 +106,7 @@ async fn cquery(
         target_call_stacks,
         show_providers,
         correct_owner,
+        streaming,
         ..
     } = request;
     // The request will always have a universe value, an empty one indicates the user didn't provide a universe.
@@ -134,6 +136,26 @@ async fn cquery(
 
     let evaluator = &evaluator;
 
+    if *streaming {
+        if !query_args.is_empty() {
+            return Err(QueryCommandError::StreamingMultiQuery.into());
+        }
+        let mut printer =
+            output_configuration.streaming_printer(&mut stdout, *target_call_stacks)?;
+        let error_messages = match evaluator
+            .eval_query_streaming(
+                query,
+                target_universe.as_ref().map(|v| &v[..]),
+                &mut printer,
+            )
+            .await
+        {
+            Ok(()) => vec![],
+            Err(e) => vec![format!("{:#}", e)],
+        };
+        return Ok(CqueryResponse { error_messages });
+    }
+
     // TODO(nga): this should support configured target patterns
     //   similarly to what we do for `build` command.
     //   Something like this should work:
@@ -158,6 +180,7 @@ async fn cquery(
                     targets,
                     *target_call_stacks,
                     should_print_providers,
+                    &*ctx,
                 )
                 .await
         }
@@ -168,6 +191,7 @@ async fn cquery(
                     results,
                     *target_call_stacks,
                     should_print_providers,
+                    &*ctx,
                 )
                 .await
         }
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use buck2_cli_proto::QueryOutputFormat;
 use thiserror::Error;
 
 pub mod aquery;
@@ -20,4 +21,8 @@ enum QueryCommandError {
         "query result was a set of files and one or more --output-attribute was requested, but files have not attributes"
     )]
     FileSetHasNoAttributes,
+    #[error("--streaming does not support the `{0:?}` output format")]
+    StreamingUnsupportedFormat(QueryOutputFormat),
+    #[error("--streaming does not support multi-queries (queries with `%s` arguments)")]
+    StreamingMultiQuery,
 }
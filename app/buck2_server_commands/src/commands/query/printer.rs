@@ -9,21 +9,32 @@
 
 #![allow(clippy::drop_non_drop)] // FIXME?
 
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::marker::PhantomData;
 
+use anyhow::Context;
 use async_trait::async_trait;
 use buck2_build_api::interpreter::rule_defs::provider::collection::FrozenProviderCollectionValue;
 use buck2_cli_proto::QueryOutputFormat;
+use buck2_common::dice::cells::HasCellResolver;
+use buck2_core::bzl::ImportPath;
+use buck2_core::cells::build_file_cell::BuildFileCell;
+use buck2_core::cells::cell_path::CellPath;
 use buck2_core::cells::CellResolver;
+use buck2_core::package::PackageLabel;
+use buck2_interpreter_for_build::interpreter::calculation::InterpreterCalculation;
 use buck2_query::query::compatibility::MaybeCompatible;
 use buck2_query::query::environment::QueryTarget;
 use buck2_query::query::environment::QueryTargets;
+use buck2_query::query::environment::TargetSink;
 use buck2_query::query::syntax::simple::eval::file_set::FileSet;
 use buck2_query::query::syntax::simple::eval::multi_query::MultiQueryResult;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
 use buck2_query::query::syntax::simple::eval::values::QueryEvaluationValue;
 use buck2_util::indent::indent;
+use dice::DiceComputations;
 use dupe::Clone_;
 use dupe::Copy_;
 use dupe::Dupe_;
@@ -36,6 +47,8 @@ use serde::Serialize;
 use serde::Serializer;
 
 use crate::commands::query::QueryCommandError;
+use crate::dot::files::DotFileGraph;
+use crate::dot::query_result::DotQueryResultGraph;
 use crate::dot::targets::DotTargetGraph;
 use crate::dot::Dot;
 use crate::dot::DotCompact;
@@ -52,6 +65,51 @@ pub trait ProviderLookUp<T: QueryTarget>: Send + Sync {
     -> anyhow::Result<MaybeCompatible<FrozenProviderCollectionValue>>;
 }
 
+/// Looks up the files loaded by build files and `.bzl` files, to draw edges between files in DOT
+/// output.
+#[async_trait]
+pub trait FileLoadsLookUp: Send + Sync {
+    async fn loads(&self, file: &CellPath) -> anyhow::Result<Vec<CellPath>>;
+}
+
+#[async_trait]
+impl FileLoadsLookUp for DiceComputations {
+    async fn loads(&self, file: &CellPath) -> anyhow::Result<Vec<CellPath>> {
+        let cell_resolver = self.get_cell_resolver().await?;
+        let is_build_file = match file.path().file_name() {
+            Some(name) => cell_resolver
+                .get(file.cell())?
+                .buildfiles()
+                .iter()
+                .any(|buildfile| buildfile == name),
+            None => false,
+        };
+
+        let imports = if is_build_file {
+            let package = PackageLabel::from_cell_path(
+                file.parent()
+                    .with_context(|| format!("Build file `{}` has no parent", file))?,
+            );
+            self.get_interpreter_results(package)
+                .await?
+                .imports()
+                .to_vec()
+        } else if file.path().as_str().ends_with(".bzl") {
+            let import = ImportPath::new(file.clone(), BuildFileCell::new(file.cell()))?;
+            self.get_loaded_module_from_import_path(&import)
+                .await?
+                .imports()
+                .cloned()
+                .collect()
+        } else {
+            // Other files (e.g. sources) don't load anything.
+            Vec::new()
+        };
+
+        Ok(imports.into_iter().map(|i| i.path().clone()).collect())
+    }
+}
+
 #[derive(Debug)]
 pub struct QueryResultPrinter<'a> {
     resolver: &'a CellResolver,
@@ -250,12 +308,34 @@ impl<'a> QueryResultPrinter<'a> {
         })
     }
 
+    /// Returns a sink that prints targets as they are added. Only the default and JSON formats
+    /// can be streamed, and providers can't be printed.
+    pub fn streaming_printer<T: QueryTarget, W: std::io::Write + Send + Sync>(
+        &self,
+        output: W,
+        target_call_stacks: bool,
+    ) -> anyhow::Result<StreamingQueryResultPrinter<'_, T, W>> {
+        let json = match self.output_format {
+            QueryOutputFormat::Default => false,
+            QueryOutputFormat::Json => true,
+            format => return Err(QueryCommandError::StreamingUnsupportedFormat(format).into()),
+        };
+        Ok(StreamingQueryResultPrinter {
+            output,
+            attributes: &self.attributes,
+            json,
+            target_call_stacks,
+            _marker: PhantomData,
+        })
+    }
+
     pub async fn print_multi_output<'b, T: QueryTarget, W: std::io::Write>(
         &self,
         mut output: W,
         multi_result: MultiQueryResult<T>,
         target_call_stacks: bool,
         print_providers: ShouldPrintProviders<'b, T>,
+        file_loads: &dyn FileLoadsLookUp,
     ) -> anyhow::Result<()> {
         match (self.output_format, &self.attributes) {
             // A multi-query only has interesting output with --json output. For non-json output it gets merged together.
@@ -304,12 +384,65 @@ impl<'a> QueryResultPrinter<'a> {
                 writeln!(&mut output)?;
                 captured_error
             }
+            (QueryOutputFormat::Dot | QueryOutputFormat::DotCompact, _) => {
+                // Depending on the literal, a multi-query can evaluate to targets or files, so
+                // these are drawn in the same graph.
+                let mut targets = TargetSet::new();
+                let mut files = FileSet::new(Default::default());
+                for (_, result) in multi_result.0 {
+                    match result? {
+                        QueryEvaluationValue::TargetSet(v) => targets.extend(&v),
+                        QueryEvaluationValue::FileSet(v) => files.insert_all(&v),
+                    }
+                }
+
+                if files.is_empty() {
+                    return self
+                        .print_single_output(
+                            output,
+                            QueryEvaluationValue::TargetSet(targets),
+                            target_call_stacks,
+                            print_providers,
+                            file_loads,
+                        )
+                        .await;
+                }
+                if targets.is_empty() {
+                    return self
+                        .print_single_output(
+                            output,
+                            QueryEvaluationValue::FileSet(files),
+                            target_call_stacks,
+                            print_providers,
+                            file_loads,
+                        )
+                        .await;
+                }
+
+                let graph = DotQueryResultGraph {
+                    files: DotFileGraph::new(
+                        self.resolver,
+                        &files,
+                        &lookup_file_loads(&files, file_loads).await?,
+                    )?,
+                    targets: DotTargetGraph {
+                        targets,
+                        attributes: self.attributes.clone(),
+                    },
+                };
+                if self.output_format == QueryOutputFormat::Dot {
+                    Dot::render(&graph, &mut output)
+                } else {
+                    DotCompact::render(&graph, &mut output)
+                }
+            }
             _ => {
                 self.print_single_output(
                     output,
                     multi_result.merged()?,
                     target_call_stacks,
                     print_providers,
+                    file_loads,
                 )
                 .await
             }
@@ -322,6 +455,7 @@ impl<'a> QueryResultPrinter<'a> {
         result: QueryEvaluationValue<T>,
         call_stack: bool,
         print_providers: ShouldPrintProviders<'b, T>,
+        file_loads: &dyn FileLoadsLookUp,
     ) -> anyhow::Result<()> {
         match result {
             QueryEvaluationValue::TargetSet(targets) => match self.output_format {
@@ -392,10 +526,18 @@ impl<'a> QueryResultPrinter<'a> {
                         writeln!(&mut output)?;
                     }
                     QueryOutputFormat::Dot => {
-                        unimplemented!("dot output for files not implemented yet")
+                        let loads = lookup_file_loads(&files, file_loads).await?;
+                        Dot::render(
+                            &DotFileGraph::new(self.resolver, &files, &loads)?,
+                            &mut output,
+                        )?;
                     }
                     QueryOutputFormat::DotCompact => {
-                        unimplemented!("dot_compact output for files not implemented yet")
+                        let loads = lookup_file_loads(&files, file_loads).await?;
+                        DotCompact::render(
+                            &DotFileGraph::new(self.resolver, &files, &loads)?,
+                            &mut output,
+                        )?;
                     }
                 }
             }
@@ -405,6 +547,45 @@ impl<'a> QueryResultPrinter<'a> {
     }
 }
 
+/// Prints each target of a streaming query as soon as it is found, flushing the output after
+/// each one. JSON output has one value per line: the label, or a map from the label to its
+/// attributes.
+pub struct StreamingQueryResultPrinter<'a, T: QueryTarget, W: std::io::Write> {
+    output: W,
+    attributes: &'a Option<RegexSet>,
+    json: bool,
+    target_call_stacks: bool,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<'a, T: QueryTarget, W: std::io::Write + Send + Sync> TargetSink<T>
+    for StreamingQueryResultPrinter<'a, T, W>
+{
+    fn add(&mut self, target: &T) -> anyhow::Result<()> {
+        let target = PrintableQueryTarget {
+            value: target,
+            attributes: self.attributes,
+            providers: None,
+            target_call_stacks: self.target_call_stacks,
+        };
+        if self.json {
+            let mut ser = serde_json::Serializer::new(&mut self.output);
+            if self.attributes.is_some() || self.target_call_stacks {
+                (&mut ser).collect_map(std::iter::once((target.label(), &target)))?;
+            } else {
+                target.label().serialize(&mut ser)?;
+            }
+            std::mem::drop(ser);
+            writeln!(&mut self.output)?;
+        } else {
+            writeln!(&mut self.output, "{}", target)?;
+        }
+        // The output is buffered, so flush it to send each target to the client as it's found.
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
 async fn printable_targets<'a, T: QueryTarget>(
     targets: &'a TargetSet<T>,
     print_providers: ShouldPrintProviders<'a, T>,
@@ -431,3 +612,18 @@ async fn printable_targets<'a, T: QueryTarget>(
     .into_iter()
     .collect::<anyhow::Result<_>>()
 }
+
+async fn lookup_file_loads(
+    files: &FileSet,
+    file_loads: &dyn FileLoadsLookUp,
+) -> anyhow::Result<HashMap<CellPath, Vec<CellPath>>> {
+    futures::future::try_join_all(files.iter().map(|file| async move {
+        let loads = file_loads
+            .loads(file)
+            .await
+            .with_context(|| format!("Error looking up the files loaded by `{}`", file))?;
+        anyhow::Ok((file.clone(), loads))
+    }))
+    .await
+    .map(|loads| loads.into_iter().collect())
+}
@@ -25,6 +25,7 @@ use dice::DiceTransaction;
 
 use crate::commands::query::printer::QueryResultPrinter;
 use crate::commands::query::printer::ShouldPrintProviders;
+use crate::commands::query::QueryCommandError;
 
 pub async fn uquery_command(
     ctx: &dyn ServerCommandContextTrait,
@@ -67,7 +68,7 @@ impl ServerCommandTemplate for UqueryServerCommand {
 
 async fn uquery(
     server_ctx: &dyn ServerCommandContextTrait,
-    mut stdout: impl Write,
+    mut stdout: impl Write + Send + Sync,
     ctx: DiceTransaction,
     request: &UqueryRequest,
 ) -> anyhow::Result<UqueryResponse> {
@@ -83,6 +84,7 @@ async fn uquery(
         query_args,
         context,
         target_call_stacks,
+        streaming,
         ..
     } = request;
 
@@ -96,6 +98,19 @@ async fn uquery(
         get_uquery_evaluator(&ctx, server_ctx.working_dir(), global_target_platform).await?;
     let evaluator = &evaluator;
 
+    if *streaming {
+        if !query_args.is_empty() {
+            return Err(QueryCommandError::StreamingMultiQuery.into());
+        }
+        let mut printer =
+            output_configuration.streaming_printer(&mut stdout, *target_call_stacks)?;
+        let error_messages = match evaluator.eval_query_streaming(query, &mut printer).await {
+            Ok(()) => vec![],
+            Err(e) => vec![format!("{:#}", e)],
+        };
+        return Ok(UqueryResponse { error_messages });
+    }
+
     let query_result = evaluator.eval_query(query, query_args).await?;
 
     let result = match query_result {
@@ -106,6 +121,7 @@ async fn uquery(
                     targets,
                     *target_call_stacks,
                     ShouldPrintProviders::No,
+                    &*ctx,
                 )
                 .await
         }
@@ -116,6 +132,7 @@ async fn uquery(
                     results,
                     *target_call_stacks,
                     ShouldPrintProviders::No,
+                    &*ctx,
                 )
                 .await
         }
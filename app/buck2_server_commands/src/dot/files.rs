@@ -0,0 +1,112 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use buck2_core::cells::cell_path::CellPath;
+use buck2_core::cells::CellResolver;
+use buck2_query::query::syntax::simple::eval::file_set::FileSet;
+
+use crate::dot::DotDigraph;
+use crate::dot::DotEdge;
+use crate::dot::DotNode;
+use crate::dot::DotNodeAttrs;
+
+pub struct DotFileGraphNode {
+    path: String,
+    loads: Vec<String>,
+}
+
+/// A simple adapter for creating a DotDiGraph for a FileSet. Files have edges to the files they
+/// load (e.g. from a build file to the `.bzl` files it loads).
+pub struct DotFileGraph {
+    nodes: Vec<DotFileGraphNode>,
+}
+
+impl DotFileGraph {
+    /// `loads` maps files in `files` to the files they load. Like for targets, only edges to other
+    /// files in the set are included.
+    pub fn new(
+        resolver: &CellResolver,
+        files: &FileSet,
+        loads: &HashMap<CellPath, Vec<CellPath>>,
+    ) -> anyhow::Result<Self> {
+        let resolve = |file: &CellPath| -> anyhow::Result<String> {
+            Ok(resolver.resolve_path(file.as_ref())?.to_string())
+        };
+
+        let in_set: HashSet<&CellPath> = files.iter().collect();
+
+        let mut nodes = Vec::with_capacity(files.len());
+        for file in files.iter() {
+            let mut file_loads = Vec::new();
+            for load in loads.get(file).into_iter().flatten() {
+                if in_set.contains(load) {
+                    file_loads.push(resolve(load)?);
+                }
+            }
+            nodes.push(DotFileGraphNode {
+                path: resolve(file)?,
+                loads: file_loads,
+            });
+        }
+        Ok(Self { nodes })
+    }
+
+    pub(crate) fn nodes(&self) -> &[DotFileGraphNode] {
+        &self.nodes
+    }
+}
+
+impl<'a> DotDigraph<'a> for DotFileGraph {
+    type Node = DotFileGraphNode;
+
+    fn name(&self) -> &str {
+        "result_graph"
+    }
+
+    fn for_each_node<F: FnMut(&Self::Node) -> anyhow::Result<()>>(
+        &'a self,
+        mut f: F,
+    ) -> anyhow::Result<()> {
+        for node in &self.nodes {
+            f(node)?;
+        }
+        Ok(())
+    }
+
+    fn for_each_edge<F: FnMut(&DotEdge) -> anyhow::Result<()>>(
+        &'a self,
+        node: &Self::Node,
+        mut f: F,
+    ) -> anyhow::Result<()> {
+        for load in &node.loads {
+            f(&DotEdge {
+                from: &node.path,
+                to: load,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl DotNode for DotFileGraphNode {
+    fn attrs(&self) -> anyhow::Result<DotNodeAttrs> {
+        Ok(DotNodeAttrs {
+            style: Some("filled".to_owned()),
+            color: Some("#DFDFEC".to_owned()),
+            ..DotNodeAttrs::default()
+        })
+    }
+
+    fn id(&self) -> String {
+        self.path.clone()
+    }
+}
@@ -28,6 +28,8 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use starlark_map::small_map::SmallMap;
 
+pub mod files;
+pub mod query_result;
 pub mod targets;
 
 #[derive(Default, Debug)]
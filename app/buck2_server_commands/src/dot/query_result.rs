@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use buck2_query::query::environment::QueryTarget;
+
+use crate::dot::files::DotFileGraph;
+use crate::dot::files::DotFileGraphNode;
+use crate::dot::targets::DotTargetGraph;
+use crate::dot::targets::DotTargetGraphNode;
+use crate::dot::DotDigraph;
+use crate::dot::DotEdge;
+use crate::dot::DotNode;
+use crate::dot::DotNodeAttrs;
+
+pub enum DotQueryResultGraphNode<'a, T: QueryTarget> {
+    Target(DotTargetGraphNode<'a, T>),
+    File(&'a DotFileGraphNode),
+}
+
+/// Both targets and files in a single graph, for multi-queries where some literals evaluate to
+/// targets and others to files.
+pub struct DotQueryResultGraph<T: QueryTarget> {
+    pub targets: DotTargetGraph<T>,
+    pub files: DotFileGraph,
+}
+
+impl<'a, T: QueryTarget> DotDigraph<'a> for DotQueryResultGraph<T> {
+    type Node = DotQueryResultGraphNode<'a, T>;
+
+    fn name(&self) -> &str {
+        "result_graph"
+    }
+
+    fn for_each_node<F: FnMut(&Self::Node) -> anyhow::Result<()>>(
+        &'a self,
+        mut f: F,
+    ) -> anyhow::Result<()> {
+        self.targets
+            .for_each_node(|node| f(&DotQueryResultGraphNode::Target(node.clone())))?;
+        for node in self.files.nodes() {
+            f(&DotQueryResultGraphNode::File(node))?;
+        }
+        Ok(())
+    }
+
+    fn for_each_edge<F: FnMut(&DotEdge) -> anyhow::Result<()>>(
+        &'a self,
+        node: &Self::Node,
+        f: F,
+    ) -> anyhow::Result<()> {
+        match node {
+            DotQueryResultGraphNode::Target(node) => self.targets.for_each_edge(node, f),
+            DotQueryResultGraphNode::File(node) => self.files.for_each_edge(node, f),
+        }
+    }
+}
+
+impl<'a, T: QueryTarget> DotNode for DotQueryResultGraphNode<'a, T> {
+    fn attrs(&self) -> anyhow::Result<DotNodeAttrs> {
+        match self {
+            DotQueryResultGraphNode::Target(node) => node.attrs(),
+            DotQueryResultGraphNode::File(node) => node.attrs(),
+        }
+    }
+
+    fn id(&self) -> String {
+        match self {
+            DotQueryResultGraphNode::Target(node) => node.id(),
+            DotQueryResultGraphNode::File(node) => node.id(),
+        }
+    }
+}
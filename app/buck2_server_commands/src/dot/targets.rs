@@ -10,6 +10,7 @@
 use buck2_query::query::environment::QueryTarget;
 use buck2_query::query::environment::QueryTargets;
 use buck2_query::query::syntax::simple::eval::set::TargetSet;
+use dupe::Clone_;
 use regex::RegexSet;
 use starlark_map::small_map::SmallMap;
 
@@ -18,6 +19,7 @@ use crate::dot::DotEdge;
 use crate::dot::DotNode;
 use crate::dot::DotNodeAttrs;
 
+#[derive(Clone_)]
 pub struct DotTargetGraphNode<'a, T: QueryTarget>(&'a T, &'a DotTargetGraph<T>);
 
 /// A simple adapter for creating a DotDiGraph for a TargetSet.
@@ -132,7 +132,10 @@ impl StreamingCommand for StarlarkDebugAttachCommand {
         false
     }
 
-    fn extra_subscribers(&self) -> Vec<Box<dyn EventSubscriber>> {
+    fn extra_subscribers(
+        &self,
+        _ctx: &ClientCommandContext,
+    ) -> anyhow::Result<Vec<Box<dyn EventSubscriber>>> {
         /// We add an additional subscriber that converts a handful of informative events
         /// to DAP "output" events. Without this, at best these would go to stderr, but vscode's
         /// executable DAP client ignores stderr, so this subscriber allows us to get that information
@@ -210,7 +213,7 @@ impl StreamingCommand for StarlarkDebugAttachCommand {
             }
         }
 
-        vec![Box::new(ConvertToDap)]
+        Ok(vec![Box::new(ConvertToDap)])
     }
 }
 
@@ -26,9 +26,11 @@ use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::partial_result_dispatcher::PartialResultDispatcher;
 
 use crate::debug::StarlarkDebugAttachCommand;
+use crate::format::StarlarkFormatCommand;
 use crate::lint::StarlarkLintCommand;
 
 mod debug;
+mod format;
 mod lint;
 pub mod server;
 mod util;
@@ -47,6 +49,7 @@ pub enum StarlarkCommand {
 #[derive(Debug, clap::Subcommand, serde::Serialize, serde::Deserialize)]
 pub enum StarlarkOpaqueCommand {
     Lint(StarlarkLintCommand),
+    Fmt(StarlarkFormatCommand),
 }
 
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize, Default)]
@@ -87,6 +90,7 @@ impl StarlarkOpaqueCommand {
     fn as_subcommand(&self) -> &dyn StarlarkOpaqueSubcommand {
         match self {
             Self::Lint(cmd) => cmd,
+            Self::Fmt(cmd) => cmd,
         }
     }
 }
@@ -18,6 +18,8 @@ use buck2_common::dice::data::HasIoProvider;
 use buck2_common::dice::file_ops::HasFileOps;
 use buck2_common::io::IoProvider;
 use buck2_core::cells::CellResolver;
+use buck2_core::fs::fs_util;
+use buck2_core::fs::project::ProjectRoot;
 use buck2_interpreter::path::StarlarkPath;
 use buck2_server_ctx::ctx::ServerCommandContextTrait;
 use buck2_server_ctx::ctx::ServerCommandDiceContext;
@@ -38,6 +40,10 @@ pub struct StarlarkLintCommand {
     #[clap(flatten)]
     common_opts: StarlarkCommandCommonOptions,
 
+    /// Apply the automatic fixes for lints which have them, then report the remaining lints.
+    #[clap(long)]
+    fix: bool,
+
     #[clap(value_name = "PATH", required = true)]
     paths: Vec<PathArg>,
 }
@@ -47,16 +53,50 @@ async fn lint_file(
     cell_resolver: &CellResolver,
     io: &dyn IoProvider,
     cached_globals: &mut CachedGlobals<'_>,
+    fix: Option<&ProjectRoot>,
 ) -> anyhow::Result<Vec<Lint>> {
     let dialect = path.file_type().dialect(false);
     let proj_path = cell_resolver.resolve_path(path.path().as_ref().as_ref())?;
     let path_str = proj_path.to_string();
     let content = io
-        .read_file_if_exists(proj_path)
+        .read_file_if_exists(proj_path.clone())
         .await?
         .with_context(|| format!("File not found: `{}`", path_str))?;
     match AstModule::parse(&path_str, content.clone(), &dialect) {
-        Ok(ast) => Ok(ast.lint(Some(&*cached_globals.get_names(path).await?))),
+        Ok(ast) => {
+            let globals = cached_globals.get_names(path).await?;
+            let lints = ast.lint(Some(&*globals));
+            match fix {
+                Some(project_root) if lints.iter().any(|x| !x.edits.is_empty()) => {
+                    let fixed = ast.apply_lint_fixes(&lints);
+                    match AstModule::parse(&path_str, fixed.clone(), &dialect) {
+                        Ok(fixed_ast) => {
+                            fs_util::write(project_root.resolve(&proj_path), &fixed)?;
+                            // Report whatever the fixes didn't deal with.
+                            Ok(fixed_ast.lint(Some(&*globals)))
+                        }
+                        Err(err) => {
+                            // Don't write a file we can't parse: leave it alone and report the
+                            // lints we failed to fix.
+                            let mut lints = lints;
+                            lints.push(Lint {
+                                location: FileSpan::new(path_str, content),
+                                short_name: "fix_error".to_owned(),
+                                serious: true,
+                                problem: format!(
+                                    "Applying the fixes produced invalid Starlark, so the file was left unchanged: {:#}",
+                                    err
+                                ),
+                                original: "".to_owned(),
+                                edits: Vec::new(),
+                            });
+                            Ok(lints)
+                        }
+                    }
+                }
+                _ => Ok(lints),
+            }
+        }
         Err(err) => {
             // There was a parse error, so we don't want to fail, we want to give a nice error message
             // Do the best we can - it is probably a `Diagnostic`, which gives us more precise info.
@@ -70,6 +110,7 @@ async fn lint_file(
                 serious: true,
                 problem: format!("{:#}", message),
                 original: "".to_owned(),
+                edits: Vec::new(),
             }])
         }
     }
@@ -95,9 +136,14 @@ impl StarlarkOpaqueSubcommand for StarlarkLintCommand {
                 let files =
                     starlark_files(&self.paths, server_ctx, &cell_resolver, &fs, &*io).await?;
                 for file in &files {
-                    let lints =
-                        lint_file(&file.borrow(), &cell_resolver, &*io, &mut cached_globals)
-                            .await?;
+                    let lints = lint_file(
+                        &file.borrow(),
+                        &cell_resolver,
+                        &*io,
+                        &mut cached_globals,
+                        self.fix.then(|| server_ctx.project_root()),
+                    )
+                    .await?;
                     lint_count += lints.len();
                     for lint in lints {
                         writeln!(stdout, "{}", lint)?;
@@ -23,6 +23,31 @@ pub struct Config {
     #[clap(long, default_value = "600", parse(try_from_str=try_parse_timeout_from_str))]
     pub timeout: Duration,
 
+    /// Number of times to rerun a failing test. A test that passes when rerun is reported as
+    /// flaky.
+    #[clap(long, default_value = "0")]
+    pub retries: u32,
+
+    /// Max number of tests (and test listings) to run at the same time. By default this is only
+    /// limited by the Buck2 executor.
+    #[clap(long, parse(try_from_str=try_parse_max_concurrency_from_str))]
+    pub max_concurrency: Option<usize>,
+
+    /// List the test cases of tests built with a known test framework, and run them in shards of
+    /// `--shard-size` test cases. By default every test runs as a single command.
+    #[clap(long)]
+    pub list_test_cases: bool,
+
+    /// Number of test cases to run with each command when listing test cases, 1 by default. The
+    /// test cases run by one command are reported as a single test, since only its exit code is
+    /// known.
+    #[clap(
+        long,
+        requires = "list-test-cases",
+        parse(try_from_str=try_parse_shard_size_from_str)
+    )]
+    pub shard_size: Option<usize>,
+
     #[clap(flatten)]
     ignored_args: IgnoredArgs,
 }
@@ -71,3 +96,61 @@ fn try_parse_timeout_from_str(input: &str) -> anyhow::Result<Duration> {
     let seconds = input.parse().context("Could not parse provided timeout")?;
     Ok(Duration::from_secs(seconds))
 }
+
+fn try_parse_max_concurrency_from_str(input: &str) -> anyhow::Result<usize> {
+    let max_concurrency = input
+        .parse()
+        .context("Could not parse provided max concurrency")?;
+    if max_concurrency == 0 {
+        return Err(anyhow::anyhow!("Max concurrency must be at least 1"));
+    }
+    Ok(max_concurrency)
+}
+
+fn try_parse_shard_size_from_str(input: &str) -> anyhow::Result<usize> {
+    let shard_size = input
+        .parse()
+        .context("Could not parse provided shard size")?;
+    if shard_size == 0 {
+        return Err(anyhow::anyhow!("Shard size must be at least 1"));
+    }
+    Ok(shard_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Config> {
+        Ok(Config::try_parse_from(
+            ["test_runner", "--buck-test-info", "info.json"]
+                .iter()
+                .chain(args),
+        )?)
+    }
+
+    #[test]
+    fn test_parse_limits() -> anyhow::Result<()> {
+        let config = parse(&[
+            "--max-concurrency",
+            "2",
+            "--list-test-cases",
+            "--shard-size",
+            "3",
+        ])?;
+        assert_eq!(Some(2), config.max_concurrency);
+        assert!(config.list_test_cases);
+        assert_eq!(Some(3), config.shard_size);
+
+        let config = parse(&[])?;
+        assert_eq!(None, config.max_concurrency);
+        assert!(!config.list_test_cases);
+        assert_eq!(None, config.shard_size);
+
+        assert!(parse(&["--max-concurrency", "0"]).is_err());
+        assert!(parse(&["--list-test-cases", "--shard-size", "0"]).is_err());
+        // Sharding only applies to listed test cases.
+        assert!(parse(&["--shard-size", "3"]).is_err());
+        Ok(())
+    }
+}
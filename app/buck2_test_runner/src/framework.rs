@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+/// Test frameworks whose test binaries can list their test cases and run a subset of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestFramework {
+    Gtest,
+    /// The unittest based main used by the prelude's `python_test`.
+    Pyunit,
+    Pytest,
+    /// The libtest harness used by Rust tests.
+    Rust,
+}
+
+impl TestFramework {
+    /// The framework for the `type` of an `ExternalRunnerTestInfo`.
+    pub fn for_test_type(test_type: &str) -> Option<Self> {
+        match test_type {
+            "gtest" => Some(Self::Gtest),
+            "pyunit" => Some(Self::Pyunit),
+            "pytest" => Some(Self::Pytest),
+            "rust" => Some(Self::Rust),
+            _ => None,
+        }
+    }
+
+    /// Arguments that make the test binary print its test cases instead of running them.
+    pub fn list_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            Self::Gtest => &["--gtest_list_tests"],
+            Self::Pyunit => &["--list-tests", "--list-format", "buck"],
+            Self::Pytest => &["--collect-only", "-q"],
+            Self::Rust => &["--list", "--format", "terse"],
+        };
+        args.iter().map(|a| (*a).to_owned()).collect()
+    }
+
+    /// Parses the output of running the binary with `list_args`.
+    pub fn parse_listing(self, stdout: &str) -> Vec<String> {
+        match self {
+            Self::Gtest => parse_gtest_listing(stdout),
+            Self::Pyunit => stdout
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_owned())
+                .collect(),
+            Self::Pytest => stdout
+                .lines()
+                .map(str::trim)
+                .filter(|l| l.contains("::"))
+                .map(|l| l.to_owned())
+                .collect(),
+            Self::Rust => stdout
+                .lines()
+                .filter_map(|l| l.strip_suffix(": test"))
+                .map(|l| l.to_owned())
+                .collect(),
+        }
+    }
+
+    /// Arguments that make the test binary only run `cases`.
+    pub fn filter_args(self, cases: &[String]) -> Vec<String> {
+        match self {
+            Self::Gtest => vec![format!("--gtest_filter={}", cases.join(":"))],
+            Self::Pyunit => vec![
+                "--regex".to_owned(),
+                format!(
+                    "^({})$",
+                    cases
+                        .iter()
+                        .map(|c| python_regex_escape(c))
+                        .collect::<Vec<_>>()
+                        .join("|")
+                ),
+            ],
+            Self::Pytest => cases.to_vec(),
+            Self::Rust => cases
+                .iter()
+                .cloned()
+                .chain(std::iter::once("--exact".to_owned()))
+                .collect(),
+        }
+    }
+}
+
+/// The listing has a line for every suite, followed by an indented line for every test in it:
+///
+/// ```text
+/// Suite.
+///   Test
+///   Param/0  # GetParam() = 1
+/// ```
+fn parse_gtest_listing(stdout: &str) -> Vec<String> {
+    let mut cases = Vec::new();
+    let mut suite = None;
+    for line in stdout.lines() {
+        let name = match line.split_once('#') {
+            Some((name, _comment)) => name,
+            None => line,
+        };
+        if name.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(' ') {
+            if let Some(suite) = suite {
+                cases.push(format!("{}{}", suite, name.trim()));
+            }
+        } else {
+            suite = Some(name.trim());
+        }
+    }
+    cases
+}
+
+fn python_regex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gtest_listing() {
+        let stdout = "Running main() from gtest_main.cc\nFoo.\n  Bar\n  Baz\nParam/Qux.  # TypeParam = int\n  Quux/0  # GetParam() = 1\n";
+        assert_eq!(
+            vec!["Foo.Bar", "Foo.Baz", "Param/Qux.Quux/0"],
+            TestFramework::Gtest.parse_listing(stdout)
+        );
+    }
+
+    #[test]
+    fn test_parse_rust_listing() {
+        let stdout = "tests::a: test\ntests::b: test\nbench: benchmark\n";
+        assert_eq!(
+            vec!["tests::a", "tests::b"],
+            TestFramework::Rust.parse_listing(stdout)
+        );
+    }
+
+    #[test]
+    fn test_parse_pytest_listing() {
+        let stdout =
+            "test_a.py::test_one\ntest_a.py::TestB::test_two\n\n2 tests collected in 0.01s\n";
+        assert_eq!(
+            vec!["test_a.py::test_one", "test_a.py::TestB::test_two"],
+            TestFramework::Pytest.parse_listing(stdout)
+        );
+    }
+
+    #[test]
+    fn test_filter_args() {
+        let cases = vec!["a.B#c".to_owned(), "d.E#f".to_owned()];
+        assert_eq!(
+            vec!["--gtest_filter=a.B#c:d.E#f"],
+            TestFramework::Gtest.filter_args(&cases)
+        );
+        assert_eq!(
+            vec!["--regex", r"^(a\.B#c|d\.E#f)$"],
+            TestFramework::Pyunit.filter_args(&cases)
+        );
+        assert_eq!(
+            vec!["a.B#c", "d.E#f", "--exact"],
+            TestFramework::Rust.filter_args(&cases)
+        );
+    }
+}
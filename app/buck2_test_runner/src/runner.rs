@@ -14,6 +14,7 @@ use buck2_test_api::data::ConfiguredTargetHandle;
 use buck2_test_api::data::DisplayMetadata;
 use buck2_test_api::data::ExecutionResult2;
 use buck2_test_api::data::ExecutionStatus;
+use buck2_test_api::data::ExecutionStream;
 use buck2_test_api::data::ExternalRunnerSpec;
 use buck2_test_api::data::ExternalRunnerSpecValue;
 use buck2_test_api::data::RequiredLocalResources;
@@ -26,9 +27,11 @@ use futures::channel::mpsc::UnboundedReceiver;
 use futures::StreamExt;
 use host_sharing::HostSharingRequirements;
 use parking_lot::Mutex;
+use tokio::sync::Semaphore;
 
 use crate::config::Config;
 use crate::config::EnvValue;
+use crate::framework::TestFramework;
 
 pub type SpecReceiver = UnboundedReceiver<ExternalRunnerSpec>;
 
@@ -38,11 +41,17 @@ pub type SpecReceiver = UnboundedReceiver<ExternalRunnerSpec>;
 /// if no external test runner is provided. This ensures that `buck2 test` works
 /// out-of-the-box for open-source users.
 ///
+/// Every test runs as a single command, unless `--list-test-cases` is passed: then the test cases
+/// of binaries built with a known test framework (see `TestFramework`) are listed and run in
+/// shards of `--shard-size` test cases.
+///
 /// **This is intended for open-source use only.**
 pub struct Buck2TestRunner {
     orchestrator_client: TestOrchestratorClient,
     spec_receiver: Mutex<Option<SpecReceiver>>,
     config: Config,
+    /// Set by `--max-concurrency`.
+    concurrency_limit: Option<Semaphore>,
 }
 
 impl Buck2TestRunner {
@@ -52,10 +61,12 @@ impl Buck2TestRunner {
         args: Vec<String>,
     ) -> anyhow::Result<Self> {
         let config = Config::try_parse_from(args).context("Error parsing test runner arguments")?;
+        let concurrency_limit = config.max_concurrency.map(Semaphore::new);
         Ok(Self {
             orchestrator_client,
             spec_receiver: Mutex::new(Some(spec_receiver)),
             config,
+            concurrency_limit,
         })
     }
 
@@ -69,59 +80,165 @@ impl Buck2TestRunner {
             drop(maybe_receiver);
         }
         let run_verdict = receiver
-            .map(async move |spec| {
-                let name = format!(
-                    "{}//{}:{}",
-                    spec.target.cell, spec.target.package, spec.target.target
-                );
-                let target_handle = spec.target.handle.to_owned();
-
-                let execution_result = self
-                    .execute_test_from_spec(spec)
-                    .await
-                    .expect("Test execution request failed");
-
-                let test_result = get_test_result(name, target_handle, execution_result);
-                let test_status = test_result.status.clone();
-
-                self.report_test_result(test_result)
-                    .await
-                    .expect("Test result reporting failed");
-
-                test_status
-            })
+            .map(|spec| self.run_tests_from_spec(spec))
             // Use an arbitrarily large buffer -- execution throttling will be handled by the Buck2
-            // executor, so no need to hold back on requests here.
+            // executor (and `--max-concurrency`), so no need to hold back on requests here.
             .buffer_unordered(10000)
-            // If any individual test failed, consider the entire run to have failed.
+            // If any individual test failed, consider the entire run to have failed. Every test
+            // gets to finish even if running one fails with an error, which is reported at the end.
             .fold(
-                RunVerdict::Pass,
-                async move |mut run_verdict, test_status| {
-                    if test_status != TestStatus::PASS {
-                        run_verdict = RunVerdict::Fail;
+                Ok(RunVerdict::Pass),
+                async move |run_verdict: anyhow::Result<RunVerdict>, test_statuses| {
+                    let run_verdict = run_verdict?;
+                    if test_statuses?.iter().any(|s| *s != TestStatus::PASS) {
+                        return Ok(RunVerdict::Fail);
                     }
-                    run_verdict
+                    Ok(run_verdict)
                 },
             )
-            .await;
+            .await?;
 
         self.orchestrator_client
             .end_of_test_results(run_verdict.exit_code())
             .await
     }
 
-    async fn execute_test_from_spec(
+    /// Runs the test cases of a spec, or the whole spec as a single test if its test cases can't
+    /// be listed. Returns the final status of every test.
+    async fn run_tests_from_spec(
         &self,
         spec: ExternalRunnerSpec,
-    ) -> anyhow::Result<ExecutionResult2> {
-        let display_metadata = DisplayMetadata::Testing {
-            suite: spec.target.target,
-            testcases: Vec::new(),
+    ) -> anyhow::Result<Vec<TestStatus>> {
+        let name = format!(
+            "{}//{}:{}",
+            spec.target.cell, spec.target.package, spec.target.target
+        );
+
+        let framework = if self.config.list_test_cases {
+            TestFramework::for_test_type(&spec.test_type)
+        } else {
+            None
+        };
+        let listing = match framework {
+            Some(framework) => self
+                .list_test_cases(&spec, framework)
+                .await?
+                .map(|cases| (framework, cases)),
+            None => None,
         };
 
+        match listing {
+            Some((framework, cases)) if !cases.is_empty() => {
+                self.orchestrator_client
+                    .report_tests_discovered(
+                        spec.target.handle.clone(),
+                        name.clone(),
+                        cases.clone(),
+                    )
+                    .await?;
+
+                let shards = cases
+                    .chunks(self.config.shard_size.unwrap_or(1))
+                    .map(|shard| {
+                        self.run_with_retries(
+                            &spec,
+                            shard_name(&name, shard),
+                            shard.to_vec(),
+                            framework.filter_args(shard),
+                        )
+                    });
+                // Let every shard finish even if one fails with an error.
+                futures::future::join_all(shards)
+                    .await
+                    .into_iter()
+                    .collect()
+            }
+            // Not listing, or listing failed or found nothing: run the whole spec.
+            _ => Ok(vec![
+                self.run_with_retries(&spec, name, Vec::new(), Vec::new())
+                    .await?,
+            ]),
+        }
+    }
+
+    /// Lists the test cases of a spec. Returns `None` if the test binary could not list them.
+    async fn list_test_cases(
+        &self,
+        spec: &ExternalRunnerSpec,
+        framework: TestFramework,
+    ) -> anyhow::Result<Option<Vec<String>>> {
+        let execution_result = self
+            .execute_test_from_spec(
+                spec,
+                DisplayMetadata::Listing(spec.target.target.clone()),
+                framework.list_args(),
+            )
+            .await?;
+        match (execution_result.status, execution_result.stdout) {
+            (ExecutionStatus::Finished { exitcode: 0 }, ExecutionStream::Inline(stdout)) => Ok(
+                Some(framework.parse_listing(&String::from_utf8_lossy(&stdout))),
+            ),
+            _ => Ok(None),
+        }
+    }
+
+    /// Runs a command that runs the test `name`, rerunning it while it fails, up to `--retries`
+    /// times. Every attempt is reported, with the attempts that are retried as `RERUN`. Returns
+    /// the final status.
+    async fn run_with_retries(
+        &self,
+        spec: &ExternalRunnerSpec,
+        name: String,
+        testcases: Vec<String>,
+        extra_args: Vec<String>,
+    ) -> anyhow::Result<TestStatus> {
+        let max_attempts = self.config.retries + 1;
+        let mut attempt = 1;
+        loop {
+            let execution_result = self
+                .execute_test_from_spec(
+                    spec,
+                    DisplayMetadata::Testing {
+                        suite: spec.target.target.clone(),
+                        testcases: testcases.clone(),
+                    },
+                    extra_args.clone(),
+                )
+                .await?;
+
+            let test_result =
+                get_test_result(name.clone(), spec.target.handle.clone(), execution_result);
+            let (status, msg) = attempt_status(&test_result.status, attempt, max_attempts);
+
+            self.report_test_result(TestResult {
+                status: status.clone(),
+                msg,
+                ..test_result
+            })
+            .await?;
+
+            if status != TestStatus::RERUN {
+                return Ok(status);
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn execute_test_from_spec(
+        &self,
+        spec: &ExternalRunnerSpec,
+        display_metadata: DisplayMetadata,
+        extra_args: Vec<String>,
+    ) -> anyhow::Result<ExecutionResult2> {
         let command = spec
             .command
-            .into_iter()
+            .iter()
+            .cloned()
+            .chain(
+                extra_args
+                    .into_iter()
+                    .map(ExternalRunnerSpecValue::Verbatim),
+            )
             .map(|spec_value| ArgValue {
                 content: ArgValueContent::ExternalRunnerSpecValue(spec_value),
                 format: None,
@@ -142,12 +259,12 @@ impl Buck2TestRunner {
 
         let env = spec
             .env
-            .into_iter()
+            .iter()
             .map(|(key, value)| {
                 (
-                    key,
+                    key.clone(),
                     ArgValue {
-                        content: ArgValueContent::ExternalRunnerSpecValue(value),
+                        content: ArgValueContent::ExternalRunnerSpecValue(value.clone()),
                         format: None,
                     },
                 )
@@ -155,11 +272,15 @@ impl Buck2TestRunner {
             .chain(config_env)
             .collect();
 
-        let target_handle = spec.target.handle;
+        let target_handle = spec.target.handle.clone();
         let host_sharing_requirements = HostSharingRequirements::default();
         let pre_create_dirs = Vec::new();
         let executor_override = None;
 
+        let _permit = match &self.concurrency_limit {
+            Some(semaphore) => Some(semaphore.acquire().await?),
+            None => None,
+        };
         self.orchestrator_client
             .execute2(
                 display_metadata,
@@ -182,6 +303,43 @@ impl Buck2TestRunner {
     }
 }
 
+/// The name a shard of test cases is reported under.
+fn shard_name(name: &str, shard: &[String]) -> String {
+    match shard {
+        [case] => format!("{} - {}", name, case),
+        cases => format!("{} - [{}]", name, cases.join(", ")),
+    }
+}
+
+/// The status to report for an attempt that ended with `status`, and a message explaining it. A
+/// failed attempt that will be retried is reported as `RERUN`.
+fn attempt_status(
+    status: &TestStatus,
+    attempt: u32,
+    max_attempts: u32,
+) -> (TestStatus, Option<String>) {
+    let failed = matches!(status, TestStatus::FAIL | TestStatus::TIMEOUT);
+    if failed && attempt < max_attempts {
+        (
+            TestStatus::RERUN,
+            Some(format!(
+                "Attempt {} of {} failed, retrying",
+                attempt, max_attempts
+            )),
+        )
+    } else if *status == TestStatus::PASS && attempt > 1 {
+        (
+            TestStatus::PASS,
+            Some(format!(
+                "Flaky: passed on attempt {} of {}",
+                attempt, max_attempts
+            )),
+        )
+    } else {
+        (status.clone(), None)
+    }
+}
+
 fn get_test_result(
     name: String,
     target: ConfiguredTargetHandle,
@@ -221,3 +379,67 @@ impl RunVerdict {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_name() {
+        assert_eq!(
+            "root//foo:bar - a",
+            shard_name("root//foo:bar", &["a".to_owned()])
+        );
+        assert_eq!(
+            "root//foo:bar - [a, b]",
+            shard_name("root//foo:bar", &["a".to_owned(), "b".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_attempt_status_retries_failures() {
+        assert_eq!(
+            (
+                TestStatus::RERUN,
+                Some("Attempt 1 of 3 failed, retrying".to_owned())
+            ),
+            attempt_status(&TestStatus::FAIL, 1, 3)
+        );
+        assert_eq!(
+            (
+                TestStatus::RERUN,
+                Some("Attempt 2 of 3 failed, retrying".to_owned())
+            ),
+            attempt_status(&TestStatus::TIMEOUT, 2, 3)
+        );
+        // The last attempt reports the failure.
+        assert_eq!(
+            (TestStatus::FAIL, None),
+            attempt_status(&TestStatus::FAIL, 3, 3)
+        );
+        assert_eq!(
+            (TestStatus::FAIL, None),
+            attempt_status(&TestStatus::FAIL, 1, 1)
+        );
+        // Other statuses aren't retried.
+        assert_eq!(
+            (TestStatus::SKIP, None),
+            attempt_status(&TestStatus::SKIP, 1, 3)
+        );
+    }
+
+    #[test]
+    fn test_attempt_status_flaky() {
+        assert_eq!(
+            (TestStatus::PASS, None),
+            attempt_status(&TestStatus::PASS, 1, 3)
+        );
+        assert_eq!(
+            (
+                TestStatus::PASS,
+                Some("Flaky: passed on attempt 2 of 3".to_owned())
+            ),
+            attempt_status(&TestStatus::PASS, 2, 3)
+        );
+    }
+}
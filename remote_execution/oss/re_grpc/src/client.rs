@@ -8,16 +8,25 @@
  */
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env::VarError;
+use std::fmt;
+use std::io::SeekFrom;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context;
 use buck2_core::fs::fs_util;
 use buck2_re_configuration::Buck2OssReConfiguration;
+use buck2_re_configuration::CasCompression;
 use buck2_re_configuration::HttpHeader;
 use dupe::Dupe;
+use futures::channel::mpsc;
 use futures::future::Future;
+use futures::future::TryFutureExt;
+use futures::sink::SinkExt;
 use futures::stream::BoxStream;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
@@ -26,25 +35,43 @@ use once_cell::sync::Lazy;
 use prost::Message;
 use re_grpc_proto::build::bazel::remote::execution::v2::action_cache_client::ActionCacheClient;
 use re_grpc_proto::build::bazel::remote::execution::v2::batch_update_blobs_request::Request;
+use re_grpc_proto::build::bazel::remote::execution::v2::capabilities_client::CapabilitiesClient;
 use re_grpc_proto::build::bazel::remote::execution::v2::compressor;
 use re_grpc_proto::build::bazel::remote::execution::v2::content_addressable_storage_client::ContentAddressableStorageClient;
+use re_grpc_proto::build::bazel::remote::execution::v2::digest_function;
 use re_grpc_proto::build::bazel::remote::execution::v2::execution_client::ExecutionClient;
 use re_grpc_proto::build::bazel::remote::execution::v2::execution_stage;
+use re_grpc_proto::build::bazel::remote::execution::v2::priority_capabilities::PriorityRange;
 use re_grpc_proto::build::bazel::remote::execution::v2::ActionResult;
 use re_grpc_proto::build::bazel::remote::execution::v2::BatchReadBlobsRequest;
 use re_grpc_proto::build::bazel::remote::execution::v2::BatchReadBlobsResponse;
 use re_grpc_proto::build::bazel::remote::execution::v2::BatchUpdateBlobsRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::BatchUpdateBlobsResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::CacheCapabilities;
 use re_grpc_proto::build::bazel::remote::execution::v2::Digest;
 use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteOperationMetadata;
 use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteRequest as GExecuteRequest;
 use re_grpc_proto::build::bazel::remote::execution::v2::ExecuteResponse as GExecuteResponse;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecutionCapabilities;
+use re_grpc_proto::build::bazel::remote::execution::v2::ExecutionPolicy;
+use re_grpc_proto::build::bazel::remote::execution::v2::FindMissingBlobsRequest as GFindMissingBlobsRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::FindMissingBlobsResponse as GFindMissingBlobsResponse;
 use re_grpc_proto::build::bazel::remote::execution::v2::GetActionResultRequest;
+use re_grpc_proto::build::bazel::remote::execution::v2::GetCapabilitiesRequest;
 use re_grpc_proto::build::bazel::remote::execution::v2::ResultsCachePolicy;
+use re_grpc_proto::build::bazel::remote::execution::v2::ServerCapabilities;
+use re_grpc_proto::google::bytestream::byte_stream_client::ByteStreamClient;
+use re_grpc_proto::google::bytestream::QueryWriteStatusRequest;
+use re_grpc_proto::google::bytestream::ReadRequest;
+use re_grpc_proto::google::bytestream::WriteRequest;
 use re_grpc_proto::google::longrunning::operation::Result as OpResult;
 use re_grpc_proto::google::rpc::Code;
 use re_grpc_proto::google::rpc::Status;
 use regex::Regex;
 use tokio::fs::OpenOptions;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
 use tokio::io::AsyncWriteExt;
 use tonic::codegen::InterceptedService;
 use tonic::metadata;
@@ -56,13 +83,30 @@ use tonic::transport::Certificate;
 use tonic::transport::Channel;
 use tonic::transport::Identity;
 use tonic::transport::Uri;
+use uuid::Uuid;
 
+use crate::compression::*;
+use crate::digest_ttl::DigestTtls;
 use crate::error::*;
 use crate::metadata::*;
 use crate::request::*;
 use crate::response::*;
 
-const INSTANCE_NAME: &str = "";
+/// Maximum total size of the blobs we send or receive in a single batch request. Blobs that do not
+/// fit in a batch on their own are transferred using the ByteStream API instead. This is the
+/// default gRPC maximum message size (4MiB), minus some headroom for the rest of the message. If
+/// the server advertises a lower limit, we use that instead.
+const MAX_TOTAL_BATCH_SIZE_BYTES: i64 = 4 * 1024 * 1024 - 64 * 1024;
+
+/// Size of the chunks we send in ByteStream writes.
+const BYTESTREAM_CHUNK_SIZE: i64 = 1024 * 1024;
+
+/// How many chunks we read ahead of the server when doing a ByteStream write.
+const BYTESTREAM_BUFFERED_CHUNKS: usize = 2;
+
+/// How many times we attempt a ByteStream write (resuming from what the server committed) before
+/// giving up.
+const BYTESTREAM_WRITE_ATTEMPTS: usize = 3;
 
 fn tdigest_to(tdigest: TDigest) -> Digest {
     Digest {
@@ -219,22 +263,234 @@ impl REClientBuilder {
 
         let interceptor = InjectHeadersInterceptor::new(&opts.http_headers)?;
 
+        let cas = cas.context("Error creating CAS client")?;
+        let execution = execution.context("Error creating Execution client")?;
+
+        let instance_names = InstanceNames::new(opts);
+
+        // The CAS and the engine might not be the same server, so we ask each of them about the
+        // part we'll be using.
+        let (cas_capabilities, execution_capabilities) = futures::future::try_join(
+            get_capabilities(
+                CapabilitiesClient::with_interceptor(cas.clone(), interceptor.dupe()),
+                &instance_names.default,
+            )
+            .map_err(|e| e.context("Error getting CAS capabilities")),
+            get_capabilities(
+                CapabilitiesClient::with_interceptor(execution.clone(), interceptor.dupe()),
+                &instance_names.default,
+            )
+            .map_err(|e| e.context("Error getting Execution capabilities")),
+        )
+        .await?;
+
+        let capabilities = RECapabilities::new(
+            cas_capabilities.and_then(|c| c.cache_capabilities),
+            execution_capabilities.and_then(|c| c.execution_capabilities),
+            opts.compression,
+        );
+
         let grpc_clients = GRPCClients {
             cas_client: ContentAddressableStorageClient::with_interceptor(
-                cas.context("Error creating CAS client")?,
-                interceptor.dupe(),
-            ),
-            execution_client: ExecutionClient::with_interceptor(
-                execution.context("Error creating Execution client")?,
+                cas.clone(),
                 interceptor.dupe(),
             ),
+            // ByteStream is served by the CAS for the purpose of transferring blobs.
+            bytestream_client: ByteStreamClient::with_interceptor(cas, interceptor.dupe()),
+            execution_client: ExecutionClient::with_interceptor(execution, interceptor.dupe()),
             action_cache_client: ActionCacheClient::with_interceptor(
                 action_cache.context("Error creating ActionCache client")?,
                 interceptor.dupe(),
             ),
         };
 
-        Ok(REClient::new(grpc_clients))
+        let digest_ttls = DigestTtls::new(Duration::from_secs(opts.cas_ttl_secs));
+
+        Ok(REClient::new(
+            grpc_clients,
+            capabilities,
+            instance_names,
+            digest_ttls,
+        ))
+    }
+}
+
+/// The REAPI instance names we send requests to.
+#[derive(Clone, Debug, Default)]
+struct InstanceNames {
+    default: String,
+    /// Overrides for specific use cases.
+    by_use_case: HashMap<String, String>,
+}
+
+impl InstanceNames {
+    fn new(opts: &Buck2OssReConfiguration) -> Self {
+        Self {
+            default: opts.instance_name.clone().unwrap_or_default(),
+            by_use_case: opts
+                .use_case_instance_names
+                .iter()
+                .map(|x| (x.use_case.clone(), x.instance_name.clone()))
+                .collect(),
+        }
+    }
+
+    /// The instance name to use for a request made with `metadata`.
+    fn for_metadata(&self, metadata: &RemoteExecutionMetadata) -> &str {
+        self.by_use_case
+            .get(&metadata.use_case_id)
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Ask the server for its capabilities. Returns `None` if the server does not implement the
+/// Capabilities service, in which case we fall back to defaults.
+async fn get_capabilities(
+    mut client: CapabilitiesClient<InterceptedService<Channel, InjectHeadersInterceptor>>,
+    instance_name: &str,
+) -> anyhow::Result<Option<ServerCapabilities>> {
+    let res = client
+        .get_capabilities(GetCapabilitiesRequest {
+            instance_name: instance_name.to_owned(),
+        })
+        .await;
+
+    match res {
+        Ok(capabilities) => Ok(Some(capabilities.into_inner())),
+        Err(status) if status.code() == tonic::Code::Unimplemented => {
+            tracing::debug!("Server does not implement GetCapabilities, using defaults");
+            Ok(None)
+        }
+        Err(status) => Err(status.into()),
+    }
+}
+
+/// What we learned about the server through `GetCapabilities` when connecting.
+#[derive(Clone, Debug)]
+struct RECapabilities {
+    /// Max total size of the blobs in a batch request.
+    max_total_batch_size: i64,
+    /// Digest functions the server supports. This is empty if the server didn't tell us.
+    digest_functions: Vec<i32>,
+    /// Ranges of cache priorities the server supports. This is empty if it doesn't support any.
+    cache_priorities: Vec<PriorityRange>,
+    /// Ranges of execution priorities the server supports. This is empty if it doesn't support
+    /// any.
+    execution_priorities: Vec<PriorityRange>,
+    /// Compressor to use for ByteStream transfers and batch downloads.
+    compressor: compressor::Value,
+    /// Compressor to use for batch uploads. Servers advertise this separately.
+    batch_update_compressor: compressor::Value,
+}
+
+impl RECapabilities {
+    fn new(
+        cache: Option<CacheCapabilities>,
+        execution: Option<ExecutionCapabilities>,
+        compression: CasCompression,
+    ) -> Self {
+        let cache = cache.unwrap_or_default();
+        let execution = execution.unwrap_or_default();
+
+        // 0 means the server does not have a limit of its own, but we're still bounded by gRPC.
+        let max_total_batch_size = if cache.max_batch_total_size_bytes > 0 {
+            std::cmp::min(cache.max_batch_total_size_bytes, MAX_TOTAL_BATCH_SIZE_BYTES)
+        } else {
+            MAX_TOTAL_BATCH_SIZE_BYTES
+        };
+
+        let mut digest_functions = cache.digest_functions;
+        if execution.digest_function != digest_function::Value::Unknown as i32
+            && !digest_functions.contains(&execution.digest_function)
+        {
+            digest_functions.push(execution.digest_function);
+        }
+
+        // Identity is always supported, and isn't listed by servers.
+        let wanted = configured_compressor(compression);
+        let compressor = if wanted == compressor::Value::Identity
+            || cache.supported_compressors.contains(&(wanted as i32))
+        {
+            wanted
+        } else {
+            tracing::warn!(
+                "Remote execution server does not support {:?} compression, blobs will be \
+                transferred uncompressed",
+                wanted
+            );
+            compressor::Value::Identity
+        };
+        let batch_update_compressor = if cache
+            .supported_batch_update_compressors
+            .contains(&(compressor as i32))
+        {
+            compressor
+        } else {
+            compressor::Value::Identity
+        };
+
+        Self {
+            max_total_batch_size,
+            digest_functions,
+            cache_priorities: cache
+                .cache_priority_capabilities
+                .map(|c| c.priorities)
+                .unwrap_or_default(),
+            execution_priorities: execution
+                .execution_priority_capabilities
+                .map(|c| c.priorities)
+                .unwrap_or_default(),
+            compressor,
+            batch_update_compressor,
+        }
+    }
+
+    fn check_digest_function(&self, name: &str) -> anyhow::Result<()> {
+        let function = match name {
+            "SHA1" => digest_function::Value::Sha1,
+            "SHA256" => digest_function::Value::Sha256,
+            "BLAKE3" => digest_function::Value::Blake3,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Digest function `{}` cannot be used with remote execution",
+                    name
+                ));
+            }
+        };
+
+        if self.digest_functions.is_empty() || self.digest_functions.contains(&(function as i32)) {
+            return Ok(());
+        }
+
+        let supported = self
+            .digest_functions
+            .iter()
+            .map(|f| match digest_function::Value::from_i32(*f) {
+                Some(f) => format!("{:?}", f).to_uppercase(),
+                None => format!("UNKNOWN({})", f),
+            })
+            .collect::<Vec<_>>();
+
+        Err(anyhow::anyhow!(
+            "Remote execution server does not support digest function `{}` (supported: {}). \
+            Set `digest_algorithms` in the `[buck2]` section of your `.buckconfig` to one the \
+            server supports",
+            name,
+            supported.join(", ")
+        ))
+    }
+}
+
+/// Priorities are defined by the server, so we only send the priority we were asked for if the
+/// server says it supports it, and otherwise let the server use its default (0).
+fn supported_priority(priority: i32, ranges: &[PriorityRange]) -> i32 {
+    if ranges
+        .iter()
+        .any(|r| r.min_priority <= priority && priority <= r.max_priority)
+    {
+        priority
+    } else {
+        0
     }
 }
 
@@ -288,6 +544,7 @@ pub struct GRPCClients {
         ContentAddressableStorageClient<InterceptedService<Channel, InjectHeadersInterceptor>>,
     execution_client: ExecutionClient<InterceptedService<Channel, InjectHeadersInterceptor>>,
     action_cache_client: ActionCacheClient<InterceptedService<Channel, InjectHeadersInterceptor>>,
+    bytestream_client: ByteStreamClient<InterceptedService<Channel, InjectHeadersInterceptor>>,
 }
 
 #[derive(Default)]
@@ -299,6 +556,9 @@ pub struct REState {
 
 pub struct REClient {
     grpc_clients: GRPCClients,
+    capabilities: RECapabilities,
+    instance_names: InstanceNames,
+    digest_ttls: DigestTtls,
     state: Mutex<REState>,
 }
 
@@ -310,13 +570,27 @@ impl Drop for REClient {
 }
 
 impl REClient {
-    pub fn new(grpc_clients: GRPCClients) -> Self {
+    fn new(
+        grpc_clients: GRPCClients,
+        capabilities: RECapabilities,
+        instance_names: InstanceNames,
+        digest_ttls: DigestTtls,
+    ) -> Self {
         REClient {
             grpc_clients,
+            capabilities,
+            instance_names,
+            digest_ttls,
             state: Mutex::new(REState::default()),
         }
     }
 
+    /// Check that the server supports the digest function buck2 is configured to use. The name
+    /// is the one used in buckconfig (e.g. `SHA256`).
+    pub fn check_digest_function(&self, name: &str) -> anyhow::Result<()> {
+        self.capabilities.check_digest_function(name)
+    }
+
     pub async fn get_action_result(
         &self,
         metadata: RemoteExecutionMetadata,
@@ -327,7 +601,7 @@ impl REClient {
         let res = client
             .get_action_result(with_internal_metadata(
                 GetActionResultRequest {
-                    instance_name: INSTANCE_NAME.into(),
+                    instance_name: self.instance_names.for_metadata(&metadata).to_owned(),
                     action_digest: Some(tdigest_to(request.digest)),
                     ..Default::default()
                 },
@@ -335,9 +609,11 @@ impl REClient {
             ))
             .await?;
 
+        // Servers are expected to keep the outputs of the action results they return around for a
+        // while.
         Ok(ActionResultResponse {
             action_result: convert_action_result(res.into_inner())?,
-            ttl: 0,
+            ttl: self.digest_ttls.ttl_secs(),
         })
     }
 
@@ -354,18 +630,30 @@ impl REClient {
         metadata: RemoteExecutionMetadata,
         mut execute_request: ExecuteRequest,
     ) -> anyhow::Result<BoxStream<'static, anyhow::Result<ExecuteWithProgressResponse>>> {
-        // TODO(aloiscochard): Map those properly in the request
-        // use crate::proto::build::bazel::remote::execution::v2::ExecutionPolicy;
-
         let mut client = self.grpc_clients.execution_client.clone();
 
         let action_digest = tdigest_to(execute_request.action_digest.clone());
 
         let request = GExecuteRequest {
-            instance_name: INSTANCE_NAME.into(),
+            instance_name: self.instance_names.for_metadata(&metadata).to_owned(),
             skip_cache_lookup: false,
-            execution_policy: None,
-            results_cache_policy: Some(ResultsCachePolicy { priority: 0 }),
+            execution_policy: execute_request
+                .execution_policy
+                .as_ref()
+                .map(|p| ExecutionPolicy {
+                    priority: supported_priority(
+                        p.priority,
+                        &self.capabilities.execution_priorities,
+                    ),
+                }),
+            results_cache_policy: Some(ResultsCachePolicy {
+                priority: execute_request
+                    .results_cache_policy
+                    .as_ref()
+                    .map_or(0, |p| {
+                        supported_priority(p.priority, &self.capabilities.cache_priorities)
+                    }),
+            }),
             action_digest: Some(action_digest.clone()),
         };
 
@@ -374,6 +662,8 @@ impl REClient {
             .await?
             .into_inner();
 
+        let action_result_ttl = self.digest_ttls.ttl_secs();
+
         let stream = futures::stream::try_unfold(stream, move |mut stream| async {
             let msg = match stream.try_next().await.context("RE channel error")? {
                 Some(msg) => msg,
@@ -407,7 +697,7 @@ impl REClient {
                         let execute_response = ExecuteResponse {
                             action_result,
                             action_result_digest: TDigest::default(),
-                            action_result_ttl: 0,
+                            action_result_ttl,
                             error: REError {
                                 code: TCode::OK,
                                 ..Default::default()
@@ -471,72 +761,46 @@ impl REClient {
         metadata: RemoteExecutionMetadata,
         request: UploadRequest,
     ) -> anyhow::Result<UploadResponse> {
-        let mut client = self.grpc_clients.cas_client.clone();
-
-        let files_with_digest: Vec<Request> = request
-            .files_with_digest
-            .unwrap_or_default()
-            .into_try_map(|x| {
-                anyhow::Ok(Request {
-                    digest: Some(tdigest_to(x.digest)),
-                    // FIXME: This could do a lot of blocking reads
-                    data: fs_util::read(&x.name)?,
-                    compressor: compressor::Value::Identity as i32,
-                })
-            })?;
-
-        let re_request = BatchUpdateBlobsRequest {
-            instance_name: INSTANCE_NAME.into(),
-            requests: [
-                request
-                    .inlined_blobs_with_digest
-                    .unwrap_or_default()
-                    .into_map(|x| Request {
-                        digest: Some(tdigest_to(x.digest)),
-                        data: x.blob,
-                        compressor: compressor::Value::Identity as i32,
-                    }),
-                files_with_digest,
-            ]
-            .concat(),
-        };
+        let metadata = &metadata;
+        let instance_name = self.instance_names.for_metadata(metadata);
 
-        let blob_hashes = re_request
-            .requests
+        let digests = request
+            .inlined_blobs_with_digest
             .iter()
-            .map(|x| x.digest.as_ref().unwrap().hash.clone())
-            .collect::<Vec<String>>();
-        let response = client
-            .batch_update_blobs(with_internal_metadata(re_request, metadata))
-            .await?;
+            .flatten()
+            .map(|x| x.digest.clone())
+            .chain(
+                request
+                    .files_with_digest
+                    .iter()
+                    .flatten()
+                    .map(|x| x.digest.clone()),
+            )
+            .collect::<Vec<_>>();
+        let now = Instant::now();
+
+        let res = upload_impl(
+            request,
+            instance_name,
+            self.capabilities.max_total_batch_size,
+            self.capabilities.batch_update_compressor,
+            |re_request| self.find_missing_blobs(metadata.clone(), re_request),
+            |re_request| async move {
+                let mut client = self.grpc_clients.cas_client.clone();
+                Ok(client
+                    .batch_update_blobs(with_internal_metadata(re_request, metadata.clone()))
+                    .await?
+                    .into_inner())
+            },
+            |digest, upload| self.bytestream_write(metadata.clone(), digest, upload),
+        )
+        .await?;
 
-        let failures: Vec<String> = response
-            .get_ref()
-            .responses
-            .iter()
-            .filter_map(|r| {
-                r.status.as_ref().and_then(|s| {
-                    if s.code == (Code::Ok as i32) {
-                        None
-                    } else {
-                        Some(format!(
-                            "Unable to upload blob '{}', rpc status code: {}, message: \"{}\"",
-                            r.digest.as_ref().map_or("N/A", |d| &d.hash),
-                            s.code,
-                            s.message
-                        ))
-                    }
-                })
-            })
-            .collect();
+        // Everything we just uploaded (or didn't need to) is now in the CAS.
+        self.digest_ttls
+            .record_present(instance_name, &digests, now);
 
-        if failures.is_empty() {
-            tracing::debug!("uploaded: {:?}", blob_hashes);
-            // TODO(aloiscochard): Add something interesting in UploadResponse?
-            Ok(UploadResponse {})
-        } else {
-            Err(anyhow::anyhow!("Batch upload failed: {:?}", failures))
-        }
+        Ok(res)
     }
 
     pub async fn upload_blob(
@@ -553,28 +817,174 @@ impl REClient {
         metadata: RemoteExecutionMetadata,
         request: DownloadRequest,
     ) -> anyhow::Result<DownloadResponse> {
-        download_impl(request, |re_request| async {
-            let mut client = self.grpc_clients.cas_client.clone();
-            Ok(client
-                .batch_read_blobs(with_internal_metadata(re_request, metadata))
-                .await?
-                .into_inner())
-        })
+        let metadata = &metadata;
+
+        download_impl(
+            request,
+            self.instance_names.for_metadata(metadata),
+            self.capabilities.max_total_batch_size,
+            self.capabilities.compressor,
+            |re_request| async move {
+                let mut client = self.grpc_clients.cas_client.clone();
+                Ok(client
+                    .batch_read_blobs(with_internal_metadata(re_request, metadata.clone()))
+                    .await?
+                    .into_inner())
+            },
+            |digest| self.bytestream_read(metadata.clone(), digest),
+        )
         .await
     }
 
+    /// Write a blob to the CAS using the ByteStream API. If the write fails partway through, we ask
+    /// the server how much it committed and resume from there.
+    async fn bytestream_write(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        digest: TDigest,
+        upload: CasUpload,
+    ) -> anyhow::Result<()> {
+        let mut client = self.grpc_clients.bytestream_client.clone();
+
+        let compressor = self.capabilities.compressor;
+        let resource_name = bytestream_write_resource_name(
+            self.instance_names.for_metadata(&metadata),
+            compressor,
+            &Uuid::new_v4().to_string(),
+            &digest,
+        );
+        let size = digest.size_in_bytes;
+
+        let mut offset = 0;
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let reader = upload
+                .open(compressor, offset)
+                .await
+                .with_context(|| format!("Error reading {}", upload))?;
+
+            let (tx, rx) = mpsc::channel(BYTESTREAM_BUFFERED_CHUNKS);
+            let (sent, written) = futures::future::join(
+                send_bytestream_chunks(tx, &resource_name, reader, offset),
+                client.write(with_internal_metadata(rx, metadata.clone())),
+            )
+            .await;
+
+            // If we failed to read the data we are uploading, retrying won't help.
+            let sent = sent.with_context(|| format!("Error reading {}", upload))?;
+
+            let error = match written {
+                Ok(response)
+                    if is_write_complete(
+                        compressor,
+                        size,
+                        sent,
+                        response.get_ref().committed_size,
+                    ) =>
+                {
+                    return Ok(());
+                }
+                Ok(response) => anyhow::anyhow!(
+                    "Server committed {} bytes, which is not all of what we sent",
+                    response.get_ref().committed_size,
+                ),
+                Err(status) => status.into(),
+            };
+
+            if attempts >= BYTESTREAM_WRITE_ATTEMPTS {
+                return Err(error.context(format!(
+                    "Error writing `{}` to `{}` after {} attempts",
+                    upload, resource_name, attempts
+                )));
+            }
+
+            tracing::debug!(
+                "ByteStream write to `{}` failed, resuming: {:#}",
+                resource_name,
+                error
+            );
+
+            offset = match client
+                .query_write_status(with_internal_metadata(
+                    QueryWriteStatusRequest {
+                        resource_name: resource_name.clone(),
+                    },
+                    metadata.clone(),
+                ))
+                .await
+            {
+                Ok(status) if status.get_ref().complete => return Ok(()),
+                Ok(status) => status.get_ref().committed_size,
+                // The server doesn't know about this write (e.g. it never got the first chunk),
+                // so we start over.
+                Err(_) => 0,
+            };
+        }
+    }
+
+    /// Read a blob from the CAS using the ByteStream API, as a stream of chunks.
+    fn bytestream_read(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        digest: &TDigest,
+    ) -> BoxStream<'static, anyhow::Result<Vec<u8>>> {
+        let mut client = self.grpc_clients.bytestream_client.clone();
+
+        let compressor = self.capabilities.compressor;
+        let request = ReadRequest {
+            resource_name: bytestream_read_resource_name(
+                self.instance_names.for_metadata(&metadata),
+                compressor,
+                digest,
+            ),
+            read_offset: 0,
+            read_limit: 0,
+        };
+
+        let chunks = async move {
+            let stream = client
+                .read(with_internal_metadata(request, metadata))
+                .await?
+                .into_inner();
+            anyhow::Ok(stream.map(|r| anyhow::Ok(r?.data)))
+        }
+        .try_flatten_stream()
+        .boxed();
+
+        decompress_stream(compressor, chunks)
+    }
+
+    async fn find_missing_blobs(
+        &self,
+        metadata: RemoteExecutionMetadata,
+        request: GFindMissingBlobsRequest,
+    ) -> anyhow::Result<GFindMissingBlobsResponse> {
+        let mut client = self.grpc_clients.cas_client.clone();
+        Ok(client
+            .find_missing_blobs(with_internal_metadata(request, metadata))
+            .await?
+            .into_inner())
+    }
+
     pub async fn get_digests_ttl(
         &self,
-        _metadata: RemoteExecutionMetadata,
+        metadata: RemoteExecutionMetadata,
         request: GetDigestsTtlRequest,
     ) -> anyhow::Result<GetDigestsTtlResponse> {
-        // TODO(aloiscochard): For now we just say all digests have expired,
-        // we should fetch and return real TTL instead
-        Ok(GetDigestsTtlResponse {
-            digests_with_ttl: request
-                .digests
-                .into_map(|digest| DigestWithTtl { digest, ttl: 0 }),
-        })
+        let metadata = &metadata;
+
+        get_digests_ttl_impl(
+            request,
+            self.instance_names.for_metadata(metadata),
+            self.capabilities.max_total_batch_size,
+            &self.digest_ttls,
+            Instant::now(),
+            |re_request| self.find_missing_blobs(metadata.clone(), re_request),
+        )
+        .await
     }
 
     pub fn get_execution_client(&self) -> &Self {
@@ -697,101 +1107,582 @@ fn convert_action_result(action_result: ActionResult) -> anyhow::Result<TActionR
     Ok(action_result)
 }
 
-async fn download_impl<F, Fut>(request: DownloadRequest, f: F) -> anyhow::Result<DownloadResponse>
-where
-    F: FnOnce(BatchReadBlobsRequest) -> Fut,
-    Fut: Future<Output = anyhow::Result<BatchReadBlobsResponse>>,
-{
-    let inlined_digests = request.inlined_digests.unwrap_or_default();
-    let file_digests = request.file_digests.unwrap_or_default();
+/// A blob we need to write to the CAS, which might not have been read into memory yet.
+enum CasUpload {
+    Inlined(Arc<[u8]>),
+    File(String),
+}
 
-    let re_request = BatchReadBlobsRequest {
-        instance_name: INSTANCE_NAME.into(),
-        digests: file_digests
-            .iter()
-            .map(|req| &req.named_digest.digest)
-            .chain(inlined_digests.iter())
-            .map(|d| tdigest_to(d.clone()))
-            .filter(|d| d.size_bytes > 0)
-            .collect(),
-        acceptable_compressors: vec![compressor::Value::Identity as i32],
-    };
+impl CasUpload {
+    async fn read(&self) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Inlined(data) => Ok(data.to_vec()),
+            // FIXME: This could do a lot of blocking reads
+            Self::File(path) => fs_util::read(path),
+        }
+    }
 
-    let response = f(re_request).await?;
+    async fn open_at(&self, offset: i64) -> anyhow::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        let offset: u64 = offset.try_into().context("Invalid offset")?;
 
-    let response = response
-        .responses
-        .into_iter()
-        .map(|r| {
-            check_status(r.status.unwrap_or_default())?;
-            let digest = tdigest_from(r.digest.context("Response digest not found.")?);
-            anyhow::Ok((digest, r.data))
-        })
-        .collect::<Result<HashMap<_, _>, _>>()?;
+        match self {
+            Self::Inlined(data) => {
+                let mut cursor = std::io::Cursor::new(data.dupe());
+                cursor.set_position(offset);
+                Ok(Box::new(cursor))
+            }
+            Self::File(path) => {
+                let mut file = tokio::fs::File::open(path)
+                    .await
+                    .with_context(|| format!("Error opening `{}`", path))?;
+                file.seek(SeekFrom::Start(offset))
+                    .await
+                    .with_context(|| format!("Error seeking in `{}`", path))?;
+                Ok(Box::new(file))
+            }
+        }
+    }
 
-    let get = |digest: &TDigest| -> anyhow::Result<Vec<u8>> {
-        if digest.size_in_bytes == 0 {
-            return Ok(Vec::new());
+    /// Open this blob compressed with `compressor`, starting at `offset` in the compressed data.
+    async fn open(
+        &self,
+        compressor: compressor::Value,
+        offset: i64,
+    ) -> anyhow::Result<Box<dyn AsyncRead + Send + Unpin>> {
+        if compressor == compressor::Value::Identity {
+            return self.open_at(offset).await;
         }
 
-        Ok(response
-            .get(digest)
-            .with_context(|| format!("Did not receive digest data for `{}`", digest))?
-            .clone())
-    };
+        // We can't seek in the compressed data, so we compress from the start and skip over what
+        // the server already has.
+        let offset: u64 = offset.try_into().context("Invalid offset")?;
+        let mut reader = compress_reader(
+            compressor,
+            tokio::io::BufReader::new(self.open_at(0).await?),
+        );
+        let skipped =
+            tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink()).await?;
+        if skipped != offset {
+            return Err(anyhow::anyhow!(
+                "Compressed data is {} bytes, cannot resume at {}",
+                skipped,
+                offset
+            ));
+        }
+        Ok(reader)
+    }
+}
 
-    let inlined_blobs = inlined_digests.into_try_map(|digest| {
-        let data = get(&digest)?;
+impl fmt::Display for CasUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inlined(..) => write!(f, "inlined blob"),
+            Self::File(path) => write!(f, "file `{}`", path),
+        }
+    }
+}
 
-        anyhow::Ok(InlinedDigestWithStatus {
-            digest,
-            status: tstatus_ok(),
-            blob: data,
-        })
-    })?;
+/// The ByteStream resource name to read a blob from the CAS.
+fn bytestream_read_resource_name(
+    instance_name: &str,
+    compressor: compressor::Value,
+    digest: &TDigest,
+) -> String {
+    with_instance_name(
+        instance_name,
+        format!(
+            "{}/{}/{}",
+            blobs_path(compressor),
+            digest.hash,
+            digest.size_in_bytes
+        ),
+    )
+}
 
-    let writes = file_digests.iter().map(|req| async {
-        let data = get(&req.named_digest.digest)?;
+/// The ByteStream resource name to write a blob to the CAS. The `upload_id` must be unique to
+/// this write, since it's what lets the server tell concurrent uploads of the same blob apart.
+fn bytestream_write_resource_name(
+    instance_name: &str,
+    compressor: compressor::Value,
+    upload_id: &str,
+    digest: &TDigest,
+) -> String {
+    with_instance_name(
+        instance_name,
+        format!(
+            "uploads/{}/{}/{}/{}",
+            upload_id,
+            blobs_path(compressor),
+            digest.hash,
+            digest.size_in_bytes
+        ),
+    )
+}
 
-        let mut opts = OpenOptions::new();
-        opts.read(true).write(true).create_new(true);
-        #[cfg(unix)]
-        {
-            if req.is_executable {
-                opts.mode(0o755);
+fn with_instance_name(instance_name: &str, resource: String) -> String {
+    if instance_name.is_empty() {
+        resource
+    } else {
+        format!("{}/{}", instance_name, resource)
+    }
+}
+
+/// Send the contents of `reader` to a ByteStream write, starting at `offset`. Returns the total
+/// size of the data written once we sent all of it, or `None` if we stopped early because the
+/// server hung up, in which case the response to the write tells us why.
+async fn send_bytestream_chunks(
+    mut tx: mpsc::Sender<WriteRequest>,
+    resource_name: &str,
+    mut reader: impl AsyncRead + Unpin,
+    mut offset: i64,
+) -> anyhow::Result<Option<i64>> {
+    let mut first = true;
+
+    loop {
+        // We don't know the size of compressed data up front, so we send chunks until the reader
+        // is exhausted.
+        let mut data = Vec::new();
+        (&mut reader)
+            .take(BYTESTREAM_CHUNK_SIZE as u64)
+            .read_to_end(&mut data)
+            .await?;
+
+        let write_offset = offset;
+        offset += data.len() as i64;
+        let finish_write = (data.len() as i64) < BYTESTREAM_CHUNK_SIZE;
+
+        let request = WriteRequest {
+            // This is only required on the first request.
+            resource_name: if first {
+                resource_name.to_owned()
             } else {
-                opts.mode(0o644);
-            }
+                String::new()
+            },
+            write_offset,
+            finish_write,
+            data,
+        };
+        first = false;
+
+        if tx.send(request).await.is_err() {
+            return Ok(None);
         }
 
-        async {
-            let mut file = opts
-                .open(&req.named_digest.name)
-                .await
-                .context("Error opening")?;
-            file.write_all(&data).await.context("Error writing")?;
-            file.flush().await.context("Error flushing")?;
-            anyhow::Ok(())
+        if finish_write {
+            return Ok(Some(offset));
         }
-        .await
-        .with_context(|| {
-            format!(
-                "Error writing digest `{}` to `{}`",
-                req.named_digest.digest, req.named_digest.name,
-            )
-        })
-    });
+    }
+}
 
-    futures::future::try_join_all(writes).await?;
+/// Whether the response to a ByteStream write of a blob of `size` bytes tells us the blob is now
+/// in the CAS. `sent` is the total size of the data we sent, if we sent all of it.
+fn is_write_complete(
+    compressor: compressor::Value,
+    size: i64,
+    sent: Option<i64>,
+    committed_size: i64,
+) -> bool {
+    match compressor {
+        // Servers may return early (with the full size) if they already have the blob.
+        compressor::Value::Identity => committed_size == size,
+        // For compressed writes, servers report the compressed size, or -1 if they already have
+        // the blob.
+        _ => committed_size == -1 || Some(committed_size) == sent,
+    }
+}
 
-    Ok(DownloadResponse {
-        inlined_blobs: Some(inlined_blobs),
-        directories: None,
-    })
+/// Split `items` into batches whose total size does not exceed `max_total_batch_size`. Items that
+/// are bigger than that on their own get a batch of their own.
+fn split_into_batches<T>(
+    items: impl IntoIterator<Item = T>,
+    max_total_batch_size: i64,
+    size: impl Fn(&T) -> i64,
+) -> Vec<Vec<T>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+
+    for item in items {
+        let item_size = size(&item);
+        if !batch.is_empty() && batch_size + item_size > max_total_batch_size {
+            batches.push(std::mem::take(&mut batch));
+            batch_size = 0;
+        }
+        batch_size += item_size;
+        batch.push(item);
+    }
+
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+
+    batches
 }
 
-fn with_internal_metadata<T>(t: T, metadata: RemoteExecutionMetadata) -> tonic::Request<T> {
-    // This is pretty ugly, but the protobuf spec that defines this is internal, so considering
+/// Ask the CAS which of `digests` it does not have.
+async fn find_missing_digests<'a, Fm, FmRet>(
+    instance_name: &str,
+    digests: impl IntoIterator<Item = &'a TDigest>,
+    max_total_batch_size: i64,
+    find_missing: Fm,
+) -> anyhow::Result<HashSet<TDigest>>
+where
+    Fm: Fn(GFindMissingBlobsRequest) -> FmRet,
+    FmRet: Future<Output = anyhow::Result<GFindMissingBlobsResponse>>,
+{
+    // The requests only contain digests, but there can be a lot of them, so we split those too.
+    // This approximates the size of a digest in the request.
+    let batches = split_into_batches(
+        digests.into_iter().map(|d| tdigest_to(d.clone())),
+        max_total_batch_size,
+        |d| d.hash.len() as i64 + 16,
+    );
+
+    let responses = futures::future::try_join_all(batches.into_iter().map(|blob_digests| {
+        find_missing(GFindMissingBlobsRequest {
+            instance_name: instance_name.to_owned(),
+            blob_digests,
+        })
+    }))
+    .await?;
+
+    Ok(responses
+        .into_iter()
+        .flat_map(|r| r.missing_blob_digests)
+        .map(tdigest_from)
+        .collect())
+}
+
+/// Report how long each of the requested digests will stay in the CAS. We answer from what we
+/// learned recently when we can, and otherwise ask the server which of them it still has, which
+/// also lets it extend their lifetime.
+async fn get_digests_ttl_impl<Fm, FmRet>(
+    request: GetDigestsTtlRequest,
+    instance_name: &str,
+    max_total_batch_size: i64,
+    digest_ttls: &DigestTtls,
+    now: Instant,
+    find_missing: Fm,
+) -> anyhow::Result<GetDigestsTtlResponse>
+where
+    Fm: Fn(GFindMissingBlobsRequest) -> FmRet,
+    FmRet: Future<Output = anyhow::Result<GFindMissingBlobsResponse>>,
+{
+    let unknown = request
+        .digests
+        .iter()
+        .filter(|d| digest_ttls.cached_ttl(instance_name, d, now).is_none())
+        .collect::<Vec<_>>();
+
+    if !unknown.is_empty() {
+        let missing = find_missing_digests(
+            instance_name,
+            unknown.iter().copied(),
+            max_total_batch_size,
+            find_missing,
+        )
+        .await?;
+
+        digest_ttls.record_missing(instance_name, &missing);
+        digest_ttls.record_present(
+            instance_name,
+            unknown.into_iter().filter(|d| !missing.contains(*d)),
+            now,
+        );
+    }
+
+    Ok(GetDigestsTtlResponse {
+        digests_with_ttl: request.digests.into_map(|digest| {
+            let ttl = digest_ttls
+                .cached_ttl(instance_name, &digest, now)
+                .unwrap_or(0);
+            DigestWithTtl { digest, ttl }
+        }),
+    })
+}
+
+async fn upload_impl<Fm, FmRet, Byt, BytRet, Cas, CasRet>(
+    request: UploadRequest,
+    instance_name: &str,
+    max_total_batch_size: i64,
+    compressor: compressor::Value,
+    find_missing: Fm,
+    batch_upload: Byt,
+    bytestream_upload: Cas,
+) -> anyhow::Result<UploadResponse>
+where
+    Fm: Fn(GFindMissingBlobsRequest) -> FmRet,
+    FmRet: Future<Output = anyhow::Result<GFindMissingBlobsResponse>>,
+    Byt: Fn(BatchUpdateBlobsRequest) -> BytRet,
+    BytRet: Future<Output = anyhow::Result<BatchUpdateBlobsResponse>>,
+    Cas: Fn(TDigest, CasUpload) -> CasRet,
+    CasRet: Future<Output = anyhow::Result<()>>,
+{
+    let blobs = request
+        .inlined_blobs_with_digest
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| (x.digest, CasUpload::Inlined(x.blob.into())))
+        .chain(
+            request
+                .files_with_digest
+                .unwrap_or_default()
+                .into_iter()
+                .map(|x| (x.digest, CasUpload::File(x.name))),
+        )
+        .collect::<Vec<_>>();
+
+    let blobs = if request.upload_only_missing {
+        let missing = find_missing_digests(
+            instance_name,
+            blobs.iter().map(|(d, _)| d),
+            max_total_batch_size,
+            find_missing,
+        )
+        .await?;
+        blobs
+            .into_iter()
+            .filter(|(digest, _)| missing.contains(digest))
+            .collect()
+    } else {
+        blobs
+    };
+
+    // Blobs that fit in a batch are uploaded using the batch API, the others are streamed.
+    let (batched, streamed): (Vec<_>, Vec<_>) = blobs
+        .into_iter()
+        .partition(|(digest, _)| digest.size_in_bytes <= max_total_batch_size);
+
+    let batches = split_into_batches(batched, max_total_batch_size, |(digest, _)| {
+        digest.size_in_bytes
+    });
+
+    let batch_uploads = batches.into_iter().map(|batch| async {
+        let requests =
+            futures::future::try_join_all(batch.into_iter().map(|(digest, upload)| async move {
+                anyhow::Ok(Request {
+                    digest: Some(tdigest_to(digest)),
+                    data: compress(compressor, upload.read().await?)?,
+                    compressor: compressor as i32,
+                })
+            }))
+            .await?;
+
+        let re_request = BatchUpdateBlobsRequest {
+            instance_name: instance_name.to_owned(),
+            requests,
+        };
+
+        let blob_hashes = re_request
+            .requests
+            .iter()
+            .map(|x| x.digest.as_ref().unwrap().hash.clone())
+            .collect::<Vec<String>>();
+
+        let response = batch_upload(re_request).await?;
+
+        let failures: Vec<String> = response
+            .responses
+            .iter()
+            .filter_map(|r| {
+                r.status.as_ref().and_then(|s| {
+                    if s.code == (Code::Ok as i32) {
+                        None
+                    } else {
+                        Some(format!(
+                            "Unable to upload blob '{}', rpc status code: {}, message: \"{}\"",
+                            r.digest.as_ref().map_or("N/A", |d| &d.hash),
+                            s.code,
+                            s.message
+                        ))
+                    }
+                })
+            })
+            .collect();
+
+        if failures.is_empty() {
+            tracing::debug!("uploaded: {:?}", blob_hashes);
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Batch upload failed: {:?}", failures))
+        }
+    });
+
+    let stream_uploads = streamed.into_iter().map(|(digest, upload)| {
+        let hash = digest.hash.clone();
+        bytestream_upload(digest, upload).map_ok(move |()| {
+            tracing::debug!("uploaded: {:?}", hash);
+        })
+    });
+
+    futures::future::try_join(
+        futures::future::try_join_all(batch_uploads),
+        futures::future::try_join_all(stream_uploads),
+    )
+    .await?;
+
+    // TODO(aloiscochard): Add something interesting in UploadResponse?
+    Ok(UploadResponse {})
+}
+
+async fn download_impl<Byt, BytRet, Cas>(
+    request: DownloadRequest,
+    instance_name: &str,
+    max_total_batch_size: i64,
+    compressor: compressor::Value,
+    batch_download: Byt,
+    bytestream_download: Cas,
+) -> anyhow::Result<DownloadResponse>
+where
+    Byt: Fn(BatchReadBlobsRequest) -> BytRet,
+    BytRet: Future<Output = anyhow::Result<BatchReadBlobsResponse>>,
+    Cas: Fn(&TDigest) -> BoxStream<'static, anyhow::Result<Vec<u8>>>,
+{
+    let inlined_digests = request.inlined_digests.unwrap_or_default();
+    let file_digests = request.file_digests.unwrap_or_default();
+
+    // Blobs that are too large to fit in a batch are streamed using ByteStream.
+    let is_streamed = |digest: &TDigest| digest.size_in_bytes > max_total_batch_size;
+
+    let batches = split_into_batches(
+        file_digests
+            .iter()
+            .map(|req| &req.named_digest.digest)
+            .chain(inlined_digests.iter())
+            .filter(|d| d.size_in_bytes > 0 && !is_streamed(*d))
+            .map(|d| tdigest_to(d.clone())),
+        max_total_batch_size,
+        |d| d.size_bytes,
+    );
+
+    // The server may still choose to send blobs uncompressed, so we always accept that.
+    let mut acceptable_compressors = vec![compressor::Value::Identity as i32];
+    if compressor != compressor::Value::Identity {
+        acceptable_compressors.push(compressor as i32);
+    }
+
+    let responses = futures::future::try_join_all(batches.into_iter().map(|digests| {
+        batch_download(BatchReadBlobsRequest {
+            instance_name: instance_name.to_owned(),
+            digests,
+            acceptable_compressors: acceptable_compressors.clone(),
+        })
+    }))
+    .await?;
+
+    let response = responses
+        .into_iter()
+        .flat_map(|r| r.responses)
+        .map(|r| {
+            check_status(r.status.unwrap_or_default())?;
+            let digest = tdigest_from(r.digest.context("Response digest not found.")?);
+            let data = decompress(r.compressor, r.data)
+                .with_context(|| format!("Error decompressing digest `{}`", digest))?;
+            anyhow::Ok((digest, data))
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+
+    let get = |digest: &TDigest| -> anyhow::Result<Vec<u8>> {
+        if digest.size_in_bytes == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(response
+            .get(digest)
+            .with_context(|| format!("Did not receive digest data for `{}`", digest))?
+            .clone())
+    };
+
+    let bytestream_download = &bytestream_download;
+
+    let inlined_blobs =
+        futures::future::try_join_all(inlined_digests.into_iter().map(|digest| async move {
+            let data = if is_streamed(&digest) {
+                let data: Vec<u8> = bytestream_download(&digest)
+                    .try_concat()
+                    .await
+                    .with_context(|| format!("Error reading digest `{}`", digest))?;
+                check_downloaded_size(&digest, data.len() as i64)?;
+                data
+            } else {
+                get(&digest)?
+            };
+
+            anyhow::Ok(InlinedDigestWithStatus {
+                digest,
+                status: tstatus_ok(),
+                blob: data,
+            })
+        }))
+        .await?;
+
+    let writes = file_digests.iter().map(|req| async {
+        let digest = &req.named_digest.digest;
+
+        let mut opts = OpenOptions::new();
+        opts.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            if req.is_executable {
+                opts.mode(0o755);
+            } else {
+                opts.mode(0o644);
+            }
+        }
+
+        async {
+            let mut file = opts
+                .open(&req.named_digest.name)
+                .await
+                .context("Error opening")?;
+
+            if is_streamed(digest) {
+                // Those can be large, so we write them straight to disk as we receive them.
+                let mut chunks = bytestream_download(digest);
+                let mut written = 0;
+                while let Some(chunk) = chunks.try_next().await.context("Error reading")? {
+                    file.write_all(&chunk).await.context("Error writing")?;
+                    written += chunk.len() as i64;
+                }
+                check_downloaded_size(digest, written)?;
+            } else {
+                let data = get(digest)?;
+                file.write_all(&data).await.context("Error writing")?;
+            }
+
+            file.flush().await.context("Error flushing")?;
+            anyhow::Ok(())
+        }
+        .await
+        .with_context(|| {
+            format!(
+                "Error writing digest `{}` to `{}`",
+                req.named_digest.digest, req.named_digest.name,
+            )
+        })
+    });
+
+    futures::future::try_join_all(writes).await?;
+
+    Ok(DownloadResponse {
+        inlined_blobs: Some(inlined_blobs),
+        directories: None,
+    })
+}
+
+fn check_downloaded_size(digest: &TDigest, size: i64) -> anyhow::Result<()> {
+    if size != digest.size_in_bytes {
+        return Err(anyhow::anyhow!(
+            "Received {} bytes for digest `{}`",
+            size,
+            digest
+        ));
+    }
+    Ok(())
+}
+
+fn with_internal_metadata<T>(t: T, metadata: RemoteExecutionMetadata) -> tonic::Request<T> {
+    // This is pretty ugly, but the protobuf spec that defines this is internal, so considering
     // field numbers need to be stable anyway (= low risk), and this is not used in prod (= low
     // impact if this goes wrong), we just inline it here. This is a small hack that lets us use
     // our internal RE using this GRPC client for testing.
@@ -855,6 +1746,7 @@ mod tests {
     use re_grpc_proto::build::bazel::remote::execution::v2::batch_read_blobs_response;
 
     use super::*;
+    use crate::InlinedBlobWithDigest;
     use crate::NamedDigest;
     use crate::NamedDigestWithPermissions;
 
@@ -920,12 +1812,19 @@ mod tests {
             ],
         };
 
-        download_impl(req, |req| async move {
-            assert_eq!(req.digests.len(), 2);
-            assert_eq!(req.digests[0], tdigest_to(digest1.clone()));
-            assert_eq!(req.digests[1], tdigest_to(digest2.clone()));
-            Ok(res)
-        })
+        download_impl(
+            req,
+            "",
+            MAX_TOTAL_BATCH_SIZE_BYTES,
+            compressor::Value::Identity,
+            |req| {
+                assert_eq!(req.digests.len(), 2);
+                assert_eq!(req.digests[0], tdigest_to(digest1.clone()));
+                assert_eq!(req.digests[1], tdigest_to(digest2.clone()));
+                futures::future::ready(Ok(res.clone()))
+            },
+            |_| panic!("Unexpected ByteStream read"),
+        )
         .await?;
 
         assert_eq!(tokio::fs::read(&path1).await?, vec![1, 2, 3]);
@@ -982,12 +1881,19 @@ mod tests {
             ],
         };
 
-        let res = download_impl(req, |req| async move {
-            assert_eq!(req.digests.len(), 2);
-            assert_eq!(req.digests[0], tdigest_to(digest1.clone()));
-            assert_eq!(req.digests[1], tdigest_to(digest2.clone()));
-            Ok(res)
-        })
+        let res = download_impl(
+            req,
+            "",
+            MAX_TOTAL_BATCH_SIZE_BYTES,
+            compressor::Value::Identity,
+            |req| {
+                assert_eq!(req.digests.len(), 2);
+                assert_eq!(req.digests[0], tdigest_to(digest1.clone()));
+                assert_eq!(req.digests[1], tdigest_to(digest2.clone()));
+                futures::future::ready(Ok(res.clone()))
+            },
+            |_| panic!("Unexpected ByteStream read"),
+        )
         .await?;
 
         let inlined_blobs = res.inlined_blobs.unwrap();
@@ -1018,10 +1924,17 @@ mod tests {
 
         let res = BatchReadBlobsResponse { responses: vec![] };
 
-        let res = download_impl(req, |req| async move {
-            assert_eq!(req.digests.len(), 0);
-            Ok(res)
-        })
+        let res = download_impl(
+            req,
+            "",
+            MAX_TOTAL_BATCH_SIZE_BYTES,
+            compressor::Value::Identity,
+            |req| {
+                assert_eq!(req.digests.len(), 0);
+                futures::future::ready(Ok(res.clone()))
+            },
+            |_| panic!("Unexpected ByteStream read"),
+        )
         .await?;
 
         let inlined_blobs = res.inlined_blobs.unwrap();
@@ -1034,6 +1947,631 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_download_bytestream() -> anyhow::Result<()> {
+        let work = tempfile::tempdir()?;
+
+        let path = work.path().join("path");
+        let path = path.to_str().context("tempdir is not utf8")?;
+
+        let small = TDigest {
+            hash: "aa".to_owned(),
+            size_in_bytes: 2,
+            ..Default::default()
+        };
+
+        let large = TDigest {
+            hash: "bb".to_owned(),
+            size_in_bytes: 6,
+            ..Default::default()
+        };
+
+        let req = DownloadRequest {
+            inlined_digests: Some(vec![small.clone(), large.clone()]),
+            file_digests: Some(vec![NamedDigestWithPermissions {
+                named_digest: NamedDigest {
+                    name: path.to_owned(),
+                    digest: large.clone(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        let res = BatchReadBlobsResponse {
+            responses: vec![batch_read_blobs_response::Response {
+                digest: Some(tdigest_to(small.clone())),
+                data: vec![1, 2],
+                ..Default::default()
+            }],
+        };
+
+        let res = download_impl(
+            req,
+            "",
+            4,
+            compressor::Value::Identity,
+            |req| {
+                assert_eq!(req.digests, vec![tdigest_to(small.clone())]);
+                futures::future::ready(Ok(res.clone()))
+            },
+            |digest| {
+                assert_eq!(digest, &large);
+                futures::stream::iter(vec![Ok(vec![1, 2, 3, 4]), Ok(vec![5, 6])]).boxed()
+            },
+        )
+        .await?;
+
+        let inlined_blobs = res.inlined_blobs.unwrap();
+        assert_eq!(inlined_blobs.len(), 2);
+        assert_eq!(inlined_blobs[0].blob, vec![1, 2]);
+        assert_eq!(inlined_blobs[1].blob, vec![1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(tokio::fs::read(&path).await?, vec![1, 2, 3, 4, 5, 6]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_bytestream_truncated() -> anyhow::Result<()> {
+        let digest = TDigest {
+            hash: "aa".to_owned(),
+            size_in_bytes: 6,
+            ..Default::default()
+        };
+
+        let req = DownloadRequest {
+            inlined_digests: Some(vec![digest]),
+            ..Default::default()
+        };
+
+        let res = download_impl(
+            req,
+            "",
+            4,
+            compressor::Value::Identity,
+            |_| futures::future::ready(Ok(BatchReadBlobsResponse::default())),
+            |_| futures::stream::iter(vec![Ok(vec![1, 2, 3, 4])]).boxed(),
+        )
+        .await;
+
+        assert!(res.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_batches_and_streams() -> anyhow::Result<()> {
+        let blob = |hash: &str, size: usize| InlinedBlobWithDigest {
+            digest: TDigest {
+                hash: hash.to_owned(),
+                size_in_bytes: size as i64,
+                ..Default::default()
+            },
+            blob: vec![0; size],
+            ..Default::default()
+        };
+
+        let req = UploadRequest {
+            inlined_blobs_with_digest: Some(vec![
+                blob("aa", 3),
+                blob("bb", 3),
+                blob("cc", 10),
+                blob("dd", 1),
+            ]),
+            ..Default::default()
+        };
+
+        let batches = Mutex::new(Vec::new());
+        let streams = Mutex::new(Vec::new());
+
+        upload_impl(
+            req,
+            "",
+            4,
+            compressor::Value::Identity,
+            |_| panic!("Unexpected FindMissingBlobs"),
+            |req| {
+                batches
+                    .lock()
+                    .unwrap()
+                    .push(req.requests.into_map(|r| r.digest.unwrap().hash).join(","));
+                futures::future::ready(Ok(BatchUpdateBlobsResponse::default()))
+            },
+            |digest, upload| {
+                streams.lock().unwrap().push(digest.hash);
+                assert!(matches!(upload, CasUpload::Inlined(data) if data.len() == 10));
+                futures::future::ready(Ok(()))
+            },
+        )
+        .await?;
+
+        let mut batches = batches.into_inner().unwrap();
+        batches.sort();
+        assert_eq!(batches, vec!["aa".to_owned(), "bb,dd".to_owned()]);
+        assert_eq!(streams.into_inner().unwrap(), vec!["cc".to_owned()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_only_missing() -> anyhow::Result<()> {
+        let blob = |hash: &str| InlinedBlobWithDigest {
+            digest: TDigest {
+                hash: hash.to_owned(),
+                size_in_bytes: 1,
+                ..Default::default()
+            },
+            blob: vec![0],
+            ..Default::default()
+        };
+
+        let req = UploadRequest {
+            inlined_blobs_with_digest: Some(vec![blob("aa"), blob("bb"), blob("cc")]),
+            upload_only_missing: true,
+            ..Default::default()
+        };
+
+        let uploaded = Mutex::new(Vec::new());
+
+        upload_impl(
+            req,
+            "instance",
+            100,
+            compressor::Value::Identity,
+            |req| {
+                assert_eq!(req.instance_name, "instance");
+                assert_eq!(req.blob_digests.len(), 3);
+                futures::future::ready(Ok(GFindMissingBlobsResponse {
+                    missing_blob_digests: vec![tdigest_to(blob("bb").digest)],
+                }))
+            },
+            |req| {
+                assert_eq!(req.instance_name, "instance");
+                uploaded
+                    .lock()
+                    .unwrap()
+                    .extend(req.requests.into_iter().map(|r| r.digest.unwrap().hash));
+                futures::future::ready(Ok(BatchUpdateBlobsResponse::default()))
+            },
+            |_, _| panic!("Unexpected ByteStream write"),
+        )
+        .await?;
+
+        assert_eq!(uploaded.into_inner().unwrap(), vec!["bb".to_owned()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_compressed() -> anyhow::Result<()> {
+        let digest1 = TDigest {
+            hash: "aa".to_owned(),
+            size_in_bytes: 3,
+            ..Default::default()
+        };
+
+        let digest2 = TDigest {
+            hash: "bb".to_owned(),
+            size_in_bytes: 3,
+            ..Default::default()
+        };
+
+        let req = DownloadRequest {
+            inlined_digests: Some(vec![digest1.clone(), digest2.clone()]),
+            ..Default::default()
+        };
+
+        // The server is free to pick any of the compressors we accept for each blob.
+        let res = BatchReadBlobsResponse {
+            responses: vec![
+                batch_read_blobs_response::Response {
+                    digest: Some(tdigest_to(digest1.clone())),
+                    data: compress(compressor::Value::Zstd, vec![1, 2, 3])?,
+                    compressor: compressor::Value::Zstd as i32,
+                    ..Default::default()
+                },
+                batch_read_blobs_response::Response {
+                    digest: Some(tdigest_to(digest2.clone())),
+                    data: vec![4, 5, 6],
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let res = download_impl(
+            req,
+            "",
+            MAX_TOTAL_BATCH_SIZE_BYTES,
+            compressor::Value::Zstd,
+            |req| {
+                assert_eq!(
+                    req.acceptable_compressors,
+                    vec![
+                        compressor::Value::Identity as i32,
+                        compressor::Value::Zstd as i32
+                    ]
+                );
+                futures::future::ready(Ok(res.clone()))
+            },
+            |_| panic!("Unexpected ByteStream read"),
+        )
+        .await?;
+
+        let inlined_blobs = res.inlined_blobs.unwrap();
+        assert_eq!(inlined_blobs[0].blob, vec![1, 2, 3]);
+        assert_eq!(inlined_blobs[1].blob, vec![4, 5, 6]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_compressed() -> anyhow::Result<()> {
+        let req = UploadRequest {
+            inlined_blobs_with_digest: Some(vec![InlinedBlobWithDigest {
+                digest: TDigest {
+                    hash: "aa".to_owned(),
+                    size_in_bytes: 3,
+                    ..Default::default()
+                },
+                blob: vec![1, 2, 3],
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        let uploaded = Mutex::new(Vec::new());
+
+        upload_impl(
+            req,
+            "",
+            100,
+            compressor::Value::Zstd,
+            |_| panic!("Unexpected FindMissingBlobs"),
+            |req| {
+                uploaded.lock().unwrap().extend(req.requests);
+                futures::future::ready(Ok(BatchUpdateBlobsResponse::default()))
+            },
+            |_, _| panic!("Unexpected ByteStream write"),
+        )
+        .await?;
+
+        let uploaded = uploaded.into_inner().unwrap();
+        assert_eq!(uploaded.len(), 1);
+        assert_eq!(uploaded[0].compressor, compressor::Value::Zstd as i32);
+        assert_eq!(
+            decompress(uploaded[0].compressor, uploaded[0].data.clone())?,
+            vec![1, 2, 3]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_digests_ttl() -> anyhow::Result<()> {
+        let digest = |hash: &str| TDigest {
+            hash: hash.to_owned(),
+            size_in_bytes: 1,
+            ..Default::default()
+        };
+
+        let digest_ttls = DigestTtls::new(Duration::from_secs(100));
+        let now = Instant::now();
+        digest_ttls.record_present("", &[digest("aa")], now);
+
+        let res = get_digests_ttl_impl(
+            GetDigestsTtlRequest {
+                digests: vec![digest("aa"), digest("bb"), digest("cc")],
+                ..Default::default()
+            },
+            "",
+            100,
+            &digest_ttls,
+            now + Duration::from_secs(10),
+            |req| {
+                // We already know about `aa`.
+                assert_eq!(
+                    req.blob_digests,
+                    vec![tdigest_to(digest("bb")), tdigest_to(digest("cc"))]
+                );
+                futures::future::ready(Ok(GFindMissingBlobsResponse {
+                    missing_blob_digests: vec![tdigest_to(digest("cc"))],
+                }))
+            },
+        )
+        .await?;
+
+        assert_eq!(
+            res.digests_with_ttl
+                .map(|d| (d.digest.hash.as_str(), d.ttl)),
+            vec![("aa", 90), ("bb", 100), ("cc", 0)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_capabilities_batch_size() {
+        let caps = RECapabilities::new(None, None, CasCompression::None);
+        assert_eq!(caps.max_total_batch_size, MAX_TOTAL_BATCH_SIZE_BYTES);
+
+        let caps = RECapabilities::new(
+            Some(CacheCapabilities {
+                max_batch_total_size_bytes: 1000,
+                ..Default::default()
+            }),
+            None,
+            CasCompression::None,
+        );
+        assert_eq!(caps.max_total_batch_size, 1000);
+
+        let caps = RECapabilities::new(
+            Some(CacheCapabilities {
+                max_batch_total_size_bytes: i64::MAX,
+                ..Default::default()
+            }),
+            None,
+            CasCompression::None,
+        );
+        assert_eq!(caps.max_total_batch_size, MAX_TOTAL_BATCH_SIZE_BYTES);
+    }
+
+    #[test]
+    fn test_capabilities_digest_function() {
+        // Servers that don't tell us anything get the benefit of the doubt.
+        let caps = RECapabilities::new(None, None, CasCompression::None);
+        assert!(caps.check_digest_function("SHA256").is_ok());
+
+        let caps = RECapabilities::new(
+            Some(CacheCapabilities {
+                digest_functions: vec![digest_function::Value::Sha256 as i32],
+                ..Default::default()
+            }),
+            Some(ExecutionCapabilities {
+                digest_function: digest_function::Value::Sha1 as i32,
+                ..Default::default()
+            }),
+            CasCompression::None,
+        );
+        assert!(caps.check_digest_function("SHA256").is_ok());
+        assert!(caps.check_digest_function("SHA1").is_ok());
+        assert!(caps.check_digest_function("BLAKE3").is_err());
+        assert!(caps.check_digest_function("BLAKE3-KEYED").is_err());
+    }
+
+    #[test]
+    fn test_capabilities_compression() {
+        let caps = RECapabilities::new(None, None, CasCompression::Zstd);
+        assert_eq!(caps.compressor, compressor::Value::Identity);
+        assert_eq!(caps.batch_update_compressor, compressor::Value::Identity);
+
+        let caps = RECapabilities::new(
+            Some(CacheCapabilities {
+                supported_compressors: vec![compressor::Value::Zstd as i32],
+                ..Default::default()
+            }),
+            None,
+            CasCompression::Zstd,
+        );
+        assert_eq!(caps.compressor, compressor::Value::Zstd);
+        assert_eq!(caps.batch_update_compressor, compressor::Value::Identity);
+
+        let caps = RECapabilities::new(
+            Some(CacheCapabilities {
+                supported_compressors: vec![compressor::Value::Zstd as i32],
+                supported_batch_update_compressors: vec![compressor::Value::Zstd as i32],
+                ..Default::default()
+            }),
+            None,
+            CasCompression::Zstd,
+        );
+        assert_eq!(caps.compressor, compressor::Value::Zstd);
+        assert_eq!(caps.batch_update_compressor, compressor::Value::Zstd);
+
+        let caps = RECapabilities::new(
+            Some(CacheCapabilities {
+                supported_compressors: vec![compressor::Value::Zstd as i32],
+                ..Default::default()
+            }),
+            None,
+            CasCompression::Deflate,
+        );
+        assert_eq!(caps.compressor, compressor::Value::Identity);
+    }
+
+    #[test]
+    fn test_supported_priority() {
+        let ranges = vec![PriorityRange {
+            min_priority: -10,
+            max_priority: 10,
+        }];
+        assert_eq!(supported_priority(5, &ranges), 5);
+        assert_eq!(supported_priority(i32::MAX, &ranges), 0);
+        assert_eq!(supported_priority(5, &[]), 0);
+    }
+
+    #[tokio::test]
+    async fn test_send_bytestream_chunks() -> anyhow::Result<()> {
+        let size = BYTESTREAM_CHUNK_SIZE * 2 + 10;
+        let upload = CasUpload::Inlined(vec![7; size as usize].into());
+
+        let (tx, rx) = mpsc::channel(10);
+        let sent = send_bytestream_chunks(tx, "name", upload.open_at(0).await?, 0).await?;
+        assert_eq!(sent, Some(size));
+        let requests = rx.collect::<Vec<_>>().await;
+
+        assert_eq!(
+            requests.map(|r| (
+                r.resource_name.as_str(),
+                r.write_offset,
+                r.data.len() as i64,
+                r.finish_write
+            )),
+            vec![
+                ("name", 0, BYTESTREAM_CHUNK_SIZE, false),
+                ("", BYTESTREAM_CHUNK_SIZE, BYTESTREAM_CHUNK_SIZE, false),
+                ("", BYTESTREAM_CHUNK_SIZE * 2, 10, true),
+            ]
+        );
+
+        // Resuming from an offset only sends what is left.
+        let (tx, rx) = mpsc::channel(10);
+        let sent =
+            send_bytestream_chunks(tx, "name", upload.open_at(size - 5).await?, size - 5).await?;
+        assert_eq!(sent, Some(size));
+        let requests = rx.collect::<Vec<_>>().await;
+
+        assert_eq!(
+            requests.map(|r| (
+                r.resource_name.as_str(),
+                r.write_offset,
+                r.data.len() as i64,
+                r.finish_write
+            )),
+            vec![("name", size - 5, 5, true)]
+        );
+
+        // When the data is a multiple of the chunk size, we finish with an empty chunk.
+        let (tx, rx) = mpsc::channel(10);
+        let data = vec![7; BYTESTREAM_CHUNK_SIZE as usize];
+        send_bytestream_chunks(tx, "name", std::io::Cursor::new(data), 0).await?;
+        let requests = rx.collect::<Vec<_>>().await;
+
+        assert_eq!(
+            requests.map(|r| (r.write_offset, r.data.len() as i64, r.finish_write)),
+            vec![
+                (0, BYTESTREAM_CHUNK_SIZE, false),
+                (BYTESTREAM_CHUNK_SIZE, 0, true),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cas_upload_open_compressed() -> anyhow::Result<()> {
+        let data = b"hello hello hello hello".repeat(100);
+        let upload = CasUpload::Inlined(data.clone().into());
+
+        let mut compressed = Vec::new();
+        upload
+            .open(compressor::Value::Zstd, 0)
+            .await?
+            .read_to_end(&mut compressed)
+            .await?;
+        assert_eq!(
+            decompress(compressor::Value::Zstd as i32, compressed.clone())?,
+            data
+        );
+
+        // Resuming skips over the compressed data the server already has.
+        let mut rest = Vec::new();
+        upload
+            .open(compressor::Value::Zstd, 10)
+            .await?
+            .read_to_end(&mut rest)
+            .await?;
+        assert_eq!(rest, compressed[10..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_write_complete() {
+        assert!(is_write_complete(
+            compressor::Value::Identity,
+            10,
+            Some(10),
+            10
+        ));
+        assert!(is_write_complete(compressor::Value::Identity, 10, None, 10));
+        assert!(!is_write_complete(
+            compressor::Value::Identity,
+            10,
+            Some(10),
+            5
+        ));
+        assert!(is_write_complete(compressor::Value::Zstd, 10, Some(4), 4));
+        assert!(is_write_complete(compressor::Value::Zstd, 10, None, -1));
+        assert!(!is_write_complete(compressor::Value::Zstd, 10, Some(4), 2));
+    }
+
+    #[test]
+    fn test_bytestream_resource_names() {
+        let digest = TDigest {
+            hash: "aa".to_owned(),
+            size_in_bytes: 3,
+            ..Default::default()
+        };
+
+        let identity = compressor::Value::Identity;
+        let zstd = compressor::Value::Zstd;
+
+        assert_eq!(
+            bytestream_read_resource_name("", identity, &digest),
+            "blobs/aa/3"
+        );
+        assert_eq!(
+            bytestream_read_resource_name("instance", identity, &digest),
+            "instance/blobs/aa/3"
+        );
+        assert_eq!(
+            bytestream_read_resource_name("instance", zstd, &digest),
+            "instance/compressed-blobs/zstd/aa/3"
+        );
+        assert_eq!(
+            bytestream_write_resource_name("", identity, "uuid", &digest),
+            "uploads/uuid/blobs/aa/3"
+        );
+        assert_eq!(
+            bytestream_write_resource_name("instance", identity, "uuid", &digest),
+            "instance/uploads/uuid/blobs/aa/3"
+        );
+        assert_eq!(
+            bytestream_write_resource_name("instance", zstd, "uuid", &digest),
+            "instance/uploads/uuid/compressed-blobs/zstd/aa/3"
+        );
+    }
+
+    #[test]
+    fn test_instance_names() -> anyhow::Result<()> {
+        let instance_names = InstanceNames::new(&Buck2OssReConfiguration {
+            instance_name: Some("main".to_owned()),
+            use_case_instance_names: vec!["gpu=main/gpu".parse()?],
+            ..Default::default()
+        });
+
+        let metadata = |use_case: &str| RemoteExecutionMetadata {
+            use_case_id: use_case.to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(instance_names.for_metadata(&metadata("gpu")), "main/gpu");
+        assert_eq!(
+            instance_names.for_metadata(&metadata("buck2-default")),
+            "main"
+        );
+        assert_eq!(
+            InstanceNames::new(&Default::default()).for_metadata(&metadata("gpu")),
+            ""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_into_batches() {
+        assert_eq!(
+            split_into_batches(vec![1, 2, 3, 5, 1], 5, |x| *x),
+            vec![vec![1, 2], vec![3], vec![5], vec![1]]
+        );
+        assert_eq!(
+            split_into_batches(Vec::<i64>::new(), 5, |x| *x),
+            Vec::<Vec<i64>>::new()
+        );
+    }
+
     #[test]
     fn test_substitute_env_vars() {
         let getter = |s: &str| match s {
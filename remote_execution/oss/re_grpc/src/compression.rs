@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Compression of blobs transferred to and from the CAS, as described by the `Compressor` message
+//! of the REAPI.
+
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::Context;
+use async_compression::tokio::bufread::DeflateDecoder;
+use async_compression::tokio::bufread::DeflateEncoder;
+use async_compression::tokio::bufread::ZstdDecoder;
+use async_compression::tokio::bufread::ZstdEncoder;
+use buck2_re_configuration::CasCompression;
+use futures::stream::BoxStream;
+use futures::stream::StreamExt;
+use re_grpc_proto::build::bazel::remote::execution::v2::compressor;
+use tokio::io::AsyncBufRead;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+use tokio_util::io::StreamReader;
+
+/// The compressor corresponding to what is set in the buckconfig.
+pub(crate) fn configured_compressor(compression: CasCompression) -> compressor::Value {
+    match compression {
+        CasCompression::None => compressor::Value::Identity,
+        CasCompression::Zstd => compressor::Value::Zstd,
+        CasCompression::Deflate => compressor::Value::Deflate,
+    }
+}
+
+/// The path of blobs in ByteStream resource names. Compressed blobs live under
+/// `compressed-blobs/{compressor}`, but are still addressed by their uncompressed digest.
+pub(crate) fn blobs_path(compressor: compressor::Value) -> &'static str {
+    match compressor {
+        compressor::Value::Identity => "blobs",
+        compressor::Value::Zstd => "compressed-blobs/zstd",
+        compressor::Value::Deflate => "compressed-blobs/deflate",
+    }
+}
+
+pub(crate) fn compress(compressor: compressor::Value, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    match compressor {
+        compressor::Value::Identity => Ok(data),
+        compressor::Value::Zstd => Ok(zstd::bulk::compress(&data, 0)?),
+        compressor::Value::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data)?;
+            Ok(encoder.finish()?)
+        }
+    }
+}
+
+/// Decompress `data`, using the compressor the server told us it used (which is a `i32` in the
+/// protobuf messages).
+pub(crate) fn decompress(compressor: i32, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    match compressor::Value::from_i32(compressor) {
+        Some(compressor::Value::Identity) => Ok(data),
+        Some(compressor::Value::Zstd) => {
+            Ok(zstd::stream::decode_all(&data[..]).context("Invalid zstd data")?)
+        }
+        Some(compressor::Value::Deflate) => {
+            let mut out = Vec::new();
+            flate2::read::DeflateDecoder::new(&data[..])
+                .read_to_end(&mut out)
+                .context("Invalid deflate data")?;
+            Ok(out)
+        }
+        None => Err(anyhow::anyhow!("Unknown compressor: {}", compressor)),
+    }
+}
+
+/// Compress the contents of `reader` as we read it.
+pub(crate) fn compress_reader(
+    compressor: compressor::Value,
+    reader: impl AsyncBufRead + Send + Unpin + 'static,
+) -> Box<dyn AsyncRead + Send + Unpin> {
+    match compressor {
+        compressor::Value::Identity => Box::new(reader),
+        compressor::Value::Zstd => Box::new(ZstdEncoder::new(reader)),
+        compressor::Value::Deflate => Box::new(DeflateEncoder::new(reader)),
+    }
+}
+
+/// Decompress a stream of chunks as we receive them.
+pub(crate) fn decompress_stream(
+    compressor: compressor::Value,
+    chunks: BoxStream<'static, anyhow::Result<Vec<u8>>>,
+) -> BoxStream<'static, anyhow::Result<Vec<u8>>> {
+    let decoder: Box<dyn AsyncRead + Send + Unpin> = match compressor {
+        compressor::Value::Identity => return chunks,
+        compressor::Value::Zstd => Box::new(ZstdDecoder::new(stream_reader(chunks))),
+        compressor::Value::Deflate => Box::new(DeflateDecoder::new(stream_reader(chunks))),
+    };
+
+    ReaderStream::new(decoder)
+        .map(|chunk| anyhow::Ok(chunk?.to_vec()))
+        .boxed()
+}
+
+fn stream_reader(
+    chunks: BoxStream<'static, anyhow::Result<Vec<u8>>>,
+) -> impl AsyncBufRead + Send + Unpin + 'static {
+    StreamReader::new(chunks.map(|chunk| {
+        chunk
+            .map(bytes::Bytes::from)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream::TryStreamExt;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    const COMPRESSORS: &[compressor::Value] = &[
+        compressor::Value::Identity,
+        compressor::Value::Zstd,
+        compressor::Value::Deflate,
+    ];
+
+    #[test]
+    fn test_compress_roundtrip() -> anyhow::Result<()> {
+        let data = b"hello hello hello hello".repeat(100);
+
+        for compressor in COMPRESSORS {
+            let compressed = compress(*compressor, data.clone())?;
+            assert_eq!(decompress(*compressor as i32, compressed)?, data);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compress_stream_roundtrip() -> anyhow::Result<()> {
+        let data = b"hello hello hello hello".repeat(100);
+
+        for compressor in COMPRESSORS {
+            let mut compressed = Vec::new();
+            compress_reader(*compressor, std::io::Cursor::new(data.clone()))
+                .read_to_end(&mut compressed)
+                .await?;
+
+            // The streaming and bulk variants must be compatible.
+            assert_eq!(decompress(*compressor as i32, compressed.clone())?, data);
+
+            let chunks = compressed
+                .chunks(7)
+                .map(|c| anyhow::Ok(c.to_vec()))
+                .collect::<Vec<_>>();
+            let decompressed: Vec<u8> =
+                decompress_stream(*compressor, futures::stream::iter(chunks).boxed())
+                    .try_concat()
+                    .await?;
+            assert_eq!(decompressed, data);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_unknown() {
+        assert!(decompress(100, vec![1, 2, 3]).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! The REAPI does not tell clients when blobs will be evicted from the CAS, so we model it here:
+//! we assume a blob stays around for a fixed TTL after the server last told us it had it. Servers
+//! are expected to extend the lifetime of blobs they report as present in `FindMissingBlobs`, or
+//! that they just accepted in an upload.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::digest::TDigest;
+
+/// How many digests we remember per instance before we start dropping the oldest ones.
+const MAX_TRACKED_DIGESTS: usize = 1_000_000;
+
+pub(crate) struct DigestTtls {
+    ttl: Duration,
+    max_tracked: usize,
+    /// When the server last told us it had each blob, per instance name.
+    present: Mutex<HashMap<String, PresentDigests>>,
+}
+
+#[derive(Default)]
+struct PresentDigests {
+    seen: HashMap<TDigest, Instant>,
+    /// Digests in the order they were recorded. A digest that was recorded again since has a
+    /// stale entry here, which we recognize by its `Instant` not matching `seen`.
+    order: VecDeque<(TDigest, Instant)>,
+}
+
+impl PresentDigests {
+    fn insert(&mut self, digest: &TDigest, now: Instant, max_tracked: usize) {
+        self.seen.insert(digest.clone(), now);
+        self.order.push_back((digest.clone(), now));
+
+        while self.seen.len() > max_tracked {
+            let Some((oldest, seen)) = self.order.pop_front() else {
+                break;
+            };
+            if self.seen.get(&oldest) == Some(&seen) {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        // Drop stale entries once they make up most of `order`, so it doesn't grow without
+        // bound when the same digests keep getting recorded. This is amortized O(1) per insert.
+        if self.order.len() > 2 * max_tracked {
+            let seen = &self.seen;
+            self.order.retain(|(d, at)| seen.get(d) == Some(at));
+        }
+    }
+}
+
+impl DigestTtls {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self::with_max_tracked(ttl, MAX_TRACKED_DIGESTS)
+    }
+
+    fn with_max_tracked(ttl: Duration, max_tracked: usize) -> Self {
+        Self {
+            ttl,
+            max_tracked,
+            present: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The TTL of a blob the server just told us it has, in seconds.
+    pub(crate) fn ttl_secs(&self) -> i64 {
+        self.ttl.as_secs() as i64
+    }
+
+    pub(crate) fn record_present<'a>(
+        &self,
+        instance_name: &str,
+        digests: impl IntoIterator<Item = &'a TDigest>,
+        now: Instant,
+    ) {
+        let mut present = self.present.lock().unwrap_or_else(|e| e.into_inner());
+        let present = present.entry(instance_name.to_owned()).or_default();
+
+        for digest in digests {
+            present.insert(digest, now, self.max_tracked);
+        }
+    }
+
+    pub(crate) fn record_missing<'a>(
+        &self,
+        instance_name: &str,
+        digests: impl IntoIterator<Item = &'a TDigest>,
+    ) {
+        let mut present = self.present.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(present) = present.get_mut(instance_name) {
+            for digest in digests {
+                // Its entry in `order` is now stale and gets dropped when we get to it.
+                present.seen.remove(digest);
+            }
+        }
+    }
+
+    /// The remaining TTL of `digest`, in seconds, if we heard about it recently enough to not need
+    /// to ask the server again. Once a blob is past half its TTL we ask again, since that's also
+    /// what lets the server extend it.
+    pub(crate) fn cached_ttl(
+        &self,
+        instance_name: &str,
+        digest: &TDigest,
+        now: Instant,
+    ) -> Option<i64> {
+        let present = self.present.lock().unwrap_or_else(|e| e.into_inner());
+        let seen = *present.get(instance_name)?.seen.get(digest)?;
+        let remaining = self.ttl.checked_sub(now.saturating_duration_since(seen))?;
+        if remaining > self.ttl / 2 {
+            Some(remaining.as_secs() as i64)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(hash: &str) -> TDigest {
+        TDigest {
+            hash: hash.to_owned(),
+            size_in_bytes: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cached_ttl() {
+        let ttls = DigestTtls::new(Duration::from_secs(100));
+        let now = Instant::now();
+
+        assert_eq!(ttls.cached_ttl("", &digest("aa"), now), None);
+
+        ttls.record_present("", &[digest("aa")], now);
+        assert_eq!(ttls.cached_ttl("", &digest("aa"), now), Some(100));
+        assert_eq!(
+            ttls.cached_ttl("", &digest("aa"), now + Duration::from_secs(30)),
+            Some(70)
+        );
+        // Past half the TTL, we want to ask the server again.
+        assert_eq!(
+            ttls.cached_ttl("", &digest("aa"), now + Duration::from_secs(60)),
+            None
+        );
+
+        // Instances have separate CASes.
+        assert_eq!(ttls.cached_ttl("other", &digest("aa"), now), None);
+
+        ttls.record_missing("", &[digest("aa")]);
+        assert_eq!(ttls.cached_ttl("", &digest("aa"), now), None);
+    }
+
+    #[test]
+    fn test_evicts_oldest_first() {
+        let ttls = DigestTtls::with_max_tracked(Duration::from_secs(100), 2);
+        let now = Instant::now();
+
+        ttls.record_present("", &[digest("aa"), digest("bb")], now);
+        // Seeing "aa" again makes "bb" the oldest.
+        ttls.record_present("", &[digest("aa")], now + Duration::from_secs(1));
+        ttls.record_present("", &[digest("cc")], now + Duration::from_secs(2));
+
+        let later = now + Duration::from_secs(3);
+        assert_eq!(ttls.cached_ttl("", &digest("aa"), later), Some(98));
+        assert_eq!(ttls.cached_ttl("", &digest("bb"), later), None);
+        assert_eq!(ttls.cached_ttl("", &digest("cc"), later), Some(99));
+
+        // Recording the same digests over and over doesn't grow the queue without bound.
+        for i in 0..100 {
+            ttls.record_present("", &[digest("cc")], now + Duration::from_millis(i));
+        }
+        let present = ttls.present.lock().unwrap();
+        assert_eq!(present[""].seen.len(), 2);
+        assert!(present[""].order.len() <= 4);
+    }
+}
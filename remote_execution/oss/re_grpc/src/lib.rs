@@ -13,7 +13,9 @@
 #![cfg_attr(feature = "gazebo_lint", plugin(gazebo_lint))]
 
 mod client;
+mod compression;
 mod digest;
+mod digest_ttl;
 mod error;
 mod grpc;
 mod metadata;
@@ -22,14 +22,18 @@ use std::io;
 use std::iter;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use dupe::Dupe;
 use itertools::Either;
 use lsp_types::Diagnostic;
 use lsp_types::Url;
+use starlark::collections::SmallMap;
 use starlark::docs::get_registered_starlark_docs;
 use starlark::docs::render_docs_as_code;
 use starlark::docs::Doc;
 use starlark::docs::DocItem;
+use starlark::docs::Identifier;
 use starlark::environment::FrozenModule;
 use starlark::environment::Globals;
 use starlark::environment::Module;
@@ -66,6 +70,7 @@ pub(crate) struct Context {
     pub(crate) module: Option<Module>,
     pub(crate) builtin_docs: HashMap<LspUrl, String>,
     pub(crate) builtin_symbols: HashMap<String, LspUrl>,
+    pub(crate) global_docs: Arc<SmallMap<String, Doc>>,
 }
 
 /// The outcome of evaluating (checking, parsing or running) given starlark code.
@@ -126,6 +131,23 @@ impl Context {
             .into_iter()
             .map(|(u, ds)| (u, render_docs_as_code(&ds)))
             .collect();
+        let global_docs = globals
+            .member_documentation()
+            .into_iter()
+            .filter_map(|(name, item)| {
+                Some((
+                    name.clone(),
+                    Doc {
+                        id: Identifier {
+                            name,
+                            location: None,
+                        },
+                        item: item?,
+                        custom_attrs: HashMap::new(),
+                    },
+                ))
+            })
+            .collect();
 
         Ok(Self {
             mode,
@@ -134,6 +156,7 @@ impl Context {
             module,
             builtin_docs,
             builtin_symbols,
+            global_docs: Arc::new(global_docs),
         })
     }
 
@@ -252,8 +275,32 @@ impl Context {
         )
     }
 
+    /// Apply the automatic fixes for any lints in a file, returning whether it changed.
+    pub(crate) fn fix(&self, file: &Path) -> anyhow::Result<bool> {
+        let content = fs::read_to_string(file)?;
+        let module = AstModule::parse(&file.to_string_lossy(), content.clone(), &dialect())?;
+        let lints = module.lint(self.lint_globals().as_ref());
+        let fixed = module.apply_lint_fixes(&lints);
+        let changed = fixed != content;
+        if changed {
+            // Don't write a file we can't parse, leave it alone (with its lints) instead.
+            AstModule::parse(&file.to_string_lossy(), fixed.clone(), &dialect()).map_err(|e| {
+                anyhow::anyhow!("Applying the fixes produced invalid Starlark: {:#}", e)
+            })?;
+            fs::write(file, fixed)?;
+        }
+        Ok(changed)
+    }
+
     fn check(&self, module: &AstModule) -> impl Iterator<Item = EvalMessage> {
-        let globals = if self.prelude.is_empty() {
+        module
+            .lint(self.lint_globals().as_ref())
+            .into_iter()
+            .map(EvalMessage::from)
+    }
+
+    fn lint_globals(&self) -> Option<HashSet<String>> {
+        if self.prelude.is_empty() {
             None
         } else {
             let mut globals = HashSet::new();
@@ -268,12 +315,7 @@ impl Context {
             }
 
             Some(globals)
-        };
-
-        module
-            .lint(globals.as_ref())
-            .into_iter()
-            .map(EvalMessage::from)
+        }
     }
 }
 
@@ -345,6 +387,13 @@ impl LspContext for Context {
     ) -> anyhow::Result<Option<LspUrl>> {
         Ok(self.builtin_symbols.get(symbol).cloned())
     }
+
+    fn get_global_symbol_docs(
+        &self,
+        _current_file: &LspUrl,
+    ) -> anyhow::Result<Arc<SmallMap<String, Doc>>> {
+        Ok(self.global_docs.dupe())
+    }
 }
 
 pub(crate) fn globals() -> Globals {
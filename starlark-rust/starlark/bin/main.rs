@@ -26,6 +26,8 @@ use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -46,8 +48,10 @@ use starlark::errors::EvalMessage;
 use starlark::errors::EvalSeverity;
 use starlark::lsp;
 use starlark::read_line::ReadLine;
+use starlark::syntax::AstModule;
 use walkdir::WalkDir;
 
+use crate::eval::dialect;
 use crate::eval::ContextMode;
 use crate::types::LintMessage;
 
@@ -68,6 +72,8 @@ struct Args {
             "docs",
             "evaluate",
             "files",
+            "format",
+            "fix",
         ],
     )]
     lsp: bool,
@@ -85,6 +91,8 @@ struct Args {
             "prelude",
             "evaluate",
             "files",
+            "format",
+            "fix",
         ],
     )]
     dap: bool,
@@ -96,6 +104,20 @@ struct Args {
     )]
     check: bool,
 
+    #[arg(
+        long = "fix",
+        help = "Apply the automatic fixes for lints, then run checks and lints.",
+        conflicts_with_all = &["lsp", "dap", "evaluate"],
+    )]
+    fix: bool,
+
+    #[arg(
+        long = "format",
+        help = "Reformat the files in place.",
+        conflicts_with_all = &["lsp", "dap", "check", "fix", "json", "docs", "evaluate"],
+    )]
+    format: bool,
+
     #[arg(
         long = "json",
         help = "Show output as JSON lines.",
@@ -200,6 +222,19 @@ impl Stats {
     }
 }
 
+/// A message about a whole file, rather than a span in it.
+fn file_message(file: &Path, name: &str, description: &str) -> EvalMessage {
+    EvalMessage {
+        path: file.display().to_string(),
+        span: None,
+        severity: EvalSeverity::Advice,
+        name: name.to_owned(),
+        description: description.to_owned(),
+        full_error_with_span: None,
+        original: None,
+    }
+}
+
 fn drain(xs: impl Iterator<Item = EvalMessage>, json: bool, stats: &mut Stats) {
     for x in xs {
         stats.increment(x.severity);
@@ -217,6 +252,29 @@ fn drain(xs: impl Iterator<Item = EvalMessage>, json: bool, stats: &mut Stats) {
     }
 }
 
+/// Rewrite a file with its canonical formatting, returning whether it changed.
+fn format_file(file: &Path) -> anyhow::Result<bool> {
+    let content = fs::read_to_string(file)?;
+    let formatted =
+        AstModule::parse_with_comments(&file.to_string_lossy(), content.clone(), &dialect())?
+            .format();
+    let changed = formatted != content;
+    if changed {
+        fs::write(file, formatted)?;
+    }
+    Ok(changed)
+}
+
+fn report(stats: &Stats, json: bool) -> anyhow::Result<()> {
+    if !json {
+        println!("{}", stats);
+        if stats.error > 0 {
+            return Err(anyhow::anyhow!("Failed with {} errors", stats.error));
+        }
+    }
+    Ok(())
+}
+
 fn interactive(ctx: &Context) -> anyhow::Result<()> {
     let mut rl = ReadLine::new("STARLARK_RUST_HISTFILE")?;
     loop {
@@ -256,7 +314,7 @@ fn main() -> anyhow::Result<()> {
             .as_ref()
             .map_or("bzl", |x| x.strip_prefix('.').unwrap_or(x.as_str()));
         let mut ctx = Context::new(
-            if args.check {
+            if args.check || args.fix {
                 ContextMode::Check
             } else {
                 ContextMode::Run
@@ -294,6 +352,18 @@ fn main() -> anyhow::Result<()> {
                 }
                 ArgsDoc::Code => println!("{}", render_docs_as_code(&builtin)),
             };
+        } else if args.format {
+            let mut stats = Stats::default();
+            for file in expand_dirs(ext, args.files.clone()) {
+                stats.increment_file();
+                let message = match format_file(&file) {
+                    Ok(true) => Some(file_message(&file, "formatted", "Reformatted")),
+                    Ok(false) => None,
+                    Err(e) => Some(EvalMessage::from_anyhow(&file, &e)),
+                };
+                drain(message.into_iter(), args.json, &mut stats);
+            }
+            report(&stats, args.json)?;
         } else if is_interactive {
             interactive(&ctx)?;
         } else {
@@ -305,15 +375,23 @@ fn main() -> anyhow::Result<()> {
 
             for file in expand_dirs(ext, args.files.clone()) {
                 stats.increment_file();
+                if args.fix {
+                    let message = match ctx.fix(&file) {
+                        Ok(true) => Some(file_message(&file, "fixed", "Fixed lints")),
+                        Ok(false) => None,
+                        // The lints we couldn't fix are reported below.
+                        Err(e) => Some(file_message(
+                            &file,
+                            "not-fixed",
+                            &format!("Not fixing lints: {:#}", e),
+                        )),
+                    };
+                    drain(message.into_iter(), args.json, &mut stats);
+                }
                 drain(ctx.file(&file).messages, args.json, &mut stats);
             }
 
-            if !args.json {
-                println!("{}", stats);
-                if stats.error > 0 {
-                    return Err(anyhow::anyhow!("Failed with {} errors", stats.error));
-                }
-            }
+            report(&stats, args.json)?;
         }
     }
     Ok(())
@@ -0,0 +1,223 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Documentation for functions that are defined in a module, built directly from the AST
+//! so that it is available without evaluating the module.
+
+use std::collections::HashMap;
+
+use crate::analysis::definition::LspModule;
+use crate::codemap::ResolvedSpan;
+use crate::docs::Doc;
+use crate::docs::DocFunction;
+use crate::docs::DocItem;
+use crate::docs::DocParam;
+use crate::docs::DocString;
+use crate::docs::DocStringKind;
+use crate::docs::DocType;
+use crate::docs::Identifier;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstNoPayload;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::DefP;
+use crate::syntax::ast::ParameterP;
+use crate::syntax::ast::StmtP;
+
+impl LspModule {
+    /// Get the documentation for a function defined at the top level of this module.
+    pub(crate) fn find_exported_function_doc(&self, name: &str) -> Option<Doc> {
+        self.ast
+            .top_level_statements()
+            .into_iter()
+            .find_map(|x| match &x.node {
+                StmtP::Def(def) if def.name.0 == name => Some(self.function_doc(def)),
+                _ => None,
+            })
+    }
+
+    /// Get the documentation for the function whose name is defined at `name_span`. This
+    /// includes functions that are nested within other functions.
+    pub(crate) fn find_function_doc_at(&self, name_span: ResolvedSpan) -> Option<Doc> {
+        fn visit<'a>(
+            module: &'a LspModule,
+            name_span: ResolvedSpan,
+            stmt: &'a AstStmt,
+            ret: &mut Option<&'a DefP<AstNoPayload>>,
+        ) {
+            if ret.is_some() {
+                return;
+            }
+            match &stmt.node {
+                StmtP::Def(def) if module.ast.codemap.resolve_span(def.name.span) == name_span => {
+                    *ret = Some(def);
+                }
+                x => x.visit_stmt(|x| visit(module, name_span, x, ret)),
+            }
+        }
+
+        let mut ret = None;
+        visit(self, name_span, &self.ast.statement, &mut ret);
+        ret.map(|def| self.function_doc(def))
+    }
+
+    fn function_doc(&self, def: &DefP<AstNoPayload>) -> Doc {
+        let doc_type = |typ: Option<&AstExpr>| {
+            typ.map(|typ| DocType {
+                raw_type: self.ast.codemap.source_span(typ.span).to_owned(),
+            })
+        };
+        let params = def
+            .params
+            .iter()
+            .map(|p| match &p.node {
+                ParameterP::Normal(name, typ) => DocParam::Arg {
+                    name: name.0.clone(),
+                    docs: None,
+                    typ: doc_type(typ.as_deref()),
+                    default_value: None,
+                },
+                ParameterP::WithDefaultValue(name, typ, default_value) => DocParam::Arg {
+                    name: name.0.clone(),
+                    docs: None,
+                    typ: doc_type(typ.as_deref()),
+                    default_value: Some(
+                        self.ast.codemap.source_span(default_value.span).to_owned(),
+                    ),
+                },
+                ParameterP::NoArgs => DocParam::NoArgs,
+                ParameterP::Args(name, typ) => DocParam::Args {
+                    name: name.0.clone(),
+                    docs: None,
+                    typ: doc_type(typ.as_deref()),
+                },
+                ParameterP::KwArgs(name, typ) => DocParam::Kwargs {
+                    name: name.0.clone(),
+                    docs: None,
+                    typ: doc_type(typ.as_deref()),
+                },
+            })
+            .collect();
+        let docstring = DocString::extract_raw_starlark_docstring(&*def.body);
+
+        Doc {
+            id: Identifier {
+                name: def.name.0.clone(),
+                location: None,
+            },
+            item: DocItem::Function(DocFunction::from_docstring(
+                DocStringKind::Starlark,
+                params,
+                doc_type(def.return_type.as_deref()),
+                docstring.as_deref(),
+            )),
+            custom_attrs: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use textwrap::dedent;
+
+    use crate::analysis::definition::LspModule;
+    use crate::codemap::ResolvedSpan;
+    use crate::docs::DocItem;
+    use crate::docs::DocParam;
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn module(program: &str) -> LspModule {
+        LspModule::new(AstModule::parse("foo.star", dedent(program), &Dialect::Extended).unwrap())
+    }
+
+    #[test]
+    fn test_exported_function_doc() {
+        let module = module(
+            r#"
+def foo(x, y: str.type = "y", *args, **kwargs) -> int.type:
+    """
+    Summary line.
+
+    Args:
+        x: The x value
+    """
+    pass
+"#,
+        );
+        let doc = module.find_exported_function_doc("foo").unwrap();
+        assert_eq!("foo", doc.id.name);
+        let f = match doc.item {
+            DocItem::Function(f) => f,
+            _ => panic!("Expected a function"),
+        };
+        assert_eq!("Summary line.", f.docs.unwrap().summary);
+        assert_eq!("int.type", f.ret.typ.unwrap().raw_type);
+        match &f.params[..] {
+            [
+                DocParam::Arg {
+                    name: x,
+                    docs: x_docs,
+                    ..
+                },
+                DocParam::Arg {
+                    name: y,
+                    typ: y_typ,
+                    default_value: y_default,
+                    ..
+                },
+                DocParam::Args { name: args, .. },
+                DocParam::Kwargs { name: kwargs, .. },
+            ] => {
+                assert_eq!("x", x);
+                assert_eq!("The x value", x_docs.as_ref().unwrap().summary);
+                assert_eq!("y", y);
+                assert_eq!("str.type", y_typ.as_ref().unwrap().raw_type);
+                assert_eq!(Some("\"y\""), y_default.as_deref());
+                assert_eq!("args", args);
+                assert_eq!("kwargs", kwargs);
+            }
+            params => panic!("Unexpected params {:?}", params),
+        }
+
+        assert!(module.find_exported_function_doc("bar").is_none());
+    }
+
+    #[test]
+    fn test_nested_function_doc() {
+        let module = module(
+            r#"
+def foo():
+    def bar(a):
+        """Bar docs"""
+        pass
+    return bar
+"#,
+        );
+        // Nested functions are not exported.
+        assert!(module.find_exported_function_doc("bar").is_none());
+
+        let doc = module
+            .find_function_doc_at(ResolvedSpan {
+                begin_line: 2,
+                begin_column: 8,
+                end_line: 2,
+                end_column: 11,
+            })
+            .unwrap();
+        assert_eq!("bar", doc.id.name);
+    }
+}
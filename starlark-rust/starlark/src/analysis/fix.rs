@@ -0,0 +1,118 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::analysis::types::Lint;
+use crate::codemap::Span;
+use crate::syntax::AstModule;
+
+/// Do two edits conflict. Insertions at the same point conflict, since we can't order them.
+fn overlaps(x: Span, y: Span) -> bool {
+    x.begin() == y.begin() || (x.begin() < y.end() && y.begin() < x.end())
+}
+
+impl AstModule {
+    /// Apply the [`edits`](Lint::edits) of lints produced by [`lint`](AstModule::lint) for
+    /// this module, returning the fixed source code. If the edits of several lints overlap
+    /// only the first is applied, so linting the result may find more to fix.
+    pub fn apply_lint_fixes(&self, lints: &[Lint]) -> String {
+        let mut edits = Vec::new();
+        for lint in lints {
+            let conflict = lint.edits.iter().any(|x| {
+                edits
+                    .iter()
+                    .any(|(span, _)| overlaps(x.location.span, *span))
+            });
+            if !conflict {
+                edits.extend(
+                    lint.edits
+                        .iter()
+                        .map(|x| (x.location.span, x.replacement.as_str())),
+                );
+            }
+        }
+        edits.sort_by_key(|(span, _)| span.begin());
+
+        let source = self.codemap.source();
+        let mut res = String::with_capacity(source.len());
+        let mut pos = 0;
+        for (span, replacement) in edits {
+            res.push_str(&source[pos..span.begin().get() as usize]);
+            res.push_str(replacement);
+            pos = span.end().get() as usize;
+        }
+        res.push_str(&source[pos..]);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn fix(program: &str) -> String {
+        let module = AstModule::parse("X", program.to_owned(), &Dialect::Extended).unwrap();
+        let globals = HashSet::from(["print".to_owned(), "any".to_owned(), "list".to_owned()]);
+        module.apply_lint_fixes(&module.lint(Some(&globals)))
+    }
+
+    #[test]
+    fn test_fix_unused_load() {
+        assert_eq!(
+            fix("load(\"a\", \"x\", \"y\", \"z\")\nprint(y)\n"),
+            "load(\"a\", \"y\")\nprint(y)\n"
+        );
+        assert_eq!(
+            fix("load(\"a\", \"x\", \"y\", z = \"w\")\nprint(x)\n"),
+            "load(\"a\", \"x\")\nprint(x)\n"
+        );
+        assert_eq!(
+            fix("load(\"a\", \"x\")\nload(\"b\", \"y\")\nprint(y)\n"),
+            "load(\"b\", \"y\")\nprint(y)\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_redundant_return() {
+        assert_eq!(
+            fix("def f():\n    print(1)\n    return\n\ndef g(x):\n    if x:\n        return\n"),
+            "def f():\n    print(1)\n\ndef g(x):\n    if x:\n        pass\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_performance() {
+        assert_eq!(
+            fix("def f(xs, **kwargs):\n    return (any(list(xs)), dict(**kwargs))\n"),
+            "def f(xs, **kwargs):\n    return (any(xs), dict(kwargs))\n"
+        );
+    }
+
+    #[test]
+    fn test_fix_underscore_definition() {
+        assert_eq!(
+            fix("def f(x):\n    def _g(y):\n        return y + x\n    return [_g(z) for z in x]\n"),
+            "def f(x):\n    def g(y):\n        return y + x\n    return [g(z) for z in x]\n"
+        );
+        // Don't rename if the new name is already taken.
+        let program =
+            "def f(x):\n    def _g(y):\n        return y + x\n    g = 1\n    return _g(g)\n";
+        assert_eq!(fix(program), program);
+    }
+}
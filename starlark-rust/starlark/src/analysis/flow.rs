@@ -17,6 +17,7 @@
 
 use thiserror::Error;
 
+use crate::analysis::types::statement_deletion;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::codemap::CodeMap;
@@ -233,22 +234,49 @@ fn reachable(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) ->
 // If you have a definition which ends with return, or a loop which ends with continue
 // that is a useless statement that just
 fn redundant(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
-    fn check(is_loop: bool, codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
+    // The fix is to delete the statement, or if it is alone in its block, replace it with `pass`.
+    fn redundant_lint(
+        codemap: &CodeMap,
+        x: &AstStmt,
+        alone: bool,
+        problem: FlowIssue,
+    ) -> LintT<FlowIssue> {
+        let lint = LintT::new(codemap, x.span, problem);
+        if alone {
+            lint.with_edit(x.span, "pass")
+        } else {
+            lint.with_edit(statement_deletion(codemap, x.span), "")
+        }
+    }
+
+    fn check(
+        is_loop: bool,
+        alone: bool,
+        codemap: &CodeMap,
+        x: &AstStmt,
+        res: &mut Vec<LintT<FlowIssue>>,
+    ) {
         match &**x {
-            Stmt::Continue if is_loop => {
-                res.push(LintT::new(codemap, x.span, FlowIssue::RedundantContinue))
-            }
-            Stmt::Return(None) if !is_loop => {
-                res.push(LintT::new(codemap, x.span, FlowIssue::RedundantReturn))
-            }
+            Stmt::Continue if is_loop => res.push(redundant_lint(
+                codemap,
+                x,
+                alone,
+                FlowIssue::RedundantContinue,
+            )),
+            Stmt::Return(None) if !is_loop => res.push(redundant_lint(
+                codemap,
+                x,
+                alone,
+                FlowIssue::RedundantReturn,
+            )),
             Stmt::Statements(xs) if !xs.is_empty() => {
-                check(is_loop, codemap, xs.last().unwrap(), res)
+                check(is_loop, xs.len() == 1, codemap, xs.last().unwrap(), res)
             }
-            Stmt::If(_, x) => check(is_loop, codemap, x, res),
+            Stmt::If(_, x) => check(is_loop, true, codemap, x, res),
             Stmt::IfElse(_, x_y) => {
                 let (x, y) = &**x_y;
-                check(is_loop, codemap, x, res);
-                check(is_loop, codemap, y, res);
+                check(is_loop, true, codemap, x, res);
+                check(is_loop, true, codemap, y, res);
             }
             _ => {}
         }
@@ -258,9 +286,9 @@ fn redundant(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
         match &**x {
             Stmt::For(_, over_body) => {
                 let (_over, body) = &**over_body;
-                check(true, codemap, body, res)
+                check(true, true, codemap, body, res)
             }
-            Stmt::Def(DefP { body, .. }) => check(false, codemap, body, res),
+            Stmt::Def(DefP { body, .. }) => check(false, true, codemap, body, res),
             _ => {}
         }
         // We always want to look inside everything for other types of violation
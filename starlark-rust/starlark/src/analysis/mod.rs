@@ -20,19 +20,26 @@ use std::collections::HashSet;
 pub use types::EvalMessage;
 pub use types::EvalSeverity;
 pub use types::Lint;
+pub use types::LintEdit;
 
+use crate::analysis::suppressions::Suppressions;
 use crate::analysis::types::LintT;
 use crate::syntax::AstModule;
 
 mod bind;
 pub(crate) mod definition;
+mod docs;
 mod dubious;
 mod exported;
 mod find_call_name;
+mod fix;
 mod flow;
 mod incompatible;
 mod names;
 mod performance;
+pub(crate) mod references;
+pub(crate) mod suppressions;
+pub(crate) mod symbols;
 mod types;
 mod underscore;
 
@@ -40,6 +47,7 @@ impl AstModule {
     /// Run a static linter over the module. If the complete set of global variables are known
     /// they can be passed as the `globals` argument, resulting in name-resolution lint errors.
     /// The precise checks run by the linter are not considered stable between versions.
+    /// Lints disabled by a `# starlark-lint-disable: <name>` comment are not returned.
     pub fn lint(&self, globals: Option<&HashSet<String>>) -> Vec<Lint> {
         let mut res = Vec::new();
         res.extend(flow::lint(self).into_iter().map(LintT::erase));
@@ -48,6 +56,8 @@ impl AstModule {
         res.extend(names::lint(self, globals).into_iter().map(LintT::erase));
         res.extend(underscore::lint(self).into_iter().map(LintT::erase));
         res.extend(performance::lint(self).into_iter().map(LintT::erase));
+        let suppressions = Suppressions::new(self);
+        res.retain(|x| !suppressions.is_suppressed(x));
         res
     }
 }
@@ -24,10 +24,12 @@ use crate::analysis::bind;
 use crate::analysis::bind::Assigner;
 use crate::analysis::bind::Bind;
 use crate::analysis::bind::Scope;
+use crate::analysis::types::statement_deletion;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::codemap::CodeMap;
 use crate::codemap::Span;
+use crate::syntax::ast::Stmt;
 use crate::syntax::AstModule;
 
 #[derive(Error, Debug)]
@@ -89,9 +91,58 @@ pub(crate) fn lint(
     if let Some(globals) = globals {
         undefined_variable(&module.codemap, &scope, globals, &mut res);
     }
+    unused_load_edits(module, &mut res);
     res
 }
 
+/// Add edits which remove the unused symbols from `load` statements, removing the whole
+/// statement if none of its symbols are used.
+fn unused_load_edits(module: &AstModule, res: &mut [LintT<NameWarning>]) {
+    let unused: HashSet<Span> = res
+        .iter()
+        .filter(|x| matches!(x.problem, NameWarning::UnusedLoad(..)))
+        .map(|x| x.location.span)
+        .collect();
+    if unused.is_empty() {
+        return;
+    }
+
+    let mut edits = HashMap::new();
+    for stmt in module.top_level_statements() {
+        let load = match &**stmt {
+            Stmt::Load(load) => load,
+            _ => continue,
+        };
+        let is_unused: Vec<bool> = load
+            .args
+            .iter()
+            .map(|(local, _)| unused.contains(&local.span))
+            .collect();
+        let arg_span = |i: usize| load.args[i].0.span.merge(load.args[i].1.span);
+        for (i, (local, _)) in load.args.iter().enumerate() {
+            if !is_unused[i] {
+                continue;
+            }
+            let span = if is_unused.iter().all(|x| *x) {
+                statement_deletion(&module.codemap, stmt.span)
+            } else if is_unused[i + 1..].iter().any(|x| !*x) {
+                // Remove this symbol and the separator after it.
+                Span::new(arg_span(i).begin(), arg_span(i + 1).begin())
+            } else {
+                // Everything after is removed, so remove the separator before.
+                Span::new(arg_span(i - 1).end(), arg_span(i).end())
+            };
+            edits.insert(local.span, span);
+        }
+    }
+
+    for x in res {
+        if let Some(span) = edits.get(&x.location.span) {
+            x.edits.push((*span, String::new()));
+        }
+    }
+}
+
 fn undefined_variable(
     codemap: &CodeMap,
     scope: &Scope,
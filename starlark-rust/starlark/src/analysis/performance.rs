@@ -57,13 +57,14 @@ fn match_dict_copy(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<LintT<Performan
     // If we see `dict(**x)` suggest `dict(x)`
     match &**x {
         Expr::Call(fun, args) if args.len() == 1 => match (&***fun, &*args[0]) {
-            (Expr::Identifier(f, _), Argument::KwArgs(arg)) if f.node == "dict" => {
-                res.push(LintT::new(
+            (Expr::Identifier(f, _), Argument::KwArgs(arg)) if f.node == "dict" => res.push(
+                LintT::new(
                     codemap,
                     x.span,
                     Performance::DictWithoutStarStar(x.to_string(), format!("dict({})", arg.node)),
-                ))
-            }
+                )
+                .with_edit(x.span, format!("dict({})", codemap.source_span(arg.span))),
+            ),
             _ => {}
         },
         _ => {}
@@ -85,18 +86,31 @@ fn match_inefficient_bool_check(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<Li
                             Performance::EagerAndInefficientBoolCheck(f.node.clone()),
                         )),
                     // any(list(_get_some_dict()))
-                    Expr::Call(any_call, _) => match &***any_call {
+                    Expr::Call(any_call, any_args) => match &***any_call {
                         Expr::Identifier(any_id, _)
                             if any_id.node == "dict" || any_id.node == "list" =>
                         {
-                            res.push(LintT::new(
+                            let mut lint = LintT::new(
                                 codemap,
                                 x.span,
                                 Performance::InefficientBoolCheck(
                                     x.to_string(),
                                     any_id.node.clone(),
                                 ),
-                            ))
+                            );
+                            // Iterating a list gives the same values as the iterable it was
+                            // made from, so `any(list(xs))` is `any(xs)`. Not so for dict.
+                            if let [list_arg] = &**any_args {
+                                if let Argument::Positional(list_arg) = &**list_arg {
+                                    if any_id.node == "list" {
+                                        lint = lint.with_edit(
+                                            arg.span,
+                                            codemap.source_span(list_arg.span),
+                                        );
+                                    }
+                                }
+                            }
+                            res.push(lint)
                         }
                         _ => {}
                     },
@@ -0,0 +1,399 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::analysis::bind::scope;
+use crate::analysis::bind::Assigner;
+use crate::analysis::bind::Bind;
+use crate::analysis::bind::Scope;
+use crate::analysis::definition::LspModule;
+use crate::codemap::ResolvedSpan;
+use crate::codemap::Span;
+
+/// What the symbol that references were requested for refers to. Used to find references
+/// in other modules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReferenceTarget {
+    /// A symbol that can only be accessed from within the current module.
+    Local,
+    /// A symbol defined at the top level of the current module, which other modules may load.
+    Exported { name: String },
+    /// A symbol that was loaded from the module at `path`, where it is called `name`.
+    /// `aliased` is whether it is bound to a different name in this module, e.g.
+    /// `load("bar.star", x = "baz")`.
+    Loaded {
+        path: String,
+        name: String,
+        aliased: bool,
+    },
+    /// A symbol that is not defined in the module, so is presumed to be a global.
+    Global { name: String },
+}
+
+/// All of the references to a single symbol within a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct References {
+    pub(crate) target: ReferenceTarget,
+    /// Where the symbol is first bound in this module, if it is bound in this module.
+    pub(crate) definition: Option<ResolvedSpan>,
+    /// Every access and assignment of the symbol, including `definition`, in source order.
+    pub(crate) spans: Vec<ResolvedSpan>,
+}
+
+/// How an identifier in the module is bound.
+#[derive(Debug, Clone, Copy)]
+enum Binding<'a> {
+    /// Bound in a scope in this module. `span` is where it is first bound in that scope,
+    /// and `top_level` is whether that scope is the module's scope.
+    Local {
+        span: Span,
+        assigner: &'a Assigner,
+        top_level: bool,
+    },
+    /// Not bound anywhere in the module.
+    Global,
+}
+
+/// A single access or assignment of an identifier.
+struct Occurrence<'a> {
+    name: &'a str,
+    span: Span,
+    binding: Binding<'a>,
+}
+
+impl<'a> Occurrence<'a> {
+    fn same_symbol(&self, other: &Occurrence) -> bool {
+        match (self.binding, other.binding) {
+            (Binding::Local { span: a, .. }, Binding::Local { span: b, .. }) => a == b,
+            (Binding::Global, Binding::Global) => self.name == other.name,
+            _ => false,
+        }
+    }
+}
+
+/// Find every identifier in `scope` and its child scopes, along with how it is bound.
+fn occurrences(scope: &Scope) -> Vec<Occurrence> {
+    fn resolve<'a>(stack: &[&'a Scope], name: &str) -> Binding<'a> {
+        for (i, scope) in stack.iter().copied().enumerate().rev() {
+            if let Some((assigner, span)) = scope.bound.get(name) {
+                return Binding::Local {
+                    span: *span,
+                    assigner,
+                    top_level: i == 0,
+                };
+            }
+        }
+        Binding::Global
+    }
+
+    fn walk<'a>(scope: &'a Scope, stack: &mut Vec<&'a Scope>, res: &mut Vec<Occurrence<'a>>) {
+        stack.push(scope);
+        for bind in &scope.inner {
+            let (name, span) = match bind {
+                Bind::Set(_, x) => (x.0.as_str(), x.span),
+                Bind::Get(x) => (x.node.as_str(), x.span),
+                Bind::GetDotted(x) => (x.variable.node.as_str(), x.variable.span),
+                Bind::Scope(inner) => {
+                    walk(inner, stack, res);
+                    continue;
+                }
+                Bind::Flow => continue,
+            };
+            res.push(Occurrence {
+                name,
+                span,
+                binding: resolve(stack, name),
+            });
+        }
+        stack.pop();
+    }
+
+    let mut res = Vec::new();
+    walk(scope, &mut Vec::new(), &mut res);
+    res
+}
+
+impl LspModule {
+    /// Find all of the references to the symbol at the given zero based `line` and `col`.
+    ///
+    /// Returns `None` if there is no identifier at that location.
+    pub(crate) fn find_references(&self, line: u32, col: u32) -> Option<References> {
+        let pos = self.resolve_position(line, col)?;
+        let scope = scope(&self.ast);
+        let occurrences = occurrences(&scope);
+        let found = occurrences.iter().find(|x| x.span.contains(pos))?;
+
+        let target = match found.binding {
+            Binding::Local {
+                assigner: Assigner::Load { path, name },
+                ..
+            } => ReferenceTarget::Loaded {
+                path: path.node.clone(),
+                name: name.node.clone(),
+                aliased: found.name != name.node,
+            },
+            Binding::Local {
+                top_level: true, ..
+            } if !found.name.starts_with('_') => ReferenceTarget::Exported {
+                name: found.name.to_owned(),
+            },
+            Binding::Local { .. } => ReferenceTarget::Local,
+            Binding::Global => ReferenceTarget::Global {
+                name: found.name.to_owned(),
+            },
+        };
+        let definition = match found.binding {
+            Binding::Local { span, .. } => Some(self.ast.codemap.resolve_span(span)),
+            Binding::Global => None,
+        };
+        let spans = self.resolve_spans(
+            occurrences
+                .iter()
+                .filter(|x| x.same_symbol(found))
+                .map(|x| x.span),
+        );
+        Some(References {
+            target,
+            definition,
+            spans,
+        })
+    }
+
+    /// Find all of the references to a symbol called `name` that is defined at the top
+    /// level of this module.
+    pub(crate) fn find_exported_references(&self, name: &str) -> Option<References> {
+        let (line, col) = {
+            let span = self.find_exported_symbol(name)?;
+            (span.begin_line as u32, span.begin_column as u32)
+        };
+        self.find_references(line, col)
+    }
+
+    /// Find all of the references to the symbol `name` loaded from another module.
+    ///
+    /// `matches_path` is called with the path of each `load()` statement, and should
+    /// return whether that path refers to the module that defines `name`.
+    pub(crate) fn find_loaded_references(
+        &self,
+        name: &str,
+        matches_path: impl Fn(&str) -> bool,
+    ) -> Vec<ResolvedSpan> {
+        let scope = scope(&self.ast);
+        let spans = occurrences(&scope)
+            .into_iter()
+            .filter_map(|x| match x.binding {
+                Binding::Local {
+                    assigner:
+                        Assigner::Load {
+                            path,
+                            name: loaded_name,
+                        },
+                    top_level: true,
+                    ..
+                } if loaded_name.node == name && matches_path(&path.node) => Some(x.span),
+                _ => None,
+            });
+        self.resolve_spans(spans)
+    }
+
+    /// Find the edits needed to rename the symbol `name`, loaded from another module, to
+    /// `new_name`.
+    ///
+    /// The name in each matching `load()` is replaced, keeping its quotes. Where the symbol
+    /// is loaded under its own name, every reference to it is renamed too; where it is
+    /// loaded under an alias, the alias is left alone.
+    pub(crate) fn rename_loaded_symbol(
+        &self,
+        name: &str,
+        new_name: &str,
+        matches_path: impl Fn(&str) -> bool,
+    ) -> Vec<(ResolvedSpan, String)> {
+        let scope = scope(&self.ast);
+        let mut edits = Vec::new();
+        for x in occurrences(&scope) {
+            let loaded_name = match x.binding {
+                Binding::Local {
+                    assigner:
+                        Assigner::Load {
+                            path,
+                            name: loaded_name,
+                        },
+                    top_level: true,
+                    ..
+                } if loaded_name.node == name && matches_path(&path.node) => loaded_name,
+                _ => continue,
+            };
+            let source = self.ast.codemap.source_span(loaded_name.span);
+            let quotes = &source[..source.len() - source.trim_start_matches(['"', '\'']).len()];
+            edits.push((
+                loaded_name.span,
+                format!("{}{}{}", quotes, new_name, quotes),
+            ));
+            // Without an alias, the name in the `load()` is also where the symbol is bound.
+            if x.name == name && x.span != loaded_name.span {
+                edits.push((x.span, new_name.to_owned()));
+            }
+        }
+        edits.sort_by_key(|(span, _)| span.begin());
+        edits.dedup();
+        edits
+            .into_iter()
+            .map(|(span, text)| (self.ast.codemap.resolve_span(span), text))
+            .collect()
+    }
+
+    fn resolve_spans(&self, spans: impl Iterator<Item = Span>) -> Vec<ResolvedSpan> {
+        let mut spans = spans.collect::<Vec<_>>();
+        spans.sort_by_key(|x| x.begin());
+        // `x += 1` both reads and assigns `x`, at the same location.
+        spans.dedup();
+        spans
+            .into_iter()
+            .map(|x| self.ast.codemap.resolve_span(x))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use textwrap::dedent;
+
+    use super::*;
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn module(program: &str) -> LspModule {
+        LspModule::new(AstModule::parse("foo.star", dedent(program), &Dialect::Extended).unwrap())
+    }
+
+    fn span(line: usize, begin: usize, end: usize) -> ResolvedSpan {
+        ResolvedSpan {
+            begin_line: line,
+            begin_column: begin,
+            end_line: line,
+            end_column: end,
+        }
+    }
+
+    #[test]
+    fn test_find_references() {
+        let module = module(
+            r#"
+load("bar.star", "baz")
+x = 1
+def foo(x):
+    x += baz
+    return x
+y = foo(x) + len(x)
+_z = baz(len)
+"#,
+        );
+
+        // The parameter shadows the global.
+        assert_eq!(
+            Some(References {
+                target: ReferenceTarget::Local,
+                definition: Some(span(3, 8, 9)),
+                spans: vec![span(3, 8, 9), span(4, 4, 5), span(5, 11, 12)],
+            }),
+            module.find_references(5, 11)
+        );
+        assert_eq!(
+            Some(References {
+                target: ReferenceTarget::Exported {
+                    name: "x".to_owned()
+                },
+                definition: Some(span(2, 0, 1)),
+                spans: vec![span(2, 0, 1), span(6, 8, 9), span(6, 17, 18)],
+            }),
+            module.find_references(6, 17)
+        );
+        assert_eq!(
+            Some(References {
+                target: ReferenceTarget::Loaded {
+                    path: "bar.star".to_owned(),
+                    name: "baz".to_owned(),
+                    aliased: false,
+                },
+                definition: Some(span(1, 17, 22)),
+                spans: vec![span(1, 17, 22), span(4, 9, 12), span(7, 5, 8)],
+            }),
+            module.find_references(7, 5)
+        );
+        assert_eq!(
+            Some(References {
+                target: ReferenceTarget::Global {
+                    name: "len".to_owned(),
+                },
+                definition: None,
+                spans: vec![span(6, 13, 16), span(7, 9, 12)],
+            }),
+            module.find_references(7, 10)
+        );
+        assert_eq!(
+            Some(ReferenceTarget::Local),
+            module.find_references(7, 0).map(|x| x.target)
+        );
+        assert_eq!(None, module.find_references(2, 3));
+
+        assert_eq!(
+            Some(vec![span(3, 4, 7), span(6, 4, 7)]),
+            module.find_exported_references("foo").map(|x| x.spans)
+        );
+        assert_eq!(
+            vec![span(1, 17, 22), span(4, 9, 12), span(7, 5, 8)],
+            module.find_loaded_references("baz", |path| path == "bar.star")
+        );
+        assert!(
+            module
+                .find_loaded_references("baz", |path| path == "other.star")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_rename_loaded_symbol() {
+        let module = module(
+            r#"
+load("bar.star", "baz", x = 'baz')
+load("other.star", "quux")
+baz(x, quux)
+"#,
+        );
+
+        assert_eq!(
+            Some(ReferenceTarget::Loaded {
+                path: "bar.star".to_owned(),
+                name: "baz".to_owned(),
+                aliased: true,
+            }),
+            module.find_references(3, 4).map(|x| x.target)
+        );
+        assert_eq!(
+            vec![
+                (span(1, 17, 22), "\"renamed\"".to_owned()),
+                (span(1, 28, 33), "'renamed'".to_owned()),
+                (span(3, 0, 3), "renamed".to_owned()),
+            ],
+            module.rename_loaded_symbol("baz", "renamed", |path| path == "bar.star")
+        );
+        assert!(
+            module
+                .rename_loaded_symbol("quux", "renamed", |path| path == "bar.star")
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lints can be disabled with a comment `# starlark-lint-disable: <name>, ...`, giving the
+//! [`short_name`](crate::analysis::Lint::short_name) of the lints to disable. A comment at the
+//! end of a line disables the lints on that line, and a comment on a line of its own disables
+//! them in the statement that follows it. A comment `# starlark-lint-disable-file: <name>, ...`
+//! disables the lints in the whole file.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::analysis::types::Lint;
+use crate::codemap::Span;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::Stmt;
+use crate::syntax::AstModule;
+
+const DISABLE: &str = "starlark-lint-disable:";
+const DISABLE_FILE: &str = "starlark-lint-disable-file:";
+
+/// The lints disabled in a module.
+pub(crate) struct Suppressions {
+    /// Lints disabled for the whole file.
+    file: HashSet<String>,
+    /// Lints disabled by line, 0-indexed.
+    lines: HashMap<usize, HashSet<String>>,
+    /// Lints disabled in the span of a statement.
+    statements: Vec<(Span, HashSet<String>)>,
+}
+
+/// The text of a comment after the `#`.
+fn comment_text(comment: &str) -> &str {
+    comment.trim_start_matches('#').trim_start()
+}
+
+/// Whether a comment disables lints. [`parse`](AstModule::parse) keeps these comments, so that we
+/// don't need to lex the module again to find them.
+pub(crate) fn is_suppression_comment(comment: &str) -> bool {
+    let text = comment_text(comment);
+    text.starts_with(DISABLE) || text.starts_with(DISABLE_FILE)
+}
+
+/// The spans of all the statements in `x`, in the order they start.
+fn statement_spans(x: &AstStmt, res: &mut Vec<Span>) {
+    if !matches!(x.node, Stmt::Statements(_)) {
+        res.push(x.span);
+    }
+    x.visit_stmt(|x| statement_spans(x, res));
+}
+
+impl Suppressions {
+    pub(crate) fn new(module: &AstModule) -> Self {
+        let codemap = &module.codemap;
+        let mut statements = Vec::new();
+        statement_spans(&module.statement, &mut statements);
+
+        let mut res = Suppressions {
+            file: HashSet::new(),
+            lines: HashMap::new(),
+            statements: Vec::new(),
+        };
+        for comment in &module.comments {
+            let text = comment_text(comment);
+            let (names, file) = if let Some(names) = text.strip_prefix(DISABLE) {
+                (names, false)
+            } else if let Some(names) = text.strip_prefix(DISABLE_FILE) {
+                (names, true)
+            } else {
+                continue;
+            };
+            let names = names
+                .split(',')
+                .map(|x| x.trim().to_owned())
+                .filter(|x| !x.is_empty());
+
+            if file {
+                res.file.extend(names);
+                continue;
+            }
+
+            let line = codemap.find_line(comment.span.begin());
+            let line_begin = codemap.line_span(line).begin();
+            let own_line = codemap
+                .source_span(Span::new(line_begin, comment.span.begin()))
+                .trim()
+                .is_empty();
+            if !own_line {
+                res.lines.entry(line).or_default().extend(names);
+            } else {
+                let next = statements.partition_point(|x| x.begin() < comment.span.end());
+                if let Some(statement) = statements.get(next) {
+                    res.statements.push((*statement, names.collect()));
+                }
+            }
+        }
+        res
+    }
+
+    /// Is this lint disabled by a comment.
+    pub(crate) fn is_suppressed(&self, lint: &Lint) -> bool {
+        if self.file.contains(&lint.short_name) {
+            return true;
+        }
+        let pos = lint.location.span.begin();
+        let line = lint.location.file.find_line(pos);
+        if self
+            .lines
+            .get(&line)
+            .map_or(false, |names| names.contains(&lint.short_name))
+        {
+            return true;
+        }
+        self.statements
+            .iter()
+            .any(|(span, names)| span.contains(pos) && names.contains(&lint.short_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slice_vec_ext::SliceExt;
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn lints(program: &str) -> Vec<String> {
+        let module = AstModule::parse("X", program.to_owned(), &Dialect::Extended).unwrap();
+        module
+            .lint(None)
+            .map(|x| format!("{}:{}", x.short_name, x.location.resolve_span().begin_line))
+    }
+
+    #[test]
+    fn test_suppress_line() {
+        let program = r#"
+def f():
+    fail("f")
+    print(1) # starlark-lint-disable: unreachable
+def g():
+    fail("g")
+    # starlark-lint-disable: no-effect, unreachable
+
+    print(2)
+def h():
+    fail("h")
+    print(3) # starlark-lint-disable: no-effect
+"#;
+        assert_eq!(lints(program), &["unreachable:11"]);
+    }
+
+    #[test]
+    fn test_suppress_statement() {
+        let program = r#"
+# Some header.
+# starlark-lint-disable: unreachable
+def f():
+    fail("f")
+    print(1)
+def g():
+    fail("g")
+    print(2)
+"#;
+        assert_eq!(lints(program), &["unreachable:8"]);
+    }
+
+    #[test]
+    fn test_suppress_file() {
+        let program = r#"
+def f():
+    fail("f")
+    print(1)
+# starlark-lint-disable-file: unreachable
+def g():
+    fail("g")
+    print(2)
+"#;
+        assert_eq!(lints(program), Vec::<String>::new());
+    }
+}
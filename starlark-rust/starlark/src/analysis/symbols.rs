@@ -0,0 +1,320 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+
+use crate::analysis::definition::LspModule;
+use crate::codemap::Pos;
+use crate::codemap::ResolvedSpan;
+use crate::codemap::Spanned;
+use crate::syntax::ast::ArgumentP;
+use crate::syntax::ast::AstLiteral;
+use crate::syntax::ast::AstNoPayload;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::DefP;
+use crate::syntax::ast::ExprP;
+use crate::syntax::ast::StmtP;
+use crate::syntax::uniplate::Visit;
+
+/// The kind of a symbol that is defined in a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SymbolKind {
+    /// A function defined with `def`.
+    Function,
+    /// A variable, parameter, or a symbol brought in with `load()`.
+    Variable,
+    /// A top level call with a `name` argument, e.g. a target in a BUCK file.
+    Target,
+}
+
+/// A symbol that is defined at the top level of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    pub(crate) kind: SymbolKind,
+    /// Extra details about the symbol, e.g. the function that was called to create a target.
+    pub(crate) detail: Option<String>,
+    /// The span of the whole statement that defines the symbol.
+    pub(crate) span: ResolvedSpan,
+    /// The span of just the name of the symbol.
+    pub(crate) name_span: ResolvedSpan,
+}
+
+/// A call to a function that encloses a position in a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FunctionCall {
+    /// The location of the identifier of the function that is called.
+    pub(crate) function_span: ResolvedSpan,
+    /// The names of the named arguments that are already passed to the call.
+    pub(crate) named_args: Vec<String>,
+}
+
+/// Add the names bound by `stmt` to `names`, without descending into function bodies.
+fn bound_names(stmt: &AstStmt, names: &mut BTreeMap<String, SymbolKind>) {
+    match &stmt.node {
+        StmtP::Def(def) => {
+            names.insert(def.name.0.clone(), SymbolKind::Function);
+        }
+        StmtP::Assign(lhs, _) | StmtP::AssignModify(lhs, _, _) | StmtP::For(lhs, _) => {
+            lhs.visit_lvalue(|x| {
+                names.insert(x.0.clone(), SymbolKind::Variable);
+            });
+            stmt.visit_stmt(|x| bound_names(x, names));
+        }
+        StmtP::Load(load) => {
+            for (local, _) in &load.args {
+                names.insert(local.0.clone(), SymbolKind::Variable);
+            }
+        }
+        _ => stmt.visit_stmt(|x| bound_names(x, names)),
+    }
+}
+
+impl LspModule {
+    /// Get the functions, variables and targets that are defined at the top level of this
+    /// module, in the order that they appear.
+    pub(crate) fn find_symbols(&self) -> Vec<Symbol> {
+        let codemap = &self.ast.codemap;
+        let mut symbols = Vec::new();
+        for stmt in self.ast.top_level_statements() {
+            match &stmt.node {
+                StmtP::Def(def) => symbols.push(Symbol {
+                    name: def.name.0.clone(),
+                    kind: SymbolKind::Function,
+                    detail: None,
+                    span: codemap.resolve_span(stmt.span),
+                    name_span: codemap.resolve_span(def.name.span),
+                }),
+                StmtP::Assign(lhs, _) => lhs.visit_lvalue(|x| {
+                    symbols.push(Symbol {
+                        name: x.0.clone(),
+                        kind: SymbolKind::Variable,
+                        detail: None,
+                        span: codemap.resolve_span(stmt.span),
+                        name_span: codemap.resolve_span(x.span),
+                    })
+                }),
+                StmtP::Expression(Spanned {
+                    node: ExprP::Call(function, args),
+                    ..
+                }) => {
+                    let name = args.iter().find_map(|x| match &x.node {
+                        ArgumentP::Named(
+                            arg_name,
+                            Spanned {
+                                node: ExprP::Literal(AstLiteral::String(s)),
+                                ..
+                            },
+                        ) if arg_name.node == "name" => Some(s),
+                        _ => None,
+                    });
+                    if let Some(name) = name {
+                        symbols.push(Symbol {
+                            name: name.node.clone(),
+                            kind: SymbolKind::Target,
+                            detail: Some(codemap.source_span(function.span).to_owned()),
+                            span: codemap.resolve_span(stmt.span),
+                            name_span: codemap.resolve_span(name.span),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        symbols
+    }
+
+    /// Get the names that are visible at the given zero based `line` and `col`, sorted by name.
+    ///
+    /// This includes the top level symbols of the module, and the parameters and variables
+    /// of any functions that enclose the position. Global symbols are not included.
+    pub(crate) fn find_names_in_scope(&self, line: u32, col: u32) -> Vec<(String, SymbolKind)> {
+        fn enclosing_defs<'a>(stmt: &'a AstStmt, pos: Pos, res: &mut Vec<&'a DefP<AstNoPayload>>) {
+            match &stmt.node {
+                StmtP::Def(def) if stmt.span.contains(pos) => {
+                    res.push(def);
+                    enclosing_defs(&def.body, pos, res);
+                }
+                StmtP::Def(_) => {}
+                x => x.visit_stmt(|x| enclosing_defs(x, pos, res)),
+            }
+        }
+
+        let mut names = BTreeMap::new();
+        bound_names(&self.ast.statement, &mut names);
+        if let Some(pos) = self.resolve_position(line, col) {
+            let mut defs = Vec::new();
+            enclosing_defs(&self.ast.statement, pos, &mut defs);
+            for def in defs {
+                for param in &def.params {
+                    if let (Some(name), _, _) = param.split() {
+                        names.insert(name.0.clone(), SymbolKind::Variable);
+                    }
+                }
+                bound_names(&def.body, &mut names);
+            }
+        }
+        names.into_iter().collect()
+    }
+
+    /// Find the innermost call of a named function whose arguments enclose the given zero
+    /// based `line` and `col`.
+    pub(crate) fn find_function_call_at(&self, line: u32, col: u32) -> Option<FunctionCall> {
+        fn visit_node(
+            module: &LspModule,
+            pos: Pos,
+            ret: &mut Option<FunctionCall>,
+            node: Visit<AstNoPayload>,
+        ) {
+            if let Visit::Expr(Spanned {
+                node: ExprP::Call(function, args),
+                span,
+            }) = &node
+            {
+                if let ExprP::Identifier(name, _) = &function.node {
+                    if span.contains(pos) && name.span.end() < pos {
+                        *ret = Some(FunctionCall {
+                            function_span: module.ast.codemap.resolve_span(name.span),
+                            named_args: args
+                                .iter()
+                                .filter_map(|x| match &x.node {
+                                    ArgumentP::Named(name, _) => Some(name.node.clone()),
+                                    _ => None,
+                                })
+                                .collect(),
+                        });
+                    }
+                }
+            }
+            node.visit_children(|x| visit_node(module, pos, ret, x));
+        }
+
+        let pos = self.resolve_position(line, col)?;
+        let mut ret = None;
+        visit_node(self, pos, &mut ret, Visit::Stmt(&self.ast.statement));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use textwrap::dedent;
+
+    use super::*;
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn module(program: &str) -> LspModule {
+        LspModule::new(AstModule::parse("foo.star", dedent(program), &Dialect::Extended).unwrap())
+    }
+
+    #[test]
+    fn test_find_symbols() {
+        let module = module(
+            r#"
+load("foo.star", "bar")
+x, y = 1, 2
+def foo(a):
+    z = a
+    return z
+cxx_library(
+    name = "lib",
+    srcs = [],
+)
+foo(1)
+"#,
+        );
+        let symbols = module
+            .find_symbols()
+            .into_iter()
+            .map(|x| (x.name, x.kind, x.detail, x.name_span.begin_line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("x".to_owned(), SymbolKind::Variable, None, 2),
+                ("y".to_owned(), SymbolKind::Variable, None, 2),
+                ("foo".to_owned(), SymbolKind::Function, None, 3),
+                (
+                    "lib".to_owned(),
+                    SymbolKind::Target,
+                    Some("cxx_library".to_owned()),
+                    7
+                ),
+            ],
+            symbols
+        );
+    }
+
+    #[test]
+    fn test_find_names_in_scope() {
+        let module = module(
+            r#"
+load("foo.star", baz = "bar")
+x = 1
+def foo(a, *args, **kwargs):
+    for b in a:
+        c = b
+    return c
+def other(d):
+    pass
+"#,
+        );
+        let names = |line, col| {
+            module
+                .find_names_in_scope(line, col)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["baz", "foo", "other", "x"], names(2, 0));
+        assert_eq!(
+            vec!["a", "args", "b", "baz", "c", "foo", "kwargs", "other", "x"],
+            names(6, 4)
+        );
+        assert_eq!(vec!["baz", "d", "foo", "other", "x"], names(8, 4));
+        assert_eq!(
+            Some(&("foo".to_owned(), SymbolKind::Function)),
+            module.find_names_in_scope(2, 0).get(1)
+        );
+    }
+
+    #[test]
+    fn test_find_function_call_at() {
+        let module = module(
+            r#"
+foo(a = 1, b = bar(c = 2), )
+"#,
+        );
+        let call = module.find_function_call_at(1, 27).unwrap();
+        assert_eq!(vec!["a", "b"], call.named_args);
+        assert_eq!(
+            ResolvedSpan {
+                begin_line: 1,
+                begin_column: 0,
+                end_line: 1,
+                end_column: 3,
+            },
+            call.function_span
+        );
+
+        let call = module.find_function_call_at(1, 23).unwrap();
+        assert_eq!(vec!["c"], call.named_args);
+
+        // The function name itself is not inside the call's arguments.
+        assert_eq!(None, module.find_function_call_at(1, 1));
+    }
+}
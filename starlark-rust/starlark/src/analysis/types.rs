@@ -42,6 +42,8 @@ pub(crate) struct LintT<T> {
     pub location: FileSpan,
     pub original: String,
     pub problem: T,
+    /// Replacements which fix the problem, see [`Lint::edits`].
+    pub edits: Vec<(Span, String)>,
 }
 
 /// A lint produced by [`AstModule::lint`](crate::syntax::AstModule::lint).
@@ -58,6 +60,18 @@ pub struct Lint {
     pub problem: String,
     /// The source code at [`location`](Lint::location).
     pub original: String,
+    /// Edits which mechanically fix the problem, all of which must be applied together.
+    /// Empty if there is no automatic fix.
+    pub edits: Vec<LintEdit>,
+}
+
+/// A replacement of some source code, which is part of fixing a [`Lint`].
+#[derive(Debug, Clone)]
+pub struct LintEdit {
+    /// Which code location to replace.
+    pub location: FileSpan,
+    /// The code to replace it with.
+    pub replacement: String,
 }
 
 impl Display for Lint {
@@ -79,20 +93,63 @@ impl<T: LintWarning> LintT<T> {
             original: location.file.source_span(span).to_owned(),
             location,
             problem,
+            edits: Vec::new(),
         }
     }
 
+    /// Add an edit which replaces the code at `span` as part of fixing this lint.
+    pub(crate) fn with_edit(mut self, span: Span, replacement: impl Into<String>) -> Self {
+        self.edits.push((span, replacement.into()));
+        self
+    }
+
     pub(crate) fn erase(self) -> Lint {
+        let edits = self
+            .edits
+            .into_iter()
+            .map(|(span, replacement)| LintEdit {
+                location: self.location.file.file_span(span),
+                replacement,
+            })
+            .collect();
         Lint {
             location: self.location,
             short_name: self.problem.short_name().to_owned(),
             serious: self.problem.is_serious(),
             problem: self.problem.to_string(),
             original: self.original,
+            edits,
         }
     }
 }
 
+/// The span to delete to remove the statement at `span`. If the statement has lines to
+/// itself that's the whole of those lines, so we don't leave blank lines behind.
+pub(crate) fn statement_deletion(codemap: &CodeMap, span: Span) -> Span {
+    let line_begin = codemap.line_span(codemap.find_line(span.begin())).begin();
+    if !codemap
+        .source_span(Span::new(line_begin, span.begin()))
+        .trim()
+        .is_empty()
+    {
+        return span;
+    }
+    let end_line = codemap.line_span(codemap.find_line(span.end()));
+    if end_line.begin() == span.end() {
+        // The statement includes its trailing newline.
+        return Span::new(line_begin, span.end());
+    }
+    if codemap
+        .source_span(Span::new(span.end(), end_line.end()))
+        .trim()
+        .is_empty()
+    {
+        Span::new(line_begin, end_line.end())
+    } else {
+        span
+    }
+}
+
 /// A standardised set of severities.
 #[derive(Debug, Serialize, Dupe, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
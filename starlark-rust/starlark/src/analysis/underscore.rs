@@ -19,9 +19,13 @@ use std::collections::HashSet;
 
 use thiserror::Error;
 
+use crate::analysis::bind;
+use crate::analysis::bind::Bind;
+use crate::analysis::bind::Scope;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::codemap::CodeMap;
+use crate::codemap::Span;
 use crate::syntax::ast::Assign;
 use crate::syntax::ast::AstExpr;
 use crate::syntax::ast::AstStmt;
@@ -54,10 +58,73 @@ impl LintWarning for UnderscoreWarning {
 pub(crate) fn lint(module: &AstModule) -> Vec<LintT<UnderscoreWarning>> {
     let mut res = Vec::new();
     inappropriate_underscore(&module.codemap, &module.statement, true, &mut res);
+    if !res.is_empty() {
+        rename_edits(&bind::scope(module), &mut res);
+    }
     use_ignored(&module.codemap, &module.statement, &mut res);
     res
 }
 
+/// Add edits which rename the underscore definitions to drop the underscores,
+/// provided the new name isn't already used.
+fn rename_edits(scope: &Scope, res: &mut [LintT<UnderscoreWarning>]) {
+    // Find the scope containing the definition at `span`.
+    fn defining_scope(scope: &Scope, span: Span) -> Option<&Scope> {
+        scope.inner.iter().find_map(|x| match x {
+            Bind::Set(_, x) if x.span == span => Some(scope),
+            Bind::Scope(inner) => defining_scope(inner, span),
+            _ => None,
+        })
+    }
+
+    // Is `name` bound or referred to anywhere in `scope`, including inner scopes.
+    fn uses(scope: &Scope, name: &str) -> bool {
+        scope.bound.contains_key(name)
+            || scope.free.contains_key(name)
+            || scope
+                .inner
+                .iter()
+                .any(|x| matches!(x, Bind::Scope(inner) if uses(inner, name)))
+    }
+
+    // All the places `name` is mentioned, which refer to its binding in `scope`.
+    fn mentions(scope: &Scope, name: &str, res: &mut Vec<Span>) {
+        for x in &scope.inner {
+            match x {
+                Bind::Set(_, x) if x.0 == name => res.push(x.span),
+                Bind::Get(x) if x.node == name => res.push(x.span),
+                Bind::GetDotted(x) if x.variable.node == name => res.push(x.variable.span),
+                Bind::Scope(inner) if !inner.bound.contains_key(name) => mentions(inner, name, res),
+                _ => {}
+            }
+        }
+    }
+
+    for x in res {
+        let name = match &x.problem {
+            UnderscoreWarning::UnderscoreDefinition(name) => name,
+            _ => continue,
+        };
+        let new_name = name.trim_start_matches('_');
+        if !new_name.starts_with(|c: char| c.is_alphabetic()) {
+            continue;
+        }
+        if let Some(scope) = defining_scope(scope, x.location.span) {
+            if uses(scope, new_name) {
+                continue;
+            }
+            let mut spans = Vec::new();
+            mentions(scope, name, &mut spans);
+            spans.sort_by_key(|x| x.begin());
+            spans.dedup();
+            x.edits = spans
+                .into_iter()
+                .map(|span| (span, new_name.to_owned()))
+                .collect();
+        }
+    }
+}
+
 // There's no reason to make a def or lambda and give it an underscore name not at the top level
 fn inappropriate_underscore(
     codemap: &CodeMap,
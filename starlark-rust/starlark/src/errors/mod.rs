@@ -33,6 +33,7 @@ use annotate_snippets::snippet::SourceAnnotation;
 pub use crate::analysis::EvalMessage;
 pub use crate::analysis::EvalSeverity;
 pub use crate::analysis::Lint;
+pub use crate::analysis::LintEdit;
 use crate::codemap::CodeMap;
 use crate::codemap::FileSpan;
 use crate::codemap::Span;
@@ -65,6 +65,7 @@ impl<'v, 'a> Evaluator<'v, 'a> {
             codemap,
             statement,
             dialect,
+            comments: _,
         } = ast;
 
         let codemap = self
@@ -40,26 +40,61 @@ use lsp_types::notification::DidCloseTextDocument;
 use lsp_types::notification::DidOpenTextDocument;
 use lsp_types::notification::LogMessage;
 use lsp_types::notification::PublishDiagnostics;
+use lsp_types::request::CodeActionRequest;
+use lsp_types::request::Completion;
+use lsp_types::request::DocumentSymbolRequest;
+use lsp_types::request::Formatting;
 use lsp_types::request::GotoDefinition;
+use lsp_types::request::HoverRequest;
+use lsp_types::request::References;
+use lsp_types::request::Rename;
+use lsp_types::CodeAction;
+use lsp_types::CodeActionKind;
+use lsp_types::CodeActionOrCommand;
+use lsp_types::CodeActionParams;
+use lsp_types::CodeActionProviderCapability;
+use lsp_types::CodeActionResponse;
+use lsp_types::CompletionItem;
+use lsp_types::CompletionItemKind;
+use lsp_types::CompletionOptions;
+use lsp_types::CompletionParams;
+use lsp_types::CompletionResponse;
 use lsp_types::DefinitionOptions;
 use lsp_types::Diagnostic;
 use lsp_types::DidChangeTextDocumentParams;
 use lsp_types::DidCloseTextDocumentParams;
 use lsp_types::DidOpenTextDocumentParams;
+use lsp_types::DocumentFormattingParams;
+use lsp_types::DocumentSymbol;
+use lsp_types::DocumentSymbolParams;
+use lsp_types::DocumentSymbolResponse;
+use lsp_types::Documentation;
 use lsp_types::GotoDefinitionParams;
 use lsp_types::GotoDefinitionResponse;
+use lsp_types::Hover;
+use lsp_types::HoverContents;
+use lsp_types::HoverParams;
+use lsp_types::HoverProviderCapability;
 use lsp_types::InitializeParams;
+use lsp_types::Location;
 use lsp_types::LocationLink;
 use lsp_types::LogMessageParams;
+use lsp_types::MarkupContent;
+use lsp_types::MarkupKind;
 use lsp_types::MessageType;
 use lsp_types::OneOf;
+use lsp_types::Position;
 use lsp_types::PublishDiagnosticsParams;
 use lsp_types::Range;
+use lsp_types::ReferenceParams;
+use lsp_types::RenameParams;
 use lsp_types::ServerCapabilities;
 use lsp_types::TextDocumentSyncCapability;
 use lsp_types::TextDocumentSyncKind;
+use lsp_types::TextEdit;
 use lsp_types::Url;
 use lsp_types::WorkDoneProgressOptions;
+use lsp_types::WorkspaceEdit;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -70,9 +105,21 @@ use crate::analysis::definition::Definition;
 use crate::analysis::definition::DottedDefinition;
 use crate::analysis::definition::IdentifierDefinition;
 use crate::analysis::definition::LspModule;
+use crate::analysis::references::ReferenceTarget;
+use crate::analysis::symbols::Symbol;
+use crate::analysis::symbols::SymbolKind;
 use crate::codemap::ResolvedSpan;
+use crate::collections::SmallMap;
+use crate::docs::Doc;
+use crate::docs::DocItem;
+use crate::docs::DocParam;
+use crate::docs::Identifier;
+use crate::docs::MarkdownFlavor;
+use crate::docs::RenderMarkdown;
 use crate::lsp::server::LoadContentsError::WrongScheme;
+use crate::syntax::lexer::is_identifier;
 use crate::syntax::AstModule;
+use crate::syntax::Dialect;
 
 /// The request to get the file contents for a starlark: URI
 struct StarlarkFileContentsRequest {}
@@ -293,6 +340,23 @@ pub trait LspContext {
         current_file: &LspUrl,
         symbol: &str,
     ) -> anyhow::Result<Option<LspUrl>>;
+
+    /// Get the documentation for all of the global symbols that are available in the
+    /// current file.
+    ///
+    /// These are shown when hovering over global symbols, and are used to complete global
+    /// symbols and the named arguments of global functions (e.g. the attributes of a rule).
+    ///
+    /// The documentation is keyed by symbol name. It is requested on every hover and
+    /// completion, so implementations should cache it rather than rebuilding it each time.
+    ///
+    /// By default, no documentation is provided.
+    fn get_global_symbol_docs(
+        &self,
+        _current_file: &LspUrl,
+    ) -> anyhow::Result<Arc<SmallMap<String, Doc>>> {
+        Ok(Arc::default())
+    }
 }
 
 /// Errors when [`LspContext::resolve_load()`] cannot resolve a given path.
@@ -303,6 +367,25 @@ enum ResolveLoadError {
     WrongScheme(String, LspUrl),
 }
 
+/// Errors when formatting a file.
+#[derive(thiserror::Error, Debug)]
+enum FormattingError {
+    /// Only files that the client has opened can be formatted.
+    #[error("File `{}` is not open", .0)]
+    NotOpen(LspUrl),
+}
+
+/// Errors when renaming a symbol.
+#[derive(thiserror::Error, Debug)]
+enum RenameError {
+    /// The new name is not a valid identifier.
+    #[error("`{}` is not a valid identifier", .0)]
+    InvalidName(String),
+    /// Globals are not defined in any file, so cannot be renamed.
+    #[error("Cannot rename global symbol `{}`", .0)]
+    Global(String),
+}
+
 /// Errors when loading contents of a starlark program.
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum LoadContentsError {
@@ -317,6 +400,8 @@ struct Backend<T: LspContext> {
     /// The `AstModule` from the last time that a file was opened / changed and parsed successfully.
     /// Entries are evicted when the file is closed.
     last_valid_parse: RwLock<HashMap<LspUrl, Arc<LspModule>>>,
+    /// The current contents of the open files. Entries are evicted when the file is closed.
+    open_files: RwLock<HashMap<LspUrl, String>>,
 }
 
 /// The logic implementations of stuff
@@ -332,6 +417,13 @@ impl<T: LspContext> Backend<T> {
         ServerCapabilities {
             text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
             definition_provider,
+            document_formatting_provider: Some(OneOf::Left(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            completion_provider: Some(CompletionOptions::default()),
+            document_symbol_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            rename_provider: Some(OneOf::Left(true)),
             ..ServerCapabilities::default()
         }
     }
@@ -354,6 +446,10 @@ impl<T: LspContext> Backend<T> {
 
     fn validate(&self, uri: Url, version: Option<i64>, text: String) -> anyhow::Result<()> {
         let uri = uri.try_into()?;
+        self.open_files
+            .write()
+            .unwrap()
+            .insert(uri.clone(), text.clone());
         let eval_result = self.context.parse_file_with_contents(&uri, text);
         if let Some(ast) = eval_result.ast {
             let module = Arc::new(LspModule::new(ast));
@@ -384,8 +480,10 @@ impl<T: LspContext> Backend<T> {
 
     fn did_close(&self, params: DidCloseTextDocumentParams) -> anyhow::Result<()> {
         {
+            let uri: LspUrl = params.text_document.uri.clone().try_into()?;
             let mut last_valid_parse = self.last_valid_parse.write().unwrap();
-            last_valid_parse.remove(&params.text_document.uri.clone().try_into()?);
+            last_valid_parse.remove(&uri);
+            self.open_files.write().unwrap().remove(&uri);
         }
         self.publish_diagnostics(params.text_document.uri, Vec::new(), None);
         Ok(())
@@ -401,6 +499,450 @@ impl<T: LspContext> Backend<T> {
         self.send_response(new_response(id, self.find_definition(params)));
     }
 
+    /// Format a whole file. The file must be open, and must parse.
+    fn formatting(&self, id: RequestId, params: DocumentFormattingParams) {
+        self.send_response(new_response(id, self.format_file(params)));
+    }
+
+    fn format_file(&self, params: DocumentFormattingParams) -> anyhow::Result<Vec<TextEdit>> {
+        let uri: LspUrl = params.text_document.uri.try_into()?;
+        let text = match self.open_files.read().unwrap().get(&uri) {
+            Some(text) => text.clone(),
+            None => return Err(FormattingError::NotOpen(uri).into()),
+        };
+        // Use the dialect the context parsed the file with, if we know it.
+        let dialect = self
+            .get_ast(&uri)
+            .map_or(Dialect::Extended, |module| module.ast.dialect.clone());
+        let formatted =
+            AstModule::parse_with_comments(&uri.to_string(), text.clone(), &dialect)?.format();
+        if formatted == text {
+            return Ok(Vec::new());
+        }
+        Ok(vec![TextEdit {
+            range: Range::new(Position::new(0, 0), end_position(&text)),
+            new_text: formatted,
+        }])
+    }
+
+    /// Offer the fixes for lints within the range as quick fixes. We only offer them if the last
+    /// valid parse of the file is of its current contents, since the edits would apply to the
+    /// wrong text otherwise.
+    fn code_action(&self, id: RequestId, params: CodeActionParams) {
+        self.send_response(new_response(id, self.find_code_actions(params)));
+    }
+
+    fn find_code_actions(&self, params: CodeActionParams) -> anyhow::Result<CodeActionResponse> {
+        let uri: LspUrl = params.text_document.uri.clone().try_into()?;
+        let module = match self.get_ast(&uri) {
+            Some(module) => module,
+            None => return Ok(Vec::new()),
+        };
+        let is_current = self
+            .open_files
+            .read()
+            .unwrap()
+            .get(&uri)
+            .map_or(false, |text| *text == module.ast.codemap.source());
+        if !is_current {
+            return Ok(Vec::new());
+        }
+        let mut actions = Vec::new();
+        for lint in module.ast.lint(None) {
+            let range: Range = lint.location.resolve_span().into();
+            if lint.edits.is_empty()
+                || range.end < params.range.start
+                || params.range.end < range.start
+            {
+                continue;
+            }
+            let edits = lint
+                .edits
+                .iter()
+                .map(|x| TextEdit {
+                    range: x.location.resolve_span().into(),
+                    new_text: x.replacement.clone(),
+                })
+                .collect();
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Fix {}: {}", lint.short_name, lint.problem),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(params.text_document.uri.clone(), edits)])),
+                    ..WorkspaceEdit::default()
+                }),
+                ..CodeAction::default()
+            }));
+        }
+        Ok(actions)
+    }
+
+    /// Show the documentation for the symbol under the cursor. This works for functions
+    /// defined in the current file, functions loaded from other files, and global symbols
+    /// that [`LspContext::get_global_symbol_docs`] provides documentation for.
+    ///
+    /// NOTE: Like goto definition, this uses the last valid parse of the file.
+    fn hover(&self, id: RequestId, params: HoverParams) {
+        self.send_response(new_response(id, self.find_hover(params)));
+    }
+
+    fn find_hover(&self, params: HoverParams) -> anyhow::Result<Option<Hover>> {
+        let uri: LspUrl = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .try_into()?;
+        let position = params.text_document_position_params.position;
+        let module = match self.get_ast(&uri) {
+            Some(module) => module,
+            None => return Ok(None),
+        };
+
+        let definition = module.find_definition(position.line, position.character);
+        let source = match definition.source() {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+        let doc = match definition {
+            Definition::Identifier(definition) => self.find_doc(&uri, &module, definition)?,
+            // Members of global objects, e.g. `native.glob`.
+            Definition::Dotted(DottedDefinition {
+                root_definition_location: IdentifierDefinition::Unresolved { name, .. },
+                segments,
+                ..
+            }) if segments.len() == 2 => match self.find_global_doc(&uri, &name)? {
+                Some(Doc {
+                    item: DocItem::Object(object),
+                    ..
+                }) => object
+                    .members
+                    .into_iter()
+                    .find(|(name, _)| *name == segments[1])
+                    .map(|(name, member)| Doc {
+                        id: Identifier {
+                            name,
+                            location: None,
+                        },
+                        item: member.to_doc_item(),
+                        custom_attrs: HashMap::new(),
+                    }),
+                _ => None,
+            },
+            Definition::Dotted(_) => None,
+        };
+
+        Ok(doc.map(|doc| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc.render_markdown(MarkdownFlavor::DocFile),
+            }),
+            range: Some(source.into()),
+        }))
+    }
+
+    /// Get the documentation for the function that an identifier refers to, if it has any.
+    fn find_doc(
+        &self,
+        uri: &LspUrl,
+        module: &LspModule,
+        definition: IdentifierDefinition,
+    ) -> anyhow::Result<Option<Doc>> {
+        match definition {
+            IdentifierDefinition::Location { destination, .. } => {
+                Ok(module.find_function_doc_at(destination))
+            }
+            IdentifierDefinition::LoadedLocation { path, name, .. } => {
+                let load_uri = self.resolve_load_path(&path, uri)?;
+                Ok(self
+                    .get_ast_or_load_from_disk(&load_uri)?
+                    .and_then(|module| module.find_exported_function_doc(&name)))
+            }
+            IdentifierDefinition::Unresolved { name, .. } => self.find_global_doc(uri, &name),
+            IdentifierDefinition::LoadPath { .. }
+            | IdentifierDefinition::StringLiteral { .. }
+            | IdentifierDefinition::NotFound => Ok(None),
+        }
+    }
+
+    fn find_global_doc(&self, uri: &LspUrl, name: &str) -> anyhow::Result<Option<Doc>> {
+        Ok(self.context.get_global_symbol_docs(uri)?.get(name).cloned())
+    }
+
+    /// Complete the named arguments of the function being called, the symbols that are
+    /// visible at the cursor, and global symbols.
+    ///
+    /// NOTE: Like goto definition, this uses the last valid parse of the file.
+    fn completion(&self, id: RequestId, params: CompletionParams) {
+        self.send_response(new_response(id, self.find_completions(params)));
+    }
+
+    fn find_completions(&self, params: CompletionParams) -> anyhow::Result<CompletionResponse> {
+        let uri: LspUrl = params.text_document_position.text_document.uri.try_into()?;
+        let position = params.text_document_position.position;
+        let mut items = Vec::new();
+        let mut names = Vec::new();
+
+        if let Some(module) = self.get_ast(&uri) {
+            if let Some(call) = module.find_function_call_at(position.line, position.character) {
+                let definition = module.find_definition(
+                    call.function_span.begin_line as u32,
+                    call.function_span.begin_column as u32,
+                );
+                if let Definition::Identifier(definition) = definition {
+                    if let Some(Doc {
+                        item: DocItem::Function(function),
+                        ..
+                    }) = self.find_doc(&uri, &module, definition)?
+                    {
+                        for param in function.params {
+                            if let DocParam::Arg { name, docs, .. } = param {
+                                if call.named_args.contains(&name) {
+                                    continue;
+                                }
+                                items.push(CompletionItem {
+                                    insert_text: Some(format!("{} = ", name)),
+                                    label: name,
+                                    kind: Some(CompletionItemKind::PROPERTY),
+                                    documentation: docs.map(|docs| {
+                                        markdown_documentation(match docs.details {
+                                            Some(details) => {
+                                                format!("{}\n\n{}", docs.summary, details)
+                                            }
+                                            None => docs.summary,
+                                        })
+                                    }),
+                                    ..CompletionItem::default()
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+
+            for (name, kind) in module.find_names_in_scope(position.line, position.character) {
+                items.push(CompletionItem {
+                    label: name.clone(),
+                    kind: Some(match kind {
+                        SymbolKind::Function => CompletionItemKind::FUNCTION,
+                        SymbolKind::Variable | SymbolKind::Target => CompletionItemKind::VARIABLE,
+                    }),
+                    ..CompletionItem::default()
+                });
+                names.push(name);
+            }
+        }
+
+        for doc in self.context.get_global_symbol_docs(&uri)?.values() {
+            // Symbols in the module shadow globals.
+            if names.contains(&doc.id.name) {
+                continue;
+            }
+            items.push(CompletionItem {
+                label: doc.id.name.clone(),
+                kind: Some(match &doc.item {
+                    DocItem::Function(_) => CompletionItemKind::FUNCTION,
+                    DocItem::Module(_) | DocItem::Object(_) => CompletionItemKind::MODULE,
+                    DocItem::Property(_) => CompletionItemKind::CONSTANT,
+                }),
+                documentation: Some(markdown_documentation(
+                    doc.render_markdown(MarkdownFlavor::DocFile),
+                )),
+                ..CompletionItem::default()
+            });
+        }
+
+        Ok(CompletionResponse::Array(items))
+    }
+
+    /// List the functions, variables and targets defined at the top level of a file.
+    ///
+    /// NOTE: Like goto definition, this uses the last valid parse of the file.
+    fn document_symbols(&self, id: RequestId, params: DocumentSymbolParams) {
+        self.send_response(new_response(id, self.find_document_symbols(params)));
+    }
+
+    fn find_document_symbols(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> anyhow::Result<DocumentSymbolResponse> {
+        let uri: LspUrl = params.text_document.uri.try_into()?;
+        let symbols = match self.get_ast(&uri) {
+            Some(module) => module
+                .find_symbols()
+                .into_iter()
+                .map(document_symbol)
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    /// Find all of the references to the symbol under the cursor.
+    ///
+    /// Symbols defined at the top level of a file, and symbols loaded from other files, are
+    /// also looked up in every open file that loads them, so that references are found across
+    /// `load()` statements.
+    ///
+    /// NOTE: Like goto definition, this uses the last valid parse of the files.
+    fn references(&self, id: RequestId, params: ReferenceParams) {
+        self.send_response(new_response(id, self.find_references(params)));
+    }
+
+    fn find_references(&self, params: ReferenceParams) -> anyhow::Result<Vec<Location>> {
+        let uri: LspUrl = params.text_document_position.text_document.uri.try_into()?;
+        let position = params.text_document_position.position;
+        let include_declaration = params.context.include_declaration;
+        let mut locations = Vec::new();
+        let mut add_locations = |uri: &LspUrl,
+                                 spans: Vec<ResolvedSpan>,
+                                 definition: Option<ResolvedSpan>|
+         -> anyhow::Result<()> {
+            let url: Url = uri.try_into()?;
+            for span in spans {
+                if !include_declaration && Some(span) == definition {
+                    continue;
+                }
+                locations.push(Location::new(url.clone(), span.into()));
+            }
+            Ok(())
+        };
+
+        let references = match self
+            .get_ast(&uri)
+            .and_then(|module| module.find_references(position.line, position.character))
+        {
+            Some(references) => references,
+            None => return Ok(Vec::new()),
+        };
+        let (defining_uri, name) = match references.target {
+            ReferenceTarget::Local | ReferenceTarget::Global { .. } => {
+                add_locations(&uri, references.spans, references.definition)?;
+                return Ok(locations);
+            }
+            ReferenceTarget::Exported { name } => {
+                add_locations(&uri, references.spans, references.definition)?;
+                (uri, name)
+            }
+            ReferenceTarget::Loaded { path, name, .. } => {
+                // The loading files, including this one, are found below.
+                let load_uri = self.resolve_load_path(&path, &uri)?;
+                if let Some(references) = self
+                    .get_ast_or_load_from_disk(&load_uri)?
+                    .and_then(|module| module.find_exported_references(&name))
+                {
+                    add_locations(&load_uri, references.spans, references.definition)?;
+                }
+                (load_uri, name)
+            }
+        };
+
+        for (uri, module) in self.open_modules() {
+            if uri == defining_uri {
+                continue;
+            }
+            let spans = module
+                .find_loaded_references(&name, |path| self.loads_from(path, &uri, &defining_uri));
+            add_locations(&uri, spans, None)?;
+        }
+        Ok(locations)
+    }
+
+    /// Rename the symbol under the cursor, along with all of its references.
+    ///
+    /// Like find references, symbols that other files can load are also renamed in every
+    /// open file that loads them, including the name in their `load()` statements. Global
+    /// symbols cannot be renamed, as they are not defined in any file.
+    ///
+    /// NOTE: Like goto definition, this uses the last valid parse of the files.
+    fn rename(&self, id: RequestId, params: RenameParams) {
+        self.send_response(new_response(id, self.find_rename_edits(params)));
+    }
+
+    fn find_rename_edits(&self, params: RenameParams) -> anyhow::Result<Option<WorkspaceEdit>> {
+        let uri: LspUrl = params.text_document_position.text_document.uri.try_into()?;
+        let position = params.text_document_position.position;
+        let new_name = params.new_name;
+        if !is_identifier(&new_name) {
+            return Err(RenameError::InvalidName(new_name).into());
+        }
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        let mut add_edits =
+            |uri: &LspUrl, edits: Vec<(ResolvedSpan, String)>| -> anyhow::Result<()> {
+                changes
+                    .entry(uri.try_into()?)
+                    .or_default()
+                    .extend(edits.into_iter().map(|(span, text)| TextEdit {
+                        range: span.into(),
+                        new_text: text,
+                    }));
+                Ok(())
+            };
+        let renamed = |spans: Vec<ResolvedSpan>| {
+            spans
+                .into_iter()
+                .map(|span| (span, new_name.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let references = match self
+            .get_ast(&uri)
+            .and_then(|module| module.find_references(position.line, position.character))
+        {
+            Some(references) => references,
+            None => return Ok(None),
+        };
+        let (defining_uri, name) = match references.target {
+            ReferenceTarget::Global { name } => return Err(RenameError::Global(name).into()),
+            // Renaming an alias doesn't affect the module it was loaded from.
+            ReferenceTarget::Local | ReferenceTarget::Loaded { aliased: true, .. } => {
+                add_edits(&uri, renamed(references.spans))?;
+                return Ok(Some(WorkspaceEdit::new(changes)));
+            }
+            ReferenceTarget::Exported { name } => {
+                add_edits(&uri, renamed(references.spans))?;
+                (uri, name)
+            }
+            ReferenceTarget::Loaded { path, name, .. } => {
+                // The loading files, including this one, are renamed below.
+                let load_uri = self.resolve_load_path(&path, &uri)?;
+                if let Some(references) = self
+                    .get_ast_or_load_from_disk(&load_uri)?
+                    .and_then(|module| module.find_exported_references(&name))
+                {
+                    add_edits(&load_uri, renamed(references.spans))?;
+                }
+                (load_uri, name)
+            }
+        };
+
+        for (uri, module) in self.open_modules() {
+            if uri == defining_uri {
+                continue;
+            }
+            let edits = module.rename_loaded_symbol(&name, &new_name, |path| {
+                self.loads_from(path, &uri, &defining_uri)
+            });
+            add_edits(&uri, edits)?;
+        }
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    /// The last valid parse of every open file.
+    fn open_modules(&self) -> Vec<(LspUrl, Arc<LspModule>)> {
+        self.last_valid_parse
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(uri, module)| (uri.clone(), module.dupe()))
+            .collect()
+    }
+
+    /// Whether the load `path` in the file at `uri` refers to the file at `defining_uri`.
+    fn loads_from(&self, path: &str, uri: &LspUrl, defining_uri: &LspUrl) -> bool {
+        self.resolve_load_path(path, uri)
+            .map_or(false, |load_uri| &load_uri == defining_uri)
+    }
+
     /// Get the file contents of a starlark: URI.
     fn get_starlark_file_contents(&self, id: RequestId, params: StarlarkFileContentsParams) {
         let response: anyhow::Result<_> = match params.uri {
@@ -620,10 +1162,22 @@ impl<T: LspContext> Backend<T> {
         for msg in &self.connection.receiver {
             match msg {
                 Message::Request(req) => {
-                    // TODO(nmj): Also implement DocumentSymbols so that some logic can
-                    //            be handled client side.
                     if let Some(params) = as_request::<GotoDefinition>(&req) {
                         self.goto_definition(req.id, params);
+                    } else if let Some(params) = as_request::<HoverRequest>(&req) {
+                        self.hover(req.id, params);
+                    } else if let Some(params) = as_request::<Completion>(&req) {
+                        self.completion(req.id, params);
+                    } else if let Some(params) = as_request::<DocumentSymbolRequest>(&req) {
+                        self.document_symbols(req.id, params);
+                    } else if let Some(params) = as_request::<References>(&req) {
+                        self.references(req.id, params);
+                    } else if let Some(params) = as_request::<Rename>(&req) {
+                        self.rename(req.id, params);
+                    } else if let Some(params) = as_request::<CodeActionRequest>(&req) {
+                        self.code_action(req.id, params);
+                    } else if let Some(params) = as_request::<Formatting>(&req) {
+                        self.formatting(req.id, params);
                     } else if let Some(params) = as_request::<StarlarkFileContentsRequest>(&req) {
                         self.get_starlark_file_contents(req.id, params);
                     } else if self.connection.handle_shutdown(&req)? {
@@ -689,12 +1243,46 @@ pub fn server_with_connection<T: LspContext>(
         connection,
         context,
         last_valid_parse: RwLock::default(),
+        open_files: RwLock::default(),
     }
     .main_loop(initialization_params)?;
 
     Ok(())
 }
 
+fn markdown_documentation(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    })
+}
+
+#[allow(deprecated)] // The `deprecated` field of `DocumentSymbol` is deprecated, but required.
+fn document_symbol(symbol: Symbol) -> DocumentSymbol {
+    DocumentSymbol {
+        name: symbol.name,
+        detail: symbol.detail,
+        kind: match symbol.kind {
+            SymbolKind::Function => lsp_types::SymbolKind::FUNCTION,
+            SymbolKind::Variable => lsp_types::SymbolKind::VARIABLE,
+            SymbolKind::Target => lsp_types::SymbolKind::OBJECT,
+        },
+        tags: None,
+        deprecated: None,
+        range: symbol.span.into(),
+        selection_range: symbol.name_span.into(),
+        children: None,
+    }
+}
+
+/// The position of the end of `text`, in UTF-16 code units as LSP expects.
+fn end_position(text: &str) -> Position {
+    let line = text.matches('\n').count();
+    let last_line = &text[text.rfind('\n').map_or(0, |i| i + 1)..];
+    let character = last_line.encode_utf16().count();
+    Position::new(line as u32, character as u32)
+}
+
 fn as_notification<T>(x: &Notification) -> Option<T::Params>
 where
     T: lsp_types::notification::Notification,
@@ -768,21 +1356,48 @@ where
 //            some paths. Revisit later.
 #[cfg(all(test, not(windows)))]
 mod test {
+    use std::collections::HashMap;
     use std::path::Path;
     use std::path::PathBuf;
 
     use anyhow::Context;
     use lsp_server::Request;
     use lsp_server::RequestId;
+    use lsp_types::request::CodeActionRequest;
+    use lsp_types::request::Completion;
+    use lsp_types::request::DocumentSymbolRequest;
+    use lsp_types::request::Formatting;
     use lsp_types::request::GotoDefinition;
+    use lsp_types::request::HoverRequest;
+    use lsp_types::request::References;
+    use lsp_types::request::Rename;
+    use lsp_types::CodeActionContext;
+    use lsp_types::CodeActionOrCommand;
+    use lsp_types::CodeActionParams;
+    use lsp_types::CompletionItemKind;
+    use lsp_types::CompletionParams;
+    use lsp_types::CompletionResponse;
+    use lsp_types::DocumentFormattingParams;
+    use lsp_types::DocumentSymbolParams;
+    use lsp_types::DocumentSymbolResponse;
     use lsp_types::GotoDefinitionParams;
     use lsp_types::GotoDefinitionResponse;
+    use lsp_types::Hover;
+    use lsp_types::HoverContents;
+    use lsp_types::HoverParams;
+    use lsp_types::Location;
     use lsp_types::LocationLink;
     use lsp_types::Position;
     use lsp_types::Range;
+    use lsp_types::ReferenceContext;
+    use lsp_types::ReferenceParams;
+    use lsp_types::RenameParams;
+    use lsp_types::SymbolKind;
     use lsp_types::TextDocumentIdentifier;
     use lsp_types::TextDocumentPositionParams;
+    use lsp_types::TextEdit;
     use lsp_types::Url;
+    use lsp_types::WorkspaceEdit;
     use textwrap::dedent;
 
     use crate::analysis::definition::helpers::FixtureWithRanges;
@@ -1809,4 +2424,482 @@ mod test {
         }
         Ok(())
     }
+
+    fn formatting_request(server: &mut TestServer, uri: Url) -> Request {
+        server.new_request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            options: Default::default(),
+            work_done_progress_params: Default::default(),
+        })
+    }
+
+    #[test]
+    fn formats_open_files() -> anyhow::Result<()> {
+        let uri = temp_file_uri("format.star");
+        let mut server = TestServer::new()?;
+
+        server.open_file(uri.clone(), "x = [1,2]  # Two.\ny = 'é'".to_owned())?;
+        let req = formatting_request(&mut server, uri.clone());
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Vec<TextEdit>>(request_id)?;
+        let expected = vec![TextEdit {
+            range: Range::new(Position::new(0, 0), Position::new(1, 7)),
+            new_text: "x = [1, 2]  # Two.\ny = \"é\"\n".to_owned(),
+        }];
+        assert_eq!(expected, response);
+
+        // Already formatted files need no edits.
+        server.change_file(uri.clone(), "x = [1, 2]\n".to_owned())?;
+        let req = formatting_request(&mut server, uri);
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Vec<TextEdit>>(request_id)?;
+        assert!(response.is_empty());
+
+        // Files that are not open can't be formatted.
+        let req = formatting_request(&mut server, temp_file_uri("not_open.star"));
+        let request_id = server.send_request(req)?;
+        assert!(server.get_response::<Vec<TextEdit>>(request_id).is_err());
+        Ok(())
+    }
+
+    fn code_actions(
+        server: &mut TestServer,
+        uri: Url,
+        range: Range,
+    ) -> anyhow::Result<Vec<CodeActionOrCommand>> {
+        let req = server.new_request::<CodeActionRequest>(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri },
+            range,
+            context: CodeActionContext::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        server.get_response::<Vec<CodeActionOrCommand>>(request_id)
+    }
+
+    #[test]
+    fn offers_lint_fixes() -> anyhow::Result<()> {
+        let uri = temp_file_uri("fixes.star");
+        let mut server = TestServer::new()?;
+        let range = Range::new(Position::new(2, 4), Position::new(2, 4));
+
+        server.open_file(uri.clone(), "def f():\n    pass\n".to_owned())?;
+        server.change_file(uri.clone(), "def f():\n    f()\n    return\n".to_owned())?;
+        let response = code_actions(&mut server, uri.clone(), range)?;
+
+        let action = match response.as_slice() {
+            [CodeActionOrCommand::CodeAction(action)] => action,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Expected one code action, got {:?}",
+                    response
+                ));
+            }
+        };
+        let expected = vec![TextEdit {
+            range: Range::new(Position::new(2, 0), Position::new(3, 0)),
+            new_text: String::new(),
+        }];
+        assert_eq!(
+            Some(&expected),
+            action
+                .edit
+                .as_ref()
+                .and_then(|x| x.changes.as_ref())
+                .and_then(|x| x.get(&uri))
+        );
+
+        // Once the file doesn't parse, the last valid parse is out of date, so we can't offer its
+        // fixes.
+        server.change_file(uri.clone(), "def f(:\n    f()\n    return\n".to_owned())?;
+        assert!(code_actions(&mut server, uri, range)?.is_empty());
+        Ok(())
+    }
+
+    fn hover_markdown(
+        server: &mut TestServer,
+        uri: Url,
+        line: u32,
+        character: u32,
+    ) -> anyhow::Result<Option<(String, Option<Range>)>> {
+        let req = server.new_request::<HoverRequest>(HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line, character },
+            },
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        match server.get_response::<Option<Hover>>(request_id)? {
+            Some(Hover {
+                contents: HoverContents::Markup(markup),
+                range,
+            }) => Ok(Some((markup.value, range))),
+            Some(hover) => Err(anyhow::anyhow!("Expected markdown, got {:?}", hover)),
+            None => Ok(None),
+        }
+    }
+
+    #[test]
+    fn hovers_over_functions() -> anyhow::Result<()> {
+        let foo_uri = temp_file_uri("hover_foo.star");
+        let bar_uri = temp_file_uri("hover_bar.star");
+
+        let foo_contents = dedent(
+            r#"
+            load("{load}", "baz")
+            def foo(x):
+                """Does foo things."""
+                return x
+            <foo>foo</foo>(1)
+            <baz>baz</baz>()
+            <native>native_function1</native>()
+            "#,
+        )
+        .replace("{load}", bar_uri.path())
+        .trim()
+        .to_owned();
+        let foo = FixtureWithRanges::from_fixture(foo_uri.path(), &foo_contents)?;
+        let bar_contents = "def baz(y = 1):\n    \"\"\"Does baz things.\"\"\"\n    pass\n";
+
+        let mut server = TestServer::new()?;
+        server.open_file(foo_uri.clone(), foo.program())?;
+        server.open_file(bar_uri, bar_contents.to_owned())?;
+
+        let (markdown, range) = hover_markdown(
+            &mut server,
+            foo_uri.clone(),
+            foo.begin_line("foo"),
+            foo.begin_column("foo"),
+        )?
+        .context("expected hover for foo")?;
+        assert!(markdown.contains("def foo(x)"), "{}", markdown);
+        assert!(markdown.contains("Does foo things."), "{}", markdown);
+        assert_eq!(Some(foo.span("foo").into()), range);
+
+        let (markdown, _) = hover_markdown(
+            &mut server,
+            foo_uri.clone(),
+            foo.begin_line("baz"),
+            foo.begin_column("baz"),
+        )?
+        .context("expected hover for baz")?;
+        assert!(markdown.contains("def baz(y = 1)"), "{}", markdown);
+        assert!(markdown.contains("Does baz things."), "{}", markdown);
+
+        let (markdown, _) = hover_markdown(
+            &mut server,
+            foo_uri.clone(),
+            foo.begin_line("native"),
+            foo.begin_column("native"),
+        )?
+        .context("expected hover for native_function1")?;
+        assert!(markdown.contains("def native_function1()"), "{}", markdown);
+
+        // Nothing to show for keywords.
+        assert_eq!(None, hover_markdown(&mut server, foo_uri, 1, 0)?);
+        Ok(())
+    }
+
+    #[test]
+    fn completes_symbols_and_named_arguments() -> anyhow::Result<()> {
+        let uri = temp_file_uri("completion.star");
+        let mut server = TestServer::new()?;
+        let contents = "def foo(alpha, beta = 1):\n    return alpha\nfoo(alpha = 1, )\n";
+        server.open_file(uri.clone(), contents.to_owned())?;
+
+        let req = server.new_request::<Completion>(CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position::new(2, 15),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let request_id = server.send_request(req)?;
+        let items = match server.get_response::<CompletionResponse>(request_id)? {
+            CompletionResponse::Array(items) => items,
+            CompletionResponse::List(list) => list.items,
+        };
+        let items = items
+            .into_iter()
+            .map(|x| (x.label, x.kind, x.insert_text))
+            .collect::<Vec<_>>();
+
+        assert!(items.contains(&(
+            "beta".to_owned(),
+            Some(CompletionItemKind::PROPERTY),
+            Some("beta = ".to_owned())
+        )));
+        // `alpha` was already passed.
+        assert!(!items.contains(&(
+            "alpha".to_owned(),
+            Some(CompletionItemKind::PROPERTY),
+            Some("alpha = ".to_owned())
+        )));
+        assert!(items.contains(&("foo".to_owned(), Some(CompletionItemKind::FUNCTION), None)));
+        assert!(items.contains(&(
+            "native_function1".to_owned(),
+            Some(CompletionItemKind::FUNCTION),
+            None
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn lists_document_symbols() -> anyhow::Result<()> {
+        let uri = temp_file_uri("symbols.star");
+        let mut server = TestServer::new()?;
+        let contents = "x = 1\ndef foo(name):\n    pass\nfoo(name = \"target\")\n";
+        server.open_file(uri.clone(), contents.to_owned())?;
+
+        let req = server.new_request::<DocumentSymbolRequest>(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let symbols = match server.get_response::<DocumentSymbolResponse>(request_id)? {
+            DocumentSymbolResponse::Nested(symbols) => symbols,
+            response => return Err(anyhow::anyhow!("Expected nested symbols: {:?}", response)),
+        };
+        let symbols = symbols
+            .into_iter()
+            .map(|x| (x.name, x.kind, x.detail, x.selection_range))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (
+                    "x".to_owned(),
+                    SymbolKind::VARIABLE,
+                    None,
+                    Range::new(Position::new(0, 0), Position::new(0, 1))
+                ),
+                (
+                    "foo".to_owned(),
+                    SymbolKind::FUNCTION,
+                    None,
+                    Range::new(Position::new(1, 4), Position::new(1, 7))
+                ),
+                (
+                    "target".to_owned(),
+                    SymbolKind::OBJECT,
+                    Some("foo".to_owned()),
+                    Range::new(Position::new(3, 11), Position::new(3, 19))
+                ),
+            ],
+            symbols
+        );
+        Ok(())
+    }
+
+    fn find_references(
+        server: &mut TestServer,
+        uri: Url,
+        line: u32,
+        character: u32,
+    ) -> anyhow::Result<Vec<Location>> {
+        let req = server.new_request::<References>(ReferenceParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line, character },
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        });
+        let request_id = server.send_request(req)?;
+        server.get_response::<Vec<Location>>(request_id)
+    }
+
+    #[test]
+    fn finds_references_across_loads() -> anyhow::Result<()> {
+        let foo_uri = temp_file_uri("references_foo.star");
+        let bar_uri = temp_file_uri("references_bar.star");
+
+        let foo_contents = dedent(
+            r#"
+            load("{load}", <baz_load>"baz"</baz_load>)
+            <baz>baz</baz>()
+            "#,
+        )
+        .replace("{load}", bar_uri.path())
+        .trim()
+        .to_owned();
+        let bar_contents = dedent(
+            r#"
+            def <baz_def>baz</baz_def>():
+                pass
+            <baz>baz</baz>()
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let foo = FixtureWithRanges::from_fixture(foo_uri.path(), &foo_contents)?;
+        let bar = FixtureWithRanges::from_fixture(bar_uri.path(), &bar_contents)?;
+
+        let mut server = TestServer::new()?;
+        server.open_file(foo_uri.clone(), foo.program())?;
+        server.open_file(bar_uri.clone(), bar.program())?;
+
+        let expected = vec![
+            Location::new(bar_uri.clone(), bar.span("baz_def").into()),
+            Location::new(bar_uri.clone(), bar.span("baz").into()),
+            Location::new(foo_uri.clone(), foo.span("baz_load").into()),
+            Location::new(foo_uri.clone(), foo.span("baz").into()),
+        ];
+
+        // From the file that loads the symbol.
+        let references = find_references(
+            &mut server,
+            foo_uri.clone(),
+            foo.begin_line("baz"),
+            foo.begin_column("baz"),
+        )?;
+        assert_eq!(expected, references);
+
+        // From the file that defines the symbol.
+        let references = find_references(
+            &mut server,
+            bar_uri,
+            bar.begin_line("baz_def"),
+            bar.begin_column("baz_def"),
+        )?;
+        assert_eq!(expected, references);
+        Ok(())
+    }
+
+    fn rename(
+        server: &mut TestServer,
+        uri: Url,
+        line: u32,
+        character: u32,
+        new_name: &str,
+    ) -> anyhow::Result<Option<WorkspaceEdit>> {
+        let req = server.new_request::<Rename>(RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line, character },
+            },
+            new_name: new_name.to_owned(),
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        server.get_response::<Option<WorkspaceEdit>>(request_id)
+    }
+
+    #[test]
+    fn renames_across_loads() -> anyhow::Result<()> {
+        let foo_uri = temp_file_uri("rename_foo.star");
+        let bar_uri = temp_file_uri("rename_bar.star");
+
+        let foo_contents = dedent(
+            r#"
+            load("{load}", <baz_load>"baz"</baz_load>, <x_def>x</x_def> = <x_load>'baz'</x_load>)
+            <baz>baz</baz>(<x>x</x>, <len>len</len>)
+            "#,
+        )
+        .replace("{load}", bar_uri.path())
+        .trim()
+        .to_owned();
+        let bar_contents = dedent(
+            r#"
+            def <baz_def>baz</baz_def>(_x, _y):
+                pass
+            <baz>baz</baz>(1, 2)
+            "#,
+        )
+        .trim()
+        .to_owned();
+        let foo = FixtureWithRanges::from_fixture(foo_uri.path(), &foo_contents)?;
+        let bar = FixtureWithRanges::from_fixture(bar_uri.path(), &bar_contents)?;
+
+        let mut server = TestServer::new()?;
+        server.open_file(foo_uri.clone(), foo.program())?;
+        server.open_file(bar_uri.clone(), bar.program())?;
+
+        let edit = |range: ResolvedSpan, new_text: &str| TextEdit {
+            range: range.into(),
+            new_text: new_text.to_owned(),
+        };
+        let expected = Some(WorkspaceEdit::new(HashMap::from([
+            (
+                bar_uri.clone(),
+                vec![
+                    edit(bar.span("baz_def"), "qux"),
+                    edit(bar.span("baz"), "qux"),
+                ],
+            ),
+            (
+                foo_uri.clone(),
+                vec![
+                    edit(foo.span("baz_load"), "\"qux\""),
+                    edit(foo.span("x_load"), "'qux'"),
+                    edit(foo.span("baz"), "qux"),
+                ],
+            ),
+        ])));
+
+        // From the file that loads the symbol.
+        let edits = rename(
+            &mut server,
+            foo_uri.clone(),
+            foo.begin_line("baz"),
+            foo.begin_column("baz"),
+            "qux",
+        )?;
+        assert_eq!(expected, edits);
+
+        // From the file that defines the symbol.
+        let edits = rename(
+            &mut server,
+            bar_uri,
+            bar.begin_line("baz_def"),
+            bar.begin_column("baz_def"),
+            "qux",
+        )?;
+        assert_eq!(expected, edits);
+
+        // Renaming an alias only changes the file it is in.
+        let edits = rename(
+            &mut server,
+            foo_uri.clone(),
+            foo.begin_line("x"),
+            foo.begin_column("x"),
+            "y",
+        )?;
+        let expected = Some(WorkspaceEdit::new(HashMap::from([(
+            foo_uri.clone(),
+            vec![edit(foo.span("x_def"), "y"), edit(foo.span("x"), "y")],
+        )])));
+        assert_eq!(expected, edits);
+
+        // Globals and names that aren't identifiers can't be used.
+        assert!(
+            rename(
+                &mut server,
+                foo_uri.clone(),
+                foo.begin_line("len"),
+                foo.begin_column("len"),
+                "length",
+            )
+            .is_err()
+        );
+        assert!(
+            rename(
+                &mut server,
+                foo_uri,
+                foo.begin_line("baz"),
+                foo.begin_column("baz"),
+                "not valid",
+            )
+            .is_err()
+        );
+        Ok(())
+    }
 }
@@ -57,6 +57,7 @@ use lsp_types::VersionedTextDocumentIdentifier;
 use maplit::hashmap;
 use serde::de::DeserializeOwned;
 
+use crate::collections::SmallMap;
 use crate::docs::render_docs_as_code;
 use crate::docs::Doc;
 use crate::docs::DocFunction;
@@ -120,6 +121,7 @@ struct TestServerContext {
     dirs: Arc<RwLock<HashSet<PathBuf>>>,
     builtin_docs: Arc<HashMap<LspUrl, String>>,
     builtin_symbols: Arc<HashMap<String, LspUrl>>,
+    global_docs: Arc<SmallMap<String, Doc>>,
 }
 
 impl LspContext for TestServerContext {
@@ -227,6 +229,13 @@ impl LspContext for TestServerContext {
     ) -> anyhow::Result<Option<LspUrl>> {
         Ok(self.builtin_symbols.get(symbol).cloned())
     }
+
+    fn get_global_symbol_docs(
+        &self,
+        _current_file: &LspUrl,
+    ) -> anyhow::Result<Arc<SmallMap<String, Doc>>> {
+        Ok(self.global_docs.dupe())
+    }
 }
 
 /// A server for use in testing that provides helpers for sending requests, correlating
@@ -357,16 +366,19 @@ impl TestServer {
         let builtin = Self::testing_builtins(&std::env::current_dir()?)?;
         let mut builtin_docs = HashMap::with_capacity(builtin.len());
         let mut builtin_symbols = HashMap::new();
+        let mut global_docs = SmallMap::new();
 
         for (u, ds) in builtin {
             builtin_docs.insert(u.clone(), render_docs_as_code(&ds));
             for d in ds {
-                builtin_symbols.insert(d.id.name, u.clone());
+                builtin_symbols.insert(d.id.name.clone(), u.clone());
+                global_docs.insert(d.id.name.clone(), d);
             }
         }
 
         let builtin_docs = Arc::new(builtin_docs);
         let builtin_symbols = Arc::new(builtin_symbols);
+        let global_docs = Arc::new(global_docs);
 
         let prelude_file_contents = builtin_docs
             .iter()
@@ -382,6 +394,7 @@ impl TestServer {
             dirs: dirs.dupe(),
             builtin_docs: builtin_docs.dupe(),
             builtin_symbols,
+            global_docs,
         };
 
         let server_thread = std::thread::spawn(|| {
@@ -0,0 +1,562 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `set()` function and methods for the `set` type.
+
+use starlark_derive::starlark_module;
+use starlark_map::small_set::SmallSet;
+
+use crate as starlark;
+use crate::environment::GlobalsBuilder;
+use crate::environment::MethodsBuilder;
+use crate::values::none::NoneType;
+use crate::values::set::value::collect_set;
+use crate::values::set::Set;
+use crate::values::set::SetMut;
+use crate::values::set::SetRef;
+use crate::values::Heap;
+use crate::values::Value;
+
+/// Collect the elements of an iterable into a set, failing if any element is unhashable.
+fn collect_iterable<'v>(xs: Value<'v>, heap: &'v Heap) -> anyhow::Result<SmallSet<Value<'v>>> {
+    if let Some(xs) = SetRef::from_value(xs) {
+        return Ok(collect_set(xs.iter_hashed()));
+    }
+    let mut res = SmallSet::new();
+    for x in xs.iterate(heap)? {
+        res.insert_hashed(x.get_hashed()?);
+    }
+    Ok(res)
+}
+
+#[starlark_module]
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    /// [set](
+    /// https://bazel.build/rules/lib/globals/all#set
+    /// ): create a set.
+    ///
+    /// `set(x)` returns a new set containing the elements of the iterable
+    /// sequence `x`, in the order they are first encountered. With no
+    /// argument, `set()` returns a new empty set.
+    ///
+    /// `set` fails if any element of `x` is unhashable.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// # (
+    /// set() == set([])
+    /// # and
+    /// list(set([3, 1, 3, 2])) == [3, 1, 2]
+    /// # and
+    /// len(set("abca".elems())) == 3
+    /// # )"#);
+    /// ```
+    #[starlark(type = Set::TYPE, speculative_exec_safe)]
+    fn set<'v>(
+        #[starlark(require = pos, type = "iter(\"\")")] elements: Option<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Set<'v>> {
+        match elements {
+            None => Ok(Set::default()),
+            Some(elements) => Ok(Set::new(collect_iterable(elements, heap)?)),
+        }
+    }
+}
+
+#[starlark_module]
+pub(crate) fn set_methods(registry: &mut MethodsBuilder) {
+    /// [set.add](
+    /// https://bazel.build/rules/lib/core/set#add
+    /// ): add an element to the set.
+    ///
+    /// `S.add(x)` adds `x` to the set S, and returns `None`. Adding an element
+    /// which is already present does nothing.
+    ///
+    /// `add` fails if `x` is unhashable, or the set is frozen or has active
+    /// iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.add(3)
+    /// x.add(1)
+    /// x == set([1, 2, 3])
+    /// # "#);
+    /// ```
+    fn add<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] element: Value<'v>,
+    ) -> anyhow::Result<NoneType> {
+        let element = element.get_hashed()?;
+        SetMut::from_value(this)?.insert_hashed(element);
+        Ok(NoneType)
+    }
+
+    /// [set.clear](
+    /// https://bazel.build/rules/lib/core/set#clear
+    /// ): remove all the elements of a set.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.clear()
+    /// x == set()
+    /// # "#);
+    /// ```
+    fn clear(this: Value) -> anyhow::Result<NoneType> {
+        SetMut::from_value(this)?.clear();
+        Ok(NoneType)
+    }
+
+    /// [set.difference](
+    /// https://bazel.build/rules/lib/core/set#difference
+    /// ): return a new set with the elements not in any of the others.
+    ///
+    /// `S.difference(*others)` returns a new set containing the elements of S
+    /// which are not present in any of the iterables `others`.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// set([1, 2, 3, 4]).difference([2], set([4, 5])) == set([1, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn difference<'v>(
+        this: SetRef<'v>,
+        #[starlark(args)] others: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Set<'v>> {
+        let mut res = collect_set(this.iter_hashed());
+        for other in others {
+            for x in other.iterate(heap)? {
+                res.remove_hashed(x.get_hashed()?.as_ref());
+            }
+        }
+        Ok(Set::new(res))
+    }
+
+    /// [set.difference_update](
+    /// https://bazel.build/rules/lib/core/set#difference_update
+    /// ): remove the elements found in any of the others.
+    ///
+    /// `S.difference_update(*others)` removes from S every element present in
+    /// any of the iterables `others`, and returns `None`.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2, 3, 4])
+    /// x.difference_update([2], set([4, 5]))
+    /// x == set([1, 3])
+    /// # "#);
+    /// ```
+    fn difference_update<'v>(
+        this: Value<'v>,
+        #[starlark(args)] others: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<NoneType> {
+        // Collect first, since `this` may also appear in `others`.
+        let mut remove = SmallSet::new();
+        for other in others {
+            for x in collect_iterable(other, heap)?.iter_hashed() {
+                remove.insert_hashed(x.copied());
+            }
+        }
+        let mut this = SetMut::from_value(this)?;
+        for x in remove.iter_hashed() {
+            this.remove_hashed(x.copied());
+        }
+        Ok(NoneType)
+    }
+
+    /// [set.discard](
+    /// https://bazel.build/rules/lib/core/set#discard
+    /// ): remove an element from the set if it is present.
+    ///
+    /// `S.discard(x)` removes `x` from the set S if present, and returns
+    /// `None`. Unlike `remove`, it does not fail if `x` is absent.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.discard(2)
+    /// x.discard(3)
+    /// x == set([1])
+    /// # "#);
+    /// ```
+    fn discard<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] element: Value<'v>,
+    ) -> anyhow::Result<NoneType> {
+        let element = element.get_hashed()?;
+        SetMut::from_value(this)?.remove_hashed(element);
+        Ok(NoneType)
+    }
+
+    /// [set.intersection](
+    /// https://bazel.build/rules/lib/core/set#intersection
+    /// ): return a new set with the elements common to all the others.
+    ///
+    /// `S.intersection(*others)` returns a new set containing the elements of
+    /// S which are present in all of the iterables `others`.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// set([1, 2, 3]).intersection([2, 3, 4], set([3, 2])) == set([2, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn intersection<'v>(
+        this: SetRef<'v>,
+        #[starlark(args)] others: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Set<'v>> {
+        let mut res = collect_set(this.iter_hashed());
+        for other in others {
+            let other = collect_iterable(other, heap)?;
+            res = collect_set(
+                res.iter_hashed()
+                    .map(|x| x.copied())
+                    .filter(|x| other.contains_hashed(x.as_ref())),
+            );
+        }
+        Ok(Set::new(res))
+    }
+
+    /// [set.intersection_update](
+    /// https://bazel.build/rules/lib/core/set#intersection_update
+    /// ): keep only the elements found in all the others.
+    ///
+    /// `S.intersection_update(*others)` removes from S every element which is
+    /// not present in all of the iterables `others`, and returns `None`.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2, 3])
+    /// x.intersection_update([2, 3, 4])
+    /// x == set([2, 3])
+    /// # "#);
+    /// ```
+    fn intersection_update<'v>(
+        this: Value<'v>,
+        #[starlark(args)] others: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<NoneType> {
+        let others = others
+            .into_iter()
+            .map(|x| collect_iterable(x, heap))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut this = SetMut::from_value(this)?;
+        let remove = this
+            .iter_hashed()
+            .filter(|x| !others.iter().all(|o| o.contains_hashed(x.as_ref())))
+            .collect::<Vec<_>>();
+        for x in remove {
+            this.remove_hashed(x);
+        }
+        Ok(NoneType)
+    }
+
+    /// [set.isdisjoint](
+    /// https://bazel.build/rules/lib/core/set#isdisjoint
+    /// ): test whether the set has no elements in common with another.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// # (
+    /// set([1, 2]).isdisjoint([3, 4])
+    /// # and
+    /// not set([1, 2]).isdisjoint(set([2]))
+    /// # )"#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn isdisjoint<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos, type = "iter(\"\")")] other: Value<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<bool> {
+        for x in other.iterate(heap)? {
+            if this.contains_hashed(x.get_hashed()?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// [set.issubset](
+    /// https://bazel.build/rules/lib/core/set#issubset
+    /// ): test whether every element of the set is in another.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// # (
+    /// set([1, 2]).issubset([1, 2, 3])
+    /// # and
+    /// not set([1, 4]).issubset(set([1, 2, 3]))
+    /// # )"#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn issubset<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos, type = "iter(\"\")")] other: Value<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<bool> {
+        let other = collect_iterable(other, heap)?;
+        Ok(this
+            .iter_hashed()
+            .all(|x| other.contains_hashed(x.as_ref())))
+    }
+
+    /// [set.issuperset](
+    /// https://bazel.build/rules/lib/core/set#issuperset
+    /// ): test whether every element of another is in the set.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// # (
+    /// set([1, 2, 3]).issuperset([1, 2])
+    /// # and
+    /// not set([1, 2]).issuperset(set([1, 4]))
+    /// # )"#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn issuperset<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos, type = "iter(\"\")")] other: Value<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<bool> {
+        for x in other.iterate(heap)? {
+            if !this.contains_hashed(x.get_hashed()?) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// [set.pop](
+    /// https://bazel.build/rules/lib/core/set#pop
+    /// ): remove and return the last element of the set.
+    ///
+    /// Sets iterate in insertion order, so this is the most recently added element.
+    /// Unlike Bazel, which removes the first element, this takes constant time.
+    ///
+    /// `pop` fails if the set is empty, frozen, or has active iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// # (
+    /// x.pop() == 2
+    /// # and
+    /// x == set([1])
+    /// # )"#);
+    /// ```
+    ///
+    /// Failure:
+    ///
+    /// ```
+    /// # starlark::assert::fail(r#"
+    /// set().pop()   # error: empty set
+    /// # "#, "empty set");
+    /// ```
+    fn pop<'v>(this: Value<'v>) -> anyhow::Result<Value<'v>> {
+        match SetMut::from_value(this)?.pop() {
+            Some(x) => Ok(x),
+            None => Err(anyhow::anyhow!("Cannot .pop() on an empty set")),
+        }
+    }
+
+    /// [set.remove](
+    /// https://bazel.build/rules/lib/core/set#remove
+    /// ): remove an element from the set.
+    ///
+    /// `S.remove(x)` removes `x` from the set S, and returns `None`.
+    ///
+    /// `remove` fails if `x` is not in the set, or the set is frozen or has
+    /// active iterators.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.remove(2)
+    /// x == set([1])
+    /// # "#);
+    /// ```
+    ///
+    /// Failure:
+    ///
+    /// ```
+    /// # starlark::assert::fail(r#"
+    /// set([1]).remove(2)   # error: not found
+    /// # "#, "not found");
+    /// ```
+    fn remove<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos)] element: Value<'v>,
+    ) -> anyhow::Result<NoneType> {
+        let hashed = element.get_hashed()?;
+        if SetMut::from_value(this)?.remove_hashed(hashed) {
+            Ok(NoneType)
+        } else {
+            Err(anyhow::anyhow!(
+                "Element `{}` not found in set `{}`",
+                element.to_repr(),
+                this.to_repr()
+            ))
+        }
+    }
+
+    /// [set.symmetric_difference](
+    /// https://bazel.build/rules/lib/core/set#symmetric_difference
+    /// ): return a new set with the elements in exactly one of the set and another.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// set([1, 2]).symmetric_difference([2, 3]) == set([1, 3])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn symmetric_difference<'v>(
+        this: SetRef<'v>,
+        #[starlark(require = pos, type = "iter(\"\")")] other: Value<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Set<'v>> {
+        let other = collect_iterable(other, heap)?;
+        let mut res = collect_set(
+            this.iter_hashed()
+                .filter(|x| !other.contains_hashed(x.as_ref())),
+        );
+        for x in other.iter_hashed() {
+            if !this.contains_hashed(x.copied()) {
+                res.insert_hashed(x.copied());
+            }
+        }
+        Ok(Set::new(res))
+    }
+
+    /// [set.symmetric_difference_update](
+    /// https://bazel.build/rules/lib/core/set#symmetric_difference_update
+    /// ): keep only the elements in exactly one of the set and another.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1, 2])
+    /// x.symmetric_difference_update([2, 3])
+    /// x == set([1, 3])
+    /// # "#);
+    /// ```
+    fn symmetric_difference_update<'v>(
+        this: Value<'v>,
+        #[starlark(require = pos, type = "iter(\"\")")] other: Value<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<NoneType> {
+        let other = collect_iterable(other, heap)?;
+        let mut this = SetMut::from_value(this)?;
+        for x in other.iter_hashed() {
+            let x = x.copied();
+            if !this.remove_hashed(x) {
+                this.insert_hashed(x);
+            }
+        }
+        Ok(NoneType)
+    }
+
+    /// [set.union](
+    /// https://bazel.build/rules/lib/core/set#union
+    /// ): return a new set with the elements of the set and all the others.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// set([1, 2]).union([2, 3], set([4])) == set([1, 2, 3, 4])
+    /// # "#);
+    /// ```
+    #[starlark(speculative_exec_safe)]
+    fn union<'v>(
+        this: SetRef<'v>,
+        #[starlark(args)] others: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Set<'v>> {
+        let mut res = collect_set(this.iter_hashed());
+        for other in others {
+            for x in other.iterate(heap)? {
+                res.insert_hashed(x.get_hashed()?);
+            }
+        }
+        Ok(Set::new(res))
+    }
+
+    /// [set.update](
+    /// https://bazel.build/rules/lib/core/set#update
+    /// ): add the elements of all the others to the set.
+    ///
+    /// `S.update(*others)` adds every element of the iterables `others` to S,
+    /// and returns `None`.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = set([1])
+    /// x.update([2, 1], set([3]))
+    /// x == set([1, 2, 3])
+    /// # "#);
+    /// ```
+    fn update<'v>(
+        this: Value<'v>,
+        #[starlark(args)] others: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<NoneType> {
+        // Collect first, since `this` may also appear in `others`.
+        let mut add = SmallSet::new();
+        for other in others {
+            for x in collect_iterable(other, heap)?.iter_hashed() {
+                add.insert_hashed(x.copied());
+            }
+        }
+        let mut this = SetMut::from_value(this)?;
+        for x in add.iter_hashed() {
+            this.insert_hashed(x.copied());
+        }
+        Ok(NoneType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_set_methods_alias() {
+        assert::is_true(
+            r#"
+x = set([1, 2])
+x.update(x)
+x.difference_update(x)
+x == set()
+"#,
+        );
+    }
+
+    #[test]
+    fn test_set_unhashable() {
+        assert::fail("set([[1]])", "not hashable");
+        assert::fail("set().add({})", "not hashable");
+    }
+
+    #[test]
+    fn test_set_type() {
+        assert::eq("type(set())", "'set'");
+        assert::is_true("type(set([1])) == type(set())");
+    }
+}
@@ -109,6 +109,10 @@ pub struct AstModule {
     pub(crate) codemap: CodeMap,
     pub(crate) statement: AstStmt,
     pub(crate) dialect: Dialect,
+    /// The comments in the module, in source order. [`parse`](AstModule::parse) only keeps the
+    /// comments that disable lints, [`parse_with_comments`](AstModule::parse_with_comments) keeps
+    /// all of them.
+    pub(crate) comments: Vec<AstString>,
 }
 
 impl AstModule {
@@ -0,0 +1,1324 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Print an [`AstModule`] in a canonical style.
+//!
+//! The style is close to what buildifier produces, but understands the whole dialect that we
+//! parse (e.g. type annotations and keyword-only arguments):
+//!
+//! * Blocks are indented by four spaces, and every statement is on its own line.
+//! * Runs of blank lines are collapsed to a single blank line.
+//! * Calls, lists, dicts, tuples, comprehensions, parameter lists and `load`s are printed on
+//!   one line if they fit. Otherwise, and if they had a trailing comma or contained comments in
+//!   the original source, they are printed with one element per line and a trailing comma.
+//! * Parentheses are only printed where they are needed.
+//! * Literals are printed as written, except that single-quoted strings that don't need
+//!   escaping are printed with double quotes.
+//!
+//! Comments are kept where they were, either on their own line or at the end of a line. If an
+//! expression we would print on one line has comments in it, we put it in parentheses (if it
+//! isn't already) and break its lines at the comments.
+
+use crate::codemap::CodeMap;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+use crate::syntax::ast::ArgumentP;
+use crate::syntax::ast::AssignP;
+use crate::syntax::ast::AstArgument;
+use crate::syntax::ast::AstAssign;
+use crate::syntax::ast::AstAssignIdent;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstLiteral;
+use crate::syntax::ast::AstNoPayload;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::AstString;
+use crate::syntax::ast::BinOp;
+use crate::syntax::ast::Clause;
+use crate::syntax::ast::DefP;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::ForClause;
+use crate::syntax::ast::LambdaP;
+use crate::syntax::ast::Load;
+use crate::syntax::ast::ParameterP;
+use crate::syntax::ast::Stmt;
+use crate::syntax::AstModule;
+
+const INDENT: &str = "    ";
+
+/// Lines longer than this are broken, where we know how to break them.
+const MAX_WIDTH: usize = 100;
+
+/// How tightly an expression binds, from loosest to tightest, following the grammar. An
+/// expression needs parentheses where an expression that binds tighter is expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    /// `lambda`, `x if c else y`.
+    Test,
+    Or,
+    And,
+    Not,
+    /// Comparisons, which don't associate.
+    Compare,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Arith,
+    Product,
+    /// Unary `+`, `-` and `~`.
+    Unary,
+    /// Everything else: literals, identifiers, calls, etc.
+    Primary,
+}
+
+impl Prec {
+    fn tighter(self) -> Prec {
+        match self {
+            Prec::Test => Prec::Or,
+            Prec::Or => Prec::And,
+            Prec::And => Prec::Not,
+            Prec::Not => Prec::Compare,
+            Prec::Compare => Prec::BitOr,
+            Prec::BitOr => Prec::BitXor,
+            Prec::BitXor => Prec::BitAnd,
+            Prec::BitAnd => Prec::Shift,
+            Prec::Shift => Prec::Arith,
+            Prec::Arith => Prec::Product,
+            Prec::Product => Prec::Unary,
+            Prec::Unary | Prec::Primary => Prec::Primary,
+        }
+    }
+
+    fn of_op(op: BinOp) -> Prec {
+        match op {
+            BinOp::Or => Prec::Or,
+            BinOp::And => Prec::And,
+            BinOp::Equal
+            | BinOp::NotEqual
+            | BinOp::Less
+            | BinOp::Greater
+            | BinOp::LessOrEqual
+            | BinOp::GreaterOrEqual
+            | BinOp::In
+            | BinOp::NotIn => Prec::Compare,
+            BinOp::BitOr => Prec::BitOr,
+            BinOp::BitXor => Prec::BitXor,
+            BinOp::BitAnd => Prec::BitAnd,
+            BinOp::LeftShift | BinOp::RightShift => Prec::Shift,
+            BinOp::Add | BinOp::Subtract => Prec::Arith,
+            BinOp::Multiply | BinOp::Percent | BinOp::Divide | BinOp::FloorDivide => Prec::Product,
+        }
+    }
+
+    fn of_expr(x: &AstExpr) -> Prec {
+        match &x.node {
+            Expr::If(..) | Expr::Lambda(..) => Prec::Test,
+            Expr::Op(_, op, _) => Prec::of_op(*op),
+            Expr::Not(..) => Prec::Not,
+            Expr::Minus(..) | Expr::Plus(..) | Expr::BitNot(..) => Prec::Unary,
+            _ => Prec::Primary,
+        }
+    }
+}
+
+/// An element of a `load` statement.
+enum LoadItem<'a> {
+    Module(&'a AstString),
+    Symbol(&'a AstAssignIdent, &'a AstString),
+}
+
+impl LoadItem<'_> {
+    fn span(&self) -> Span {
+        match self {
+            LoadItem::Module(module) => module.span,
+            LoadItem::Symbol(local, their) => {
+                if local.span == their.span {
+                    their.span
+                } else {
+                    local.span.merge(their.span)
+                }
+            }
+        }
+    }
+}
+
+struct Printer<'a> {
+    codemap: &'a CodeMap,
+    comments: &'a [AstString],
+    /// The first comment we haven't printed yet.
+    next_comment: usize,
+    /// The position in the source of the end of the last thing we printed. We use it to find
+    /// comments at the end of a line, and blank lines.
+    last_pos: Pos,
+    out: String,
+    indent: usize,
+    /// Whether we are at the start of a line, and haven't indented it yet. We only indent
+    /// lines once we print something on them, so that blank lines are empty.
+    at_line_start: bool,
+    /// Whether we are at the start of a block, where we don't want blank lines.
+    block_start: bool,
+    /// When set, we print everything on one line and ignore comments. If we find something
+    /// that shouldn't be printed on one line, we set `failed`.
+    flat: bool,
+    failed: bool,
+    /// Whether we are in parentheses we added to keep comments in place, where we can break
+    /// lines before any expression.
+    breaks: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(codemap: &'a CodeMap, comments: &'a [AstString], flat: bool) -> Self {
+        Self {
+            codemap,
+            comments,
+            next_comment: 0,
+            last_pos: Pos::new(0),
+            out: String::new(),
+            indent: 0,
+            at_line_start: !flat,
+            block_start: true,
+            flat,
+            failed: false,
+            breaks: false,
+        }
+    }
+
+    fn source(&self) -> &'a str {
+        self.codemap.source()
+    }
+
+    fn source_span(&self, span: Span) -> &'a str {
+        &self.source()[span.begin().get() as usize..span.end().get() as usize]
+    }
+
+    fn line(&self, pos: Pos) -> usize {
+        self.codemap.find_line(pos)
+    }
+
+    fn source_column(&self, pos: Pos) -> usize {
+        let line = self.codemap.line_span(self.line(pos));
+        pos.get() as usize - line.begin().get() as usize
+    }
+
+    fn write(&mut self, s: &str) {
+        if self.at_line_start {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+            self.at_line_start = false;
+        }
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        debug_assert!(!self.flat);
+        self.trim_trailing_spaces();
+        self.out.push('\n');
+        self.at_line_start = true;
+    }
+
+    fn trim_trailing_spaces(&mut self) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+    }
+
+    /// The column we are going to print the next character at.
+    fn column(&self) -> usize {
+        if self.at_line_start {
+            self.indent * INDENT.len()
+        } else {
+            let line_start = self.out.rfind('\n').map_or(0, |i| i + 1);
+            self.out[line_start..].chars().count()
+        }
+    }
+
+    /// Whether `s` fits on the current line (and its following lines, if it has several,
+    /// e.g. because of a multi-line string).
+    fn fits(&self, s: &str) -> bool {
+        s.split('\n').enumerate().all(|(i, line)| {
+            let start = if i == 0 { self.column() } else { 0 };
+            start + line.chars().count() <= MAX_WIDTH
+        })
+    }
+
+    /// Print something on one line, if possible.
+    fn try_flat(&self, span: Span, f: impl FnOnce(&mut Printer<'a>)) -> Option<String> {
+        if self.has_comment_in(span) {
+            return None;
+        }
+        let mut p = Printer::new(self.codemap, self.comments, true);
+        f(&mut p);
+        if p.failed { None } else { Some(p.out) }
+    }
+
+    /// Print something on one line if it fits there, and return whether it did.
+    fn write_flat_if_fits(&mut self, span: Span, f: impl FnOnce(&mut Printer<'a>)) -> bool {
+        match self.try_flat(span, f) {
+            Some(s) if self.fits(&s) => {
+                self.write(&s);
+                self.last_pos = span.end();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Comments and blank lines.
+
+    fn has_comment_in(&self, span: Span) -> bool {
+        let i = self
+            .comments
+            .partition_point(|c| c.span.begin() < span.begin());
+        self.comments
+            .get(i)
+            .map_or(false, |c| c.span.begin() < span.end())
+    }
+
+    fn next_comment_before(&self, pos: Pos) -> Option<&'a AstString> {
+        self.comments
+            .get(self.next_comment)
+            .filter(|c| c.span.begin() < pos)
+    }
+
+    /// Skip whitespace, line continuations and comments in the source from `pos`.
+    fn skip_trivia(&self, pos: Pos) -> Pos {
+        let source = self.source().as_bytes();
+        let mut i = pos.get() as usize;
+        while i < source.len() {
+            match source[i] {
+                b' ' | b'\t' | b'\r' | b'\n' | b'\\' => i += 1,
+                b'#' => {
+                    while i < source.len() && source[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        Pos::new(i as u32)
+    }
+
+    /// Find the `:` that ends the header of a compound statement, after `pos`.
+    fn find_colon(&self, pos: Pos) -> Pos {
+        let source = self.source().as_bytes();
+        let mut pos = pos;
+        loop {
+            pos = self.skip_trivia(pos);
+            match source.get(pos.get() as usize) {
+                Some(b'(' | b')' | b',') => pos = pos + 1,
+                _ => return pos,
+            }
+        }
+    }
+
+    /// Whether an element of a sequence that ends at `pos` is followed by a comma.
+    fn has_trailing_comma(&self, pos: Pos) -> bool {
+        let pos = self.skip_trivia(pos);
+        self.source().as_bytes().get(pos.get() as usize) == Some(&b',')
+    }
+
+    /// Whether the source had a blank line between what we printed last and `pos`.
+    fn had_blank_line(&self, pos: Pos) -> bool {
+        self.line(pos) > self.line(self.last_pos) + 1
+    }
+
+    fn blank_line_before(&mut self, pos: Pos) {
+        if !self.block_start && self.had_blank_line(pos) {
+            self.out.push('\n');
+        }
+    }
+
+    fn write_comment(&mut self, comment: &AstString) {
+        self.write(comment.node.trim_end());
+        self.next_comment += 1;
+        self.last_pos = comment.span.end();
+    }
+
+    /// Print the comments before `pos` on their own lines. We must be at the start of a line.
+    fn leading_comments(&mut self, pos: Pos) {
+        while let Some(comment) = self.next_comment_before(pos) {
+            self.blank_line_before(comment.span.begin());
+            self.write_comment(comment);
+            self.newline();
+            self.block_start = false;
+        }
+    }
+
+    /// Print the comment on the line we printed last, if there is one before `limit`.
+    fn trailing_comment(&mut self, limit: Pos) {
+        if let Some(comment) = self.next_comment_before(limit) {
+            if self.line(comment.span.begin()) == self.line(self.last_pos) {
+                self.trim_trailing_spaces();
+                self.write("  ");
+                self.write_comment(comment);
+            }
+        }
+    }
+
+    // Sequences.
+
+    /// Print a sequence of items between brackets, either on one line, or with one item per
+    /// line. `span` ends with the closing bracket (or the end of the sequence, if the sequence
+    /// doesn't have brackets).
+    fn sequence<T>(
+        &mut self,
+        (open, close): (&str, &str),
+        items: &[T],
+        span: Span,
+        item_span: impl Fn(&T) -> Span,
+        single_comma: bool,
+        mut item: impl FnMut(&mut Self, &T),
+    ) {
+        if self.flat {
+            if let Some(last) = items.last() {
+                if !(single_comma && items.len() == 1)
+                    && self.has_trailing_comma(item_span(last).end())
+                {
+                    // A trailing comma asks for one item per line.
+                    self.failed = true;
+                }
+            }
+            self.write(open);
+            for (i, x) in items.iter().enumerate() {
+                if i != 0 {
+                    self.write(", ");
+                }
+                item(self, x);
+            }
+            if single_comma && items.len() == 1 {
+                self.write(",");
+            }
+            self.write(close);
+            return;
+        }
+
+        if items.is_empty() && !self.has_comment_in(span) {
+            self.write(open);
+            self.write(close);
+            self.last_pos = span.end();
+            return;
+        }
+
+        self.write(open);
+        let first = items.first().map_or(span.end(), |x| item_span(x).begin());
+        self.trailing_comment(first);
+        self.indent += 1;
+        self.newline();
+        self.block_start = true;
+        // We are in brackets, so we can break lines wherever there are comments.
+        let breaks = self.breaks;
+        self.breaks = true;
+        for (i, x) in items.iter().enumerate() {
+            let begin = item_span(x).begin();
+            let next = items
+                .get(i + 1)
+                .map_or(span.end(), |y| item_span(y).begin());
+            self.leading_comments(begin);
+            self.blank_line_before(begin);
+            self.block_start = false;
+            item(self, x);
+            self.write(",");
+            self.last_pos = item_span(x).end();
+            self.trailing_comment(next);
+            self.newline();
+        }
+        self.leading_comments(span.end());
+        self.breaks = breaks;
+        self.indent -= 1;
+        self.write(close);
+        self.block_start = false;
+        self.last_pos = span.end();
+    }
+
+    // Expressions.
+
+    /// If there are comments before `pos`, print them and start a new line. Only valid where we
+    /// can break lines.
+    fn break_for_comments(&mut self, pos: Pos) {
+        if self.next_comment_before(pos).is_some() {
+            self.trailing_comment(pos);
+            if !self.at_line_start {
+                self.newline();
+            }
+            self.leading_comments(pos);
+        }
+    }
+
+    /// Whether `x` has comments we'd lose by printing it on one line. Expressions we can break
+    /// over several lines place the comments in them themselves.
+    fn has_stray_comment(&self, x: &AstExpr) -> bool {
+        if self.is_breakable(x) || !self.has_comment_in(x.span) {
+            return false;
+        }
+        let mut children = Vec::new();
+        x.node.visit_expr(|child| children.push(child));
+        let first = self
+            .comments
+            .partition_point(|c| c.span.begin() < x.span.begin());
+        self.comments[first..]
+            .iter()
+            .take_while(|c| c.span.begin() < x.span.end())
+            .any(|c| {
+                let pos = c.span.begin();
+                match children
+                    .iter()
+                    .find(|child| child.span.begin() <= pos && pos < child.span.end())
+                {
+                    Some(child) => self.has_stray_comment(child),
+                    None => true,
+                }
+            })
+    }
+
+    fn expr(&mut self, x: &AstExpr, prec: Prec) {
+        if !self.flat {
+            if self.breaks {
+                self.break_for_comments(x.span.begin());
+            } else if self.next_comment_before(x.span.begin()).is_some()
+                || self.has_stray_comment(x)
+            {
+                // Comments in (or just before) an expression we can't break, e.g. after the `+`
+                // in `(a +  # Comment.\n b)`, so break it where the comments are.
+                self.write("(");
+                self.indent += 1;
+                self.breaks = true;
+                self.break_for_comments(x.span.begin());
+                self.expr(x, Prec::Test);
+                self.break_for_comments(x.span.end());
+                self.breaks = false;
+                self.indent -= 1;
+                self.write(")");
+                return;
+            }
+        }
+
+        if !self.flat
+            && self.is_breakable(x)
+            && self.write_flat_if_fits(x.span, |p| p.expr(x, prec))
+        {
+            return;
+        }
+
+        let parens = Prec::of_expr(x) < prec;
+        if parens {
+            self.write("(");
+        }
+        self.expr_unparenthesized(x);
+        if parens {
+            self.write(")");
+        }
+        if !self.flat {
+            self.last_pos = x.span.end();
+        }
+    }
+
+    /// Whether we might print `x` over several lines.
+    fn is_breakable(&self, x: &AstExpr) -> bool {
+        matches!(
+            x.node,
+            Expr::Call(..)
+                | Expr::List(..)
+                | Expr::Dict(..)
+                | Expr::Tuple(..)
+                | Expr::ListComprehension(..)
+                | Expr::DictComprehension(..)
+        )
+    }
+
+    /// Whether the tuple at `span` is in parentheses in the source.
+    fn is_parenthesized(&self, span: Span) -> bool {
+        let before = &self.source()[..span.begin().get() as usize];
+        before.trim_end().ends_with('(')
+    }
+
+    /// Print an expression where a tuple doesn't need parentheses, e.g. on the right hand side
+    /// of an assignment. We only omit them if they were omitted in the source.
+    fn expr_list(&mut self, x: &AstExpr) {
+        match &x.node {
+            Expr::Tuple(xs) if !xs.is_empty() && !self.is_parenthesized(x.span) => {
+                if self.flat {
+                    self.bare_tuple(xs);
+                } else if !self.write_flat_if_fits(x.span, |p| p.bare_tuple(xs)) {
+                    // We can't break a tuple over several lines without parentheses.
+                    self.expr(x, Prec::Test);
+                }
+            }
+            _ => self.expr(x, Prec::Test),
+        }
+    }
+
+    fn bare_tuple(&mut self, xs: &[AstExpr]) {
+        for (i, x) in xs.iter().enumerate() {
+            if i != 0 {
+                self.write(", ");
+            }
+            self.expr(x, Prec::Test);
+        }
+        if xs.len() == 1 {
+            self.write(",");
+        }
+    }
+
+    fn expr_unparenthesized(&mut self, x: &AstExpr) {
+        match &x.node {
+            Expr::Tuple(xs) => {
+                self.sequence(
+                    ("(", ")"),
+                    xs,
+                    x.span,
+                    |x| x.span,
+                    true,
+                    |p, x| p.expr(x, Prec::Test),
+                );
+            }
+            Expr::Dot(e, field) => {
+                self.expr(e, Prec::Primary);
+                self.write(".");
+                self.write(&field.node);
+            }
+            Expr::Call(f, args) => {
+                self.expr(f, Prec::Primary);
+                self.sequence(
+                    ("(", ")"),
+                    args,
+                    x.span,
+                    |x| x.span,
+                    false,
+                    |p, x| p.argument(x),
+                );
+            }
+            Expr::ArrayIndirection(e_i) => {
+                let (e, i) = &**e_i;
+                self.expr(e, Prec::Primary);
+                self.write("[");
+                self.expr_list(i);
+                self.write("]");
+            }
+            Expr::Slice(e, i1, i2, i3) => {
+                self.expr(e, Prec::Primary);
+                self.write("[");
+                if let Some(i1) = i1 {
+                    self.expr(i1, Prec::Test);
+                }
+                self.write(":");
+                if let Some(i2) = i2 {
+                    self.expr(i2, Prec::Test);
+                }
+                if let Some(i3) = i3 {
+                    self.write(":");
+                    self.expr(i3, Prec::Test);
+                }
+                self.write("]");
+            }
+            Expr::Identifier(name, _) => self.write(&name.node),
+            Expr::Lambda(LambdaP { params, body, .. }) => {
+                self.write("lambda");
+                for (i, param) in params.iter().enumerate() {
+                    self.write(if i == 0 { " " } else { ", " });
+                    self.parameter(param);
+                }
+                self.write(": ");
+                self.expr(body, Prec::Test);
+            }
+            Expr::Literal(literal) => self.literal(literal),
+            Expr::Not(e) => {
+                self.write("not ");
+                self.expr(e, Prec::Not);
+            }
+            Expr::Minus(e) => {
+                self.write("-");
+                self.expr(e, Prec::Unary);
+            }
+            Expr::Plus(e) => {
+                self.write("+");
+                self.expr(e, Prec::Unary);
+            }
+            Expr::BitNot(e) => {
+                self.write("~");
+                self.expr(e, Prec::Unary);
+            }
+            Expr::Op(l, op, r) => {
+                let prec = Prec::of_op(*op);
+                let (l_prec, r_prec) = if prec == Prec::Compare {
+                    (Prec::BitOr, Prec::BitOr)
+                } else {
+                    (prec, prec.tighter())
+                };
+                self.expr(l, l_prec);
+                self.write(&op.to_string());
+                self.expr(r, r_prec);
+            }
+            Expr::If(c_t_f) => {
+                let (cond, then, els) = &**c_t_f;
+                self.expr(then, Prec::Or);
+                self.write(" if ");
+                self.expr(cond, Prec::Or);
+                self.write(" else ");
+                self.expr(els, Prec::Test);
+            }
+            Expr::List(xs) => {
+                self.last_pos = x.span.begin();
+                self.sequence(
+                    ("[", "]"),
+                    xs,
+                    x.span,
+                    |x| x.span,
+                    false,
+                    |p, x| p.expr(x, Prec::Test),
+                );
+            }
+            Expr::Dict(xs) => {
+                self.last_pos = x.span.begin();
+                self.sequence(
+                    ("{", "}"),
+                    xs,
+                    x.span,
+                    |(k, v)| k.span.merge(v.span),
+                    false,
+                    |p, (k, v)| {
+                        p.expr(k, Prec::Test);
+                        p.write(": ");
+                        p.expr(v, Prec::Test);
+                    },
+                );
+            }
+            Expr::ListComprehension(e, for_, clauses) => {
+                self.comprehension(("[", "]"), x.span, for_, clauses, |p| p.expr(e, Prec::Test));
+            }
+            Expr::DictComprehension(k_v, for_, clauses) => {
+                let (k, v) = &**k_v;
+                self.comprehension(("{", "}"), x.span, for_, clauses, |p| {
+                    p.expr(k, Prec::Test);
+                    p.write(": ");
+                    p.expr(v, Prec::Test);
+                });
+            }
+        }
+    }
+
+    fn comprehension(
+        &mut self,
+        (open, close): (&str, &str),
+        span: Span,
+        for_: &ForClause,
+        clauses: &[Clause],
+        item: impl FnOnce(&mut Self),
+    ) {
+        self.last_pos = span.begin();
+        self.write(open);
+        // We are in brackets, so we can break lines wherever there are comments.
+        let breaks = self.breaks;
+        if !self.flat {
+            self.indent += 1;
+            self.newline();
+            self.breaks = true;
+        }
+        item(self);
+        self.for_clause(for_);
+        for clause in clauses {
+            match clause {
+                Clause::For(for_) => self.for_clause(for_),
+                Clause::If(cond) => {
+                    self.comprehension_separator();
+                    self.write("if ");
+                    self.expr(cond, Prec::Or);
+                }
+            }
+        }
+        if !self.flat {
+            self.trailing_comment(span.end());
+            self.newline();
+            self.leading_comments(span.end());
+            self.indent -= 1;
+            self.breaks = breaks;
+        }
+        self.write(close);
+        self.last_pos = span.end();
+    }
+
+    fn comprehension_separator(&mut self) {
+        if self.flat {
+            self.write(" ");
+        } else {
+            self.trailing_comment(Pos::new(u32::MAX));
+            self.newline();
+        }
+    }
+
+    fn for_clause(&mut self, for_: &ForClause) {
+        self.comprehension_separator();
+        self.write("for ");
+        self.assign_list(&for_.var);
+        self.write(" in ");
+        self.expr(&for_.over, Prec::Or);
+    }
+
+    fn literal(&mut self, x: &AstLiteral) {
+        match x {
+            AstLiteral::Int(i) => self.write(self.source_span(i.span)),
+            AstLiteral::Float(f) => self.write(self.source_span(f.span)),
+            AstLiteral::String(s) => self.string(s),
+        }
+    }
+
+    fn string(&mut self, x: &AstString) {
+        let source = self.source_span(x.span);
+        match source
+            .strip_prefix('\'')
+            .and_then(|s| s.strip_suffix('\''))
+            .filter(|s| !s.starts_with("''") && !s.contains(&['"', '\\'][..]))
+        {
+            Some(contents) => {
+                self.write("\"");
+                self.write(contents);
+                self.write("\"");
+            }
+            None => self.write(source),
+        }
+    }
+
+    fn argument(&mut self, x: &AstArgument) {
+        match &x.node {
+            ArgumentP::Positional(e) => self.expr(e, Prec::Test),
+            ArgumentP::Named(name, e) => {
+                self.write(&name.node);
+                self.write(" = ");
+                self.expr(e, Prec::Test);
+            }
+            ArgumentP::Args(e) => {
+                self.write("*");
+                self.expr(e, Prec::Test);
+            }
+            ArgumentP::KwArgs(e) => {
+                self.write("**");
+                self.expr(e, Prec::Test);
+            }
+        }
+    }
+
+    fn parameter(&mut self, x: &AstParameter) {
+        let (prefix, name, ty, default) = match &x.node {
+            ParameterP::Normal(name, ty) => ("", name, ty, None),
+            ParameterP::WithDefaultValue(name, ty, default) => ("", name, ty, Some(default)),
+            ParameterP::NoArgs => {
+                self.write("*");
+                return;
+            }
+            ParameterP::Args(name, ty) => ("*", name, ty, None),
+            ParameterP::KwArgs(name, ty) => ("**", name, ty, None),
+        };
+        self.write(prefix);
+        self.write(&name.node.0);
+        if let Some(ty) = ty {
+            self.write(": ");
+            self.expr(ty, Prec::Test);
+        }
+        if let Some(default) = default {
+            self.write(" = ");
+            self.expr(default, Prec::Test);
+        }
+    }
+
+    /// Print the target of an assignment, where tuples don't need parentheses.
+    fn assign_list(&mut self, x: &AstAssign) {
+        match &x.node {
+            AssignP::Tuple(xs) if xs.len() > 1 => {
+                for (i, x) in xs.iter().enumerate() {
+                    if i != 0 {
+                        self.write(", ");
+                    }
+                    self.assign(x);
+                }
+            }
+            _ => self.assign(x),
+        }
+    }
+
+    fn assign(&mut self, x: &AstAssign) {
+        match &x.node {
+            AssignP::Tuple(xs) => {
+                self.write("(");
+                for (i, x) in xs.iter().enumerate() {
+                    if i != 0 {
+                        self.write(", ");
+                    }
+                    self.assign(x);
+                }
+                if xs.len() == 1 {
+                    self.write(",");
+                }
+                self.write(")");
+            }
+            AssignP::ArrayIndirection(e_i) => {
+                let (e, i) = &**e_i;
+                self.expr(e, Prec::Primary);
+                self.write("[");
+                self.expr_list(i);
+                self.write("]");
+            }
+            AssignP::Dot(e, field) => {
+                self.expr(e, Prec::Primary);
+                self.write(".");
+                self.write(&field.node);
+            }
+            AssignP::Identifier(name) => self.write(&name.node.0),
+        }
+        if !self.flat {
+            self.last_pos = x.span.end();
+        }
+    }
+
+    // Statements.
+
+    fn flatten<'s>(x: &'s AstStmt, res: &mut Vec<&'s AstStmt>) {
+        match &x.node {
+            Stmt::Statements(xs) => {
+                for x in xs {
+                    Self::flatten(x, res);
+                }
+            }
+            _ => res.push(x),
+        }
+    }
+
+    /// Print a sequence of statements. `limit` is where the next statement after them starts.
+    fn block(&mut self, xs: &[&AstStmt], limit: Pos) {
+        self.block_start = true;
+        for (i, x) in xs.iter().enumerate() {
+            let next = xs.get(i + 1).map_or(limit, |x| x.span.begin());
+            self.leading_comments(x.span.begin());
+            self.blank_line_before(x.span.begin());
+            self.block_start = false;
+            self.stmt(x, next);
+        }
+    }
+
+    /// Print the body of a compound statement, starting with the `:` of its header.
+    fn suite(&mut self, body: &AstStmt, limit: Pos) {
+        self.write(":");
+        self.last_pos = self.find_colon(self.last_pos);
+        self.trailing_comment(body.span.begin());
+        self.newline();
+
+        let mut xs = Vec::new();
+        Self::flatten(body, &mut xs);
+
+        self.indent += 1;
+        self.block(&xs, limit);
+        // Comments at the end of the block belong to it if they are indented like it.
+        if let Some(first) = xs.first() {
+            let column = self.source_column(first.span.begin());
+            while let Some(comment) = self.next_comment_before(limit) {
+                if self.source_column(comment.span.begin()) < column {
+                    break;
+                }
+                self.blank_line_before(comment.span.begin());
+                self.write_comment(comment);
+                self.newline();
+            }
+        }
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, x: &AstStmt, limit: Pos) {
+        match &x.node {
+            Stmt::Break => self.write("break"),
+            Stmt::Continue => self.write("continue"),
+            Stmt::Pass => self.write("pass"),
+            Stmt::Return(e) => {
+                self.write("return");
+                if let Some(e) = e {
+                    self.write(" ");
+                    self.expr_list(e);
+                }
+            }
+            Stmt::Expression(e) => self.expr_list(e),
+            Stmt::Assign(lhs, ty_rhs) => {
+                let (ty, rhs) = &**ty_rhs;
+                self.assign_list(lhs);
+                if let Some(ty) = ty {
+                    self.write(": ");
+                    self.expr(ty, Prec::Test);
+                }
+                self.write(" = ");
+                self.expr_list(rhs);
+            }
+            Stmt::AssignModify(lhs, op, rhs) => {
+                self.assign_list(lhs);
+                self.write(&op.to_string());
+                self.expr_list(rhs);
+            }
+            Stmt::Statements(_) => {
+                let mut xs = Vec::new();
+                Self::flatten(x, &mut xs);
+                self.block(&xs, limit);
+                return;
+            }
+            Stmt::If(cond, body) => {
+                self.if_stmt("if", cond, body, None, limit);
+                return;
+            }
+            Stmt::IfElse(cond, bodies) => {
+                let (then, els) = &**bodies;
+                self.if_stmt("if", cond, then, Some(els), limit);
+                return;
+            }
+            Stmt::For(var, over_body) => {
+                let (over, body) = &**over_body;
+                self.write("for ");
+                self.assign_list(var);
+                self.write(" in ");
+                self.expr(over, Prec::Test);
+                self.suite(body, limit);
+                return;
+            }
+            Stmt::Def(def) => {
+                self.def(x, def, limit);
+                return;
+            }
+            Stmt::Load(load) => self.load(x.span, load),
+        }
+        self.last_pos = x.span.end();
+        self.trailing_comment(limit);
+        self.newline();
+    }
+
+    fn if_stmt(
+        &mut self,
+        keyword: &str,
+        cond: &AstExpr,
+        then: &AstStmt,
+        els: Option<&AstStmt>,
+        limit: Pos,
+    ) {
+        self.write(keyword);
+        self.write(" ");
+        self.expr(cond, Prec::Test);
+        let els = match els {
+            None => {
+                self.suite(then, limit);
+                return;
+            }
+            Some(els) => els,
+        };
+
+        let else_pos = self.skip_trivia(then.span.end());
+        self.suite(then, else_pos);
+        self.leading_comments(else_pos);
+        self.last_pos = else_pos;
+
+        let is_elif = self.source()[else_pos.get() as usize..].starts_with("elif");
+        match &els.node {
+            Stmt::If(cond, then) if is_elif => self.if_stmt("elif", cond, then, None, limit),
+            Stmt::IfElse(cond, bodies) if is_elif => {
+                let (then, els) = &**bodies;
+                self.if_stmt("elif", cond, then, Some(els), limit)
+            }
+            _ => {
+                self.write("else");
+                self.last_pos = else_pos + 4;
+                self.suite(els, limit);
+            }
+        }
+    }
+
+    fn def(&mut self, x: &AstStmt, def: &DefP<AstNoPayload>, limit: Pos) {
+        let DefP {
+            name,
+            params,
+            return_type,
+            body,
+            ..
+        } = def;
+        let header_end = match (return_type, params.last()) {
+            (Some(return_type), _) => return_type.span.end(),
+            (None, Some(param)) => param.span.end(),
+            (None, None) => name.span.end(),
+        };
+        let colon = self.find_colon(header_end);
+        let header = Span::new(x.span.begin(), colon);
+
+        let write_header = |p: &mut Printer, params_span: Span| {
+            p.write("def ");
+            p.write(&name.node.0);
+            p.last_pos = name.span.end();
+            p.sequence(
+                ("(", ")"),
+                params,
+                params_span,
+                |x| x.span,
+                false,
+                |p, x| p.parameter(x),
+            );
+            if let Some(return_type) = return_type {
+                p.write(" -> ");
+                p.expr(return_type, Prec::Test);
+            }
+        };
+        let params_span = Span::new(name.span.end(), colon);
+        if !self.write_flat_if_fits(header, |p| write_header(p, params_span)) {
+            write_header(self, params_span);
+        }
+        self.last_pos = header_end;
+        self.suite(body, limit);
+    }
+
+    fn load(&mut self, span: Span, load: &Load) {
+        let items: Vec<LoadItem> = std::iter::once(LoadItem::Module(&load.module))
+            .chain(
+                load.args
+                    .iter()
+                    .map(|(local, their)| LoadItem::Symbol(local, their)),
+            )
+            .collect();
+        let write_load = |p: &mut Printer| {
+            p.write("load");
+            p.sequence(
+                ("(", ")"),
+                &items,
+                span,
+                |x| x.span(),
+                false,
+                |p, x| match x {
+                    LoadItem::Module(module) => p.string(module),
+                    LoadItem::Symbol(local, their) => {
+                        if local.span != their.span {
+                            p.write(&local.node.0);
+                            p.write(" = ");
+                        }
+                        p.string(their);
+                    }
+                },
+            );
+        };
+        if !self.write_flat_if_fits(span, &write_load) {
+            self.last_pos = span.begin();
+            write_load(self);
+        }
+    }
+}
+
+impl AstModule {
+    /// Print the module in a canonical style. Comments are kept if the module was parsed with
+    /// [`parse_with_comments`](AstModule::parse_with_comments). Otherwise, only the comments that
+    /// disable lints are.
+    pub fn format(&self) -> String {
+        let mut p = Printer::new(&self.codemap, &self.comments, false);
+        let mut xs = Vec::new();
+        Printer::flatten(&self.statement, &mut xs);
+        let end = self.codemap.full_span().end();
+        p.block(&xs, end);
+        p.leading_comments(end);
+        p.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    fn format(program: &str) -> String {
+        let ast =
+            AstModule::parse_with_comments("test.bzl", program.to_owned(), &Dialect::Extended)
+                .unwrap();
+        let formatted = ast.format();
+
+        // Formatting doesn't change the meaning of the program, and is idempotent.
+        let reparsed =
+            AstModule::parse_with_comments("test.bzl", formatted.clone(), &Dialect::Extended)
+                .unwrap_or_else(|e| {
+                    panic!("Formatted program doesn't parse: {}\n{}", e, formatted)
+                });
+        assert_eq!(
+            ast.statement.to_string(),
+            reparsed.statement.to_string(),
+            "Formatting changed the program:\n{}",
+            formatted
+        );
+        assert_eq!(
+            ast.comments
+                .iter()
+                .map(|c| c.node.trim_end())
+                .collect::<Vec<_>>(),
+            reparsed
+                .comments
+                .iter()
+                .map(|c| &*c.node)
+                .collect::<Vec<_>>(),
+            "Formatting lost comments:\n{}",
+            formatted
+        );
+        assert_eq!(formatted, reparsed.format(), "Formatting is not idempotent");
+
+        formatted
+    }
+
+    #[test]
+    fn test_format_simple() {
+        assert_eq!(
+            format("x=1\ny = [1,2 ,3]\ndef f(a,b=2,*,c:int=3)->str:\n  return a+b*c\n"),
+            "x = 1\ny = [1, 2, 3]\ndef f(a, b = 2, *, c: int = 3) -> str:\n    return a + b * c\n"
+        );
+    }
+
+    #[test]
+    fn test_format_blank_lines() {
+        assert_eq!(
+            format("\n\nx = 1\n\n\n\ny = 2\nz = 3\n\n"),
+            "x = 1\n\ny = 2\nz = 3\n"
+        );
+    }
+
+    #[test]
+    fn test_format_parens() {
+        assert_eq!(
+            format("x = ((a + b) * c)\ny = a + (b * c)\nz = (a - (b - c))\nw = -(a + b)\n"),
+            "x = (a + b) * c\ny = a + b * c\nz = a - (b - c)\nw = -(a + b)\n"
+        );
+        assert_eq!(
+            format("x = (not a) == b\ny = (lambda x: x) if c else (lambda y: y)\n"),
+            "x = (not a) == b\ny = (lambda x: x) if c else lambda y: y\n"
+        );
+        assert_eq!(
+            format("a, b = 1, 2\nc = (1, 2)\nd = (1,)\nreturn_ = f((1, 2))\n"),
+            "a, b = 1, 2\nc = (1, 2)\nd = (1,)\nreturn_ = f((1, 2))\n"
+        );
+    }
+
+    #[test]
+    fn test_format_strings() {
+        assert_eq!(
+            format("x = 'a'\ny = 'say \"hi\"'\nz = r'\\d'\nw = '''doc'''\n"),
+            "x = \"a\"\ny = 'say \"hi\"'\nz = r'\\d'\nw = '''doc'''\n"
+        );
+    }
+
+    #[test]
+    fn test_format_trailing_comma() {
+        assert_eq!(
+            format("deps = [\"a\", \"b\",]\nf(x, y = 1,)\n"),
+            "deps = [\n    \"a\",\n    \"b\",\n]\nf(\n    x,\n    y = 1,\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_long_lines() {
+        let long = format!(
+            "x = [{}]\n",
+            (0..40)
+                .map(|i| format!("{}", i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let formatted = format(&long);
+        assert!(formatted.starts_with("x = [\n    0,\n    1,\n"));
+        assert!(formatted.ends_with("    39,\n]\n"));
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let program = r#"
+# Leading comment.
+load(":defs.bzl", "foo")  # Trailing comment.
+
+def f(
+    a,  # The a.
+    # Before b.
+    b,
+):
+    # In f.
+    if a:  # Is a?
+        return b
+    elif b:
+        pass
+    # Before else.
+    else:
+        # In else.
+        return a
+    # At the end of f.
+
+# At the end of the file.
+"#;
+        assert_eq!(
+            format(program),
+            r#"# Leading comment.
+load(":defs.bzl", "foo")  # Trailing comment.
+
+def f(
+    a,  # The a.
+    # Before b.
+    b,
+):
+    # In f.
+    if a:  # Is a?
+        return b
+    elif b:
+        pass
+    # Before else.
+    else:
+        # In else.
+        return a
+    # At the end of f.
+
+# At the end of the file.
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_comments_in_collections() {
+        assert_eq!(
+            format("x = [  # Start.\n  1,\n  # Two.\n  2,  # After two.\n  # End.\n]\n"),
+            "x = [  # Start.\n    1,\n    # Two.\n    2,  # After two.\n    # End.\n]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_comments_in_expressions() {
+        assert_eq!(
+            format("x = (a +  # After a.\n     b)\ny = (  # Before.\n  1 + 2)\n"),
+            "x = (a +  # After a.\n    b)\ny = (  # Before.\n    1 + 2)\n"
+        );
+        assert_eq!(
+            format("f(a +  # After a.\n  b)\n"),
+            "f(\n    a +  # After a.\n    b,\n)\n"
+        );
+        // Comments in collections are kept there, so we don't need to break the `+`.
+        assert_eq!(
+            format("x = glob([\"*\"]) + [  # Extra.\n  \"a\",\n]\n"),
+            "x = glob([\"*\"]) + [  # Extra.\n    \"a\",\n]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_else_is_not_elif() {
+        assert_eq!(
+            format("if a:\n  pass\nelse:\n  if b:\n    pass\n"),
+            "if a:\n    pass\nelse:\n    if b:\n        pass\n"
+        );
+    }
+
+    #[test]
+    fn test_format_comprehension() {
+        assert_eq!(
+            format("x = [a for a in b if a]\ny = {k: v for k, v in d.items()}\n"),
+            "x = [a for a in b if a]\ny = {k: v for k, v in d.items()}\n"
+        );
+    }
+}
@@ -19,6 +19,7 @@ use std::char;
 use std::collections::VecDeque;
 use std::fmt;
 use std::fmt::Display;
+use std::mem;
 
 use derive_more::Display;
 use logos::Logos;
@@ -29,6 +30,7 @@ use thiserror::Error;
 use crate::codemap::CodeMap;
 use crate::codemap::Pos;
 use crate::codemap::Span;
+use crate::codemap::Spanned;
 use crate::errors::Diagnostic;
 use crate::syntax::cursors::CursorBytes;
 use crate::syntax::cursors::CursorChars;
@@ -66,10 +68,39 @@ pub(crate) struct Lexer<'a> {
     parens: isize, // Number of parens we have seen
     lexer: logos::Lexer<'a, Token>,
     done: bool,
+    /// The comments we have seen and were asked to keep.
+    comments: Vec<Spanned<String>>,
+    /// Which comments to keep, if any.
+    keep_comment: Option<fn(&str) -> bool>,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str, _dialect: &Dialect, codemap: CodeMap) -> Self {
+    pub fn new(input: &'a str, dialect: &Dialect, codemap: CodeMap) -> Self {
+        Self::new_impl(input, dialect, codemap, None)
+    }
+
+    /// Like `new`, but keeps the comments in the input, which can be obtained with
+    /// [`take_comments`](Lexer::take_comments) once lexing is done.
+    pub fn new_with_comments(input: &'a str, dialect: &Dialect, codemap: CodeMap) -> Self {
+        Self::new_impl(input, dialect, codemap, Some(|_| true))
+    }
+
+    /// Like `new_with_comments`, but only keeps the comments for which `keep` returns true.
+    pub fn new_with_comment_filter(
+        input: &'a str,
+        dialect: &Dialect,
+        codemap: CodeMap,
+        keep: fn(&str) -> bool,
+    ) -> Self {
+        Self::new_impl(input, dialect, codemap, Some(keep))
+    }
+
+    fn new_impl(
+        input: &'a str,
+        _dialect: &Dialect,
+        codemap: CodeMap,
+        keep_comment: Option<fn(&str) -> bool>,
+    ) -> Self {
         let lexer = Token::lexer(input);
         let mut lexer2 = Self {
             codemap,
@@ -79,6 +110,8 @@ impl<'a> Lexer<'a> {
             lexer,
             parens: 0,
             done: false,
+            comments: Vec::new(),
+            keep_comment,
         };
         if let Err(e) = lexer2.calculate_indent() {
             lexer2.buffer.push_back(Err(e));
@@ -86,6 +119,32 @@ impl<'a> Lexer<'a> {
         lexer2
     }
 
+    /// The comments seen so far that we were asked to keep, in the order they appear in the
+    /// input. Empty unless the lexer was created with
+    /// [`new_with_comments`](Lexer::new_with_comments) or
+    /// [`new_with_comment_filter`](Lexer::new_with_comment_filter).
+    pub fn take_comments(&mut self) -> Vec<Spanned<String>> {
+        mem::take(&mut self.comments)
+    }
+
+    fn add_comment(
+        comments: &mut Vec<Spanned<String>>,
+        keep_comment: Option<fn(&str) -> bool>,
+        start: usize,
+        text: &str,
+    ) {
+        if keep_comment.map_or(false, |keep| keep(text)) {
+            let text = text.trim_end_matches('\r');
+            comments.push(Spanned {
+                span: Span::new(
+                    Pos::new(start as u32),
+                    Pos::new((start + text.len()) as u32),
+                ),
+                node: text.to_owned(),
+            });
+        }
+    }
+
     fn err_pos<T>(&self, msg: LexemeError, pos: usize) -> anyhow::Result<T> {
         self.err_span(msg, pos, pos)
     }
@@ -110,7 +169,8 @@ impl<'a> Lexer<'a> {
     /// and then set self.indent properly
     fn calculate_indent(&mut self) -> anyhow::Result<()> {
         // consume tabs and spaces, output the indentation levels
-        let mut it = CursorBytes::new(self.lexer.remainder());
+        let remainder = self.lexer.remainder();
+        let mut it = CursorBytes::new(remainder);
         let mut spaces = 0;
         let mut tabs = 0;
         let mut indent_start = self.lexer.span().end;
@@ -141,9 +201,16 @@ impl<'a> Lexer<'a> {
                     // Remove skip now, so we can freely add it on later
                     spaces = 0;
                     tabs = 0;
+                    let comment_start = it.pos() - 1;
                     loop {
                         match it.next_char() {
                             None => {
+                                Self::add_comment(
+                                    &mut self.comments,
+                                    self.keep_comment,
+                                    self.lexer.span().end + comment_start,
+                                    &remainder[comment_start..],
+                                );
                                 self.lexer.bump(it.pos());
                                 return Ok(());
                             }
@@ -151,6 +218,12 @@ impl<'a> Lexer<'a> {
                             Some(_) => {}
                         }
                     }
+                    Self::add_comment(
+                        &mut self.comments,
+                        self.keep_comment,
+                        self.lexer.span().end + comment_start,
+                        &remainder[comment_start..it.pos() - 1],
+                    );
                     indent_start = self.lexer.span().end + it.pos();
                 }
                 _ => break,
@@ -429,6 +502,16 @@ impl<'a> Lexer<'a> {
                                 continue;
                             }
                         }
+                        Token::Comment => {
+                            let span = self.lexer.span();
+                            Self::add_comment(
+                                &mut self.comments,
+                                self.keep_comment,
+                                span.start,
+                                self.lexer.slice(),
+                            );
+                            continue;
+                        }
                         Token::Reserved => Some(self.err_now(LexemeError::ReservedKeyword)),
                         Token::Error => Some(self.err_now(LexemeError::InvalidInput)),
                         Token::RawDecInt => {
@@ -516,10 +599,12 @@ pub enum Token {
     #[regex(" +", logos::skip)] // Whitespace
     #[token("\\\n", logos::skip)] // Escaped newline
     #[token("\\\r\n", logos::skip)] // Escaped newline (Windows line ending)
-    #[regex(r#"#[^\n]*"#, logos::skip)] // Comments
     #[error]
     Error,
 
+    #[regex(r#"#[^\n]*"#)]
+    Comment, // A comment, which the lexer only keeps track of if asked to
+
     #[regex("\t+")] // Tabs (might be an error)
     Tabs,
 
@@ -719,6 +804,7 @@ impl Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Token::Error => write!(f, "lexical error"),
+            Token::Comment => write!(f, "comment"),
             Token::Indent => write!(f, "new indentation block"),
             Token::Dedent => write!(f, "end of indentation block"),
             Token::Newline => write!(f, "new line"),
@@ -802,3 +888,9 @@ impl<'a> Iterator for Lexer<'a> {
         self.next()
     }
 }
+
+/// Whether `s` is exactly one identifier, so can be used as a variable name. Keywords are not.
+pub(crate) fn is_identifier(s: &str) -> bool {
+    let mut lexer = Token::lexer(s);
+    matches!((lexer.next(), lexer.next()), (Some(Token::Identifier(x)), None) if x == s)
+}
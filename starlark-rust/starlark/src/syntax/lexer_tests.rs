@@ -16,6 +16,7 @@
  */
 
 use crate::assert;
+use crate::syntax::lexer::is_identifier;
 use crate::syntax::lexer::Token::*;
 
 #[test]
@@ -294,3 +295,14 @@ fn test_float_lit() {
         "0 0.123 3.14 200 10000 \n"
     );
 }
+
+#[test]
+fn test_is_identifier() {
+    assert!(is_identifier("foo_1"));
+    assert!(is_identifier("_foo"));
+    assert!(!is_identifier(""));
+    assert!(!is_identifier("1foo"));
+    assert!(!is_identifier("foo bar"));
+    assert!(!is_identifier(" foo"));
+    assert!(!is_identifier("def"));
+}
@@ -22,6 +22,7 @@ use std::path::Path;
 use dupe::Dupe;
 use lalrpop_util as lu;
 
+use crate::analysis::suppressions::is_suppression_comment;
 use crate::codemap::CodeMap;
 use crate::codemap::FileSpan;
 use crate::codemap::Pos;
@@ -30,6 +31,7 @@ use crate::collections::SmallMap;
 use crate::errors::Diagnostic;
 use crate::syntax::ast::AstModule;
 use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::AstString;
 use crate::syntax::ast::Stmt;
 use crate::syntax::dialect::Dialect;
 use crate::syntax::grammar::StarlarkParser;
@@ -114,12 +116,14 @@ impl AstModule {
         codemap: CodeMap,
         statement: AstStmt,
         dialect: &Dialect,
+        comments: Vec<AstString>,
     ) -> anyhow::Result<AstModule> {
         Stmt::validate(&codemap, &statement, dialect)?;
         Ok(AstModule {
             codemap,
             statement,
             dialect: dialect.clone(),
+            comments,
         })
     }
 
@@ -145,13 +149,51 @@ impl AstModule {
     /// ```
     pub fn parse(filename: &str, content: String, dialect: &Dialect) -> anyhow::Result<Self> {
         let codemap = CodeMap::new(filename.to_owned(), content);
-        let lexer = Lexer::new(codemap.source(), dialect, codemap.dupe());
-        match StarlarkParser::new().parse(&codemap, dialect, lexer) {
-            Ok(v) => Ok(AstModule::create(codemap, v, dialect)?),
+        // Keep the comments that disable lints, so that linting doesn't need to lex again.
+        let mut lexer = Lexer::new_with_comment_filter(
+            codemap.source(),
+            dialect,
+            codemap.dupe(),
+            is_suppression_comment,
+        );
+        match StarlarkParser::new().parse(&codemap, dialect, &mut lexer) {
+            Ok(v) => Ok(AstModule::create(
+                codemap,
+                v,
+                dialect,
+                lexer.take_comments(),
+            )?),
             Err(p) => Err(parse_error_add_span(p, codemap.source().len(), &codemap)),
         }
     }
 
+    /// Like [`parse`](AstModule::parse), but also keeps the comments in the module, so that
+    /// [`format`](AstModule::format) can preserve them.
+    pub fn parse_with_comments(
+        filename: &str,
+        content: String,
+        dialect: &Dialect,
+    ) -> anyhow::Result<Self> {
+        let codemap = CodeMap::new(filename.to_owned(), content);
+        let mut lexer = Lexer::new_with_comments(codemap.source(), dialect, codemap.dupe());
+        match StarlarkParser::new().parse(&codemap, dialect, &mut lexer) {
+            Ok(v) => Ok(AstModule::create(
+                codemap,
+                v,
+                dialect,
+                lexer.take_comments(),
+            )?),
+            Err(p) => Err(parse_error_add_span(p, codemap.source().len(), &codemap)),
+        }
+    }
+
+    /// Parse a file stored on disk, keeping its comments. For details see
+    /// [`parse_with_comments`](AstModule::parse_with_comments).
+    pub fn parse_file_with_comments(path: &Path, dialect: &Dialect) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse_with_comments(&path.to_string_lossy(), content, dialect)
+    }
+
     /// Return the file names of all the `load` statements in the module.
     /// If the [`Dialect`] had [`enable_load`](Dialect::enable_load) set to [`false`] this will be an empty list.
     pub fn loads(&self) -> Vec<AstLoad> {
@@ -70,6 +70,9 @@ impl OracleStandard {
         add::<crate::values::list::value::ListGen<crate::values::list::value::FrozenListData>>(
             &mut fallback,
         );
+        add::<crate::values::set::value::SetGen<crate::values::set::value::FrozenSetData>>(
+            &mut fallback,
+        );
         add::<crate::values::string::StarlarkStr>(&mut fallback);
         add::<crate::values::structs::value::FrozenStruct>(&mut fallback);
         add::<crate::values::tuple::value::FrozenTuple>(&mut fallback);
@@ -89,6 +92,11 @@ impl TypingOracle for OracleStandard {
             "None" => Ty::None,
             "True" | "False" => Ty::bool(),
             "zip" => Ty::special_function("zip", vec![Param::args(Ty::Any)], Ty::list(Ty::Any)),
+            "set" => Ty::special_function(
+                "set",
+                vec![Param::pos_only(Ty::Iter(Box::new(Ty::Any))).optional()],
+                Ty::name("set"),
+            ),
             "struct" => Ty::special_function(
                 "struct",
                 vec![Param::kwargs(Ty::Any)],
@@ -138,6 +146,15 @@ impl TypingOracle for OracleStandard {
                 }
                 Some(Ok(Ty::list(Ty::Tuple(res))))
             }
+            // Sets don't have their own `Ty`, so the element type isn't tracked.
+            "set" => match args {
+                [] => Some(Ok(Ty::name("set"))),
+                [Arg::Pos(x)] => match self.attribute(x, "__iter__") {
+                    Some(Err(_)) => Some(Err("Argument does not allow iteration".to_owned())),
+                    Some(Ok(_)) | None => Some(Ok(Ty::name("set"))),
+                },
+                _ => Some(Err("Expected at most one positional argument".to_owned())),
+            },
             _ => None,
         }
     }
@@ -125,6 +125,24 @@ res = [foo([])]
     assert_eq!(interface.get("res").unwrap(), &Ty::list(Ty::string()));
 }
 
+#[test]
+fn test_set() {
+    let (errs, _, interface, _) = typecheck(
+        r#"
+def foo(x: "set") -> "set":
+    return x.union([1])
+y = foo(set([1]))
+y.add(2)
+   "#,
+        &HashMap::new(),
+    );
+    assert!(errs.is_empty());
+    assert_eq!(interface.get("y").unwrap(), &Ty::name("set"));
+
+    let (errs, _, _, _) = typecheck("set([1]).append(2)", &HashMap::new());
+    assert_eq!(errs.len(), 1);
+}
+
 /// Test things that have previous claimed incorrectly they were type errors
 #[test]
 fn test_false_negative() {
@@ -26,6 +26,7 @@ use derive_more::Display;
 use serde::Serialize;
 use serde::Serializer;
 use starlark_map::small_map::SmallMap;
+use starlark_map::small_set::SmallSet;
 
 use crate as starlark;
 use crate::any::ProvidesStaticType;
@@ -52,6 +53,8 @@ use crate::values::layout::vtable::AValueVTable;
 use crate::values::list::value::ListGen;
 use crate::values::none::NoneType;
 use crate::values::num::Num;
+use crate::values::set::value::FrozenSetData;
+use crate::values::set::value::SetGen;
 use crate::values::string::StarlarkStr;
 use crate::values::traits::StarlarkValueDyn;
 use crate::values::types::any_array::AnyArray;
@@ -102,6 +105,14 @@ pub(crate) static VALUE_EMPTY_FROZEN_DICT: AValueRepr<AValueImpl<Simple, DictGen
         }),
     );
 
+pub(crate) static VALUE_EMPTY_FROZEN_SET: AValueRepr<AValueImpl<Simple, SetGen<FrozenSetData>>> =
+    alloc_static(
+        Simple,
+        SetGen(FrozenSetData {
+            content: SmallSet::new(),
+        }),
+    );
+
 /// `Array` is not `Sync`, so wrap it into this struct to store it in static variable.
 /// Empty `Array` is logically `Sync`.
 pub(crate) struct ValueEmptyArray(AValueRepr<AValueImpl<Direct, Array<'static>>>);
@@ -0,0 +1,494 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::Ref;
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::mem;
+use std::ops::Deref;
+
+use allocative::Allocative;
+use display_container::fmt_container;
+use serde::Serialize;
+use starlark_derive::StarlarkDocs;
+use starlark_map::small_set::SmallSet;
+
+use crate as starlark;
+use crate::any::ProvidesStaticType;
+use crate::coerce::coerce;
+use crate::coerce::Coerce;
+use crate::collections::Hashed;
+use crate::environment::Methods;
+use crate::environment::MethodsStatic;
+use crate::starlark_type;
+use crate::values::dict::refcell::unleak_borrow;
+use crate::values::error::ValueError;
+use crate::values::layout::avalue::VALUE_EMPTY_FROZEN_SET;
+use crate::values::set::SetRef;
+use crate::values::type_repr::StarlarkTypeRepr;
+use crate::values::AllocFrozenValue;
+use crate::values::AllocValue;
+use crate::values::Freeze;
+use crate::values::Freezer;
+use crate::values::FrozenHeap;
+use crate::values::FrozenStringValue;
+use crate::values::FrozenValue;
+use crate::values::Heap;
+use crate::values::StarlarkValue;
+use crate::values::Trace;
+use crate::values::Value;
+
+#[derive(
+    Clone,
+    Default,
+    Trace,
+    Debug,
+    ProvidesStaticType,
+    StarlarkDocs,
+    Allocative
+)]
+#[starlark_docs(builtin = "extension")]
+pub(crate) struct SetGen<T>(pub(crate) T);
+
+impl<'v, T: SetLike<'v>> Display for SetGen<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_container(f, "set([", "])", self.0.content().iter())
+    }
+}
+
+impl<'v> Display for Set<'v> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_container(f, "set([", "])", self.iter())
+    }
+}
+
+/// Define the set type.
+#[derive(Clone, Default, Trace, Debug, ProvidesStaticType, Allocative)]
+#[repr(transparent)]
+pub struct Set<'v> {
+    /// The elements of the set. They must all be hashable values.
+    content: SmallSet<Value<'v>>,
+}
+
+impl<'v> StarlarkTypeRepr for Set<'v> {
+    fn starlark_type_repr() -> String {
+        Set::TYPE.to_owned()
+    }
+}
+
+#[derive(Clone, Default, Debug, ProvidesStaticType, Allocative)]
+#[repr(transparent)]
+pub(crate) struct FrozenSetData {
+    /// The elements of the set. They must all be hashable values.
+    pub(crate) content: SmallSet<FrozenValue>,
+}
+
+/// Alias is used in `StarlarkDocs` derive.
+type FrozenSet = SetGen<FrozenSetData>;
+
+unsafe impl<'v> Coerce<Set<'v>> for FrozenSetData {}
+
+impl<'v> AllocValue<'v> for Set<'v> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        heap.alloc_complex(SetGen(RefCell::new(self)))
+    }
+}
+
+impl AllocFrozenValue for FrozenSetData {
+    fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue {
+        if self.content.is_empty() {
+            FrozenValue::new_repr(&VALUE_EMPTY_FROZEN_SET)
+        } else {
+            heap.alloc_simple(SetGen(self))
+        }
+    }
+}
+
+impl<'v> Set<'v> {
+    /// The result of calling `type()` on sets.
+    pub const TYPE: &'static str = "set";
+
+    /// Set type string as Starlark frozen string value.
+    pub fn get_type_value_static() -> FrozenStringValue {
+        SetGen::<FrozenSetData>::get_type_value_static()
+    }
+
+    /// Create a new [`Set`].
+    pub fn new(content: SmallSet<Value<'v>>) -> Self {
+        Set { content }
+    }
+
+    /// Get the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Is the set empty?
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    /// Iterate through the elements of the set, in insertion order.
+    pub fn iter<'a>(&'a self) -> impl ExactSizeIterator<Item = Value<'v>> + 'a {
+        self.content.iter().copied()
+    }
+
+    /// Iterate through the elements of the set, with their hashes.
+    pub fn iter_hashed<'a>(&'a self) -> impl ExactSizeIterator<Item = Hashed<Value<'v>>> + 'a {
+        self.content.iter_hashed().map(|x| x.copied())
+    }
+
+    /// Is the given element in the set?
+    pub fn contains(&self, value: Value<'v>) -> anyhow::Result<bool> {
+        Ok(self.contains_hashed(value.get_hashed()?))
+    }
+
+    /// Is the given hashed element in the set?
+    pub fn contains_hashed(&self, value: Hashed<Value<'v>>) -> bool {
+        self.content.contains_hashed(value.as_ref())
+    }
+
+    /// Insert an element into the set. Returns `true` if it was not already present.
+    pub fn insert_hashed(&mut self, value: Hashed<Value<'v>>) -> bool {
+        self.content.insert_hashed(value)
+    }
+
+    /// Remove an element from the set. Returns `true` if it was present.
+    pub fn remove_hashed(&mut self, value: Hashed<Value<'v>>) -> bool {
+        self.content.remove_hashed(value.as_ref())
+    }
+
+    /// Remove and return the most recently inserted element, if any.
+    pub fn pop(&mut self) -> Option<Value<'v>> {
+        self.content.pop()
+    }
+
+    /// Remove all elements from the set.
+    pub fn clear(&mut self) {
+        self.content.clear();
+    }
+
+    /// Is every element of this set also in `other`?
+    pub fn is_subset(&self, other: &Set<'v>) -> bool {
+        self.len() <= other.len() && self.iter_hashed().all(|x| other.contains_hashed(x))
+    }
+}
+
+impl FrozenSetData {
+    /// Iterate through the elements of the set, in insertion order.
+    pub fn iter<'a>(&'a self) -> impl ExactSizeIterator<Item = FrozenValue> + 'a {
+        self.content.iter().copied()
+    }
+}
+
+impl<'v> Freeze for SetGen<RefCell<Set<'v>>> {
+    type Frozen = SetGen<FrozenSetData>;
+    fn freeze(self, freezer: &Freezer) -> anyhow::Result<Self::Frozen> {
+        let content = self.0.into_inner().content.freeze(freezer)?;
+        Ok(SetGen(FrozenSetData { content }))
+    }
+}
+
+trait SetLike<'v>: Debug + Allocative {
+    type ContentRef<'a>: Deref<Target = SmallSet<Value<'v>>>
+    where
+        Self: 'a,
+        'v: 'a;
+    fn content<'a>(&'a self) -> Self::ContentRef<'a>;
+    // These functions are unsafe for the same reason
+    // `StarlarkValue` iterator functions are unsafe.
+    unsafe fn iter_start(&self);
+    unsafe fn content_unchecked(&self) -> &SmallSet<Value<'v>>;
+    unsafe fn iter_stop(&self);
+}
+
+impl<'v> SetLike<'v> for RefCell<Set<'v>> {
+    type ContentRef<'a> = Ref<'a, SmallSet<Value<'v>>> where Self: 'a, 'v: 'a;
+
+    fn content<'a>(&'a self) -> Ref<'a, SmallSet<Value<'v>>> {
+        Ref::map(self.borrow(), |x| &x.content)
+    }
+
+    #[inline]
+    unsafe fn iter_start(&self) {
+        mem::forget(self.borrow());
+    }
+
+    #[inline]
+    unsafe fn iter_stop(&self) {
+        unleak_borrow(self);
+    }
+
+    #[inline]
+    unsafe fn content_unchecked(&self) -> &SmallSet<Value<'v>> {
+        // SAFETY: this function contract is, caller must ensure that the value is borrowed.
+        &self.try_borrow_unguarded().ok().unwrap_unchecked().content
+    }
+}
+
+impl<'v> SetLike<'v> for FrozenSetData {
+    type ContentRef<'a> = &'a SmallSet<Value<'v>> where Self: 'a, 'v: 'a;
+
+    fn content<'a>(&'a self) -> &'a SmallSet<Value<'v>> {
+        coerce(&self.content)
+    }
+
+    unsafe fn iter_start(&self) {}
+
+    unsafe fn iter_stop(&self) {}
+
+    unsafe fn content_unchecked(&self) -> &SmallSet<Value<'v>> {
+        coerce(&self.content)
+    }
+}
+
+pub(crate) fn set_methods() -> Option<&'static Methods> {
+    static RES: MethodsStatic = MethodsStatic::new();
+    RES.methods(crate::stdlib::set::set_methods)
+}
+
+impl<'v, T: SetLike<'v> + 'v> SetGen<T> {
+    /// Apply `op` to the content of this set and the set `rhs`, failing if `rhs` is not a set.
+    fn binary_op(
+        &self,
+        rhs: Value<'v>,
+        op: &str,
+        heap: &'v Heap,
+        f: impl FnOnce(&SmallSet<Value<'v>>, &Set<'v>) -> SmallSet<Value<'v>>,
+    ) -> anyhow::Result<Value<'v>>
+    where
+        Self: StarlarkValue<'v>,
+    {
+        let rhs = SetRef::from_value(rhs)
+            .map_or_else(|| ValueError::unsupported_with(self, op, rhs), Ok)?;
+        let content = f(&*self.0.content(), &*rhs);
+        Ok(heap.alloc(Set::new(content)))
+    }
+}
+
+impl<'v, T: SetLike<'v> + 'v> StarlarkValue<'v> for SetGen<T>
+where
+    Self: ProvidesStaticType,
+{
+    starlark_type!(Set::TYPE);
+
+    fn get_methods() -> Option<&'static Methods> {
+        set_methods()
+    }
+
+    fn collect_repr(&self, r: &mut String) {
+        r.push_str("set([");
+        for (i, x) in self.0.content().iter().enumerate() {
+            if i != 0 {
+                r.push_str(", ");
+            }
+            x.collect_repr(r);
+        }
+        r.push_str("])");
+    }
+
+    fn collect_repr_cycle(&self, collector: &mut String) {
+        collector.push_str("set([...])");
+    }
+
+    fn to_bool(&self) -> bool {
+        !self.0.content().is_empty()
+    }
+
+    fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        match SetRef::from_value(other) {
+            None => Ok(false),
+            Some(other) => {
+                let this = self.0.content();
+                Ok(this.len() == other.len()
+                    && this
+                        .iter_hashed()
+                        .all(|x| other.contains_hashed(x.copied())))
+            }
+        }
+    }
+
+    fn length(&self) -> anyhow::Result<i32> {
+        Ok(self.0.content().len() as i32)
+    }
+
+    fn is_in(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        Ok(self
+            .0
+            .content()
+            .contains_hashed(other.get_hashed()?.as_ref()))
+    }
+
+    unsafe fn iterate(&self, me: Value<'v>, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.0.iter_start();
+        Ok(me)
+    }
+
+    unsafe fn iter_size_hint(&self, index: usize) -> (usize, Option<usize>) {
+        debug_assert!(index <= self.0.content().len());
+        let rem = self.0.content().len() - index;
+        (rem, Some(rem))
+    }
+
+    unsafe fn iter_next(&self, index: usize, _heap: &'v Heap) -> Option<Value<'v>> {
+        self.0.content_unchecked().get_index(index).copied()
+    }
+
+    unsafe fn iter_stop(&self) {
+        self.0.iter_stop();
+    }
+
+    fn bit_or(&self, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.binary_op(rhs, "|", heap, |this, rhs| {
+            let mut items = this.clone();
+            for x in rhs.iter_hashed() {
+                items.insert_hashed(x);
+            }
+            items
+        })
+    }
+
+    fn bit_and(&self, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.binary_op(rhs, "&", heap, |this, rhs| {
+            collect_set(
+                this.iter_hashed()
+                    .map(|x| x.copied())
+                    .filter(|x| rhs.contains_hashed(*x)),
+            )
+        })
+    }
+
+    fn sub(&self, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.binary_op(rhs, "-", heap, |this, rhs| {
+            collect_set(
+                this.iter_hashed()
+                    .map(|x| x.copied())
+                    .filter(|x| !rhs.contains_hashed(*x)),
+            )
+        })
+    }
+
+    fn bit_xor(&self, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.binary_op(rhs, "^", heap, |this, rhs| {
+            let mut items = collect_set(
+                this.iter_hashed()
+                    .map(|x| x.copied())
+                    .filter(|x| !rhs.contains_hashed(*x)),
+            );
+            for x in rhs.iter_hashed() {
+                if !this.contains_hashed(x.as_ref()) {
+                    items.insert_hashed(x);
+                }
+            }
+            items
+        })
+    }
+}
+
+/// Collect hashed values into a [`SmallSet`] without rehashing them.
+pub(crate) fn collect_set<'v>(xs: impl Iterator<Item = Hashed<Value<'v>>>) -> SmallSet<Value<'v>> {
+    let mut res = SmallSet::new();
+    for x in xs {
+        res.insert_hashed(x);
+    }
+    res
+}
+
+impl<'v, T: SetLike<'v>> Serialize for SetGen<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.0.content().iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_set_repr() {
+        assert::eq("repr(set())", "'set([])'");
+        assert::eq("repr(set([1, 2, 1]))", "'set([1, 2])'");
+        assert::eq("str(set(['a']))", "'set([\"a\"])'");
+    }
+
+    #[test]
+    fn test_set_equality() {
+        assert::is_true("set([1, 2]) == set([2, 1])");
+        assert::is_true("set([1, 2]) != set([1, 2, 3])");
+        assert::is_true("set([1]) != [1]");
+    }
+
+    #[test]
+    fn test_set_operators() {
+        assert::eq("set([1, 2]) | set([2, 3])", "set([1, 2, 3])");
+        assert::eq("set([1, 2]) & set([2, 3])", "set([2])");
+        assert::eq("set([1, 2]) - set([2, 3])", "set([1])");
+        assert::eq("set([1, 2]) ^ set([2, 3])", "set([1, 3])");
+        assert::fail("set([1]) | [2]", "not supported");
+    }
+
+    #[test]
+    fn test_set_hash() {
+        assert::fail("{set([1]): 1}", "not hashable");
+        assert::fail("set([set([1])])", "not hashable");
+        // Like lists and dicts, sets are not hashable even once frozen.
+        let mut a = assert::Assert::new();
+        a.module("frozen.star", "s = set([1, 2])");
+        a.fail(
+            r#"
+load("frozen.star", "s")
+{s: "x"}
+"#,
+            "not hashable",
+        );
+    }
+
+    #[test]
+    fn test_set_mutation_during_iteration() {
+        assert::fail(
+            r#"
+s = set([1, 2])
+for x in s:
+    s.add(3)
+"#,
+            "mutate an iterable for an iterator while iterating",
+        );
+    }
+
+    #[test]
+    fn test_set_frozen() {
+        let mut a = assert::Assert::new();
+        a.module("frozen.star", "s = set([1])");
+        a.fail(
+            r#"
+load("frozen.star", "s")
+s.add(2)
+"#,
+            "Immutable",
+        );
+    }
+
+    #[test]
+    fn test_set_json() {
+        assert::eq("json.encode(set([1, 'a']))", "'[1,\"a\"]'");
+    }
+}